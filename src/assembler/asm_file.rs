@@ -0,0 +1,358 @@
+use std::collections::BTreeSet;
+
+use crate::{formats::elf::SymbolType, parser::types::VariableType};
+
+use super::{
+    asm_parser::{parse_instr, parse_number, parse_symbol, split_word},
+    instructions::{Instr, Opr},
+    mnemonic::Mnemonic,
+    Codegen,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+/// Assembler for the subset of the nasm syntax generated by the compiler
+struct AsmFile {
+    codegen: Codegen,
+    section: Section,
+    externs: BTreeSet<String>,
+    /// Names of the data and bss items which can be used before they are defined
+    memory: BTreeSet<String>,
+    /// Line and name of every label used as a jump or call target
+    targets: Vec<(usize, String)>,
+}
+
+/// Assembles a whole nasm file into the text, data and bss sections of a codegen
+/// * path - name of the file used in error messages
+pub fn assemble_file(path: &str, source: &str) -> Result<Codegen, String> {
+    let mut asm = AsmFile::new(source);
+    for (i, line) in source.lines().enumerate() {
+        asm.line(i + 1, strip_comment(line).trim())
+            .map_err(|e| format!("{path}:{}: {e}", i + 1))?;
+    }
+    for (line, target) in asm.targets.iter() {
+        if !matches!(
+            asm.codegen.symbols_map.get(target),
+            Some((_, SymbolType::TextSec))
+        ) {
+            return Err(format!("{path}:{line}: undefined label \"{target}\""));
+        }
+    }
+    Ok(asm.codegen)
+}
+
+impl AsmFile {
+    fn new(source: &str) -> Self {
+        let mut asm = Self {
+            codegen: Codegen::new(),
+            section: Section::Text,
+            externs: BTreeSet::new(),
+            memory: BTreeSet::new(),
+            targets: Vec::new(),
+        };
+        // symbols are collected ahead since they change the encoding of the instructions
+        for line in source.lines() {
+            let line = strip_comment(line).trim();
+            let (word, rest) = split_word(line);
+            match word.to_lowercase().as_str() {
+                "section" | "segment" => {
+                    if let Ok(section) = parse_section(rest) {
+                        asm.section = section;
+                    }
+                }
+                "extern" => asm
+                    .externs
+                    .extend(rest.split(',').map(|s| s.trim().to_string())),
+                _ if asm.section != Section::Text && !rest.is_empty() => {
+                    asm.memory.insert(word.trim_end_matches(':').to_string());
+                }
+                _ => (),
+            }
+        }
+        asm.section = Section::Text;
+        asm
+    }
+
+    fn line(&mut self, line_num: usize, line: &str) -> Result<(), String> {
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (word, rest) = split_word(line);
+        match word.to_lowercase().as_str() {
+            "section" | "segment" => {
+                self.section = parse_section(rest)?;
+                return Ok(());
+            }
+            "global" => {
+                for name in rest.split(',') {
+                    self.codegen.globals.insert(parse_symbol(name)?);
+                }
+                return Ok(());
+            }
+            "extern" => {
+                for name in rest.split(',') {
+                    parse_symbol(name)?;
+                }
+                return Ok(());
+            }
+            _ => (),
+        }
+        match self.section {
+            Section::Text => match word.strip_suffix(':') {
+                Some(label) => {
+                    let label = parse_symbol(label)?;
+                    self.define(&label)?;
+                    self.codegen.set_lable(label);
+                    self.line(line_num, rest)
+                }
+                None => {
+                    let instr = self.instruction(line_num, line)?;
+                    self.codegen.new_instr(instr);
+                    Ok(())
+                }
+            },
+            Section::Data => {
+                let name = parse_symbol(word.trim_end_matches(':'))?;
+                self.define(&name)?;
+                let data = parse_data(rest)?;
+                self.codegen.insert_data(name, data, VariableType::String);
+                Ok(())
+            }
+            Section::Bss => {
+                let name = parse_symbol(word.trim_end_matches(':'))?;
+                self.define(&name)?;
+                let size = parse_reserve(rest)?;
+                self.codegen.insert_bss(name, size);
+                Ok(())
+            }
+        }
+    }
+
+    fn define(&self, name: &str) -> Result<(), String> {
+        match self.codegen.symbols_map.contains_key(name) {
+            true => Err(format!("symbol \"{name}\" is already defined")),
+            false => Ok(()),
+        }
+    }
+
+    fn instruction(&mut self, line_num: usize, line: &str) -> Result<Instr, String> {
+        let Self {
+            codegen,
+            externs,
+            memory,
+            targets,
+            ..
+        } = self;
+        parse_instr(line, &mut |mnem, symbol| {
+            if memory.contains(symbol) {
+                Ok(Opr::Rela(symbol.to_string()))
+            } else if externs.contains(symbol) {
+                if mnem != Mnemonic::Call {
+                    return Err(format!("extern symbol \"{symbol}\" can only be called"));
+                }
+                codegen
+                    .symbols_map
+                    .insert(symbol.to_string(), (0, SymbolType::Ffi));
+                Ok(Opr::Rela(symbol.to_string()))
+            } else if mnem.is_jump() || mnem == Mnemonic::Call {
+                targets.push((line_num, symbol.to_string()));
+                Ok(Opr::Loc(symbol.to_string()))
+            } else {
+                Err(format!(
+                    "label \"{symbol}\" can only be a jump or call target"
+                ))
+            }
+        })
+    }
+}
+
+fn parse_section(name: &str) -> Result<Section, String> {
+    match name.trim() {
+        ".text" => Ok(Section::Text),
+        ".data" => Ok(Section::Data),
+        ".bss" => Ok(Section::Bss),
+        name => Err(format!("unsupported section \"{name}\"")),
+    }
+}
+
+/// Items of a db, dw, dd or dq directive in little endian
+/// strings are padded to a multiple of the item size like nasm
+fn parse_data(directive: &str) -> Result<Vec<u8>, String> {
+    let (dtype, items) = split_word(directive);
+    let size = match dtype.to_lowercase().as_str() {
+        "db" => 1,
+        "dw" => 2,
+        "dd" => 4,
+        "dq" => 8,
+        _ => return Err(format!("unknown data directive \"{dtype}\"")),
+    };
+    let mut data = Vec::new();
+    for item in split_items(items)? {
+        let item = item.trim();
+        if let Some(text) = item
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .or_else(|| item.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+        {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(bytes.len().div_ceil(size) * size, 0);
+            data.extend(bytes);
+        } else if let Some(val) = parse_number(item) {
+            data.extend(val.to_le_bytes().iter().take(size));
+        } else {
+            return Err(format!("invalid data item \"{item}\""));
+        }
+    }
+    Ok(data)
+}
+
+/// Size of a resb, resw, resd or resq directive in bytes
+fn parse_reserve(directive: &str) -> Result<usize, String> {
+    let (dtype, count) = split_word(directive);
+    let size = match dtype.to_lowercase().as_str() {
+        "resb" => 1,
+        "resw" => 2,
+        "resd" => 4,
+        "resq" => 8,
+        _ => return Err(format!("unknown bss directive \"{dtype}\"")),
+    };
+    match parse_number(count) {
+        Some(count) if count >= 0 => Ok(count as usize * size),
+        _ => Err(format!("invalid reserve size \"{count}\"")),
+    }
+}
+
+/// Removes the comment of a line while keeping ';' inside strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ';') => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// Comma separated items of a data directive
+fn split_items(items: &str) -> Result<Vec<&str>, String> {
+    let mut res = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in items.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ',') => {
+                res.push(&items[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated string".to_string());
+    }
+    res.push(&items[start..]);
+    Ok(res)
+}
+
+#[test]
+fn test_assemble_file() {
+    use super::{memory::MemAddr, register::Reg::*};
+    let mut codegen = assemble_file(
+        "test.asm",
+        "section .text
+extern write ; libc
+global _start
+_start:
+    mov rsi, msg
+    mov byte [rbp - 8 + rax * 1], 10
+    mov dword [count], eax
+    mov rdx, -1
+    jz _start.end
+    call write
+_start.end: ret
+section .data
+msg db \"Hi;\",10
+nums dw 1,-2
+section .bss
+count resb 4
+buf resq 2
+",
+    )
+    .unwrap();
+    assert_eq!(
+        codegen.get_raw_instructs()[1..8],
+        [
+            Instr::new2(Mnemonic::Mov, RSI, Opr::Rela("msg".into())),
+            Instr::new2(
+                Mnemonic::Mov,
+                MemAddr::new_sib_s(1, RBP, -8, RAX, 1),
+                Opr::Imm8(10)
+            ),
+            Instr::new2(Mnemonic::Mov, MemAddr::new_rela_s(4, "count".into()), EAX),
+            Instr::new2(Mnemonic::Mov, RDX, Opr::Imm64(-1)),
+            Instr::new1(Mnemonic::Jz, Opr::rel("_start.end")),
+            Instr::new1(Mnemonic::Call, Opr::Rela("write".into())),
+            Instr::new1(Mnemonic::Lable, Opr::rel("_start.end")),
+        ]
+    );
+    assert!(codegen.globals.contains("_start"));
+    assert_eq!(codegen.data_buf["msg"].data, b"Hi;\n");
+    assert_eq!(codegen.data_buf["nums"].data, [1, 0, 0xFE, 0xFF]);
+    assert_eq!(codegen.data_buf["nums"].index, 4);
+    assert_eq!(codegen.bss_buf[1].index, 4);
+    assert_eq!(codegen.bss_buf[1].size, 16);
+    codegen.relocate().unwrap();
+    assert_eq!(codegen.symbols_map["write"].1, SymbolType::Ffi);
+    assert_eq!(codegen.rela_map.len(), 3);
+}
+
+#[test]
+fn test_assemble_file_errors() {
+    let error = |source: &str| assemble_file("test.asm", source).err().unwrap();
+    assert_eq!(
+        error("main:\n    jmp main.end\n"),
+        "test.asm:2: undefined label \"main.end\""
+    );
+    assert_eq!(
+        error("main:\nmain:\n"),
+        "test.asm:2: symbol \"main\" is already defined"
+    );
+    assert_eq!(
+        error("    movx rax, 1\n"),
+        "test.asm:1: unknown instruction \"movx\""
+    );
+    assert_eq!(
+        error("    mov rax, qword [rbx * 3]\n"),
+        "test.asm:1: invalid scale \"rbx * 3\""
+    );
+    assert_eq!(
+        error("section .rodata\n"),
+        "test.asm:1: unsupported section \".rodata\""
+    );
+    assert_eq!(
+        error("extern puts\n    mov rax, puts\n"),
+        "test.asm:2: extern symbol \"puts\" can only be called"
+    );
+    assert_eq!(
+        error("    add rax, 0x80000000\n"),
+        "test.asm:1: immediate 0x80000000 does not fit in 32 bits"
+    );
+    assert_eq!(
+        error("    mov [rsp - 8], 1\n"),
+        "test.asm:1: oprand size is unknown for instr (mov [rsp - 8], 1)!"
+    );
+    assert_eq!(
+        error("    mov rax, xmm0\n"),
+        "test.asm:1: invalid combination of opcode and operands (mov rax, xmm0)"
+    );
+}
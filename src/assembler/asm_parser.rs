@@ -1,146 +1,240 @@
 use std::str::FromStr;
 
-use crate::lexer::{Lexer, TokenType};
-
 #[allow(unused_imports)]
 use super::{
+    assemble::validate_instr,
     instructions::{Instr, Opr, Oprs},
     memory::MemAddr,
     mnemonic::Mnemonic::{self, *},
     register::Reg,
 };
 
-pub fn parse_asm(source: String) -> Instr {
-    let mut lexer = Lexer::new(format!("asm:{source}"), source);
-    let mnmemonic = parse_mnemonic(&mut lexer);
-    let mut ops = Vec::<Opr>::new();
-    loop {
-        if lexer.get_token().is_empty() {
-            break;
-        }
-        ops.push(parse_op(&mut lexer));
-        if lexer.get_token_type() == TokenType::Comma {
-            lexer.match_token(TokenType::Comma);
-            continue;
-        } else {
-            break;
+/// Parses a single instruction of an inline asm block
+/// symbols are labels when used by jumps and calls and static memory otherwise
+pub fn parse_asm(source: &str) -> Result<Instr, String> {
+    parse_instr(
+        source,
+        &mut |mnem, symbol| match mnem.is_jump() || mnem == Call {
+            true => Ok(Opr::Loc(symbol.to_string())),
+            false => Ok(Opr::Rela(symbol.to_string())),
+        },
+    )
+}
+
+/// Parses an instruction in the nasm syntax
+/// * symbol - creates the operand of a symbol used by the instruction
+///   symbols of memory operands must be relocations
+pub fn parse_instr(
+    source: &str,
+    symbol: &mut dyn FnMut(Mnemonic, &str) -> Result<Opr, String>,
+) -> Result<Instr, String> {
+    let (mnem, rest) = split_word(source.trim());
+    let mnem = match Mnemonic::from_str(mnem) {
+        Ok(Lable) | Err(_) => return Err(format!("unknown instruction \"{mnem}\"")),
+        Ok(mnem) => mnem,
+    };
+    let mut oprs = Vec::new();
+    if !rest.is_empty() {
+        for opr in rest.split(',') {
+            let opr = parse_opr(mnem, opr.trim(), oprs.first(), symbol)?;
+            oprs.push(opr);
         }
     }
-    let oprs = match ops.len() {
+    let oprs = match oprs.len() {
         0 => Oprs::None,
-        1 => Oprs::One(ops[0].clone()),
-        2 => Oprs::Two(ops[0].clone(), ops[1].clone()),
-        _ => unreachable!(),
+        1 => Oprs::One(oprs.remove(0)),
+        2 => Oprs::Two(oprs.remove(0), oprs.remove(0)),
+        n => return Err(format!("\"{mnem}\" does not accept {n} operands")),
     };
-    Instr::new(mnmemonic, oprs)
+    let instr = Instr::new(mnem, oprs);
+    validate_instr(&instr)?;
+    Ok(instr)
 }
 
-fn parse_mnemonic(lexer: &mut Lexer) -> Mnemonic {
-    let ident = lexer.next_token();
-    lexer.match_token(TokenType::Identifier);
-    Mnemonic::from_str(&ident.literal).unwrap()
+fn parse_opr(
+    mnem: Mnemonic,
+    opr: &str,
+    dst: Option<&Opr>,
+    symbol: &mut dyn FnMut(Mnemonic, &str) -> Result<Opr, String>,
+) -> Result<Opr, String> {
+    if let Ok(reg) = Reg::from_str(opr) {
+        return Ok(reg.into());
+    }
+    let (word, rest) = split_word(opr);
+    let size = match word.to_lowercase().as_str() {
+        "byte" => Some(1),
+        "word" => Some(2),
+        "dword" => Some(4),
+        "qword" => Some(8),
+        _ => None,
+    };
+    if let Some(size) = size {
+        return parse_mem(mnem, size, rest, symbol);
+    }
+    if opr.starts_with('[') {
+        return parse_mem(mnem, 0, opr, symbol);
+    }
+    if let Some(val) = parse_number(opr) {
+        return immediate(mnem, dst, val);
+    }
+    symbol(mnem, &parse_symbol(opr)?)
 }
 
-fn parse_op(lexer: &mut Lexer) -> Opr {
-    match lexer.get_token_type() {
-        TokenType::Identifier => {
-            if let Ok(reg) = Reg::from_str(&lexer.get_token().literal) {
-                lexer.match_token(TokenType::Identifier);
-                return reg.into();
+/// Memory operand in the form of [base + index * scale + disp] or [rel symbol]
+fn parse_mem(
+    mnem: Mnemonic,
+    size: u8,
+    opr: &str,
+    symbol: &mut dyn FnMut(Mnemonic, &str) -> Result<Opr, String>,
+) -> Result<Opr, String> {
+    let invalid = || format!("invalid memory operand \"{opr}\"");
+    let Some(inner) = opr
+        .trim()
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+    else {
+        return Err(invalid());
+    };
+    let inner = inner.trim();
+    let inner = inner.strip_prefix("rel ").unwrap_or(inner);
+    let mut base = None;
+    let mut index = None;
+    let mut disp: Option<i64> = None;
+    let mut rela = None;
+    for (negative, term) in split_terms(inner) {
+        let term = term.trim();
+        if let Some((reg, scale)) = term.split_once('*') {
+            let (reg, scale) = match parse_number(scale.trim()) {
+                Some(scale) => (reg.trim(), scale),
+                None => (scale.trim(), parse_number(reg.trim()).unwrap_or(0)),
+            };
+            if !matches!(scale, 1 | 2 | 4 | 8) {
+                return Err(format!("invalid scale \"{term}\""));
+            }
+            let reg = Reg::from_str(reg).map_err(|_| format!("invalid index \"{term}\""))?;
+            if negative || index.replace((reg, scale as u8)).is_some() {
+                return Err(invalid());
             }
-            match lexer.get_token().literal.to_lowercase().as_str() {
-                "qword" => {
-                    lexer.match_token(TokenType::Identifier);
-                    parse_mem(lexer, 8)
-                }
-                "dword" => {
-                    lexer.match_token(TokenType::Identifier);
-                    parse_mem(lexer, 4)
-                }
-                "word" => {
-                    lexer.match_token(TokenType::Identifier);
-                    parse_mem(lexer, 2)
-                }
-                "byte" => {
-                    lexer.match_token(TokenType::Identifier);
-                    parse_mem(lexer, 1)
-                }
-                _ => panic!(
-                    "[{}] Unexpected Asm Operation ({})!",
-                    lexer.get_token_loc(),
-                    lexer.get_token().literal
-                ),
+        } else if let Ok(reg) = Reg::from_str(term) {
+            match (negative, base, index) {
+                (false, None, _) => base = Some(reg),
+                (false, Some(_), None) => index = Some((reg, 1)),
+                _ => return Err(invalid()),
             }
+        } else if let Some(val) = parse_number(term) {
+            let val = if negative { -val } else { val };
+            disp = Some(disp.unwrap_or(0) + val);
+        } else {
+            let name = parse_symbol(term)?;
+            match symbol(mnem, &name)? {
+                Opr::Rela(name) if !negative => rela = Some(name),
+                _ => return Err(format!("\"{name}\" can not be used as a memory address")),
+            }
+        }
+    }
+    let disp32 =
+        |disp: i64| i32::try_from(disp).map_err(|_| format!("displacement {disp} is out of range"));
+    let mem = match (rela, base, index, disp) {
+        (Some(name), None, None, None) => MemAddr::new_rela_s(size, name),
+        (None, Some(base), None, None) => MemAddr::new_s(size, base),
+        (None, Some(base), None, Some(disp)) => MemAddr::new_disp_s(size, base, disp32(disp)?),
+        (None, Some(base), Some((index, scale)), disp) => {
+            MemAddr::new_sib_s(size, base, disp32(disp.unwrap_or(0))?, index, scale)
         }
-        TokenType::OBracket => parse_mem(lexer, 0),
-        TokenType::Int(i) => i.into(),
-        _ => panic!("Unsupported ASM operation!"),
+        _ => return Err(format!("unsupported memory operand \"{opr}\"")),
+    };
+    Ok(mem.into())
+}
+
+/// Picks the immediate variant with the same meaning as in nasm
+/// since mov to a 64bit register with a 32bit immediate is zero extended
+/// while other instructions sign extend their immediate
+/// only mov to a 64bit register accepts an immediate wider than 32bits
+fn immediate(mnem: Mnemonic, dst: Option<&Opr>, val: i64) -> Result<Opr, String> {
+    let mov64 = mnem == Mov && matches!(dst, Some(Opr::R64(_)));
+    let dst32 = match dst {
+        Some(Opr::R32(_) | Opr::R16(_) | Opr::R8(_)) => true,
+        Some(Opr::Mem(m)) => matches!(m.size, 1 | 2 | 4),
+        _ => false,
+    };
+    match val {
+        _ if mov64 && val < 0 => Ok(Opr::Imm64(val)),
+        _ if i8::try_from(val).is_ok() => Ok(Opr::Imm8(val)),
+        _ if i32::try_from(val).is_ok() => Ok(Opr::Imm32(val)),
+        _ if (mov64 || dst32) && (0..=u32::MAX as i64).contains(&val) => Ok(Opr::Imm32(val)),
+        _ if mov64 => Ok(Opr::Imm64(val)),
+        _ => Err(format!("immediate {val:#x} does not fit in 32 bits")),
     }
 }
 
-fn parse_mem(lexer: &mut Lexer, size: u8) -> Opr {
-    lexer.match_token(TokenType::OBracket);
-    let r = Reg::from_str(&lexer.get_token().literal).unwrap();
-    lexer.match_token(TokenType::Identifier);
-    let res: Opr = match lexer.get_token_type() {
-        TokenType::CBracket => Opr::Mem(MemAddr::new_s(size, r)),
-        TokenType::Plus | TokenType::Minus => {
-            let sign = lexer.get_token_type();
-            lexer.next_token();
-            let TokenType::Int(mut val) = lexer.get_token_type() else {
-                panic!("expected an integer found {}", lexer.get_token_type());
-            };
-            if sign == TokenType::Minus {
-                val = -val;
-            }
-            lexer.next_token();
-            if lexer.get_token_type() == TokenType::CBracket {
-                Opr::Mem(MemAddr::new_disp_s(size, r, val))
-            } else if lexer.get_token_type() == TokenType::Plus {
-                lexer.match_token(TokenType::Plus);
-                let r2 = Reg::from_str(&lexer.get_token().literal).unwrap();
-                lexer.match_token(TokenType::Identifier);
-                if lexer.get_token_type() == TokenType::CBracket {
-                    Opr::Mem(MemAddr::new_sib_s(size, r, val, r2, 1))
-                } else {
-                    lexer.match_token(TokenType::Multi);
-                    let TokenType::Int(scale) = lexer.get_token_type() else {
-                        panic!("expected an integer found {}", lexer.get_token_type());
-                    };
-                    if ![1, 2, 4, 8].contains(&scale) {
-                        panic!("unexpected scale for a register");
-                    }
-                    lexer.next_token();
-                    Opr::Mem(MemAddr::new_sib_s(size, r, val, r2, scale as u8))
-                }
-            } else {
-                panic!("unsupported operator \"{}\"!", lexer.get_token().literal);
+pub fn parse_symbol(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "_.$?@".contains(c);
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() && name.chars().all(valid_char) => Ok(name.to_string()),
+        _ => Err(format!("invalid symbol name \"{name}\"")),
+    }
+}
+
+/// Decimal, hexadecimal (0x) or binary (0b) integer
+pub fn parse_number(literal: &str) -> Option<i64> {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, literal),
+    };
+    let lower = literal.to_lowercase();
+    let val = if let Some(hex) = lower.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()? as i64
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse::<u64>().ok()? as i64
+    } else {
+        return None;
+    };
+    Some(if negative { val.wrapping_neg() } else { val })
+}
+
+pub fn split_word(line: &str) -> (&str, &str) {
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// Terms of a memory operand and whether they are subtracted
+fn split_terms(inner: &str) -> Vec<(bool, &str)> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        if c == '+' || c == '-' {
+            if !inner[start..i].trim().is_empty() {
+                terms.push((negative, &inner[start..i]));
             }
+            negative = c == '-';
+            start = i + 1;
         }
-        _ => panic!("unsupported operation!"),
-    };
-    lexer.match_token(TokenType::CBracket);
-    res
+    }
+    terms.push((negative, &inner[start..]));
+    terms
 }
 
 #[test]
 fn test_mnemonic_parsing() {
     assert_eq!(
         Instr::new2(Mov, Reg::RAX, Opr::Imm8(1)),
-        parse_asm("mov rax, 1".to_string())
-    );
-    assert_eq!(Instr::new0(Syscall), parse_asm("syscall".to_string()));
-    assert_eq!(
-        Instr::new1(Push, Reg::RAX),
-        parse_asm("push rax".to_string())
+        parse_asm("mov rax, 1").unwrap()
     );
+    assert_eq!(Instr::new0(Syscall), parse_asm("syscall").unwrap());
+    assert_eq!(Instr::new1(Push, Reg::RAX), parse_asm("push rax").unwrap());
     assert_eq!(
         Instr::new2(Mov, Reg::RAX, MemAddr::new(Reg::RBX)),
-        parse_asm("mov rax, [rbx]".to_string())
+        parse_asm("mov rax, [rbx]").unwrap()
     );
     assert_eq!(
         Instr::new2(Mov, Reg::RAX, MemAddr::new_disp_s(8, Reg::RBX, 2)),
-        parse_asm("mov rax, qword [rbx+2]".to_string())
+        parse_asm("mov rax, qword [rbx+2]").unwrap()
     );
     assert_eq!(
         Instr::new2(
@@ -148,6 +242,70 @@ fn test_mnemonic_parsing() {
             Reg::RAX,
             MemAddr::new_sib_s(8, Reg::RBX, 2, Reg::RAX, 4)
         ),
-        parse_asm("mov rax, qword [rbx+2 + rax*4]".to_string())
+        parse_asm("mov rax, qword [rbx+2 + rax*4]").unwrap()
+    );
+}
+
+#[test]
+fn test_symbols_and_immediates() {
+    assert_eq!(
+        Instr::new1(Jnz, Opr::rel("main.asm3.loop")),
+        parse_asm("jnz main.asm3.loop").unwrap()
+    );
+    assert_eq!(
+        Instr::new1(Call, Opr::rel("my_func")),
+        parse_asm("call my_func").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Lea, Reg::RAX, MemAddr::new_rela_s(0, "bss0".into())),
+        parse_asm("lea rax, [rel bss0]").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Mov, Reg::RSI, Opr::Rela("data1".into())),
+        parse_asm("mov rsi, data1").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Add, Reg::RAX, Opr::Imm32(200)),
+        parse_asm("add rax, 200").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Mov, Reg::RAX, Opr::Imm32(0xFFFFFFFF)),
+        parse_asm("mov rax, 0xFFFFFFFF").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Mov, Reg::RAX, Opr::Imm64(-2)),
+        parse_asm("mov rax, -2").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Mov, Reg::RAX, Opr::Imm64(0x7FFF_FFFF_FFFF)),
+        parse_asm("mov rax, 0x7FFFFFFFFFFF").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Mov, Reg::CL, Opr::Imm8(-1)),
+        parse_asm("mov cl, -1").unwrap()
+    );
+    assert_eq!(
+        parse_asm("mov rax, [rbx * 3]").unwrap_err(),
+        "invalid scale \"rbx * 3\""
+    );
+    assert_eq!(
+        Instr::new2(Mov, MemAddr::new_disp_s(8, Reg::RSP, -8), Opr::Imm8(1)),
+        parse_asm("mov qword [rsp-8], 1").unwrap()
+    );
+    assert_eq!(
+        Instr::new2(Add, Reg::EAX, Opr::Imm32(0xFFFFFFFF)),
+        parse_asm("add eax, 0xFFFFFFFF").unwrap()
+    );
+    assert_eq!(
+        parse_asm("add rax, 0x80000000").unwrap_err(),
+        "immediate 0x80000000 does not fit in 32 bits"
+    );
+    assert_eq!(
+        parse_asm("cmp rax, 0xFFFFFFFF").unwrap_err(),
+        "immediate 0xffffffff does not fit in 32 bits"
+    );
+    assert_eq!(
+        parse_asm("loop:").unwrap_err(),
+        "unknown instruction \"loop:\""
     );
 }
@@ -4,7 +4,8 @@ use super::{
     instructions::{Instr, ModrmType, Opr, Oprs},
     memory::{MemAddr, MemAddrType},
     mnemonic::Mnemonic,
-    opcodes::opcode,
+    opcodes::{opcode, sse_opcode},
+    placeholder,
     register::Reg,
 };
 
@@ -14,18 +15,19 @@ macro_rules! Register {
     };
 }
 
-macro_rules! r_8_64 {
-    () => {
-        Opr::R64(_) | Opr::R32(_) | Opr::R16(_) | Opr::R8(_)
-    };
-}
-
 pub fn assemble_instr(instr: &Instr) -> IBytes {
+    if instr.mnem.is_sse() {
+        return assemble_sse(instr);
+    }
     let mut bytes = vec![];
-    validate_opr_sizes(instr);
+    if let Err(e) = validate_opr_sizes(instr) {
+        panic!("Error: {e}");
+    }
     let instr = align_imm_oprs_to_reg(instr);
     bytes.extend(rex(&instr));
-    let (mut opcode, modrmtype) = opcode(&instr);
+    let Some((mut opcode, modrmtype)) = opcode(&instr) else {
+        unimplemented!("{instr}");
+    };
     let mut modrm_val = Vec::<u8>::new();
     match modrmtype {
         ModrmType::Add => match instr.oprs {
@@ -52,6 +54,84 @@ pub fn assemble_instr(instr: &Instr) -> IBytes {
     bytes
 }
 
+/// Checks that the instruction can be encoded without panicking while assembling it
+/// symbols are checked with the 32bit placeholder they are assembled with
+pub fn validate_instr(instr: &Instr) -> Result<(), String> {
+    let has_symbol = |opr: &Opr| matches!(opr, Opr::Rela(_) | Opr::Loc(_));
+    let instr = match &instr.oprs {
+        Oprs::One(a) if has_symbol(a) => placeholder(instr.clone()),
+        Oprs::Two(a, b) if has_symbol(a) || has_symbol(b) => placeholder(instr.clone()),
+        _ => instr.clone(),
+    };
+    if instr.mnem.is_sse() {
+        return match sse_opcode(&instr) {
+            Some(_) => Ok(()),
+            None => Err(invalid_combination(&instr)),
+        };
+    }
+    validate_opr_sizes(&instr)?;
+    match opcode(&align_imm_oprs_to_reg(&instr)) {
+        Some(_) => Ok(()),
+        None => Err(invalid_combination(&instr)),
+    }
+}
+
+fn invalid_combination(instr: &Instr) -> String {
+    format!("invalid combination of opcode and operands ({instr})")
+}
+
+/// SSE instructions: prefix, rex, 0x0f opcode and modrm
+fn assemble_sse(instr: &Instr) -> IBytes {
+    let Some(enc) = sse_opcode(instr) else {
+        unimplemented!("{instr}");
+    };
+    let Oprs::Two(first, second) = &instr.oprs else {
+        unreachable!("{instr}: Unecxpected behavior!");
+    };
+    let (reg, rm) = if enc.reg_first {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    let (Register!(reg) | Opr::Xmm(reg)) = reg else {
+        unreachable!("{instr}: Unecxpected behavior!");
+    };
+    let mut bytes = vec![enc.prefix];
+    let mut rex: u8 = 0x40;
+    if enc.rex_w {
+        rex |= 0b1000;
+    }
+    if reg.is_extended() {
+        rex |= 0b0100;
+    }
+    let modrm_val = match rm {
+        Register!(r) | Opr::Xmm(r) => {
+            if r.is_extended() {
+                rex |= 0b0001;
+            }
+            vec![_modrm(0b11, r.opcode(), reg.opcode())]
+        }
+        Opr::Mem(mem) => {
+            if !mem.is_rela() && mem.get_register().is_extended() {
+                rex |= 0b0001;
+            }
+            if let Some(s_reg) = mem.get_s_register() {
+                if s_reg.is_extended() {
+                    rex |= 0b0010;
+                }
+            }
+            _mem_modrm(reg.opcode(), mem)
+        }
+        _ => unreachable!("{instr}: Unecxpected behavior!"),
+    };
+    if rex != 0x40 {
+        bytes.push(rex);
+    }
+    bytes.extend(enc.opcode.to_be_bytes());
+    bytes.extend(modrm_val);
+    bytes
+}
+
 fn include_imm_values(bytes: &mut IBytes, instr: &Instr) {
     if instr.mnem.needs_precision_imm() {
         match &instr.oprs {
@@ -59,18 +139,14 @@ fn include_imm_values(bytes: &mut IBytes, instr: &Instr) {
                 1 => {
                     bytes.push(val.to_le_bytes()[0]);
                 }
-                2 | 4 | 8 => {
+                2 => {
+                    bytes.extend(val.to_le_bytes().iter().take(2));
+                }
+                4 | 8 => {
                     bytes.extend(val.to_le_bytes().iter().take(4));
                 }
                 _ => unreachable!(),
             },
-            Oprs::Two(Opr::Mem(m), r_8_64!())
-            | Oprs::Two(r_8_64!(), Opr::Mem(m))
-            | Oprs::One(Opr::Mem(m)) => {
-                if m.is_rela() {
-                    bytes.extend(0u32.to_le_bytes());
-                }
-            }
             Oprs::Two(
                 Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r),
                 Opr::Imm8(val) | Opr::Imm32(val) | Opr::Imm64(val),
@@ -81,6 +157,13 @@ fn include_imm_values(bytes: &mut IBytes, instr: &Instr) {
         }
     } else {
         match instr.oprs {
+            // byte and word operations take an immediate of their own size
+            Oprs::Two(ref dst, Opr::Imm8(val) | Opr::Imm32(val)) if opr_size(dst) == 1 => {
+                bytes.push(val.to_le_bytes()[0]);
+            }
+            Oprs::Two(ref dst, Opr::Imm32(val)) if opr_size(dst) == 2 => {
+                bytes.extend(val.to_le_bytes().iter().take(2));
+            }
             Oprs::Two(_, Opr::Imm8(val)) | Oprs::One(Opr::Imm8(val)) => {
                 bytes.extend(val.to_le_bytes().iter().take(1));
             }
@@ -95,6 +178,15 @@ fn include_imm_values(bytes: &mut IBytes, instr: &Instr) {
     }
 }
 
+/// Size of a register or memory operand in bytes
+fn opr_size(opr: &Opr) -> u8 {
+    match opr {
+        Register!(r) => r.size() / 8,
+        Opr::Mem(m) => m.size,
+        _ => 0,
+    }
+}
+
 fn rm_rex(r1: &Reg, mem: &MemAddr) -> IBytes {
     let mut bytes = vec![];
     let mut rex: u8 = 0x40;
@@ -121,11 +213,12 @@ fn rm_rex(r1: &Reg, mem: &MemAddr) -> IBytes {
     bytes
 }
 
-fn r_rex(r: &Reg) -> IBytes {
+/// ext_bit: REX.R if register is in the reg field and REX.B if it is in r/m or opcode
+fn r_rex(r: &Reg, ext_bit: u8) -> IBytes {
     let mut bytes = vec![];
     let mut rex: u8 = 0x40;
     if r.is_extended() {
-        rex |= 0b0100;
+        rex |= ext_bit;
     }
     if r.size() == 64 {
         rex |= 0b1000;
@@ -144,10 +237,14 @@ fn rex(instr: &Instr) -> IBytes {
         Oprs::Two(Register!(r1), Register!(r2)) => {
             let mut bytes = vec![];
             let mut rex: u8 = 0x40;
-            if r1.is_extended() {
+            let (reg, rm) = match instr.mnem.reverse_modrm() {
+                true => (r1, r2),
+                false => (r2, r1),
+            };
+            if reg.is_extended() {
                 rex |= 0b0100;
             }
-            if r2.is_extended() {
+            if rm.is_extended() {
                 rex |= 0b0001;
             }
             if r1.size() == 64 {
@@ -163,19 +260,16 @@ fn rex(instr: &Instr) -> IBytes {
         }
         Oprs::Two(Register!(r1), Opr::Mem(mem)) | Oprs::Two(Opr::Mem(mem), Register!(r1)) => {
             if mem.is_rela() {
-                return r_rex(r1);
+                return r_rex(r1, 0b0100);
             }
             rm_rex(r1, mem)
         }
-        Oprs::Two(Register!(r), _) => r_rex(r),
+        Oprs::Two(Register!(r), _) => r_rex(r, 0b0001),
         Oprs::Two(Opr::Mem(mem), _) | Oprs::One(Opr::Mem(mem)) => {
-            if mem.is_rela() {
-                todo!()
-            }
             let mut bytes = vec![];
             let mut rex: u8 = 0x40;
-            if mem.get_register().is_extended() {
-                rex |= 0b0100;
+            if !mem.is_rela() && mem.get_register().is_extended() {
+                rex |= 0b0001;
             }
             if let Some(s_reg) = mem.get_s_register() {
                 if s_reg.is_extended() {
@@ -196,7 +290,7 @@ fn rex(instr: &Instr) -> IBytes {
         Oprs::One(Register!(r)) => {
             let mut rex: u8 = 0x40;
             if r.is_extended() {
-                rex |= 0b0100;
+                rex |= 0b0001;
             }
             if instr.mnem != Mnemonic::Push && instr.mnem != Mnemonic::Pop && r.size() == 64 {
                 rex |= 0b1000;
@@ -212,7 +306,7 @@ fn rex(instr: &Instr) -> IBytes {
     }
 }
 
-fn validate_opr_sizes(instr: &Instr) -> usize {
+fn validate_opr_sizes(instr: &Instr) -> Result<usize, String> {
     if let Oprs::Two(op1, op2) = &instr.oprs {
         let mut lhs_size;
         let rhs_size;
@@ -224,9 +318,11 @@ fn validate_opr_sizes(instr: &Instr) -> usize {
                 lhs_size = mem.size * 8;
             }
             Opr::Imm8(_) | Opr::Imm32(_) | Opr::Imm64(_) => {
-                panic!("Error: First opr for instr ({instr}) can not be an Immidiate value!");
+                return Err(format!(
+                    "First opr for instr ({instr}) can not be an Immidiate value!"
+                ));
             }
-            Opr::Rela(_) | Opr::Loc(_) => unreachable!(),
+            Opr::Rela(_) | Opr::Loc(_) | Opr::Xmm(_) => return Err(invalid_combination(instr)),
         }
         match op2 {
             Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r) => {
@@ -246,17 +342,17 @@ fn validate_opr_sizes(instr: &Instr) -> usize {
                 if lhs_size != 0 {
                     rhs_size = lhs_size;
                 } else {
-                    panic!("Error: oprand size is unknown for instr ({instr})!");
+                    return Err(format!("oprand size is unknown for instr ({instr})!"));
                 }
             }
-            Opr::Rela(_) | Opr::Loc(_) => unreachable!(),
+            Opr::Rela(_) | Opr::Loc(_) | Opr::Xmm(_) => return Err(invalid_combination(instr)),
         }
         if rhs_size == 0 || lhs_size == 0 {
-            panic!("Error: oprand size is unknown for instr ({instr})!");
+            return Err(format!("oprand size is unknown for instr ({instr})!"));
         }
-        lhs_size as usize
+        Ok(lhs_size as usize)
     } else {
-        0
+        Ok(0)
     }
 }
 
@@ -265,6 +361,10 @@ fn align_imm_oprs_to_reg(instr: &Instr) -> Instr {
         (Mnemonic::Mov, Oprs::Two(Opr::R64(r), Opr::Imm32(val) | Opr::Imm8(val))) => {
             Instr::new2(Mnemonic::Mov, Opr::R32(r.convert(4)), Opr::Imm32(*val))
         }
+        // test has no sign extended imm8 form
+        (Mnemonic::Test, Oprs::Two(dst, Opr::Imm8(val))) if opr_size(dst) != 1 => {
+            Instr::new2(Mnemonic::Test, dst.clone(), Opr::Imm32(*val))
+        }
         _ => instr.clone(),
     }
 }
@@ -298,33 +398,32 @@ fn modrm_ex(ex: u8, oprs: &Oprs) -> IBytes {
 
 fn _mem_modrm(r: u8, mem: &MemAddr) -> IBytes {
     match mem.addr_type {
-        MemAddrType::Addr(reg) => {
-            if reg.opcode() != 0x04 && reg.opcode() != 0x05 {
-                vec![_modrm(0b00, reg.opcode(), r)]
-            } else {
-                unreachable!();
-            }
-        }
+        // rsp and r12 as a base need a sib without index
+        // rbp and r13 without displacement mean rip relative so they get a zero disp8
+        MemAddrType::Addr(reg) => match reg.opcode() {
+            0x04 => vec![_modrm(0b00, 0x04, r), 0x24],
+            0x05 => vec![_modrm(0b01, 0x05, r), 0x00],
+            _ => vec![_modrm(0b00, reg.opcode(), r)],
+        },
         MemAddrType::Disp(reg, disp) => {
-            if disp >= i8::MIN as i32 && disp <= i8::MAX as i32 {
-                if reg.opcode() != 0x4 {
-                    let disp_byte = disp.to_le_bytes()[0];
-                    vec![_modrm(0b01, reg.opcode(), r), disp_byte]
-                } else {
-                    unreachable!();
-                }
-            } else if reg.opcode() != 0x4 {
-                let mut bytes = vec![_modrm(0b10, reg.opcode(), r)];
-                bytes.extend(disp.to_le_bytes());
-                bytes
+            let disp8 = disp >= i8::MIN as i32 && disp <= i8::MAX as i32;
+            let mut bytes = vec![_modrm(if disp8 { 0b01 } else { 0b10 }, reg.opcode(), r)];
+            if reg.opcode() == 0x04 {
+                bytes.push(0x24);
+            }
+            if disp8 {
+                bytes.push(disp.to_le_bytes()[0]);
             } else {
-                unreachable!();
+                bytes.extend(disp.to_le_bytes());
             }
+            bytes
         }
-        MemAddrType::Sib(_, disp, _, _) => {
+        MemAddrType::Sib(base, disp, _, _) => {
             let mut bytes = vec![];
-            if disp == 0 {
+            // base of 0b101 without displacement means there is no base register
+            if disp == 0 && base.opcode() != 0x05 {
                 bytes.push(_modrm(0b00, 0x04, r));
+                bytes.push(sib(mem));
                 bytes
             } else if disp >= i8::MIN as i32 && disp <= i8::MAX as i32 {
                 bytes.push(_modrm(0b01, 0x04, r));
@@ -338,7 +437,8 @@ fn _mem_modrm(r: u8, mem: &MemAddr) -> IBytes {
                 bytes
             }
         }
-        MemAddrType::AddrRela(_) => vec![0x04, 0x25],
+        // absolute disp32 through a sib without base and index, filled by the relocation
+        MemAddrType::AddrRela(_) => vec![_modrm(0b00, 0x04, r), 0x25, 0, 0, 0, 0],
     }
 }
 
@@ -363,3 +463,134 @@ fn sib(mem: &MemAddr) -> u8 {
     res |= register.opcode() & 0b111;
     res
 }
+
+#[test]
+fn test_sse_encoding() {
+    use Mnemonic::*;
+    use Reg::*;
+    let cases: Vec<(Instr, Vec<u8>)> = vec![
+        (
+            Instr::new2(Movsd, XMM0, MemAddr::new_disp_s(8, RBP, -8)),
+            vec![0xF2, 0x0F, 0x10, 0x45, 0xF8],
+        ),
+        (
+            Instr::new2(Movsd, MemAddr::new_disp_s(8, RBP, -16), XMM1),
+            vec![0xF2, 0x0F, 0x11, 0x4D, 0xF0],
+        ),
+        (
+            Instr::new2(Movq, XMM0, RAX),
+            vec![0x66, 0x48, 0x0F, 0x6E, 0xC0],
+        ),
+        (
+            Instr::new2(Movq, RAX, XMM1),
+            vec![0x66, 0x48, 0x0F, 0x7E, 0xC8],
+        ),
+        (Instr::new2(Addsd, XMM0, XMM1), vec![0xF2, 0x0F, 0x58, 0xC1]),
+        (
+            Instr::new2(Ucomisd, XMM0, XMM1),
+            vec![0x66, 0x0F, 0x2E, 0xC1],
+        ),
+        (
+            Instr::new2(Cvtsi2sd, XMM0, RAX),
+            vec![0xF2, 0x48, 0x0F, 0x2A, 0xC0],
+        ),
+        (
+            Instr::new2(Cvttsd2si, RAX, XMM0),
+            vec![0xF2, 0x48, 0x0F, 0x2C, 0xC0],
+        ),
+    ];
+    for (instr, bytes) in cases {
+        assert_eq!(assemble_instr(&instr), bytes, "{instr}");
+    }
+}
+
+#[test]
+fn test_extended_registers_encoding() {
+    use Mnemonic::*;
+    use Reg::*;
+    let cases: Vec<(Instr, Vec<u8>)> = vec![
+        (
+            Instr::new2(Mov, R8, Opr::Imm32(4)),
+            vec![0x41, 0xB8, 0x04, 0x00, 0x00, 0x00],
+        ),
+        (Instr::new2(Mov, RAX, R8), vec![0x4C, 0x89, 0xC0]),
+        (Instr::new2(Mov, R9, RAX), vec![0x49, 0x89, 0xC1]),
+        (
+            Instr::new2(Add, R9, Opr::Imm8(1)),
+            vec![0x49, 0x83, 0xC1, 0x01],
+        ),
+        (Instr::new1(Push, R8), vec![0x41, 0x50]),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new_disp_s(8, R8, 8)),
+            vec![0x49, 0x8B, 0x40, 0x08],
+        ),
+        (
+            Instr::new2(Mov, MemAddr::new_disp_s(8, RBP, -8), R9),
+            vec![0x4C, 0x89, 0x4D, 0xF8],
+        ),
+    ];
+    for (instr, bytes) in cases {
+        assert_eq!(assemble_instr(&instr), bytes, "{instr}");
+    }
+}
+
+#[test]
+fn test_jump_encoding() {
+    use Mnemonic::*;
+    let cases: Vec<(Instr, Vec<u8>)> = vec![
+        (Instr::new1(Jmp, Opr::Imm8(-2)), vec![0xEB, 0xFE]),
+        (
+            Instr::new1(Jmp, Opr::Imm32(0x100)),
+            vec![0xE9, 0x00, 0x01, 0x00, 0x00],
+        ),
+        (Instr::new1(Jl, Opr::Imm8(4)), vec![0x7C, 0x04]),
+        (Instr::new1(Ja, Opr::Imm8(-4)), vec![0x77, 0xFC]),
+        (Instr::new1(Jo, Opr::Imm8(0)), vec![0x70, 0x00]),
+        (
+            Instr::new1(Jge, Opr::Imm32(-6)),
+            vec![0x0F, 0x8D, 0xFA, 0xFF, 0xFF, 0xFF],
+        ),
+        (
+            Instr::new1(Jz, Opr::Imm32(0)),
+            vec![0x0F, 0x84, 0x00, 0x00, 0x00, 0x00],
+        ),
+    ];
+    for (instr, bytes) in cases {
+        assert_eq!(assemble_instr(&instr), bytes, "{instr}");
+    }
+}
+
+#[test]
+fn test_special_base_encoding() {
+    use Mnemonic::*;
+    use Reg::*;
+    let cases: Vec<(Instr, Vec<u8>)> = vec![
+        (
+            Instr::new2(Mov, RAX, MemAddr::new(RSP)),
+            vec![0x48, 0x8B, 0x04, 0x24],
+        ),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new(RBP)),
+            vec![0x48, 0x8B, 0x45, 0x00],
+        ),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new(R12)),
+            vec![0x49, 0x8B, 0x04, 0x24],
+        ),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new(R13)),
+            vec![0x49, 0x8B, 0x45, 0x00],
+        ),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new_disp_s(8, RSP, 8)),
+            vec![0x48, 0x8B, 0x44, 0x24, 0x08],
+        ),
+        (
+            Instr::new2(Mov, RAX, MemAddr::new_disp_s(8, R12, 0x100)),
+            vec![0x49, 0x8B, 0x84, 0x24, 0x00, 0x01, 0x00, 0x00],
+        ),
+    ];
+    for (instr, bytes) in cases {
+        assert_eq!(assemble_instr(&instr), bytes, "{instr}");
+    }
+}
@@ -0,0 +1,630 @@
+use super::{
+    instructions::{Instr, Opr, Oprs},
+    memory::{MemAddr, MemAddrType},
+    mnemonic::Mnemonic::{self, *},
+    register::Reg::{self, *},
+};
+
+static GP_REGS: [Reg; 16] = [
+    RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI, R8, R9, R10, R11, R12, R13, R14, R15,
+];
+static XMM_REGS: [Reg; 8] = [XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7];
+static HIGH_8BIT_REGS: [Reg; 4] = [AH, CH, DH, BH];
+static CMOVS: [Option<Mnemonic>; 16] = [
+    None,
+    None,
+    Some(Cmovb),
+    Some(Cmovae),
+    Some(Cmove),
+    Some(Cmovne),
+    Some(Cmovbe),
+    Some(Cmova),
+    None,
+    None,
+    None,
+    None,
+    Some(Cmovl),
+    Some(Cmovge),
+    Some(Cmovle),
+    Some(Cmovg),
+];
+static JCCS: [Mnemonic; 16] = [
+    Jo, Jno, Jb, Jae, Jz, Jne, Jbe, Ja, Js, Jns, Jp, Jnp, Jl, Jge, Jle, Jg,
+];
+
+/// Fields of a modrm byte (reg and rm are extended with rex)
+struct Modrm {
+    mode: u8,
+    reg: u8,
+    rm: u8,
+}
+
+/// Decodes a single instruction from the start of a byte stream
+/// supports every encoding that the assembler can generate
+/// jumps and calls keep their relative displacement as an immediate value
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    rex: u8,
+    opsize16: bool,
+    /// mandatory prefix of sse instructions (0x66 or 0xf2)
+    prefix: Option<u8>,
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or("Unexpected end of instruction")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Little endian sign extended immediate value
+    fn imm(&mut self, size: usize) -> Result<i64, String> {
+        let mut value: u64 = 0;
+        for i in 0..size {
+            value |= (self.byte()? as u64) << (8 * i);
+        }
+        let shift = 64 - 8 * size as u32;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    fn rex_w(&self) -> bool {
+        self.rex & 0b1000 != 0
+    }
+
+    /// Size of the operands in bytes
+    fn operand_size(&self) -> u8 {
+        if self.rex_w() {
+            8
+        } else if self.opsize16 {
+            2
+        } else {
+            4
+        }
+    }
+
+    fn modrm(&mut self) -> Result<Modrm, String> {
+        let byte = self.byte()?;
+        Ok(Modrm {
+            mode: byte >> 6,
+            reg: ((byte >> 3) & 0b111) | ((self.rex & 0b0100) << 1),
+            rm: (byte & 0b111) | ((self.rex & 0b0001) << 3),
+        })
+    }
+
+    fn reg(&self, num: u8, size: u8) -> Opr {
+        if size == 1 && self.rex == 0 && (4..8).contains(&num) {
+            return Opr::R8(HIGH_8BIT_REGS[num as usize - 4]);
+        }
+        GP_REGS[num as usize].convert(size).into()
+    }
+
+    fn xmm(&self, num: u8) -> Result<Opr, String> {
+        match XMM_REGS.get(num as usize) {
+            Some(reg) => Ok(Opr::Xmm(*reg)),
+            None => Err(format!("Unsupported register xmm{num}")),
+        }
+    }
+
+    /// Register or memory operand of the rm field
+    fn rm(&mut self, modrm: &Modrm, size: u8) -> Result<Opr, String> {
+        match modrm.mode {
+            0b11 => Ok(self.reg(modrm.rm, size)),
+            _ => Ok(Opr::Mem(self.mem(modrm, size)?)),
+        }
+    }
+
+    fn xmm_rm(&mut self, modrm: &Modrm, size: u8) -> Result<Opr, String> {
+        match modrm.mode {
+            0b11 => self.xmm(modrm.rm),
+            _ => Ok(Opr::Mem(self.mem(modrm, size)?)),
+        }
+    }
+
+    fn disp(&mut self, mode: u8) -> Result<i32, String> {
+        match mode {
+            0b01 => Ok(self.imm(1)? as i32),
+            0b10 => Ok(self.imm(4)? as i32),
+            _ => Ok(0),
+        }
+    }
+
+    fn mem(&mut self, modrm: &Modrm, size: u8) -> Result<MemAddr, String> {
+        let addr_type = match modrm.rm & 0b111 {
+            0b100 => {
+                let sib = self.byte()?;
+                let scale = 1 << (sib >> 6);
+                let index = ((sib >> 3) & 0b111) | ((self.rex & 0b0010) << 2);
+                let base = (sib & 0b111) | ((self.rex & 0b0001) << 3);
+                if modrm.mode == 0b00 && base & 0b111 == 0b101 {
+                    // absolute address that is filled by a relocation
+                    let addr = self.imm(4)?;
+                    MemAddrType::AddrRela(format!("{addr:#x}"))
+                } else {
+                    let disp = self.disp(modrm.mode)?;
+                    let base = GP_REGS[base as usize];
+                    match (index, modrm.mode) {
+                        (0b100, 0b00) => MemAddrType::Addr(base),
+                        (0b100, _) => MemAddrType::Disp(base, disp),
+                        _ => MemAddrType::Sib(base, disp, GP_REGS[index as usize], scale),
+                    }
+                }
+            }
+            0b101 if modrm.mode == 0b00 => {
+                return Err("Rip relative addressing is not supported".to_string());
+            }
+            _ => {
+                let base = GP_REGS[modrm.rm as usize];
+                match modrm.mode {
+                    0b00 => MemAddrType::Addr(base),
+                    mode => MemAddrType::Disp(base, self.disp(mode)?),
+                }
+            }
+        };
+        Ok(MemAddr { addr_type, size })
+    }
+
+    /// Register and register or memory operands of a modrm byte
+    /// * rm_first - the rm field is the first operand
+    fn modrm_oprs(&mut self, size: u8, rm_first: bool) -> Result<Oprs, String> {
+        let modrm = self.modrm()?;
+        let rm = self.rm(&modrm, size)?;
+        let reg = self.reg(modrm.reg, size);
+        Ok(match rm_first {
+            true => Oprs::Two(rm, reg),
+            false => Oprs::Two(reg, rm),
+        })
+    }
+
+    fn imm_opr(&mut self, size: usize) -> Result<Opr, String> {
+        let value = self.imm(size)?;
+        Ok(match size {
+            1 => Opr::Imm8(value),
+            8 => Opr::Imm64(value),
+            _ => Opr::Imm32(value),
+        })
+    }
+
+    fn decode(&mut self) -> Result<Instr, String> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(0x66) => self.opsize16 = true,
+                Some(prefix @ 0xf2) => self.prefix = Some(*prefix),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if let Some(rex @ 0x40..=0x4f) = self.bytes.get(self.pos) {
+            self.rex = *rex;
+            self.pos += 1;
+        }
+        let opcode = self.byte()?;
+        let size = self.operand_size();
+        let instr = match opcode {
+            0x0f => return self.decode_0f(),
+            0x00 => Instr::new(Add, self.modrm_oprs(1, true)?),
+            0x01 => Instr::new(Add, self.modrm_oprs(size, true)?),
+            0x08 => Instr::new(Or, self.modrm_oprs(1, true)?),
+            0x09 => Instr::new(Or, self.modrm_oprs(size, true)?),
+            0x20 => Instr::new(And, self.modrm_oprs(1, true)?),
+            0x21 => Instr::new(And, self.modrm_oprs(size, true)?),
+            0x28 => Instr::new(Sub, self.modrm_oprs(1, true)?),
+            0x29 => Instr::new(Sub, self.modrm_oprs(size, true)?),
+            0x2b => Instr::new(Sub, self.modrm_oprs(size, false)?),
+            0x2d => Instr::new2(Sub, self.reg(0, size), self.imm_opr(4)?),
+            0x38 => Instr::new(Cmp, self.modrm_oprs(1, true)?),
+            0x39 => Instr::new(Cmp, self.modrm_oprs(size, true)?),
+            0x50..=0x57 => Instr::new1(Push, self.reg(self.opcode_reg(opcode), 8)),
+            0x58..=0x5f => Instr::new1(Pop, self.reg(self.opcode_reg(opcode), 8)),
+            0x68 => Instr::new1(Push, self.imm_opr(4)?),
+            0x6a => Instr::new1(Push, self.imm_opr(1)?),
+            0x70..=0x7f => Instr::new1(JCCS[opcode as usize - 0x70], self.imm_opr(1)?),
+            0x80 | 0x81 | 0x83 => {
+                let size = if opcode == 0x80 { 1 } else { size };
+                let modrm = self.modrm()?;
+                let rm = self.rm(&modrm, size)?;
+                let mnem = match modrm.reg & 0b111 {
+                    0 => Add,
+                    1 => Or,
+                    4 => And,
+                    5 => Sub,
+                    7 => Cmp,
+                    ext => return Err(format!("Unknown instruction {opcode:#04x} /{ext}")),
+                };
+                let imm = match (opcode, size) {
+                    (0x80 | 0x83, _) => self.imm_opr(1)?,
+                    (_, 2) => Opr::Imm32(self.imm(2)?),
+                    _ => self.imm_opr(4)?,
+                };
+                Instr::new2(mnem, rm, imm)
+            }
+            0x84 => Instr::new(Test, self.modrm_oprs(1, true)?),
+            0x85 => Instr::new(Test, self.modrm_oprs(size, true)?),
+            0x88 => Instr::new(Mov, self.modrm_oprs(1, true)?),
+            0x89 => Instr::new(Mov, self.modrm_oprs(size, true)?),
+            0x8a => Instr::new(Mov, self.modrm_oprs(1, false)?),
+            0x8b => Instr::new(Mov, self.modrm_oprs(size, false)?),
+            0x8d => {
+                let modrm = self.modrm()?;
+                let mem = self.mem(&modrm, 0)?;
+                Instr::new2(Lea, self.reg(modrm.reg, size), mem)
+            }
+            0x90 => Instr::new0(Nop),
+            0x99 if self.rex_w() => Instr::new0(Cqo),
+            0xb8..=0xbf => {
+                let reg = self.reg(self.opcode_reg(opcode), size);
+                Instr::new2(Mov, reg, self.imm_opr(size as usize)?)
+            }
+            0xc1 | 0xd3 => {
+                let modrm = self.modrm()?;
+                let rm = self.rm(&modrm, size)?;
+                let mnem = match modrm.reg & 0b111 {
+                    4 => Sal,
+                    5 => Shr,
+                    7 => Sar,
+                    ext => return Err(format!("Unknown instruction {opcode:#04x} /{ext}")),
+                };
+                match opcode {
+                    0xc1 => Instr::new2(mnem, rm, self.imm_opr(1)?),
+                    _ => Instr::new2(mnem, rm, CL),
+                }
+            }
+            0xc3 => Instr::new0(Ret),
+            0xc6 | 0xc7 => {
+                let size = if opcode == 0xc6 { 1 } else { size };
+                let modrm = self.modrm()?;
+                let Opr::Mem(mem) = self.rm(&modrm, size)? else {
+                    return Err("Expected a memory operand".to_string());
+                };
+                // 64bit values are sign extended from 32bit
+                let imm = match size {
+                    1 => self.imm_opr(1)?,
+                    2 => Opr::Imm32(self.imm(2)?),
+                    _ => self.imm_opr(4)?,
+                };
+                Instr::new2(Mov, mem, imm)
+            }
+            0xc9 => Instr::new0(Leave),
+            0xe8 => Instr::new1(Call, self.imm_opr(4)?),
+            0xe9 => Instr::new1(Jmp, self.imm_opr(4)?),
+            0xeb => Instr::new1(Jmp, self.imm_opr(1)?),
+            0xf6 => {
+                let modrm = self.modrm()?;
+                match modrm.reg & 0b111 {
+                    0 => Instr::new2(Test, self.rm(&modrm, 1)?, self.imm_opr(1)?),
+                    ext => return Err(format!("Unknown instruction {opcode:#04x} /{ext}")),
+                }
+            }
+            0xf7 => {
+                let modrm = self.modrm()?;
+                let rm = self.rm(&modrm, size)?;
+                let mnem = match modrm.reg & 0b111 {
+                    0 => {
+                        let imm = match size {
+                            2 => Opr::Imm32(self.imm(2)?),
+                            _ => self.imm_opr(4)?,
+                        };
+                        return Ok(Instr::new2(Test, rm, imm));
+                    }
+                    2 => Not,
+                    3 => Neg,
+                    4 => Mul,
+                    7 => Idiv,
+                    ext => return Err(format!("Unknown instruction {opcode:#04x} /{ext}")),
+                };
+                Instr::new1(mnem, rm)
+            }
+            0xff => {
+                let modrm = self.modrm()?;
+                match modrm.reg & 0b111 {
+                    0 => Instr::new1(Inc, self.rm(&modrm, size)?),
+                    ext => return Err(format!("Unknown instruction {opcode:#04x} /{ext}")),
+                }
+            }
+            _ => return Err(format!("Unknown opcode {opcode:#04x}")),
+        };
+        Ok(instr)
+    }
+
+    /// Register encoded in the low bits of the opcode
+    fn opcode_reg(&self, opcode: u8) -> u8 {
+        (opcode & 0b111) | ((self.rex & 0b0001) << 3)
+    }
+
+    /// Two byte opcodes (0x0f prefixed) including sse instructions
+    fn decode_0f(&mut self) -> Result<Instr, String> {
+        let opcode = self.byte()?;
+        let size = self.operand_size();
+        let instr = match (self.prefix, self.opsize16, opcode) {
+            (None, false, 0x05) => Instr::new0(Syscall),
+            (None, _, 0x40..=0x4f) => {
+                let Some(mnem) = CMOVS[opcode as usize - 0x40] else {
+                    return Err(format!("Unknown opcode 0x0f{opcode:02x}"));
+                };
+                Instr::new(mnem, self.modrm_oprs(size, false)?)
+            }
+            (None, _, 0x80..=0x8f) => Instr::new1(JCCS[opcode as usize - 0x80], self.imm_opr(4)?),
+            (None, _, 0xaf) => Instr::new(Imul, self.modrm_oprs(size, false)?),
+            (Some(0xf2), _, 0x10 | 0x51 | 0x58 | 0x59 | 0x5c | 0x5e) => {
+                let mnem = match opcode {
+                    0x10 => Movsd,
+                    0x51 => Sqrtsd,
+                    0x58 => Addsd,
+                    0x59 => Mulsd,
+                    0x5c => Subsd,
+                    _ => Divsd,
+                };
+                let modrm = self.modrm()?;
+                Instr::new2(mnem, self.xmm(modrm.reg)?, self.xmm_rm(&modrm, 8)?)
+            }
+            (Some(0xf2), _, 0x11) => {
+                let modrm = self.modrm()?;
+                Instr::new2(Movsd, self.xmm_rm(&modrm, 8)?, self.xmm(modrm.reg)?)
+            }
+            (Some(0xf2), _, 0x2a) => {
+                let modrm = self.modrm()?;
+                let src = self.rm(&modrm, if self.rex_w() { 8 } else { 4 })?;
+                Instr::new2(Cvtsi2sd, self.xmm(modrm.reg)?, src)
+            }
+            (Some(0xf2), _, 0x2c) => {
+                let modrm = self.modrm()?;
+                let dst = self.reg(modrm.reg, if self.rex_w() { 8 } else { 4 });
+                Instr::new2(Cvttsd2si, dst, self.xmm_rm(&modrm, 8)?)
+            }
+            (None, true, 0x2e | 0x57) => {
+                let mnem = if opcode == 0x2e { Ucomisd } else { Xorpd };
+                let modrm = self.modrm()?;
+                Instr::new2(mnem, self.xmm(modrm.reg)?, self.xmm_rm(&modrm, 8)?)
+            }
+            (None, true, 0x6e) if self.rex_w() => {
+                let modrm = self.modrm()?;
+                Instr::new2(Movq, self.xmm(modrm.reg)?, self.rm(&modrm, 8)?)
+            }
+            (None, true, 0x7e) if self.rex_w() => {
+                let modrm = self.modrm()?;
+                Instr::new2(Movq, self.rm(&modrm, 8)?, self.xmm(modrm.reg)?)
+            }
+            _ => return Err(format!("Unknown opcode 0x0f{opcode:02x}")),
+        };
+        Ok(instr)
+    }
+}
+
+/// Decodes the instruction at the start of bytes
+/// returns the instruction and its length in bytes
+pub fn decode_instr(bytes: &[u8]) -> Result<(Instr, usize), String> {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        rex: 0,
+        opsize16: false,
+        prefix: None,
+    };
+    let instr = decoder.decode()?;
+    Ok((instr, decoder.pos))
+}
+
+/// Text of a decoded instruction
+/// relative jumps and calls show the offset of their target
+/// * end - offset of the end of the instruction
+pub fn display_decoded(instr: &Instr, end: usize) -> String {
+    match &instr.oprs {
+        Oprs::One(Opr::Imm8(disp) | Opr::Imm32(disp))
+            if instr.mnem.is_jump() || instr.mnem == Call =>
+        {
+            format!("{} {:#x}", instr.mnem, end as i64 + disp)
+        }
+        _ => instr.to_string(),
+    }
+}
+
+/// Instructions of a mnemonic with the instruction they are expected to decode to
+/// the match is exhaustive so new mnemonics have to be covered here
+#[cfg(test)]
+fn round_trip_cases(mnem: Mnemonic) -> Vec<(Instr, Instr)> {
+    let same = |instr: Instr| (instr.clone(), instr);
+    let mem = |size: u8, disp: i32| MemAddr::new_disp_s(size, RBP, disp);
+    let cmov = |mnem: Mnemonic| vec![same(Instr::new2(mnem, RCX, RDX))];
+    let jcc = |mnem: Mnemonic| {
+        vec![
+            same(Instr::new1(mnem, Opr::Imm8(-2))),
+            same(Instr::new1(mnem, Opr::Imm32(0x1000))),
+        ]
+    };
+    let sse = |mnem: Mnemonic| {
+        vec![
+            same(Instr::new2(mnem, XMM0, XMM1)),
+            same(Instr::new2(mnem, XMM6, mem(8, -8))),
+        ]
+    };
+    match mnem {
+        Lea => vec![
+            same(Instr::new2(Lea, RAX, MemAddr::new_disp(RBP, -8))),
+            same(Instr::new2(Lea, R12, MemAddr::new_sib(RAX, 16, RBX, 8))),
+            same(Instr::new2(Lea, RSI, MemAddr::new_sib(R13, 0, R9, 2))),
+        ],
+        Mov => vec![
+            same(Instr::new2(Mov, RAX, RBX)),
+            same(Instr::new2(Mov, mem(8, -8), R9)),
+            same(Instr::new2(Mov, EAX, mem(4, -4))),
+            same(Instr::new2(Mov, AX, MemAddr::new_sib_s(2, RBX, 0, R12, 2))),
+            same(Instr::new2(Mov, MemAddr::new_s(1, RAX), CL)),
+            same(Instr::new2(Mov, AL, mem(1, 300))),
+            same(Instr::new2(Mov, SIL, DIL)),
+            same(Instr::new2(Mov, RAX, Opr::Imm64(-1))),
+            same(Instr::new2(Mov, mem(8, -8), Opr::Imm32(-1))),
+            same(Instr::new2(Mov, mem(2, -2), Opr::Imm32(300))),
+            same(Instr::new2(Mov, mem(1, -1), Opr::Imm8(7))),
+            // 64bit registers are zero extended by 32bit moves
+            (
+                Instr::new2(Mov, R8, Opr::Imm32(5)),
+                Instr::new2(Mov, R8D, Opr::Imm32(5)),
+            ),
+        ],
+        Cmove | Cmovne | Cmovg | Cmovl | Cmovge | Cmovle | Cmova | Cmovae | Cmovb | Cmovbe => {
+            cmov(mnem)
+        }
+        Push => vec![
+            same(Instr::new1(Push, RBP)),
+            same(Instr::new1(Push, R12)),
+            same(Instr::new1(Push, Opr::Imm8(1))),
+            same(Instr::new1(Push, Opr::Imm32(1000))),
+        ],
+        Pop => vec![same(Instr::new1(Pop, RBP)), same(Instr::new1(Pop, R15))],
+        Add => vec![
+            same(Instr::new2(Add, RAX, RBX)),
+            same(Instr::new2(Add, R9, Opr::Imm8(1))),
+            same(Instr::new2(Add, RSP, Opr::Imm32(1024))),
+            same(Instr::new2(Add, AL, Opr::Imm8(3))),
+            same(Instr::new2(Add, DIL, SIL)),
+            same(Instr::new2(Add, AX, Opr::Imm32(300))),
+            same(Instr::new2(Add, mem(1, -1), Opr::Imm8(-1))),
+            same(Instr::new2(Add, mem(2, -2), Opr::Imm32(-300))),
+        ],
+        Sub => vec![
+            same(Instr::new2(Sub, RCX, RDX)),
+            same(Instr::new2(Sub, RAX, mem(8, -8))),
+            same(Instr::new2(Sub, RAX, Opr::Imm32(1000))),
+            same(Instr::new2(Sub, RSP, Opr::Imm32(80))),
+            same(Instr::new2(Sub, RSP, Opr::Imm8(8))),
+            same(Instr::new2(Sub, mem(1, -1), CL)),
+            same(Instr::new2(Sub, R10B, Opr::Imm8(1))),
+        ],
+        Imul => vec![
+            same(Instr::new2(Imul, RAX, RBX)),
+            same(Instr::new2(Imul, R10, mem(8, -8))),
+        ],
+        Idiv => vec![same(Instr::new1(Idiv, RBX))],
+        Mul => vec![same(Instr::new1(Mul, RDX))],
+        Or => vec![
+            same(Instr::new2(Or, RAX, RBX)),
+            same(Instr::new2(Or, AL, BL)),
+            same(Instr::new2(Or, RAX, Opr::Imm8(1))),
+            same(Instr::new2(Or, mem(4, -4), Opr::Imm32(0x1000))),
+        ],
+        And => vec![
+            same(Instr::new2(And, RAX, R11)),
+            same(Instr::new2(And, CL, DL)),
+            same(Instr::new2(And, RCX, Opr::Imm8(-16))),
+            same(Instr::new2(And, EAX, Opr::Imm32(0xFFFF))),
+            same(Instr::new2(And, mem(1, -1), Opr::Imm8(0x7F))),
+        ],
+        Sal | Sar | Shr => {
+            let mut cases = vec![same(Instr::new2(mnem, RAX, CL))];
+            if mnem != Sar {
+                cases.push(same(Instr::new2(mnem, R14, Opr::Imm8(3))));
+            }
+            cases
+        }
+        Cmp => vec![
+            same(Instr::new2(Cmp, RAX, RBX)),
+            same(Instr::new2(Cmp, AL, BL)),
+            same(Instr::new2(Cmp, RAX, Opr::Imm8(0))),
+            same(Instr::new2(Cmp, mem(8, -8), Opr::Imm32(1000))),
+        ],
+        Test => vec![
+            same(Instr::new2(Test, RAX, RAX)),
+            same(Instr::new2(Test, AL, AL)),
+            same(Instr::new2(Test, mem(1, -1), Opr::Imm8(1))),
+            same(Instr::new2(Test, AX, Opr::Imm32(0x100))),
+            same(Instr::new2(Test, RAX, Opr::Imm32(1024))),
+            // test has no imm8 form for wider operands
+            (
+                Instr::new2(Test, RCX, Opr::Imm8(1)),
+                Instr::new2(Test, RCX, Opr::Imm32(1)),
+            ),
+        ],
+        Cqo | Nop | Syscall | Leave | Ret => vec![same(Instr::new0(mnem))],
+        Neg => vec![same(Instr::new1(Neg, RAX))],
+        Not => vec![same(Instr::new1(Not, mem(8, -8)))],
+        Inc => vec![
+            same(Instr::new1(Inc, R12)),
+            same(Instr::new1(Inc, mem(4, -4))),
+        ],
+        Call => vec![same(Instr::new1(Call, Opr::Imm32(16)))],
+        Jmp | Jz | Jne | Jo | Jno | Jb | Jae | Jbe | Ja | Js | Jns | Jp | Jnp | Jl | Jge | Jle
+        | Jg => jcc(mnem),
+        // aliases decode to the mnemonic used by the code generator
+        Jnz | Je => {
+            let alias = if mnem == Jnz { Jne } else { Jz };
+            vec![(
+                Instr::new1(mnem, Opr::Imm8(4)),
+                Instr::new1(alias, Opr::Imm8(4)),
+            )]
+        }
+        // labels are not encoded
+        Lable => vec![],
+        Movsd => vec![
+            same(Instr::new2(Movsd, XMM0, mem(8, -8))),
+            same(Instr::new2(Movsd, mem(8, -16), XMM1)),
+            same(Instr::new2(Movsd, XMM2, XMM3)),
+        ],
+        Movq => vec![
+            same(Instr::new2(Movq, XMM0, RAX)),
+            same(Instr::new2(Movq, RAX, XMM1)),
+            same(Instr::new2(Movq, XMM7, R9)),
+            same(Instr::new2(Movq, XMM2, mem(8, -8))),
+        ],
+        Addsd | Subsd | Mulsd | Divsd | Sqrtsd | Ucomisd | Xorpd => sse(mnem),
+        Cvtsi2sd => vec![
+            same(Instr::new2(Cvtsi2sd, XMM0, RAX)),
+            same(Instr::new2(Cvtsi2sd, XMM1, EAX)),
+            same(Instr::new2(Cvtsi2sd, XMM1, mem(4, -4))),
+        ],
+        Cvttsd2si => vec![
+            same(Instr::new2(Cvttsd2si, RAX, XMM0)),
+            same(Instr::new2(Cvttsd2si, R11D, XMM1)),
+        ],
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    use super::assemble::assemble_instr;
+    #[rustfmt::skip]
+    let mnemonics = [
+        Lea, Mov, Cmove, Cmovne, Cmovg, Cmovl, Cmovge, Cmovle, Cmova, Cmovae, Cmovb, Cmovbe,
+        Push, Pop, Add, Sub, Imul, Idiv, Mul, Or, And, Sal, Sar, Shr, Cmp, Test, Cqo, Neg, Not,
+        Nop, Call, Jmp, Jz, Jnz, Je, Jne, Jo, Jno, Jb, Jae, Jbe, Ja, Js, Jns, Jp, Jnp, Jl, Jge,
+        Jle, Jg, Syscall, Leave, Ret, Lable, Inc, Movsd, Movq, Addsd, Subsd, Mulsd, Divsd,
+        Sqrtsd, Ucomisd, Xorpd, Cvtsi2sd, Cvttsd2si,
+    ];
+    for mnem in mnemonics {
+        for (instr, expected) in round_trip_cases(mnem) {
+            let bytes = assemble_instr(&instr);
+            let (decoded, len) = decode_instr(&bytes).unwrap_or_else(|e| panic!("{instr}: {e}"));
+            assert_eq!(len, bytes.len(), "{instr}: {bytes:02x?}");
+            assert_eq!(decoded, expected, "{instr}: {bytes:02x?}");
+        }
+    }
+}
+
+#[test]
+fn test_display_decoded() {
+    let bytes = [0x55, 0x48, 0x89, 0xe5, 0x75, 0xfa, 0xc9, 0xc3];
+    let mut offset = 0;
+    let mut lines = Vec::new();
+    while offset < bytes.len() {
+        let (instr, len) = decode_instr(&bytes[offset..]).unwrap();
+        lines.push(format!(
+            "{offset}: {}",
+            display_decoded(&instr, offset + len)
+        ));
+        offset += len;
+    }
+    assert_eq!(
+        lines,
+        vec![
+            "0: push rbp",
+            "1: mov rbp, rsp",
+            "4: jne 0x0",
+            "6: leave",
+            "7: ret"
+        ]
+    );
+    assert!(decode_instr(&[0x48, 0x06]).is_err());
+    assert!(decode_instr(&[0x48, 0x89]).is_err());
+}
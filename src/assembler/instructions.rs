@@ -1,7 +1,5 @@
 use std::fmt::Display;
 
-use crate::parser::types::VariableType;
-
 use super::memory::MemAddrType;
 #[allow(unused_imports)]
 use super::{
@@ -25,6 +23,7 @@ pub enum Opr {
     R32(Reg),
     R16(Reg),
     R8(Reg),
+    Xmm(Reg),
     Mem(MemAddr),
     Imm8(i64),
     Imm32(i64),
@@ -34,47 +33,6 @@ pub enum Opr {
 }
 
 impl Opr {
-    pub fn sized(self, vtype: &VariableType) -> Self {
-        match self {
-            Self::R8(r) | Self::R16(r) | Self::R32(r) | Self::R64(r) => {
-                r.convert(vtype.item_size()).into()
-            }
-            Self::Mem(m) => {
-                let mut cmem = m;
-                cmem.size = vtype.item_size();
-                cmem.into()
-            }
-            Self::Imm8(v) | Self::Imm32(v) | Self::Imm64(v) => {
-                if vtype.size() == 1 {
-                    Self::Imm8(v)
-                } else if vtype.item_size() == 4 || vtype.item_size() == 2 {
-                    Self::Imm32(v)
-                } else {
-                    Self::Imm64(v)
-                }
-            }
-            _ => self.to_owned(),
-        }
-    }
-
-    pub fn is_register(&self) -> bool {
-        matches!(self, Opr::R8(_) | Opr::R16(_) | Opr::R32(_) | Opr::R64(_))
-    }
-
-    pub fn is_literal(&self) -> bool {
-        matches!(self, Opr::Imm8(_) | Opr::Imm32(_) | Opr::Imm64(_))
-    }
-
-    pub fn is_mem(&self) -> bool {
-        matches!(self, Opr::Mem(_))
-    }
-
-    pub fn get_literal_value(&self) -> i64 {
-        match self {
-            Opr::Imm8(val) | Opr::Imm32(val) | Opr::Imm64(val) => *val,
-            _ => unreachable!(),
-        }
-    }
     pub fn rel(rel: impl ToString) -> Self {
         Self::Loc(rel.to_string())
     }
@@ -88,6 +46,9 @@ impl From<MemAddr> for Opr {
 
 impl From<Reg> for Opr {
     fn from(val: Reg) -> Opr {
+        if val.is_xmm() {
+            return Self::Xmm(val);
+        }
         match val.size() {
             64 => Self::R64(val),
             32 => Self::R32(val),
@@ -127,7 +88,7 @@ impl From<i64> for Opr {
 impl Display for Opr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::R64(r) | Self::R32(r) | Self::R16(r) | Self::R8(r) => r.fmt(f),
+            Self::R64(r) | Self::R32(r) | Self::R16(r) | Self::R8(r) | Self::Xmm(r) => r.fmt(f),
             Self::Mem(m) => m.fmt(f),
             Self::Imm8(val) | Self::Imm32(val) | Self::Imm64(val) => val.fmt(f),
             Self::Rela(refer) => refer.fmt(f),
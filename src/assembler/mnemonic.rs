@@ -11,6 +11,10 @@ pub enum Mnemonic {
     Cmovl,
     Cmovge,
     Cmovle,
+    Cmova,
+    Cmovae,
+    Cmovb,
+    Cmovbe,
     Push,
     Pop,
     Add,
@@ -33,12 +37,38 @@ pub enum Mnemonic {
     Jmp,
     Jz,
     Jnz,
+    Je,
     Jne,
+    Jo,
+    Jno,
+    Jb,
+    Jae,
+    Jbe,
+    Ja,
+    Js,
+    Jns,
+    Jp,
+    Jnp,
+    Jl,
+    Jge,
+    Jle,
+    Jg,
     Syscall,
     Leave,
     Ret,
     Lable,
     Inc,
+    Movsd,
+    Movq,
+    Addsd,
+    Subsd,
+    Mulsd,
+    Divsd,
+    Sqrtsd,
+    Ucomisd,
+    Xorpd,
+    Cvtsi2sd,
+    Cvttsd2si,
 }
 
 impl Mnemonic {
@@ -55,9 +85,60 @@ impl Mnemonic {
                 | Self::Cmovge
                 | Self::Cmovl
                 | Self::Cmovle
+                | Self::Cmova
+                | Self::Cmovae
+                | Self::Cmovb
+                | Self::Cmovbe
                 | Self::Imul
         )
     }
+
+    /// Condition code of conditional jumps (low nibble of the opcode)
+    pub fn condition_code(&self) -> Option<u8> {
+        let cc = match self {
+            Self::Jo => 0x0,
+            Self::Jno => 0x1,
+            Self::Jb => 0x2,
+            Self::Jae => 0x3,
+            Self::Jz | Self::Je => 0x4,
+            Self::Jnz | Self::Jne => 0x5,
+            Self::Jbe => 0x6,
+            Self::Ja => 0x7,
+            Self::Js => 0x8,
+            Self::Jns => 0x9,
+            Self::Jp => 0xa,
+            Self::Jnp => 0xb,
+            Self::Jl => 0xc,
+            Self::Jge => 0xd,
+            Self::Jle => 0xe,
+            Self::Jg => 0xf,
+            _ => return None,
+        };
+        Some(cc)
+    }
+
+    /// Returns true for jumps to a relative location (conditional or not)
+    pub fn is_jump(&self) -> bool {
+        *self == Self::Jmp || self.condition_code().is_some()
+    }
+
+    /// Returns true for SSE instructions (encoded with a mandatory prefix)
+    pub fn is_sse(&self) -> bool {
+        matches!(
+            self,
+            Self::Movsd
+                | Self::Movq
+                | Self::Addsd
+                | Self::Subsd
+                | Self::Mulsd
+                | Self::Divsd
+                | Self::Sqrtsd
+                | Self::Ucomisd
+                | Self::Xorpd
+                | Self::Cvtsi2sd
+                | Self::Cvttsd2si
+        )
+    }
 }
 
 impl Display for Mnemonic {
@@ -71,6 +152,10 @@ impl Display for Mnemonic {
             Self::Cmovl => write!(f, "cmovl"),
             Self::Cmovge => write!(f, "cmovge"),
             Self::Cmovle => write!(f, "cmovle"),
+            Self::Cmova => write!(f, "cmova"),
+            Self::Cmovae => write!(f, "cmovae"),
+            Self::Cmovb => write!(f, "cmovb"),
+            Self::Cmovbe => write!(f, "cmovbe"),
             Self::Push => write!(f, "push"),
             Self::Pop => write!(f, "pop"),
             Self::Add => write!(f, "add"),
@@ -92,13 +177,39 @@ impl Display for Mnemonic {
             Self::Jmp => write!(f, "jmp"),
             Self::Jz => write!(f, "jz"),
             Self::Jnz => write!(f, "jnz"),
+            Self::Je => write!(f, "je"),
             Self::Jne => write!(f, "jne"),
+            Self::Jo => write!(f, "jo"),
+            Self::Jno => write!(f, "jno"),
+            Self::Jb => write!(f, "jb"),
+            Self::Jae => write!(f, "jae"),
+            Self::Jbe => write!(f, "jbe"),
+            Self::Ja => write!(f, "ja"),
+            Self::Js => write!(f, "js"),
+            Self::Jns => write!(f, "jns"),
+            Self::Jp => write!(f, "jp"),
+            Self::Jnp => write!(f, "jnp"),
+            Self::Jl => write!(f, "jl"),
+            Self::Jge => write!(f, "jge"),
+            Self::Jle => write!(f, "jle"),
+            Self::Jg => write!(f, "jg"),
             Self::Syscall => write!(f, "syscall"),
             Self::Leave => write!(f, "leave"),
             Self::Ret => write!(f, "ret"),
             Self::Nop => write!(f, "nop"),
             Self::Lable => write!(f, ""),
             Self::Inc => write!(f, "inc"),
+            Self::Movsd => write!(f, "movsd"),
+            Self::Movq => write!(f, "movq"),
+            Self::Addsd => write!(f, "addsd"),
+            Self::Subsd => write!(f, "subsd"),
+            Self::Mulsd => write!(f, "mulsd"),
+            Self::Divsd => write!(f, "divsd"),
+            Self::Sqrtsd => write!(f, "sqrtsd"),
+            Self::Ucomisd => write!(f, "ucomisd"),
+            Self::Xorpd => write!(f, "xorpd"),
+            Self::Cvtsi2sd => write!(f, "cvtsi2sd"),
+            Self::Cvttsd2si => write!(f, "cvttsd2si"),
         }
     }
 }
@@ -117,6 +228,10 @@ impl FromStr for Mnemonic {
             "cmovl" => Ok(Self::Cmovl),
             "cmovge" => Ok(Self::Cmovge),
             "cmovle" => Ok(Self::Cmovle),
+            "cmova" => Ok(Self::Cmova),
+            "cmovae" => Ok(Self::Cmovae),
+            "cmovb" => Ok(Self::Cmovb),
+            "cmovbe" => Ok(Self::Cmovbe),
             "push" => Ok(Self::Push),
             "pop" => Ok(Self::Pop),
             "add" => Ok(Self::Add),
@@ -137,12 +252,38 @@ impl FromStr for Mnemonic {
             "jmp" => Ok(Self::Jmp),
             "jz" => Ok(Self::Jz),
             "jnz" => Ok(Self::Jnz),
+            "je" => Ok(Self::Je),
             "jne" => Ok(Self::Jne),
+            "jo" => Ok(Self::Jo),
+            "jno" => Ok(Self::Jno),
+            "jb" | "jc" | "jnae" => Ok(Self::Jb),
+            "jae" | "jnb" | "jnc" => Ok(Self::Jae),
+            "jbe" | "jna" => Ok(Self::Jbe),
+            "ja" | "jnbe" => Ok(Self::Ja),
+            "js" => Ok(Self::Js),
+            "jns" => Ok(Self::Jns),
+            "jp" | "jpe" => Ok(Self::Jp),
+            "jnp" | "jpo" => Ok(Self::Jnp),
+            "jl" | "jnge" => Ok(Self::Jl),
+            "jge" | "jnl" => Ok(Self::Jge),
+            "jle" | "jng" => Ok(Self::Jle),
+            "jg" | "jnle" => Ok(Self::Jg),
             "syscall" => Ok(Self::Syscall),
             "call" => Ok(Self::Call),
             "inc" => Ok(Self::Inc),
             "leave" => Ok(Self::Leave),
             "ret" => Ok(Self::Ret),
+            "movsd" => Ok(Self::Movsd),
+            "movq" => Ok(Self::Movq),
+            "addsd" => Ok(Self::Addsd),
+            "subsd" => Ok(Self::Subsd),
+            "mulsd" => Ok(Self::Mulsd),
+            "divsd" => Ok(Self::Divsd),
+            "sqrtsd" => Ok(Self::Sqrtsd),
+            "ucomisd" => Ok(Self::Ucomisd),
+            "xorpd" => Ok(Self::Xorpd),
+            "cvtsi2sd" => Ok(Self::Cvtsi2sd),
+            "cvttsd2si" => Ok(Self::Cvttsd2si),
             _ => Err(format!("Undifiend Mnemonic {clean_s}!")),
         }
     }
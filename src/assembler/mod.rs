@@ -1,14 +1,19 @@
+pub mod asm_file;
 pub mod asm_parser;
 pub mod assemble;
 pub mod data_bss;
+pub mod disasm;
 pub mod instructions;
 pub mod memory;
 pub mod mnemonic;
 pub mod opcodes;
+pub mod peephole;
 pub mod register;
 pub mod text;
-pub mod utils;
-use std::{collections::BTreeMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
 
 use crate::{
     formats::elf::{sections::RelaItem, SymbolType},
@@ -31,6 +36,14 @@ pub fn placeholder(instr: Instr) -> Instr {
         _ => unreachable!(),
     }
 }
+/// Encoding of a jump or call with a displacement relative to the next instruction
+fn location_bytes(mnem: Mnemonic, disp: i64, long: bool) -> IBytes {
+    match long {
+        true => assemble_instr(&Instr::new1(mnem, Opr::Imm32(disp))),
+        false => assemble_instr(&Instr::new1(mnem, Opr::Imm8(disp))),
+    }
+}
+
 #[derive(Clone)]
 struct InstrData {
     instr: Instr,
@@ -64,6 +77,14 @@ pub struct Codegen {
     pub symbols_map: BTreeMap<String, (usize, SymbolType)>,
     //pub ffi_map: BTreeMap<String, String>,
     pub rela_map: Vec<RelaItem>,
+    /// Labels exported from the object file
+    pub globals: BTreeSet<String>,
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Codegen {
@@ -74,15 +95,79 @@ impl Codegen {
             data_buf: BTreeMap::new(),
             symbols_map: BTreeMap::new(),
             rela_map: Vec::new(),
+            globals: BTreeSet::new(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn get_raw_instructs(&self) -> Vec<Instr> {
         self.instructs.iter().map(|x| x.instr.clone()).collect()
     }
 
-    pub fn relocate(&mut self) {
+    /// Picks the shortest encoding of every jump and moves labels to their final location
+    /// jumps start with a rel8 displacement and are widened to rel32 until every target is in range
+    /// since jumps only grow this always terminates
+    fn relax_jumps(&mut self) -> Result<(), String> {
+        let is_location = |item: &InstrData| item.instr.needs_location();
+        let mut long: Vec<bool> = self
+            .instructs
+            .iter()
+            .map(|item| is_location(item) && !item.instr.mnem.is_jump())
+            .collect();
+        loop {
+            let mut labels = BTreeMap::new();
+            let mut ends = Vec::with_capacity(self.instructs.len());
+            let mut offset = 0;
+            for (item, long) in self.instructs.iter().zip(long.iter()) {
+                if let (Mnemonic::Lable, Oprs::One(Opr::Loc(tag))) =
+                    (&item.instr.mnem, &item.instr.oprs)
+                {
+                    labels.insert(tag.clone(), offset);
+                }
+                offset += match is_location(item) {
+                    true => location_bytes(item.instr.mnem, 0, *long).len(),
+                    false => item.bytes.len(),
+                };
+                ends.push(offset);
+            }
+            let target = |instr: &Instr| -> Result<i64, String> {
+                let Oprs::One(Opr::Loc(key)) = &instr.oprs else {
+                    unreachable!("{instr}");
+                };
+                match labels.get(key) {
+                    Some(target) => Ok(*target as i64),
+                    None => Err(format!("Unknown jump target ({key})")),
+                }
+            };
+            let mut changed = false;
+            for (i, item) in self.instructs.iter().enumerate() {
+                if is_location(item) && !long[i] {
+                    let disp = target(&item.instr)? - ends[i] as i64;
+                    if i8::try_from(disp).is_err() {
+                        long[i] = true;
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                continue;
+            }
+            for (i, item) in self.instructs.iter_mut().enumerate() {
+                if is_location(item) {
+                    let disp = target(&item.instr)? - ends[i] as i64;
+                    item.bytes = location_bytes(item.instr.mnem, disp, long[i]);
+                }
+            }
+            for (tag, offset) in labels {
+                self.symbols_map.insert(tag, (offset, SymbolType::TextSec));
+            }
+            return Ok(());
+        }
+    }
+
+    /// Encodes jumps and calls to labels and creates the relocations of other sections
+    pub fn relocate(&mut self) -> Result<(), String> {
+        self.relax_jumps()?;
+        self.rela_map.clear();
         let mut bytes_sum = 0;
         for item in self.instructs.iter_mut() {
             if item.instr.needs_rela_map() {
@@ -133,37 +218,50 @@ impl Codegen {
                     _ => unreachable!("{:?}", item.instr),
                 }
                 bytes_sum += item.bytes.len();
-            } else if item.instr.needs_location() {
-                let Oprs::One(Opr::Loc(key)) = item.instr.oprs.clone() else {
-                    unreachable!("{:?}", item.instr);
-                };
-                let Some(target) = self.symbols_map.get(&key) else {
-                    panic!("Unknown Target!");
-                };
-                let loc: i32 = target.0 as i32 - bytes_sum as i32 - item.bytes.len() as i32;
-                let new_bytes =
-                    assemble_instr(&Instr::new1(item.instr.mnem, Opr::Imm32(loc as i64)));
-                bytes_sum += new_bytes.len();
-                item.bytes = new_bytes;
             } else {
                 bytes_sum += item.bytes.len();
             }
         }
+        Ok(())
+    }
+
+    pub fn text_section_bytes(&mut self) -> Result<IBytes, String> {
+        self.relocate()?;
+        Ok(self
+            .instructs
+            .iter()
+            .flat_map(|item| item.bytes.iter().copied())
+            .collect())
     }
 
-    pub fn text_section_bytes(&mut self) -> IBytes {
-        self.relocate();
-        let mut bytes = Vec::new();
-        let mut _bc = 0;
+    /// Offset, bytes and decoded form of every instruction of the text section
+    /// next to the instruction it was assembled from
+    pub fn text_section_listing(&mut self) -> Result<String, String> {
+        self.relocate()?;
+        let mut listing = String::new();
+        let mut offset = 0;
         for item in self.instructs.iter() {
-            // println!(
-            //     "\x1b[92m{_bc:3X}\x1b[0m: {:02X?} \x1b[93m{}\x1b[0m",
-            //     item.bytes, item.instr
-            // );
-            _bc += item.bytes.len();
-            bytes.extend(item.bytes.clone());
+            if let (Mnemonic::Lable, Oprs::One(Opr::Loc(tag))) =
+                (&item.instr.mnem, &item.instr.oprs)
+            {
+                listing.push_str(&format!("{tag}:\n"));
+                continue;
+            }
+            let end = offset + item.bytes.len();
+            let bytes: Vec<String> = item.bytes.iter().map(|b| format!("{b:02x}")).collect();
+            let decoded = match disasm::decode_instr(&item.bytes) {
+                Ok((instr, len)) if len == item.bytes.len() => disasm::display_decoded(&instr, end),
+                Ok((_, len)) => format!("(decoded {len} of {} bytes)", item.bytes.len()),
+                Err(e) => format!("({e})"),
+            };
+            listing.push_str(&format!(
+                "{offset:8x}:  {:<30} {decoded:<36} ; {}\n",
+                bytes.join(" "),
+                item.instr
+            ));
+            offset = end;
         }
-        bytes
+        Ok(listing)
     }
 
     pub fn text_section_asm(&self) -> String {
@@ -185,42 +283,33 @@ impl Codegen {
 
     pub fn add_data(&mut self, data: Vec<u8>, dtype: VariableType) -> String {
         let name = format!("data{}", self.data_buf.len());
-        let index = match self.data_buf.values().last() {
-            Some(dt) => dt.index + dt.data.len(),
-            None => 0,
-        };
-        self.symbols_map.insert(
-            name.clone(),
-            (
-                self.data_buf.values().map(|x| x.data.len()).sum(),
-                SymbolType::DataSec,
-            ),
-        );
-        self.data_buf.insert(
-            name.clone(),
-            DataItem::new(name.clone(), index, data, dtype),
-        );
+        self.insert_data(name.clone(), data, dtype);
         name
     }
 
+    /// Appends an item to the data section under the given name
+    pub fn insert_data(&mut self, name: String, data: Vec<u8>, dtype: VariableType) {
+        let index = self.data_buf.values().map(|x| x.data.len()).sum();
+        self.symbols_map
+            .insert(name.clone(), (index, SymbolType::DataSec));
+        self.data_buf
+            .insert(name.clone(), DataItem::new(name, index, data, dtype));
+    }
+
     pub fn add_bss_seg(&mut self, size: usize) -> String {
         let bss_tag = format!("arr{}", self.bss_buf.len());
-        let index = match self.bss_buf.last() {
-            Some(dt) => dt.index + dt.size,
-            None => 0,
-        };
-        self.symbols_map.insert(
-            bss_tag.clone(),
-            (
-                self.bss_buf.iter().map(|x| x.size).sum(),
-                SymbolType::BssSec,
-            ),
-        );
-        self.bss_buf
-            .push(BssItem::new(bss_tag.clone(), index, size));
+        self.insert_bss(bss_tag.clone(), size);
         bss_tag
     }
 
+    /// Appends an item to the bss section under the given name
+    pub fn insert_bss(&mut self, name: String, size: usize) {
+        let index = self.bss_buf.iter().map(|x| x.size).sum();
+        self.symbols_map
+            .insert(name.clone(), (index, SymbolType::BssSec));
+        self.bss_buf.push(BssItem::new(name, index, size));
+    }
+
     pub fn instr2(&mut self, mnemonic: Mnemonic, opr1: impl Into<Opr>, opr2: impl Into<Opr>) {
         self.instructs
             .push(InstrData::new(Instr::new2(mnemonic, opr1, opr2)));
@@ -243,6 +332,18 @@ impl Codegen {
         self.instructs.push(InstrData::new(instr));
     }
 
+    /// Runs the peephole optimizer and recomputes the location of labels
+    pub fn peephole(&mut self) {
+        let instrs = peephole::optimize(&self.get_raw_instructs());
+        self.instructs.clear();
+        for instr in instrs {
+            match (instr.mnem, &instr.oprs) {
+                (Mnemonic::Lable, Oprs::One(Opr::Loc(tag))) => self.set_lable(tag),
+                _ => self.new_instr(instr),
+            }
+        }
+    }
+
     pub fn set_lable(&mut self, lable: impl Display) {
         let lable = lable.to_string();
         self.instructs.push(InstrData::new_lable(lable.clone()));
@@ -251,3 +352,32 @@ impl Codegen {
             .insert(lable, (real_loc, SymbolType::TextSec));
     }
 }
+
+#[test]
+fn test_relax_jumps() {
+    use self::register::Reg::RAX;
+    let mut codegen = Codegen::new();
+    codegen.set_lable("main");
+    codegen.instr1(Mnemonic::Jz, Opr::rel("main.end"));
+    codegen.set_lable("main.loop");
+    codegen.instr1(Mnemonic::Inc, RAX);
+    codegen.instr1(Mnemonic::Jl, Opr::rel("main.loop"));
+    for _ in 0..130 {
+        codegen.instr0(Mnemonic::Nop);
+    }
+    codegen.instr1(Mnemonic::Jmp, Opr::rel("main.loop"));
+    codegen.set_lable("main.end");
+    codegen.instr0(Mnemonic::Ret);
+    let bytes = codegen.text_section_bytes().unwrap();
+    // jz over more than 127 bytes needs rel32 while the short loop uses rel8
+    assert_eq!(bytes[..6], [0x0F, 0x84, 0x8C, 0x00, 0x00, 0x00]);
+    assert_eq!(bytes[6..11], [0x48, 0xFF, 0xC0, 0x7C, 0xFB]);
+    assert_eq!(bytes[141..146], [0xE9, 0x74, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(codegen.symbols_map["main.loop"].0, 6);
+    assert_eq!(codegen.symbols_map["main.end"].0, 146);
+    codegen.instr1(Mnemonic::Jmp, Opr::rel("main.missing"));
+    assert_eq!(
+        codegen.text_section_bytes(),
+        Err("Unknown jump target (main.missing)".to_string())
+    );
+}
@@ -31,8 +31,30 @@ macro_rules! imm {
     };
 }
 
-pub fn opcode(instr: &Instr) -> (u16, ModrmType) {
-    match (&instr.mnem, &instr.oprs) {
+/// Extension of the immediate forms (0x80, 0x81, 0x83) of the arithmetic instructions
+fn alu_ext(mnem: Mnemonic) -> u8 {
+    match mnem {
+        Mnemonic::Add => 0,
+        Or => 1,
+        And => 4,
+        Sub => 5,
+        Cmp => 7,
+        _ => unreachable!(),
+    }
+}
+
+/// Opcode of the instruction or None if the operands have no encoding
+pub fn opcode(instr: &Instr) -> Option<(u16, ModrmType)> {
+    Some(match (&instr.mnem, &instr.oprs) {
+        (mnem @ (Mnemonic::Add | Or | And | Sub | Cmp), Two(R8(_), imm!())) => {
+            (0x80, Ext(alu_ext(*mnem)))
+        }
+        (mnem @ (Mnemonic::Add | Or | And | Sub | Cmp), Two(Mem(m), imm!())) if m.size == 1 => {
+            (0x80, Ext(alu_ext(*mnem)))
+        }
+        (Test, Two(R8(_), imm!())) => (0xf6, Ext(0)),
+        (Test, Two(Mem(m), imm!())) if m.size == 1 => (0xf6, Ext(0)),
+        (Mnemonic::Add, Two(rm_8!(), R8(_))) => (0x00, Modrm),
         (Mnemonic::Add, Two(rm_16_64!(), r_16_64!())) => (0x01, Modrm),
         (Mnemonic::Add, Two(rm_16_64!(), Imm32(_))) => (0x81, Ext(0)),
         (Mnemonic::Add, Two(rm_16_64!(), Imm8(_))) => (0x83, Ext(0)),
@@ -40,6 +62,7 @@ pub fn opcode(instr: &Instr) -> (u16, ModrmType) {
         (Mov, Two(R8(_), Mem(_))) => (0x8A, Modrm),
         (Mov, Two(rm_16_64!(), r_16_64!())) => (0x89, Modrm),
         (Mov, Two(r_16_64!(), Mem(_))) => (0x8B, Modrm),
+        (Mov, Two(R8(_), imm!())) => (0xB0, ModrmType::Add),
         (Mov, Two(r_16_64!(), imm!())) => (0xB8, ModrmType::Add),
         (Mov, Two(Mem(m), imm!())) => {
             if m.size == 1 {
@@ -52,6 +75,7 @@ pub fn opcode(instr: &Instr) -> (u16, ModrmType) {
         (Push, One(Imm32(_) | Imm64(_))) => (0x68, ModrmType::None),
         (Push, One(R64(_))) => (0x50, ModrmType::Add),
         (Pop, One(R64(_))) => (0x58, ModrmType::Add),
+        (Sub, Two(rm_8!(), R8(_))) => (0x28, Modrm),
         (Sub, Two(rm_16_64!(), r_16_64!())) => (0x29, Modrm),
         (Sub, Two(r_16_64!(), Mem(_))) => (0x2B, Modrm),
         (Sub, Two(R64(Reg::RAX), Imm32(_))) => (0x2D, ModrmType::None),
@@ -63,8 +87,14 @@ pub fn opcode(instr: &Instr) -> (u16, ModrmType) {
         (Nop, Oprs::None) => (0x90, ModrmType::None),
         (Mul, One(R64(Reg::RDX))) => (0xf7, Ext(4)),
         (Imul, Two(r_16_64!(), rm_16_64!())) => (0x0faf, Modrm),
+        (Or, Two(rm_8!(), R8(_))) => (0x08, Modrm),
         (Or, Two(rm_16_64!(), r_16_64!())) => (0x09, Modrm),
+        (Or, Two(rm_16_64!(), Imm32(_))) => (0x81, Ext(1)),
+        (Or, Two(rm_16_64!(), Imm8(_))) => (0x83, Ext(1)),
+        (And, Two(rm_8!(), R8(_))) => (0x20, Modrm),
         (And, Two(rm_16_64!(), r_16_64!())) => (0x21, Modrm),
+        (And, Two(rm_16_64!(), Imm32(_))) => (0x81, Ext(4)),
+        (And, Two(rm_16_64!(), Imm8(_))) => (0x83, Ext(4)),
         (Sar, Two(rm_16_64!(), R8(Reg::CL))) => (0xd3, Ext(7)),
         (Shr, Two(rm_16_64!(), R8(Reg::CL))) => (0xd3, Ext(5)),
         (Shr, Two(rm_16_64!(), Imm8(_))) => (0xc1, Ext(5)),
@@ -77,23 +107,80 @@ pub fn opcode(instr: &Instr) -> (u16, ModrmType) {
         (Cmp, Two(rm_16_64!(), Imm8(_))) => (0x83, Ext(7)),
         (Neg, One(rm_16_64!())) => (0xf7, Ext(3)),
         (Not, One(rm_16_64!())) => (0xf7, Ext(2)),
-        (Jne, One(Imm8(_))) => (0x75, ModrmType::None),
-        (Jne, One(Imm32(_))) => (0x0f85, ModrmType::None),
         (Cmove, Two(r_16_64!(), rm_16_64!())) => (0x0f44, Modrm),
         (Cmovne, Two(r_16_64!(), rm_16_64!())) => (0x0f45, Modrm),
         (Cmovg, Two(r_16_64!(), rm_16_64!())) => (0x0f4f, Modrm),
         (Cmovge, Two(r_16_64!(), rm_16_64!())) => (0x0f4d, Modrm),
         (Cmovl, Two(r_16_64!(), rm_16_64!())) => (0x0f4c, Modrm),
         (Cmovle, Two(r_16_64!(), rm_16_64!())) => (0x0f4e, Modrm),
+        (Cmova, Two(r_16_64!(), rm_16_64!())) => (0x0f47, Modrm),
+        (Cmovae, Two(r_16_64!(), rm_16_64!())) => (0x0f43, Modrm),
+        (Cmovb, Two(r_16_64!(), rm_16_64!())) => (0x0f42, Modrm),
+        (Cmovbe, Two(r_16_64!(), rm_16_64!())) => (0x0f46, Modrm),
         (Call, One(imm!())) => (0xe8, ModrmType::None),
         (Jmp, One(Imm8(_))) => (0xeb, ModrmType::None),
         (Jmp, One(Imm32(_))) => (0xe9, ModrmType::None),
-        (Jz, One(Imm32(_))) => (0x0f84, ModrmType::None),
-        (Jz, One(Imm8(_))) => (0x74, ModrmType::None),
+        (jcc, One(Imm8(_))) if jcc.condition_code().is_some() => {
+            (0x70 + jcc.condition_code().unwrap() as u16, ModrmType::None)
+        }
+        (jcc, One(Imm32(_))) if jcc.condition_code().is_some() => (
+            0x0f80 + jcc.condition_code().unwrap() as u16,
+            ModrmType::None,
+        ),
+        (Test, Two(rm_8!(), R8(_))) => (0x84, Modrm),
         (Test, Two(rm_16_64!(), r_16_64!())) => (0x85, Modrm),
+        (Test, Two(rm_16_64!(), Imm32(_))) => (0xf7, Ext(0)),
         (Cqo, Oprs::None) => (0x4899, ModrmType::None),
         (Ret, Oprs::None) => (0xc3, ModrmType::None),
         (Inc, One(rm_16_64!())) => (0xff, Ext(0)),
-        _ => unimplemented!("{instr}"),
+        _ => return Option::None,
+    })
+}
+
+/// Encoding of an SSE instruction
+/// * prefix - mandatory prefix byte (emitted before rex)
+/// * rex_w - operand is a 64bit general purpose register or memory
+/// * reg_first - first operand is encoded in the reg field of modrm
+pub struct SseOpcode {
+    pub prefix: u8,
+    pub opcode: u16,
+    pub rex_w: bool,
+    pub reg_first: bool,
+}
+
+const fn sse(prefix: u8, opcode: u16, rex_w: bool, reg_first: bool) -> SseOpcode {
+    SseOpcode {
+        prefix,
+        opcode,
+        rex_w,
+        reg_first,
     }
 }
+
+macro_rules! xmm_m64 {
+    () => {
+        Xmm(_) | Mem(_)
+    };
+}
+
+pub fn sse_opcode(instr: &Instr) -> Option<SseOpcode> {
+    Some(match (&instr.mnem, &instr.oprs) {
+        (Movsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f10, false, true),
+        (Movsd, Two(Mem(_), Xmm(_))) => sse(0xf2, 0x0f11, false, false),
+        (Movq, Two(Xmm(_), R64(_) | Mem(_))) => sse(0x66, 0x0f6e, true, true),
+        (Movq, Two(R64(_) | Mem(_), Xmm(_))) => sse(0x66, 0x0f7e, true, false),
+        (Addsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f58, false, true),
+        (Mulsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f59, false, true),
+        (Subsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f5c, false, true),
+        (Divsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f5e, false, true),
+        (Sqrtsd, Two(Xmm(_), xmm_m64!())) => sse(0xf2, 0x0f51, false, true),
+        (Ucomisd, Two(Xmm(_), xmm_m64!())) => sse(0x66, 0x0f2e, false, true),
+        (Xorpd, Two(Xmm(_), xmm_m64!())) => sse(0x66, 0x0f57, false, true),
+        (Cvtsi2sd, Two(Xmm(_), R64(_))) => sse(0xf2, 0x0f2a, true, true),
+        (Cvtsi2sd, Two(Xmm(_), R32(_))) => sse(0xf2, 0x0f2a, false, true),
+        (Cvtsi2sd, Two(Xmm(_), Mem(m))) => sse(0xf2, 0x0f2a, m.size != 4, true),
+        (Cvttsd2si, Two(R64(_), xmm_m64!())) => sse(0xf2, 0x0f2c, true, true),
+        (Cvttsd2si, Two(R32(_), xmm_m64!())) => sse(0xf2, 0x0f2c, false, true),
+        _ => return Option::None,
+    })
+}
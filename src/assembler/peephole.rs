@@ -0,0 +1,400 @@
+use super::{
+    instructions::{Instr, Opr, Oprs},
+    memory::{MemAddr, MemAddrType},
+    mnemonic::Mnemonic::{self, *},
+    register::Reg::{self, *},
+};
+#[cfg(test)]
+use crate::memq;
+
+/// Rewrites the instructions starting at an index
+/// returns the number of replaced instructions and their replacement
+type Rule = fn(&[Instr], usize) -> Option<(usize, Vec<Instr>)>;
+
+static RULES: [Rule; 7] = [
+    self_move,
+    redundant_reload,
+    push_pop,
+    jump_to_next,
+    call_arg_count,
+    imm_operand,
+    forward_move,
+];
+
+/// Removes redundant instructions by rewriting small windows of the instruction stream
+/// rules are applied until none of them matches
+pub fn optimize(instrs: &[Instr]) -> Vec<Instr> {
+    let mut instrs = instrs.to_vec();
+    loop {
+        let mut changed = false;
+        let mut result = Vec::with_capacity(instrs.len());
+        let mut i = 0;
+        while i < instrs.len() {
+            match RULES.iter().find_map(|rule| rule(&instrs, i)) {
+                Some((count, replacement)) => {
+                    result.extend(replacement);
+                    i += count;
+                    changed = true;
+                }
+                None => {
+                    result.push(instrs[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        instrs = result;
+        if !changed {
+            return instrs;
+        }
+    }
+}
+
+/// How an instruction uses a register
+#[derive(Debug, PartialEq)]
+enum Effect {
+    Reads,
+    /// Register is overwritten without reading its value
+    Writes,
+    None,
+    /// Instruction has implicit operands or changes the control flow
+    Unknown,
+}
+
+fn same_reg(a: Reg, b: Reg) -> bool {
+    match a.is_xmm() || b.is_xmm() {
+        true => a == b,
+        false => a.convert(8) == b.convert(8),
+    }
+}
+
+fn mem_regs(mem: &MemAddr) -> Vec<Reg> {
+    match mem.addr_type {
+        MemAddrType::Addr(r) | MemAddrType::Disp(r, _) => vec![r],
+        MemAddrType::Sib(r, _, s, _) => vec![r, s],
+        MemAddrType::AddrRela(_) => vec![],
+    }
+}
+
+/// Returns true if operand is the register or uses it to compute an address
+fn mentions(opr: &Opr, reg: Reg) -> bool {
+    match opr {
+        Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r) | Opr::Xmm(r) => same_reg(*r, reg),
+        Opr::Mem(m) => mem_regs(m).iter().any(|r| same_reg(*r, reg)),
+        _ => false,
+    }
+}
+
+fn effect(instr: &Instr, reg: Reg) -> Effect {
+    match (instr.mnem, &instr.oprs) {
+        (Nop, _) => Effect::None,
+        (Mov | Lea | Movq | Movsd | Cvtsi2sd | Cvttsd2si, Oprs::Two(dst, src)) => {
+            if mentions(src, reg) || (matches!(dst, Opr::Mem(_)) && mentions(dst, reg)) {
+                return Effect::Reads;
+            }
+            match dst {
+                // writing the lower 32 bits clears the upper half
+                Opr::R64(r) | Opr::R32(r) if same_reg(*r, reg) => Effect::Writes,
+                Opr::R16(r) | Opr::R8(r) | Opr::Xmm(r) if same_reg(*r, reg) => Effect::Reads,
+                _ => Effect::None,
+            }
+        }
+        (
+            Add | Sub | Imul | Or | And | Sal | Sar | Shr | Cmp | Test | Cmove | Cmovne | Cmovg
+            | Cmovl | Cmovge | Cmovle | Cmova | Cmovae | Cmovb | Cmovbe | Addsd | Subsd | Mulsd
+            | Divsd | Sqrtsd | Ucomisd | Xorpd,
+            Oprs::Two(a, b),
+        ) => match mentions(a, reg) || mentions(b, reg) {
+            true => Effect::Reads,
+            false => Effect::None,
+        },
+        (Push | Neg | Not | Inc, Oprs::One(a)) => match mentions(a, reg) {
+            true => Effect::Reads,
+            false => Effect::None,
+        },
+        (Pop, Oprs::One(Opr::R64(r))) if same_reg(*r, reg) => Effect::Writes,
+        (Pop, Oprs::One(a)) => match mentions(a, reg) {
+            true => Effect::Reads,
+            false => Effect::None,
+        },
+        _ => Effect::Unknown,
+    }
+}
+
+/// Returns true if value of the register is overwritten before being read
+/// register is assumed to be live at jumps, labels and calls
+fn is_dead(instrs: &[Instr], from: usize, reg: Reg) -> bool {
+    for instr in instrs[from..].iter() {
+        match effect(instr, reg) {
+            Effect::Writes => return true,
+            Effect::Reads | Effect::Unknown => return false,
+            Effect::None => (),
+        }
+    }
+    false
+}
+
+/// Returns true for 64bit registers and memory
+fn is_qword(opr: &Opr) -> bool {
+    match opr {
+        Opr::R64(_) => true,
+        Opr::Mem(m) => m.size == 8,
+        _ => false,
+    }
+}
+
+fn imm_value(opr: &Opr) -> Option<i64> {
+    match opr {
+        Opr::Imm8(v) | Opr::Imm32(v) | Opr::Imm64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn imm_opr(value: i64) -> Option<Opr> {
+    if i8::try_from(value).is_ok() {
+        Some(Opr::Imm8(value))
+    } else if i32::try_from(value).is_ok() {
+        Some(Opr::Imm32(value))
+    } else {
+        None
+    }
+}
+
+/// mov rax, rax
+fn self_move(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    match (instrs[i].mnem, &instrs[i].oprs) {
+        (Mov, Oprs::Two(Opr::R64(a), Opr::R64(b))) if a == b => Some((1, vec![])),
+        _ => None,
+    }
+}
+
+/// mov r10, rax
+/// mov rax, r10 <- removed
+fn redundant_reload(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (Mov, Oprs::Two(a, b)) = (instrs[i].mnem, &instrs[i].oprs) else {
+        return None;
+    };
+    let (Mov, Oprs::Two(c, d)) = (instrs.get(i + 1)?.mnem, &instrs[i + 1].oprs) else {
+        return None;
+    };
+    if a != d || b != c || !is_qword(a) || !is_qword(b) {
+        return None;
+    }
+    // address of the memory should not depend on the overwritten register
+    if let (Opr::R64(r), Opr::Mem(_)) = (a, b) {
+        if mentions(b, *r) {
+            return None;
+        }
+    }
+    Some((2, vec![instrs[i].clone()]))
+}
+
+/// push rax
+/// pop rbx
+fn push_pop(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (Push, Oprs::One(Opr::R64(a))) = (instrs[i].mnem, &instrs[i].oprs) else {
+        return None;
+    };
+    let (Pop, Oprs::One(Opr::R64(b))) = (instrs.get(i + 1)?.mnem, &instrs[i + 1].oprs) else {
+        return None;
+    };
+    match a == b {
+        true => Some((2, vec![])),
+        false => Some((2, vec![Instr::new2(Mov, *b, *a)])),
+    }
+}
+
+/// jmp label
+/// label:
+fn jump_to_next(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (mnem, Oprs::One(Opr::Loc(target))) = (instrs[i].mnem, &instrs[i].oprs) else {
+        return None;
+    };
+    if !mnem.is_jump() {
+        return None;
+    }
+    let falls_through = instrs[i + 1..]
+        .iter()
+        .take_while(|instr| instr.mnem == Mnemonic::Lable)
+        .any(|instr| instr.oprs == Oprs::One(Opr::Loc(target.clone())));
+    falls_through.then(|| (1, vec![]))
+}
+
+/// mov rax, 0 <- removed
+/// call function
+/// functions of the program never read the number of vector registers used by variadic calls
+fn call_arg_count(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (Mov, Oprs::Two(Opr::R64(RAX) | Opr::R32(EAX), value)) = (instrs[i].mnem, &instrs[i].oprs)
+    else {
+        return None;
+    };
+    imm_value(value)?;
+    let next = instrs.get(i + 1)?;
+    match (next.mnem, &next.oprs) {
+        (Call, Oprs::One(Opr::Loc(_))) => Some((1, vec![])),
+        _ => None,
+    }
+}
+
+/// mov rbx, 1
+/// mov rcx, 0
+/// sub rax, rbx
+/// into
+/// mov rcx, 0
+/// sub rax, 1
+fn imm_operand(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (Mov, Oprs::Two(Opr::R64(reg), value)) = (instrs[i].mnem, &instrs[i].oprs) else {
+        return None;
+    };
+    // 32bit immediates are zero extended when moved to a register
+    let value = match value {
+        Opr::Imm64(v) => *v,
+        Opr::Imm8(v) | Opr::Imm32(v) if *v >= 0 => *v,
+        _ => return None,
+    };
+    let imm = imm_opr(value)?;
+    let offset = instrs[i + 1..]
+        .iter()
+        .position(|instr| effect(instr, *reg) != Effect::None)?;
+    let j = i + 1 + offset;
+    let (mnem @ (Add | Sub | Cmp), Oprs::Two(dst, Opr::R64(src))) =
+        (instrs[j].mnem, &instrs[j].oprs)
+    else {
+        return None;
+    };
+    if src != reg || mentions(dst, *reg) || !is_qword(dst) || !is_dead(instrs, j + 1, *reg) {
+        return None;
+    }
+    let mut replacement = instrs[i + 1..j].to_vec();
+    replacement.push(Instr::new2(mnem, dst.clone(), imm));
+    Some((j - i + 1, replacement))
+}
+
+/// mov rax, rcx
+/// mov r10, rax
+/// into
+/// mov r10, rcx
+fn forward_move(instrs: &[Instr], i: usize) -> Option<(usize, Vec<Instr>)> {
+    let (Mov, Oprs::Two(Opr::R64(reg), src)) = (instrs[i].mnem, &instrs[i].oprs) else {
+        return None;
+    };
+    let (Mov, Oprs::Two(dst, Opr::R64(value))) = (instrs.get(i + 1)?.mnem, &instrs[i + 1].oprs)
+    else {
+        return None;
+    };
+    if value != reg || mentions(dst, *reg) || !is_qword(dst) {
+        return None;
+    }
+    let to_reg = matches!(dst, Opr::R64(_));
+    let valid = match src {
+        Opr::R64(_) => true,
+        Opr::Mem(m) => m.size == 8 && to_reg,
+        // memory destinations sign extend 32bit immediates
+        Opr::Imm8(v) | Opr::Imm32(v) => to_reg || *v >= 0,
+        Opr::Imm64(_) | Opr::Rela(_) => to_reg,
+        _ => false,
+    };
+    if !valid || !is_dead(instrs, i + 2, *reg) {
+        return None;
+    }
+    Some((2, vec![Instr::new2(Mov, dst.clone(), src.clone())]))
+}
+
+#[cfg(test)]
+fn lable(tag: &str) -> Instr {
+    Instr::new1(Lable, Opr::Loc(tag.to_string()))
+}
+
+#[test]
+fn test_redundant_moves() {
+    let before = vec![
+        Instr::new2(Mov, R10, RAX),
+        Instr::new2(Mov, RAX, R10),
+        Instr::new2(Mov, RBX, RBX),
+        Instr::new2(Add, RAX, RBX),
+    ];
+    let after = vec![Instr::new2(Mov, R10, RAX), Instr::new2(Add, RAX, RBX)];
+    assert_eq!(optimize(&before), after);
+}
+
+#[test]
+fn test_push_pop() {
+    let before = vec![
+        Instr::new1(Push, RAX),
+        Instr::new1(Pop, RAX),
+        Instr::new1(Push, RAX),
+        Instr::new1(Pop, RBX),
+    ];
+    assert_eq!(optimize(&before), vec![Instr::new2(Mov, RBX, RAX)]);
+}
+
+#[test]
+fn test_forward_move() {
+    let before = vec![
+        Instr::new2(Mov, RAX, R12),
+        Instr::new2(Mov, RDI, RAX),
+        Instr::new2(Mov, RAX, 1),
+        Instr::new2(Mov, memq!(RBP, -8), RAX),
+        Instr::new2(Mov, RAX, 0),
+        Instr::new1(Call, Opr::Loc("print".to_string())),
+    ];
+    let after = vec![
+        Instr::new2(Mov, RDI, R12),
+        Instr::new2(Mov, memq!(RBP, -8), 1),
+        Instr::new1(Call, Opr::Loc("print".to_string())),
+    ];
+    assert_eq!(optimize(&before), after);
+}
+
+#[test]
+fn test_live_register_is_kept() {
+    // rax is still used by the compare
+    let before = vec![
+        Instr::new2(Mov, RAX, RCX),
+        Instr::new2(Mov, R11, RAX),
+        Instr::new2(Test, RAX, RAX),
+        // value of rax may be needed by the callee
+        Instr::new2(Mov, RAX, 2),
+        Instr::new1(Call, Opr::Rela("printf".to_string())),
+    ];
+    assert_eq!(optimize(&before), before);
+}
+
+#[test]
+fn test_imm_operand() {
+    let before = vec![
+        Instr::new2(Mov, RAX, R12),
+        Instr::new2(Mov, RBX, 2),
+        Instr::new2(Mov, RCX, 0),
+        Instr::new2(Mov, RDX, 1),
+        Instr::new2(Cmp, RAX, RBX),
+        Instr::new2(Cmove, RCX, RDX),
+        Instr::new2(Mov, RBX, R13),
+    ];
+    let after = vec![
+        Instr::new2(Mov, RAX, R12),
+        Instr::new2(Mov, RCX, 0),
+        Instr::new2(Mov, RDX, 1),
+        Instr::new2(Cmp, RAX, Opr::Imm8(2)),
+        Instr::new2(Cmove, RCX, RDX),
+        Instr::new2(Mov, RBX, R13),
+    ];
+    assert_eq!(optimize(&before), after);
+}
+
+#[test]
+fn test_jump_to_next() {
+    let before = vec![
+        Instr::new1(Jmp, Opr::Loc("end".to_string())),
+        lable("other"),
+        lable("end"),
+        Instr::new1(Jz, Opr::Loc("other".to_string())),
+        Instr::new0(Ret),
+    ];
+    let after = vec![
+        lable("other"),
+        lable("end"),
+        Instr::new1(Jz, Opr::Loc("other".to_string())),
+        Instr::new0(Ret),
+    ];
+    assert_eq!(optimize(&before), after);
+}
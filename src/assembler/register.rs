@@ -15,6 +15,12 @@ pub enum Reg {
     RDI = 0x87,
     R8 = 0x88,
     R9 = 0x89,
+    R10 = 0x8A,
+    R11 = 0x8B,
+    R12 = 0x8C,
+    R13 = 0x8D,
+    R14 = 0x8E,
+    R15 = 0x8F,
     EAX = 0x40,
     ECX = 0x41,
     EDX = 0x42,
@@ -25,6 +31,12 @@ pub enum Reg {
     EDI = 0x47,
     R8D = 0x48,
     R9D = 0x49,
+    R10D = 0x4A,
+    R11D = 0x4B,
+    R12D = 0x4C,
+    R13D = 0x4D,
+    R14D = 0x4E,
+    R15D = 0x4F,
     AX = 0x20,
     CX = 0x21,
     DX = 0x22,
@@ -35,6 +47,12 @@ pub enum Reg {
     DI = 0x27,
     R8W = 0x28,
     R9W = 0x29,
+    R10W = 0x2A,
+    R11W = 0x2B,
+    R12W = 0x2C,
+    R13W = 0x2D,
+    R14W = 0x2E,
+    R15W = 0x2F,
     AL = 0x10,
     CL = 0x11,
     DL = 0x12,
@@ -49,6 +67,20 @@ pub enum Reg {
     DIL = 0x17,
     R8B = 0x18,
     R9B = 0x19,
+    R10B = 0x1A,
+    R11B = 0x1B,
+    R12B = 0x1C,
+    R13B = 0x1D,
+    R14B = 0x1E,
+    R15B = 0x1F,
+    XMM0 = 0xC0,
+    XMM1 = 0xC1,
+    XMM2 = 0xC2,
+    XMM3 = 0xC3,
+    XMM4 = 0xC4,
+    XMM5 = 0xC5,
+    XMM6 = 0xC6,
+    XMM7 = 0xC7,
 }
 
 #[allow(non_snake_case)]
@@ -57,20 +89,25 @@ impl Reg {
         matches!(self, Self::SPL | Self::BPL | Self::SIL | Self::DIL)
     }
 
-    pub fn is_extended(&self) -> bool {
+    pub fn is_xmm(&self) -> bool {
         matches!(
             self,
-            Self::R8
-                | Self::R9
-                | Self::R8D
-                | Self::R9D
-                | Self::R8W
-                | Self::R9W
-                | Self::R8B
-                | Self::R9B
+            Self::XMM0
+                | Self::XMM1
+                | Self::XMM2
+                | Self::XMM3
+                | Self::XMM4
+                | Self::XMM5
+                | Self::XMM6
+                | Self::XMM7
         )
     }
 
+    /// r8 to r15 in any size (low nibble of 8 to 15)
+    pub fn is_extended(&self) -> bool {
+        !self.is_xmm() && (*self as u8) & 0x0f >= 8
+    }
+
     pub fn size(&self) -> u8 {
         match self {
             Self::RAX
@@ -82,7 +119,13 @@ impl Reg {
             | Self::RSI
             | Self::RDI
             | Self::R8
-            | Self::R9 => 64u8,
+            | Self::R9
+            | Self::R10
+            | Self::R11
+            | Self::R12
+            | Self::R13
+            | Self::R14
+            | Self::R15 => 64u8,
             Self::EAX
             | Self::ECX
             | Self::EDX
@@ -92,7 +135,13 @@ impl Reg {
             | Self::ESI
             | Self::EDI
             | Self::R8D
-            | Self::R9D => 32u8,
+            | Self::R9D
+            | Self::R10D
+            | Self::R11D
+            | Self::R12D
+            | Self::R13D
+            | Self::R14D
+            | Self::R15D => 32u8,
             Self::AX
             | Self::CX
             | Self::DX
@@ -102,7 +151,13 @@ impl Reg {
             | Self::SI
             | Self::DI
             | Self::R8W
-            | Self::R9W => 16u8,
+            | Self::R9W
+            | Self::R10W
+            | Self::R11W
+            | Self::R12W
+            | Self::R13W
+            | Self::R14W
+            | Self::R15W => 16u8,
             Self::AL
             | Self::CL
             | Self::DL
@@ -112,8 +167,22 @@ impl Reg {
             | Self::SIL
             | Self::DIL
             | Self::R8B
-            | Self::R9B => 8u8,
+            | Self::R9B
+            | Self::R10B
+            | Self::R11B
+            | Self::R12B
+            | Self::R13B
+            | Self::R14B
+            | Self::R15B => 8u8,
             Self::AH | Self::CH | Self::DH | Self::BH => 8u8,
+            Self::XMM0
+            | Self::XMM1
+            | Self::XMM2
+            | Self::XMM3
+            | Self::XMM4
+            | Self::XMM5
+            | Self::XMM6
+            | Self::XMM7 => 128u8,
         }
     }
 
@@ -129,6 +198,20 @@ impl Reg {
             Self::RDI | Self::EDI | Self::DI | Self::DIL | Self::BH => 7u8,
             Self::R8 | Self::R8D | Self::R8W | Self::R8B => 0u8,
             Self::R9 | Self::R9D | Self::R9W | Self::R9B => 1u8,
+            Self::R10 | Self::R10D | Self::R10W | Self::R10B => 2u8,
+            Self::R11 | Self::R11D | Self::R11W | Self::R11B => 3u8,
+            Self::R12 | Self::R12D | Self::R12W | Self::R12B => 4u8,
+            Self::R13 | Self::R13D | Self::R13W | Self::R13B => 5u8,
+            Self::R14 | Self::R14D | Self::R14W | Self::R14B => 6u8,
+            Self::R15 | Self::R15D | Self::R15W | Self::R15B => 7u8,
+            Self::XMM0 => 0u8,
+            Self::XMM1 => 1u8,
+            Self::XMM2 => 2u8,
+            Self::XMM3 => 3u8,
+            Self::XMM4 => 4u8,
+            Self::XMM5 => 5u8,
+            Self::XMM6 => 6u8,
+            Self::XMM7 => 7u8,
         }
     }
 
@@ -204,6 +287,50 @@ impl Reg {
                 8 => Self::R9,
                 _ => unreachable!(),
             },
+            Self::R10 | Self::R10D | Self::R10W | Self::R10B => match size {
+                1 => Self::R10B,
+                2 => Self::R10W,
+                4 => Self::R10D,
+                8 => Self::R10,
+                _ => unreachable!(),
+            },
+            Self::R11 | Self::R11D | Self::R11W | Self::R11B => match size {
+                1 => Self::R11B,
+                2 => Self::R11W,
+                4 => Self::R11D,
+                8 => Self::R11,
+                _ => unreachable!(),
+            },
+            Self::R12 | Self::R12D | Self::R12W | Self::R12B => match size {
+                1 => Self::R12B,
+                2 => Self::R12W,
+                4 => Self::R12D,
+                8 => Self::R12,
+                _ => unreachable!(),
+            },
+            Self::R13 | Self::R13D | Self::R13W | Self::R13B => match size {
+                1 => Self::R13B,
+                2 => Self::R13W,
+                4 => Self::R13D,
+                8 => Self::R13,
+                _ => unreachable!(),
+            },
+            Self::R14 | Self::R14D | Self::R14W | Self::R14B => match size {
+                1 => Self::R14B,
+                2 => Self::R14W,
+                4 => Self::R14D,
+                8 => Self::R14,
+                _ => unreachable!(),
+            },
+            Self::R15 | Self::R15D | Self::R15W | Self::R15B => match size {
+                1 => Self::R15B,
+                2 => Self::R15W,
+                4 => Self::R15D,
+                8 => Self::R15,
+                _ => unreachable!(),
+            },
+            // xmm registers are not sized
+            _ => *self,
         }
     }
 }
@@ -225,6 +352,12 @@ impl FromStr for Reg {
             "rdi" => Ok(Self::RDI),
             "r8" => Ok(Self::R8),
             "r9" => Ok(Self::R9),
+            "r10" => Ok(Self::R10),
+            "r11" => Ok(Self::R11),
+            "r12" => Ok(Self::R12),
+            "r13" => Ok(Self::R13),
+            "r14" => Ok(Self::R14),
+            "r15" => Ok(Self::R15),
             "eax" => Ok(Self::EAX),
             "ecx" => Ok(Self::ECX),
             "edx" => Ok(Self::EDX),
@@ -235,6 +368,12 @@ impl FromStr for Reg {
             "edi" => Ok(Self::EDI),
             "r8d" => Ok(Self::R8D),
             "r9d" => Ok(Self::R9D),
+            "r10d" => Ok(Self::R10D),
+            "r11d" => Ok(Self::R11D),
+            "r12d" => Ok(Self::R12D),
+            "r13d" => Ok(Self::R13D),
+            "r14d" => Ok(Self::R14D),
+            "r15d" => Ok(Self::R15D),
             "ax" => Ok(Self::AX),
             "cx" => Ok(Self::CX),
             "dx" => Ok(Self::DX),
@@ -245,6 +384,12 @@ impl FromStr for Reg {
             "di" => Ok(Self::DI),
             "r8w" => Ok(Self::R8W),
             "r9w" => Ok(Self::R9W),
+            "r10w" => Ok(Self::R10W),
+            "r11w" => Ok(Self::R11W),
+            "r12w" => Ok(Self::R12W),
+            "r13w" => Ok(Self::R13W),
+            "r14w" => Ok(Self::R14W),
+            "r15w" => Ok(Self::R15W),
             "ah" => Ok(Self::AH),
             "al" => Ok(Self::AL),
             "ch" => Ok(Self::CH),
@@ -259,6 +404,20 @@ impl FromStr for Reg {
             "dil" => Ok(Self::DIL),
             "r8b" => Ok(Self::R8B),
             "r9b" => Ok(Self::R9B),
+            "r10b" => Ok(Self::R10B),
+            "r11b" => Ok(Self::R11B),
+            "r12b" => Ok(Self::R12B),
+            "r13b" => Ok(Self::R13B),
+            "r14b" => Ok(Self::R14B),
+            "r15b" => Ok(Self::R15B),
+            "xmm0" => Ok(Self::XMM0),
+            "xmm1" => Ok(Self::XMM1),
+            "xmm2" => Ok(Self::XMM2),
+            "xmm3" => Ok(Self::XMM3),
+            "xmm4" => Ok(Self::XMM4),
+            "xmm5" => Ok(Self::XMM5),
+            "xmm6" => Ok(Self::XMM6),
+            "xmm7" => Ok(Self::XMM7),
             _ => Err(format!("Unsupported Register \"{lowercase_s}\"!")),
         }
     }
@@ -277,6 +436,12 @@ impl Display for Reg {
             Self::RDI => write!(f, "rdi"),
             Self::R8 => write!(f, "r8"),
             Self::R9 => write!(f, "r9"),
+            Self::R10 => write!(f, "r10"),
+            Self::R11 => write!(f, "r11"),
+            Self::R12 => write!(f, "r12"),
+            Self::R13 => write!(f, "r13"),
+            Self::R14 => write!(f, "r14"),
+            Self::R15 => write!(f, "r15"),
             Self::EAX => write!(f, "eax"),
             Self::ECX => write!(f, "ecx"),
             Self::EDX => write!(f, "edx"),
@@ -287,6 +452,12 @@ impl Display for Reg {
             Self::EDI => write!(f, "edi"),
             Self::R8D => write!(f, "r8d"),
             Self::R9D => write!(f, "r9d"),
+            Self::R10D => write!(f, "r10d"),
+            Self::R11D => write!(f, "r11d"),
+            Self::R12D => write!(f, "r12d"),
+            Self::R13D => write!(f, "r13d"),
+            Self::R14D => write!(f, "r14d"),
+            Self::R15D => write!(f, "r15d"),
             Self::AX => write!(f, "ax"),
             Self::CX => write!(f, "cx"),
             Self::DX => write!(f, "dx"),
@@ -297,6 +468,12 @@ impl Display for Reg {
             Self::DI => write!(f, "di"),
             Self::R8W => write!(f, "r8w"),
             Self::R9W => write!(f, "r9w"),
+            Self::R10W => write!(f, "r10w"),
+            Self::R11W => write!(f, "r11w"),
+            Self::R12W => write!(f, "r12w"),
+            Self::R13W => write!(f, "r13w"),
+            Self::R14W => write!(f, "r14w"),
+            Self::R15W => write!(f, "r15w"),
             Self::AH => write!(f, "ah"),
             Self::AL => write!(f, "al"),
             Self::CH => write!(f, "ch"),
@@ -311,6 +488,20 @@ impl Display for Reg {
             Self::DIL => write!(f, "dil"),
             Self::R8B => write!(f, "r8b"),
             Self::R9B => write!(f, "r9b"),
+            Self::R10B => write!(f, "r10b"),
+            Self::R11B => write!(f, "r11b"),
+            Self::R12B => write!(f, "r12b"),
+            Self::R13B => write!(f, "r13b"),
+            Self::R14B => write!(f, "r14b"),
+            Self::R15B => write!(f, "r15b"),
+            Self::XMM0 => write!(f, "xmm0"),
+            Self::XMM1 => write!(f, "xmm1"),
+            Self::XMM2 => write!(f, "xmm2"),
+            Self::XMM3 => write!(f, "xmm3"),
+            Self::XMM4 => write!(f, "xmm4"),
+            Self::XMM5 => write!(f, "xmm5"),
+            Self::XMM6 => write!(f, "xmm6"),
+            Self::XMM7 => write!(f, "xmm7"),
         }
     }
 }
@@ -1,13 +1,16 @@
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::Write;
 use std::path::Path;
 
 use crate::compiler::{CompilerContext, NSType};
+use crate::vfs::FileSystem;
 
-pub fn x86_64_nasm_generator(output: &Path, cc: &CompilerContext) -> Result<(), Box<dyn Error>> {
-    let stream = File::create(output.with_extension("asm")).unwrap();
-    let mut file = BufWriter::new(stream);
+pub fn x86_64_nasm_generator(
+    fs: &mut dyn FileSystem,
+    output: &Path,
+    cc: &CompilerContext,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = Vec::<u8>::new();
     file.write_all(b";; This File is Automatically Created Using The Nmet Compiler\n")?;
     file.write_all(b";; Under MIT License Copyright Mahan Farzaneh 2023-2024\n\n")?;
 
@@ -22,6 +25,13 @@ pub fn x86_64_nasm_generator(output: &Path, cc: &CompilerContext) -> Result<(),
     }
 
     file.write_all(b"global _start\n")?;
+    for item in cc.namespace_map.values() {
+        if let NSType::Function(f) = item {
+            if f.is_extern {
+                file.write_all(format!("global {}\n", f.ident).as_bytes())?;
+            }
+        }
+    }
     file.write_all(cc.codegen.text_section_asm().as_bytes())?;
     // for instruct in &codegen.instruct_buf {
     //     file.write_all(instruct.to_string().as_bytes())?;
@@ -44,6 +54,6 @@ pub fn x86_64_nasm_generator(output: &Path, cc: &CompilerContext) -> Result<(),
         }
     }
 
-    file.flush().unwrap();
+    fs.write(&output.with_extension("asm"), &file)?;
     Ok(())
 }
@@ -26,21 +26,100 @@ use crate::{
     assembler::{
         instructions::Opr, memory::MemAddr, mnemonic::Mnemonic::*, register::Reg::*, Codegen,
     },
+    ir::from_float,
     mem, memb, memq,
 };
 
 #[derive(Hash, PartialEq, Eq)]
 pub enum Bif {
     Print,
+    /// Prints a float (passed in xmm0) with 6 decimal places
+    PrintFloat,
 }
 
 impl Bif {
     pub fn implement(&self, codegen: &mut Codegen) {
         match self {
             Self::Print => Self::print_impl(codegen),
+            Self::PrintFloat => Self::print_float_impl(codegen),
         }
     }
 
+    /// Digits are written backwards into a 64 byte buffer at rbp-96
+    /// rcx holds the index of the first written character
+    fn print_float_impl(codegen: &mut Codegen) {
+        codegen.set_lable("print_float");
+        codegen.instr1(Push, RBP);
+        codegen.instr2(Mov, RBP, RSP);
+        codegen.instr2(Sub, RSP, 96);
+        // sign flag
+        codegen.instr2(Mov, RCX, 0);
+        codegen.instr2(Mov, RDX, 1);
+        codegen.instr2(Xorpd, XMM1, XMM1);
+        codegen.instr2(Ucomisd, XMM0, XMM1);
+        codegen.instr2(Cmovb, RCX, RDX);
+        codegen.instr2(Mov, memq!(RBP, -8), RCX);
+        codegen.instr2(Cmp, RCX, 0);
+        codegen.instr1(Jz, Opr::rel("print_float.L1"));
+        codegen.instr2(Subsd, XMM1, XMM0);
+        codegen.instr2(Movsd, XMM0, XMM1);
+        codegen.set_lable("print_float.L1");
+        // integer part and rounded fraction
+        codegen.instr2(Cvttsd2si, RAX, XMM0);
+        codegen.instr2(Mov, memq!(RBP, -16), RAX);
+        codegen.instr2(Cvtsi2sd, XMM1, RAX);
+        codegen.instr2(Subsd, XMM0, XMM1);
+        codegen.instr2(Mov, RAX, 1000000);
+        codegen.instr2(Cvtsi2sd, XMM1, RAX);
+        codegen.instr2(Mulsd, XMM0, XMM1);
+        codegen.instr2(Mov, RAX, from_float(0.5));
+        codegen.instr2(Movq, XMM1, RAX);
+        codegen.instr2(Addsd, XMM0, XMM1);
+        codegen.instr2(Cvttsd2si, RAX, XMM0);
+        codegen.instr2(Cmp, RAX, 1000000);
+        codegen.instr1(Jne, Opr::rel("print_float.L2"));
+        codegen.instr2(Mov, RAX, 0);
+        codegen.instr2(Add, memq!(RBP, -16), 1);
+        codegen.set_lable("print_float.L2");
+        codegen.instr2(Mov, RBX, 10);
+        codegen.instr2(Mov, RCX, 63);
+        codegen.instr2(Mov, memb!(RBP, -96, RCX), 10);
+        codegen.instr2(Mov, RSI, 6);
+        codegen.set_lable("print_float.L3");
+        codegen.instr2(Sub, RCX, 1);
+        codegen.instr0(Cqo);
+        codegen.instr1(Idiv, RBX);
+        codegen.instr2(Add, RDX, 48);
+        codegen.instr2(Mov, memb!(RBP, -96, RCX), DL);
+        codegen.instr2(Sub, RSI, 1);
+        codegen.instr2(Cmp, RSI, 0);
+        codegen.instr1(Jne, Opr::rel("print_float.L3"));
+        codegen.instr2(Sub, RCX, 1);
+        codegen.instr2(Mov, memb!(RBP, -96, RCX), 46);
+        codegen.instr2(Mov, RAX, memq!(RBP, -16));
+        codegen.set_lable("print_float.L4");
+        codegen.instr2(Sub, RCX, 1);
+        codegen.instr0(Cqo);
+        codegen.instr1(Idiv, RBX);
+        codegen.instr2(Add, RDX, 48);
+        codegen.instr2(Mov, memb!(RBP, -96, RCX), DL);
+        codegen.instr2(Cmp, RAX, 0);
+        codegen.instr1(Jne, Opr::rel("print_float.L4"));
+        codegen.instr2(Cmp, memq!(RBP, -8), 0);
+        codegen.instr1(Jz, Opr::rel("print_float.L5"));
+        codegen.instr2(Sub, RCX, 1);
+        codegen.instr2(Mov, memb!(RBP, -96, RCX), 45);
+        codegen.set_lable("print_float.L5");
+        codegen.instr2(Lea, RSI, mem!(RBP, -96, RCX));
+        codegen.instr2(Mov, RDX, 64);
+        codegen.instr2(Sub, RDX, RCX);
+        codegen.instr2(Mov, RDI, 1);
+        codegen.instr2(Mov, RAX, 1);
+        codegen.instr0(Syscall);
+        codegen.instr0(Leave);
+        codegen.instr0(Ret);
+    }
+
     fn print_impl(codegen: &mut Codegen) {
         codegen.set_lable("print");
         codegen.instr1(Push, RBP);
@@ -1,4 +1,3 @@
-use crate::log_cerror;
 use crate::parser::block::Block;
 
 use super::stmts::compile_stmt;
@@ -11,8 +10,7 @@ use super::CompilerContext;
 pub fn compile_block(cc: &mut CompilerContext, block: &Block) {
     for stmt in &block.stmts {
         compile_stmt(cc, stmt, block).unwrap_or_else(|e| {
-            cc.error();
-            log_cerror!(stmt.loc, "{e}");
+            cc.report(e.diagnostic(stmt.loc.clone()));
         });
     }
 }
@@ -20,8 +18,7 @@ pub fn compile_block(cc: &mut CompilerContext, block: &Block) {
 pub fn compile_function_block_alrady_scoped(cc: &mut CompilerContext, block: &Block) {
     for stmt in &block.stmts {
         compile_stmt(cc, stmt, block).unwrap_or_else(|e| {
-            cc.error();
-            log_cerror!(stmt.loc, "{e}");
+            cc.report(e.diagnostic(stmt.loc.clone()));
         });
     }
 }
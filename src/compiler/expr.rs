@@ -23,35 +23,34 @@
 *
 **********************************************************************************************/
 use crate::{
-    assembler::{
-        instructions::Opr,
-        memory::MemAddr,
-        mnemonic::Mnemonic::*,
-        register::Reg::{self, *},
-        utils::{mov_unknown_to_register, restore_last_temp_value, save_temp_value},
-    },
     error_handeling::CompilationError,
-    mem, memq,
-    optim::{fold_binary_expr, fold_compare_expr, fold_unary_expr, ExprOpr},
+    ir::{from_float, IRCall, IRExpr, IROperation, IRType, IRVar},
+    optim::ExprOpr,
     parser::{
         block::Block,
-        expr::{
-            ArrayIndex, BinaryExpr, CompareExpr, CompareOp, Expr, ExprType, FunctionCall, Op,
-            UnaryExpr,
-        },
-        types::VariableType,
+        expr::{ArrayIndex, BinaryExpr, CompareExpr, Expr, ExprType, FunctionCall, Op, UnaryExpr},
+        types::{StructItemType, StructType, VariableType},
     },
 };
 
-use super::{function_args_register, CompilerContext, NSType};
+use super::{CompilerContext, NSType};
 
 /// This function is part of the Nmet compiler and programming language.
 /// It takes expression (Expr) and a compiler context (CompilerContext)
-/// as input and generates assembly code for the expression.
+/// as input and generates IR for the expression.
+/// Errors are located at the innermost expression that caused them
 pub fn compile_expr(
     cc: &mut CompilerContext,
     block: &Block,
     expr: &Expr,
+) -> Result<ExprOpr, CompilationError> {
+    compile_expr_type(cc, block, expr).map_err(|e| e.at(&expr.loc))
+}
+
+fn compile_expr_type(
+    cc: &mut CompilerContext,
+    block: &Block,
+    expr: &Expr,
 ) -> Result<ExprOpr, CompilationError> {
     match &expr.etype {
         ExprType::Compare(c) => compile_compare_expr(cc, block, c),
@@ -64,19 +63,34 @@ pub fn compile_expr(
         ExprType::ArrayIndex(ai) => compile_array_index(cc, block, ai),
         ExprType::Variable(v) => {
             let v_map = cc.variables_map.get(v, block)?;
-            let mem_acss = v_map.mem();
-            Ok(ExprOpr::new(mem_acss, v_map.vtype))
+            let value = cc.ir.inst(IROperation::Load(v_map.ir_var())).unwrap();
+            Ok(ExprOpr::new(value, v_map.vtype))
         }
-        ExprType::Bool(b) => Ok(ExprOpr::new(*b as i32, VariableType::Bool)),
-        ExprType::Char(x) => Ok(ExprOpr::new(*x as i32, VariableType::Char)),
-        ExprType::Int(x) => Ok(ExprOpr::new(*x, VariableType::Int)),
+        ExprType::Bool(b) => Ok(ExprOpr::new(IRExpr::Imm(*b as i64), VariableType::Bool)),
+        ExprType::Char(x) => Ok(ExprOpr::new(IRExpr::Imm(*x as i64), VariableType::Char)),
+        ExprType::Int(x) => Ok(ExprOpr::new(IRExpr::Imm(*x as i64), VariableType::Int)),
         ExprType::String(str) => {
-            let id = cc
-                .codegen
-                .add_data(str.as_bytes().to_vec(), VariableType::String);
-            Ok(ExprOpr::new(Opr::Rela(id.to_owned()), VariableType::String))
+            let id = cc.ir.add_data(str.as_bytes().to_vec());
+            Ok(ExprOpr::new(IRExpr::Data(id), VariableType::String))
         }
-        ExprType::Float(_) => todo!(),
+        ExprType::Float(x) => Ok(ExprOpr::new(
+            IRExpr::Imm(from_float(*x)),
+            VariableType::Float,
+        )),
+    }
+}
+
+/// Converts the value of an expression between integer and floating point
+/// representation so it matches the target type
+pub fn convert_expr(cc: &mut CompilerContext, eo: ExprOpr, vtype: &VariableType) -> IRExpr {
+    let from_float = eo.vtype == VariableType::Float;
+    let to_float = vtype == &VariableType::Float;
+    if !from_float && to_float && eo.vtype.is_numeric() {
+        cc.ir.inst(IROperation::IntToFloat(eo.value)).unwrap()
+    } else if from_float && !to_float && vtype.is_numeric() {
+        cc.ir.inst(IROperation::FloatToInt(eo.value)).unwrap()
+    } else {
+        eo.value
     }
 }
 
@@ -85,44 +99,21 @@ pub fn compile_compare_expr(
     block: &Block,
     cexpr: &CompareExpr,
 ) -> Result<ExprOpr, CompilationError> {
-    // Compile the left Exprssion
     let left = compile_expr(cc, block, cexpr.left.as_ref())?;
-    // Store in memory if register
-    if left.is_temp() {
-        save_temp_value(cc, left.value.clone());
-    }
-    // Compile the right Exprssion
     let right = compile_expr(cc, block, cexpr.right.as_ref())?;
-
-    // Check for possiblity of optimization
-    // NOTE: If valuse where literal noting has been added to the codegen
-    if left.value.is_literal() && right.value.is_literal() {
-        return fold_compare_expr(&left, &right, &cexpr.op);
+    if left.value.is_imm() && right.value.is_imm() {
+        left.vtype.cast(&right.vtype)?;
     }
-
-    // Move Result to RBX Register
-    mov_unknown_to_register(cc, RBX, right.value.clone());
-    // Retrive the first instr values to RAX
-    if left.is_temp() {
-        restore_last_temp_value(cc, RAX);
+    let opr = if left.vtype == VariableType::Float || right.vtype == VariableType::Float {
+        let l = convert_expr(cc, left, &VariableType::Float);
+        let r = convert_expr(cc, right, &VariableType::Float);
+        IROperation::FCmp(cexpr.op.clone(), l, r)
     } else {
-        mov_unknown_to_register(cc, RAX, left.value.clone());
-    }
-    // Result of Compare instruction
-    cc.codegen.instr2(Mov, RCX, 0);
-    cc.codegen.instr2(Mov, RDX, 1);
-    cc.codegen.instr2(Cmp, RAX, RBX);
-    // set the result based on flag register
-    let mnem = match cexpr.op {
-        CompareOp::Eq => Cmove,
-        CompareOp::NotEq => Cmovne,
-        CompareOp::Bigger => Cmovg,
-        CompareOp::Smaller => Cmovl,
-        CompareOp::BiggerEq => Cmovge,
-        CompareOp::SmallerEq => Cmovle,
+        IROperation::Cmp(cexpr.op.clone(), left.value, right.value)
     };
-    cc.codegen.instr2(mnem, RCX, RDX);
-    Ok(ExprOpr::new(RCX, VariableType::Bool))
+    // NOTE: If valuse where literal the result is folded
+    let value = cc.ir.inst(opr).unwrap();
+    Ok(ExprOpr::new(value, VariableType::Bool))
 }
 
 fn compile_binary_expr(
@@ -130,70 +121,29 @@ fn compile_binary_expr(
     block: &Block,
     bexpr: &BinaryExpr,
 ) -> Result<ExprOpr, CompilationError> {
-    // Compile the left Exprssion
     let left = compile_expr(cc, block, bexpr.left.as_ref())?;
-    // Store in memory if register
-    if left.is_temp() {
-        save_temp_value(cc, left.value.clone());
-    }
-    // Compile the right Exprssion
     let right = compile_expr(cc, block, bexpr.right.as_ref())?;
-
-    // Check for possiblity of optimization
-    // NOTE: If valuse where literal noting has been added to the codegen
-    if left.value.is_literal() && right.value.is_literal() {
-        return fold_binary_expr(&left, &right, &bexpr.op);
-    }
-
-    // Move Result of right to RBX Register
-    mov_unknown_to_register(cc, RBX, right.value.clone());
-    // Retrive the left expr result to RAX
-    if left.is_temp() {
-        restore_last_temp_value(cc, RAX);
-    } else {
-        mov_unknown_to_register(cc, RAX, left.value.clone());
+    if left.vtype == VariableType::Float || right.vtype == VariableType::Float {
+        return compile_float_binary_expr(cc, bexpr, left, right);
     }
-    match bexpr.op {
-        Op::Plus => {
-            cc.codegen.instr2(Add, RAX, RBX);
-        }
-        Op::Sub => {
-            cc.codegen.instr2(Sub, RAX, RBX);
-        }
-        Op::Multi => {
-            cc.codegen.instr2(Imul, RAX, RBX);
-        }
-        Op::Devide => {
-            // Result of this operation is rax
-            cc.codegen.instr0(Cqo);
-            cc.codegen.instr1(Idiv, RBX);
-        }
-        Op::Mod => {
-            cc.codegen.instr0(Cqo);
-            cc.codegen.instr1(Idiv, RBX);
-            cc.codegen.instr2(Mov, RAX, RDX);
-        }
-        Op::Or => {
-            cc.codegen.instr2(Or, RAX, RBX);
-        }
-        Op::And => {
-            cc.codegen.instr2(And, RAX, RBX);
-        }
-        Op::Lsh => {
-            cc.codegen.instr2(Mov, RCX, RBX);
-            cc.codegen.instr2(Sal, RAX, CL);
-        }
-        Op::Rsh => {
-            cc.codegen.instr2(Mov, RCX, RBX);
-            cc.codegen.instr2(Sar, RAX, CL);
-        }
+    let (l, r) = (left.value, right.value);
+    let opr = match bexpr.op {
+        Op::Plus => IROperation::Add(l, r),
+        Op::Sub => IROperation::Sub(l, r),
+        Op::Multi => IROperation::Mult(l, r),
+        Op::Devide => IROperation::Divide(l, r),
+        Op::Mod => IROperation::Mod(l, r),
+        Op::Or => IROperation::Or(l, r),
+        Op::And => IROperation::And(l, r),
+        Op::Lsh => IROperation::Sal(l, r),
+        Op::Rsh => IROperation::Sar(l, r),
         Op::LogicalOr => {
-            cc.codegen.instr2(Or, RAX, RBX);
-            return Ok(ExprOpr::new(RAX, VariableType::Bool));
+            let value = cc.ir.inst(IROperation::Or(l, r)).unwrap();
+            return Ok(ExprOpr::new(value, VariableType::Bool));
         }
         Op::LogicalAnd => {
-            cc.codegen.instr2(And, RAX, RBX);
-            return Ok(ExprOpr::new(RAX, VariableType::Bool));
+            let value = cc.ir.inst(IROperation::And(l, r)).unwrap();
+            return Ok(ExprOpr::new(value, VariableType::Bool));
         }
         Op::Not => {
             return Err(CompilationError::InValidBinaryOperation(
@@ -202,8 +152,37 @@ fn compile_binary_expr(
                 right.vtype.to_string(),
             ));
         }
+    };
+    let vtype = left.vtype.cast(&right.vtype)?;
+    // NOTE: If valuse where literal the result is folded
+    let value = cc.ir.inst(opr).unwrap();
+    Ok(ExprOpr::new(value, vtype))
+}
+
+fn compile_float_binary_expr(
+    cc: &mut CompilerContext,
+    bexpr: &BinaryExpr,
+    left: ExprOpr,
+    right: ExprOpr,
+) -> Result<ExprOpr, CompilationError> {
+    let vtype = left.vtype.cast(&right.vtype)?;
+    if !matches!(bexpr.op, Op::Plus | Op::Sub | Op::Multi | Op::Devide) {
+        return Err(CompilationError::InValidBinaryOperation(
+            bexpr.op.to_owned(),
+            left.vtype.to_string(),
+            right.vtype.to_string(),
+        ));
     }
-    Ok(ExprOpr::new(RAX, left.vtype.cast(&right.vtype)?))
+    let l = convert_expr(cc, left, &vtype);
+    let r = convert_expr(cc, right, &vtype);
+    let opr = match bexpr.op {
+        Op::Plus => IROperation::FAdd(l, r),
+        Op::Sub => IROperation::FSub(l, r),
+        Op::Multi => IROperation::FMult(l, r),
+        _ => IROperation::FDivide(l, r),
+    };
+    let value = cc.ir.inst(opr).unwrap();
+    Ok(ExprOpr::new(value, vtype))
 }
 
 fn compile_array_index(
@@ -212,11 +191,16 @@ fn compile_array_index(
     ai: &ArrayIndex,
 ) -> Result<ExprOpr, CompilationError> {
     let v_map = cc.variables_map.get(&ai.ident, block)?;
+    if !matches!(v_map.vtype, VariableType::Array(_, _)) {
+        return Err(CompilationError::UnexpectedType(v_map.vtype.to_string()));
+    }
     let indexer = compile_expr(cc, block, &ai.indexer)?;
-    mov_unknown_to_register(cc, RBX, indexer.value);
-    let mem_acss = v_map.mem_with_offset_reg(Reg::RBX);
+    let var = v_map
+        .ir_var()
+        .indexed(indexer.value, v_map.vtype.item_size());
+    let value = cc.ir.inst(IROperation::Load(var)).unwrap();
     match v_map.vtype {
-        VariableType::Array(t, _) => Ok(ExprOpr::new(mem_acss, t.as_ref().clone())),
+        VariableType::Array(t, _) => Ok(ExprOpr::new(value, t.as_ref().clone())),
         _ => unreachable!(),
     }
 }
@@ -227,31 +211,28 @@ fn compile_unaray_expr(
     uexpr: &UnaryExpr,
 ) -> Result<ExprOpr, CompilationError> {
     let left_eo = compile_expr(cc, block, &uexpr.right)?;
-    if left_eo.value.is_literal() {
-        return fold_unary_expr(&left_eo, &uexpr.op);
-    }
     let new_type = match left_eo.vtype {
         VariableType::UInt => VariableType::Int,
         VariableType::ULong => VariableType::Long,
         VariableType::Char => VariableType::Int,
         _ => left_eo.vtype,
     };
-    match uexpr.op {
-        Op::Sub => {
-            mov_unknown_to_register(cc, RAX, left_eo.value);
-            cc.codegen.instr1(Neg, RAX);
-            Ok(ExprOpr::new(RAX, new_type))
-        }
-        Op::Plus => Ok(ExprOpr::new(left_eo.value, new_type)),
-        Op::Not => {
-            mov_unknown_to_register(cc, RAX, left_eo.value);
-            cc.codegen.instr1(Not, RAX);
-            Ok(ExprOpr::new(RAX, new_type))
-        }
+    let value = match uexpr.op {
+        Op::Sub if new_type == VariableType::Float => cc
+            .ir
+            .inst(IROperation::FSub(
+                IRExpr::Imm(from_float(0.0)),
+                left_eo.value,
+            ))
+            .unwrap(),
+        Op::Sub => cc.ir.inst(IROperation::Neg(left_eo.value)).unwrap(),
+        Op::Plus => left_eo.value,
+        Op::Not => cc.ir.inst(IROperation::Not(left_eo.value)).unwrap(),
         _ => {
             unreachable!();
         }
-    }
+    };
+    Ok(ExprOpr::new(value, new_type))
 }
 
 fn compile_access(
@@ -264,14 +245,28 @@ fn compile_access(
     let VariableType::Struct(struc) = v_map.vtype.clone() else {
         return Err(CompilationError::UnexpectedType(v_map.vtype.to_string()));
     };
-    let acv = match &expr.etype {
-        ExprType::Variable(v) => struc.items.get(v).unwrap(),
-        _ => todo!(),
+    let acv = struct_member(&struc, expr)?;
+    let ptr = cc.ir.inst(IROperation::Load(v_map.ir_var())).unwrap();
+    let var = IRVar::ptr(ptr, acv.offset, acv.vtype.item_size());
+    let value = cc.ir.inst(IROperation::Load(var)).unwrap();
+    Ok(ExprOpr::new(value, acv.vtype.clone()))
+}
+
+/// Member of a struct used by a member access
+/// only direct members are supported (a.b but not a.b.c or a.b[0])
+pub fn struct_member<'a>(
+    struc: &'a StructType,
+    member: &Expr,
+) -> Result<&'a StructItemType, CompilationError> {
+    let ExprType::Variable(ident) = &member.etype else {
+        return Err(CompilationError::Err(format!(
+            "Only direct members of struct ({}) can be accessed",
+            struc.ident
+        )));
     };
-    mov_unknown_to_register(cc, RDX, mem!(RBP, v_map.offset).into());
-    cc.codegen.instr2(Add, RDX, acv.offset);
-    mov_unknown_to_register(cc, RAX, MemAddr::new_s(acv.vtype.item_size(), RDX).into());
-    Ok(ExprOpr::new(RAX, acv.vtype.clone()))
+    struc.items.get(ident).ok_or_else(|| {
+        CompilationError::Err(format!("Struct ({}) has no member ({ident})", struc.ident))
+    })
 }
 
 fn compile_ptr(
@@ -282,21 +277,12 @@ fn compile_ptr(
     match &expr.etype {
         ExprType::Variable(v) => {
             let v_map = cc.variables_map.get(v, block)?;
-            match v_map.vtype {
-                VariableType::Array(_, _) => {
-                    cc.codegen.instr2(Lea, RAX, mem!(RBP, v_map.offset));
-                    Ok(ExprOpr::new(RAX, VariableType::Pointer))
-                }
-                _ => {
-                    cc.codegen.instr2(Mov, RAX, RBP);
-                    cc.codegen.instr2(Sub, RAX, -(v_map.offset));
-                    Ok(ExprOpr::new(RAX, VariableType::Pointer))
-                }
-            }
-        }
-        _ => {
-            todo!("Impl Pointers");
+            let value = cc.ir.inst(IROperation::Lea(v_map.ir_var())).unwrap();
+            Ok(ExprOpr::new(value, VariableType::Pointer))
         }
+        _ => Err(CompilationError::Err(
+            "Only the address of a variable can be taken".to_string(),
+        )),
     }
 }
 
@@ -305,44 +291,42 @@ fn compile_function_call(
     block: &Block,
     fc: &FunctionCall,
 ) -> Result<ExprOpr, CompilationError> {
-    let mut expr_list = Vec::new();
-    for arg in fc.args.iter().rev() {
-        let expr_op = compile_expr(cc, block, arg)?;
-        if expr_op.is_temp() {
-            save_temp_value(cc, expr_op.value.clone());
-        }
-        expr_list.push(expr_op);
-    }
-    for (i, item) in expr_list.iter().rev().enumerate() {
-        if item.is_temp() {
-            restore_last_temp_value(cc, function_args_register(i));
-        } else {
-            mov_unknown_to_register(cc, function_args_register(i), item.value.clone());
-        }
-    }
     let Some(func) = cc.namespace_map.get(&fc.ident) else {
         return Err(CompilationError::FunctionOutOfScope(fc.ident.clone()));
     };
-    cc.codegen.instr2(Mov, RAX, 0);
-    let fun;
-    match func {
-        NSType::Function(f) => {
-            fun = f.to_owned();
-            cc.codegen.instr1(Call, Opr::Loc(fc.ident.clone()));
-        }
-        NSType::Ffi(f, ff) => {
-            fun = f.to_owned();
-            cc.codegen.instr1(Pop, RBP);
-            cc.codegen.instr1(Call, Opr::Rela(ff.to_string()));
-            cc.codegen.instr1(Push, RBP);
-            cc.codegen.instr2(Mov, RBP, RSP);
-        }
+    let (fun, ffi_name) = match func {
+        NSType::Function(f) => (f.to_owned(), None),
+        NSType::Ffi(f, ff) => (f.to_owned(), Some(ff.to_string())),
         _ => return Err(CompilationError::UndefinedNameSpace(fc.ident.clone())),
+    };
+    let mut args = Vec::new();
+    for (i, arg) in fc.args.iter().enumerate().rev() {
+        let eo = compile_expr(cc, block, arg)?;
+        // extra arguments of variadic functions keep their own type
+        let vtype = match fun.args.get(i) {
+            Some(farg) => farg.typedef.clone(),
+            None => eo.vtype.clone(),
+        };
+        let value = convert_expr(cc, eo, &vtype);
+        match vtype {
+            VariableType::Float => args.push((value, IRType::Float)),
+            _ => args.push((value, IRType::Int)),
+        }
     }
+    args.reverse();
+    let ret = match fun.ret_type {
+        VariableType::Float => IRType::Float,
+        _ => IRType::Int,
+    };
+    let opr = match ffi_name {
+        Some(ff) => IROperation::Ffi(IRCall::new(ff, args, ret)),
+        None => IROperation::Call(IRCall::new(&fc.ident, args, ret)),
+    };
+    let value = cc.ir.inst(opr).unwrap();
     if fun.ret_type != VariableType::Void {
-        Ok(ExprOpr::new(RAX, fun.ret_type.clone()))
+        Ok(ExprOpr::new(value, fun.ret_type.clone()))
     } else {
-        Ok(ExprOpr::new(0, fun.ret_type.clone()))
+        Ok(ExprOpr::new(IRExpr::Imm(0), fun.ret_type.clone()))
     }
 }
 
@@ -353,18 +337,12 @@ fn compile_deref(
 ) -> Result<ExprOpr, CompilationError> {
     let t = compile_expr(cc, block, expr)?;
     match t.vtype {
-        VariableType::Array(_, _) => {
-            todo!("Changed!");
-        }
         VariableType::Pointer => {
-            let Opr::R64(r) = t.value else {
-                return Err(CompilationError::UnmatchingTypes(
-                    VariableType::Pointer,
-                    t.vtype,
-                ));
-            };
-            mov_unknown_to_register(cc, RCX, memq!(r).into());
-            Ok(ExprOpr::new(RCX, VariableType::Any))
+            let value = cc
+                .ir
+                .inst(IROperation::Load(IRVar::ptr(t.value, 0, 8)))
+                .unwrap();
+            Ok(ExprOpr::new(value, VariableType::Any))
         }
         _ => Err(CompilationError::UnmatchingTypes(
             VariableType::Pointer,
@@ -24,59 +24,76 @@
 **********************************************************************************************/
 
 use crate::{
-    assembler::{mnemonic::Mnemonic::*, register::Reg::*},
+    ir::{IRExpr, IRInline, IROperation, IRType},
     parser::{
         block::Block,
-        function::{FunctionArg, FunctionDef},
+        function::{FunctionArg, FunctionAttr, FunctionDef},
+        types::VariableType,
     },
 };
 
 use super::{
-    block::compile_function_block_alrady_scoped, function_args_register_sized,
-    variables::VariableMap, CompilerContext, VariableMapBase,
+    block::compile_function_block_alrady_scoped, variables::VariableMap, CompilerContext,
+    VariableMapBase,
 };
 
 pub fn function_args(cc: &mut CompilerContext, block: &Block, args: &[FunctionArg]) {
-    for (args_count, arg) in args.iter().enumerate() {
+    // integer and floating point arguments are counted separately
+    // the rest are passed on the stack in order
+    let (mut int_count, mut float_count, mut stack_count) = (0, 0, 0);
+    for arg in args.iter() {
         let map = VariableMap::new(
             VariableMapBase::Stack(block.id.to_string()),
             cc.mem_offset,
             arg.typedef.clone(),
             false,
         );
-        if args_count < 6 {
-            let mem_acss = map.mem();
-            let reg = function_args_register_sized(args_count, &map.vtype);
-            cc.codegen.instr2(Mov, mem_acss, reg);
+        let value = if arg.typedef == VariableType::Float && float_count < 8 {
+            float_count += 1;
+            cc.ir.inst(IROperation::FArg(float_count - 1)).unwrap()
+        } else if arg.typedef != VariableType::Float && int_count < 6 {
+            int_count += 1;
+            cc.ir.inst(IROperation::Arg(int_count - 1)).unwrap()
         } else {
-            todo!();
-        }
+            stack_count += 1;
+            cc.ir.inst(IROperation::StackArg(stack_count - 1)).unwrap()
+        };
+        cc.ir.inst(IROperation::Store(map.ir_var(), value));
         let _ = cc.variables_map.insert(&arg.ident, map);
         cc.mem_offset += 8;
-        cc.codegen.instr2(Sub, RSP, 8);
     }
 }
 
 pub fn compile_function(cc: &mut CompilerContext, f: &FunctionDef) {
     cc.mem_offset = 0;
     cc.variables_map.purge();
-    //cc.variables_map = HashMap::new();
-    cc.codegen.set_lable(f.block.start_name());
+    cc.ir.new_func(f.block.start_name());
+    if f.decl.ret_type == VariableType::Float {
+        cc.ir.func().ret = IRType::Float;
+    }
+    cc.allowed_warnings.clear();
+    for attr in f.attrs.iter() {
+        match attr {
+            FunctionAttr::Inline => cc.ir.func().inline = IRInline::Always,
+            FunctionAttr::NoInline => cc.ir.func().inline = IRInline::Never,
+            FunctionAttr::Allow(warning) => cc.allowed_warnings.push(*warning),
+        }
+    }
+    // Return value is kept while the deferred statements run
+    cc.defer_ret = if f.defer_block.stmts.is_empty() {
+        None
+    } else {
+        Some(cc.ir.func().new_temp())
+    };
 
-    cc.codegen.instr1(Push, RBP);
-    cc.codegen.instr2(Mov, RBP, RSP);
     function_args(cc, &f.block, &f.decl.args);
     /*--- Scoping function variables ---*/
     compile_function_block_alrady_scoped(cc, &f.block);
-    //compile_block(cc, &f.block);
-    // revert rbp
-    cc.codegen.set_lable(f.block.end_name());
-    cc.codegen.instr1(Push, RAX);
+    cc.ir.label(f.block.end_name());
     // TODO: Issue a warning for assgigning variables in defer block
     compile_function_block_alrady_scoped(cc, &f.defer_block);
     /*--- Unscoping function variables ---*/
-    cc.codegen.instr1(Pop, RAX);
-
-    cc.codegen.instr0(Leave);
-    cc.codegen.instr0(Ret);
+    cc.ir
+        .inst(IROperation::Ret(cc.defer_ret.map(IRExpr::TmpVar)));
+    cc.ir.func().frame_size = cc.mem_offset;
 }
@@ -29,19 +29,20 @@ mod function;
 mod stmts;
 mod variables;
 
-use crate::assembler::instructions::Opr;
-use crate::assembler::mnemonic::Mnemonic;
-use crate::assembler::{register::Reg, Codegen};
+use crate::assembler::Codegen;
 use crate::compiler::{bif::Bif, function::compile_function};
+use crate::error_handeling::{Diagnostic, Loc, Severity};
+use crate::ir::{codegen::generate_codegen, IRCall, IRExpr, IRGenerator, IROperation, IRType};
+use crate::optim::PassManager;
 use crate::parser::block::Block;
 use crate::parser::function::FunctionDecl;
 use crate::parser::parse_source_file;
 use crate::parser::program::{ProgramFile, ProgramItem};
 use crate::parser::types::StructType;
-use crate::parser::types::VariableType;
-use crate::{log_error, CompilerOptions};
+use crate::vfs::FileSystem;
+use crate::warnings::{Warning, Warnings};
+use crate::CompilerOptions;
 use std::collections::{BTreeMap, HashSet};
-use std::process::exit;
 
 use self::variables::{insert_variable, NameSpaceMapping, VariableMapBase};
 
@@ -54,13 +55,23 @@ pub enum NSType {
 
 pub struct CompilerContext {
     pub codegen: Codegen,
+    pub ir: IRGenerator,
     pub options: CompilerOptions,
     pub variables_map: NameSpaceMapping,
     pub namespace_map: BTreeMap<String, NSType>,
     pub bif_set: HashSet<Bif>,
     pub mem_offset: usize,
+    /// Temporary holding the return value of functions with defer block
+    pub defer_ret: Option<usize>,
     pub program_file: String,
-    errors: usize,
+    /// Levels of the warnings set by the options
+    pub warnings: Warnings,
+    /// Warnings allowed by the attributes of the current function
+    pub allowed_warnings: Vec<Warning>,
+    /// Reported errors and warnings
+    pub diagnostics: Vec<Diagnostic>,
+    /// Statements changed by the optimization passes (--print-passes)
+    pub pass_log: Vec<String>,
 }
 
 impl CompilerContext {
@@ -69,15 +80,33 @@ impl CompilerContext {
             program_file,
             options: options.clone(),
             codegen: Codegen::new(),
+            ir: IRGenerator::new(),
             bif_set: HashSet::new(),
             variables_map: NameSpaceMapping::new(),
             namespace_map: BTreeMap::new(),
             mem_offset: 0,
-            errors: 0,
+            defer_ret: None,
+            warnings: options.warnings.clone(),
+            allowed_warnings: Vec::new(),
+            diagnostics: Vec::new(),
+            pass_log: Vec::new(),
         }
     }
-    pub fn error(&mut self) {
-        self.errors += 1;
+    pub fn report(&mut self, diag: Diagnostic) {
+        self.diagnostics.push(diag);
+    }
+    /// Reports a warning unless it is allowed
+    pub fn warn(&mut self, warning: Warning, diag: Diagnostic) {
+        if let Some(diag) = self.warnings.check(warning, &self.allowed_warnings, diag) {
+            self.report(diag);
+        }
+    }
+    /// Number of reported errors including denied warnings
+    pub fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
     }
     pub fn is_lib(&self) -> bool {
         self.options.static_lib || self.options.dynamic_lib
@@ -90,46 +119,46 @@ pub fn impl_bifs(cc: &mut CompilerContext) {
     }
 }
 
-pub fn function_args_register_sized(arg_numer: usize, vtype: &VariableType) -> Reg {
-    match arg_numer {
-        0 => Reg::RDI.convert(vtype.item_size()),
-        1 => Reg::RSI.convert(vtype.item_size()),
-        2 => Reg::RDX.convert(vtype.item_size()),
-        3 => Reg::RCX.convert(vtype.item_size()),
-        4 => Reg::R8.convert(vtype.item_size()),
-        5 => Reg::R9.convert(vtype.item_size()),
-        _ => unreachable!(),
-    }
+fn _frame_size(mem_offset: usize) -> usize {
+    2 << mem_offset.ilog2() as usize
 }
 
-pub fn function_args_register(arg_numer: usize) -> Reg {
-    match arg_numer {
-        0 => Reg::RDI,
-        1 => Reg::RSI,
-        2 => Reg::RDX,
-        3 => Reg::RCX,
-        4 => Reg::R8,
-        5 => Reg::R9,
-        _ => unreachable!(),
+/// Parses and compiles a file into the codegen of the context
+/// Diagnostics are collected in the context instead of being printed
+/// The file and its imports are read from the given file system
+///
+/// Returns the number of errors if compilation failed
+pub fn compile(cc: &mut CompilerContext, fs: &dyn FileSystem, path: String) -> Result<(), usize> {
+    match parse_source_file(fs, path, cc.options.target_platform) {
+        Ok(program) => compile_program(cc, &program),
+        Err(diagnostics) => {
+            let errors = diagnostics.len();
+            cc.diagnostics.extend(diagnostics);
+            Err(errors)
+        }
     }
 }
 
-fn _frame_size(mem_offset: usize) -> usize {
-    2 << mem_offset.ilog2() as usize
-}
-
-pub fn compile(cc: &mut CompilerContext, path: String) {
-    let program = parse_source_file(path.clone());
-    compile_init_function(cc, &program);
+/// Compiles a parsed program into the codegen of the context
+pub fn compile_program(cc: &mut CompilerContext, program: &ProgramFile) -> Result<(), usize> {
+    compile_init_function(cc, program);
     for item in program.items.iter() {
         if let ProgramItem::Func(f) = item {
             compile_function(cc, f);
         }
     }
-    if cc.errors > 0 {
-        log_error!("Compilation Failed due to {} previous errors!", cc.errors);
-        exit(-1);
+    if cc.errors() > 0 {
+        return Err(cc.errors());
     }
+    let mut passes = PassManager::for_level(cc.options.opt_level);
+    passes.log = cc.options.print_passes;
+    cc.pass_log = passes.run(&mut cc.ir);
+    if let Err(e) = generate_codegen(&cc.ir, &mut cc.codegen) {
+        let loc = Loc::new(cc.program_file.clone(), 1, 1);
+        cc.report(Diagnostic::error(e, loc).with_code("E0012"));
+        return Err(cc.errors());
+    }
+    Ok(())
 }
 
 fn collect_types(cc: &mut CompilerContext, program: &ProgramFile) {
@@ -152,34 +181,34 @@ fn collect_types(cc: &mut CompilerContext, program: &ProgramFile) {
                     .insert(s.ident.clone(), NSType::Struct(s.clone()));
             }
             ProgramItem::StaticVar(sv) => {
-                let _ = insert_variable(
-                    cc,
-                    &global_block,
-                    sv,
-                    VariableMapBase::Global(sv.ident.clone()),
-                );
+                let base = VariableMapBase::Global(sv.ident.clone());
+                if let Err(e) = insert_variable(cc, &global_block, sv, base) {
+                    cc.report(e.diagnostic(sv.loc.clone()));
+                }
             }
         }
     }
 }
 
 fn compile_init_function(cc: &mut CompilerContext, program: &ProgramFile) {
-    if !cc.is_lib() {
-        cc.codegen.set_lable("_start");
-        cc.codegen.instr1(Mnemonic::Push, Reg::RBP);
-        cc.codegen.instr2(Mnemonic::Mov, Reg::RBP, Reg::RSP);
-    }
+    cc.ir.new_func("_start");
     collect_types(cc, program);
+    cc.ir.func().frame_size = cc.mem_offset;
     if cc.is_lib() {
+        cc.ir.code.pop();
         return;
     }
     if !cc.namespace_map.contains_key("main") {
-        log_error!("Executable programs should have an entry point");
-        exit(-1);
+        let loc = Loc::new(cc.program_file.clone(), 1, 1);
+        cc.report(
+            Diagnostic::error("Executable programs should have an entry point", loc)
+                .with_code("E0012")
+                .with_help("define a main function or compile with --lib"),
+        );
+        return;
     }
-    cc.codegen
-        .instr1(Mnemonic::Call, Opr::Loc("main".to_owned()));
-    cc.codegen.instr2(Mnemonic::Mov, Reg::RAX, 60);
-    cc.codegen.instr2(Mnemonic::Mov, Reg::RDI, 0);
-    cc.codegen.instr0(Mnemonic::Syscall);
+    cc.ir
+        .inst(IROperation::Call(IRCall::new("main", vec![], IRType::Int)));
+    cc.ir
+        .inst(IROperation::Syscall(vec![IRExpr::Imm(60), IRExpr::Imm(0)]));
 }
@@ -22,32 +22,32 @@
 *     3. This notice may not be removed or altered from any source distribution.
 *
 **********************************************************************************************/
+use std::{collections::HashSet, str::FromStr};
+
 use crate::{
     assembler::{
-        asm_parser::parse_asm,
-        instructions::Opr,
-        memory::MemAddr,
-        mnemonic::Mnemonic::*,
-        register::Reg::*,
-        utils::{mov_unknown_to_register, restore_last_temp_value, save_temp_value},
+        asm_parser::{parse_asm, parse_symbol},
+        register::Reg,
     },
-    error_handeling::CompilationError,
-    log_cerror, log_warn, mem,
+    error_handeling::{CompilationError, Diagnostic},
+    ir::{IRCall, IRExpr, IROperation, IRType, IRVar},
+    optim::ExprOpr,
     parser::{
         assign::{Assign, AssignOp},
         block::Block,
-        expr::{CompareExpr, CompareOp, Expr, ExprType},
+        expr::{CompareExpr, CompareOp, Expr, ExprType, Op},
         stmt::{ElseBlock, ForLoop, IFStmt, Stmt, StmtType, WhileStmt},
         types::VariableType,
     },
+    warnings::Warning,
 };
 
 use super::{
     bif::Bif,
     block::compile_block,
-    expr::{compile_compare_expr, compile_expr},
+    expr::{compile_compare_expr, compile_expr, convert_expr, struct_member},
     variables::insert_variable,
-    CompilerContext, VariableMapBase,
+    CompilerContext, NSType, VariableMapBase,
 };
 
 fn compile_if_stmt(
@@ -63,26 +63,25 @@ fn compile_if_stmt(
         _ => ifs.then_block.end_name(),
     };
 
-    mov_unknown_to_register(cc, RAX, condition_eo.value);
-    cc.codegen.instr2(Test, RAX, RAX);
-    cc.codegen.instr1(Jz, Opr::Loc(next_loc.clone()));
+    cc.ir
+        .inst(IROperation::Jz(condition_eo.value, next_loc.clone().into()));
 
     compile_block(cc, &ifs.then_block);
     match ifs.else_block.as_ref() {
         ElseBlock::None => {
-            cc.codegen.set_lable(next_loc);
+            cc.ir.label(next_loc);
             Ok(())
         }
         ElseBlock::Else(b) => {
-            cc.codegen.instr1(Jmp, Opr::Loc(exit_tag.clone()));
-            cc.codegen.set_lable(next_loc);
+            cc.ir.jmp(&exit_tag);
+            cc.ir.label(next_loc);
             compile_block(cc, b);
-            cc.codegen.set_lable(exit_tag);
+            cc.ir.label(exit_tag);
             Ok(())
         }
         ElseBlock::Elif(iff) => {
-            cc.codegen.instr1(Jmp, Opr::Loc(exit_tag.clone()));
-            cc.codegen.set_lable(next_loc);
+            cc.ir.jmp(&exit_tag);
+            cc.ir.label(next_loc);
             compile_if_stmt(cc, iff, exit_tag)?;
             Ok(())
         }
@@ -97,16 +96,27 @@ fn compile_print(
     let expr_opr = compile_expr(cc, block, expr)?;
     match &expr.etype {
         ExprType::String(s) => {
-            cc.codegen.instr2(Mov, RAX, 1);
-            cc.codegen.instr2(Mov, RDI, 1);
-            cc.codegen.instr2(Mov, RSI, expr_opr.value);
-            cc.codegen.instr2(Mov, RDX, s.len());
-            cc.codegen.instr0(Syscall);
+            cc.ir.inst(IROperation::Syscall(vec![
+                IRExpr::Imm(1),
+                IRExpr::Imm(1),
+                expr_opr.value,
+                IRExpr::Imm(s.len() as i64),
+            ]));
+        }
+        _ if expr_opr.vtype == VariableType::Float => {
+            cc.bif_set.insert(Bif::PrintFloat);
+            let args = vec![(expr_opr.value, IRType::Float)];
+            cc.ir.inst(IROperation::Call(IRCall::new(
+                "print_float",
+                args,
+                IRType::Int,
+            )));
         }
         _ => {
             cc.bif_set.insert(Bif::Print);
-            mov_unknown_to_register(cc, RDI, expr_opr.value);
-            cc.codegen.instr1(Call, Opr::Loc("print".to_string()));
+            let args = vec![(expr_opr.value, IRType::Int)];
+            cc.ir
+                .inst(IROperation::Call(IRCall::new("print", args, IRType::Int)));
         }
     }
     Ok(())
@@ -133,34 +143,57 @@ pub fn compile_stmt(
             ExprType::FunctionCall(fc) => {
                 let eo = compile_expr(cc, block, e)?;
                 if eo.vtype != VariableType::Void {
-                    log_warn!(
-                        "({}), Unused return value of function {}!",
-                        stmt.loc,
-                        fc.ident
-                    );
+                    let diag = Diagnostic::warning(
+                        format!("Unused return value of function {}", fc.ident),
+                        stmt.loc.clone(),
+                    )
+                    .with_label("returns a value of type ".to_string() + &eo.vtype.to_string());
+                    cc.warn(Warning::UnusedReturn, diag);
                 }
                 Ok(())
             }
             _ => {
-                log_warn!("({}) Expression with no effect ignored!", stmt.loc);
+                let diag = Diagnostic::warning("Expression with no effect ignored", e.loc.clone())
+                    .with_label("this expression is not used");
+                cc.warn(Warning::UnusedValue, diag);
                 Ok(())
             }
         },
         StmtType::Return(e) => {
             let ret_expr = compile_expr(cc, block, e)?;
-            mov_unknown_to_register(cc, RAX, ret_expr.value);
-            // cc.codegen.instr0(Leave);
-            // cc.codegen.instr0(Ret);
-            cc.codegen.instr1(Jmp, Opr::Loc(block.master_end_name()));
+            let ret_type = match cc.ir.func().ret {
+                IRType::Float => VariableType::Float,
+                IRType::Int => VariableType::Long,
+            };
+            let value = convert_expr(cc, ret_expr, &ret_type);
+            // Deferred statements run before returning from the function
+            match cc.defer_ret {
+                Some(ret) => {
+                    cc.ir.inst_to(ret, IROperation::Mov(value));
+                    cc.ir.jmp(block.master_end_name());
+                }
+                None => {
+                    if cc.options.no_tail_calls || !replace_tail_call(cc, &value) {
+                        cc.ir.inst(IROperation::Ret(Some(value)));
+                    }
+                }
+            }
             Ok(())
         }
-        StmtType::InlineAsm(instructs) => {
-            for instr in instructs {
-                match compile_inline_asm(cc, block, instr) {
+        StmtType::InlineAsm(asm) => {
+            let instructs =
+                asm.expand(|ident| Ok(cc.variables_map.get(ident, block)?.mem().size))?;
+            // labels are local to the asm block
+            let prefix = format!("{}.asm{}", cc.ir.func().name, stmt.loc.line);
+            let labels: HashSet<&str> = instructs
+                .iter()
+                .filter_map(|(instr, _)| instr.trim().strip_suffix(':'))
+                .collect();
+            for (instr, loc) in instructs.iter() {
+                match compile_inline_asm(cc, block, &prefix, &labels, instr) {
                     Ok(_) => (),
                     Err(e) => {
-                        cc.error();
-                        log_cerror!(stmt.loc, "{e}");
+                        cc.report(e.diagnostic(loc.clone()));
                     }
                 }
             }
@@ -171,6 +204,23 @@ pub fn compile_stmt(
     }
 }
 
+/// Turns the call that computes the returned value into a tail call
+/// calls with arguments passed on the stack are kept since they use the caller frame
+fn replace_tail_call(cc: &mut CompilerContext, value: &IRExpr) -> bool {
+    let Some(last) = cc.ir.func().instrs.last_mut() else {
+        return false;
+    };
+    let IROperation::Call(call) = &last.opr else {
+        return false;
+    };
+    if last.dst.map(IRExpr::TmpVar).as_ref() != Some(value) || !call.args_in_regs() {
+        return false;
+    }
+    last.opr = IROperation::TailCall(call.clone());
+    last.dst = None;
+    true
+}
+
 fn compile_break_coninue(
     cc: &mut CompilerContext,
     block: &Block,
@@ -181,52 +231,97 @@ fn compile_break_coninue(
     } else {
         block.last_loop_start_name()?
     };
-    cc.codegen.instr1(Jmp, Opr::Loc(exit_loc));
+    cc.ir.jmp(exit_loc);
     Ok(())
 }
 
+/// Replaces variables and symbols of an inline asm instruction with their location
+/// * %ident - memory of the variable
+/// * label - label of the asm block
+/// * ident - static variable or function
 fn compile_inline_asm(
     cc: &mut CompilerContext,
     block: &Block,
-    instr: &String,
+    prefix: &str,
+    labels: &HashSet<&str>,
+    instr: &str,
 ) -> Result<(), CompilationError> {
-    if instr.contains('%') {
-        let mut final_instr = instr.clone();
-        let chars = final_instr.chars().collect::<Vec<char>>();
-        let mut index = 0;
-        let is_empty = |index: usize| (index >= chars.len());
-        while !is_empty(index) {
-            if chars[index] == '%' {
-                let mut ident = String::new();
-                let first_index = index;
+    let invalid = |e: String| CompilationError::Err(format!("Invalid inline asm ({instr}): {e}"));
+    let source = instr.trim();
+    if let Some(label) = source.strip_suffix(':') {
+        parse_symbol(label).map_err(invalid)?;
+        cc.ir.inst(IROperation::Asm(format!("{prefix}.{label}:")));
+        return Ok(());
+    }
+    let chars = source.chars().collect::<Vec<char>>();
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut final_instr = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let first_index = index;
+        if chars[index] == '%' {
+            index += 1;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
                 index += 1;
-                while !is_empty(index) && (chars[index].is_alphanumeric() || chars[index] == '_') {
-                    ident.push(chars[index]);
-                    index += 1;
-                }
-                if !ident.is_empty() {
-                    let v_map = cc.variables_map.get(&ident, block)?;
-                    let mem_acss = v_map.mem().to_string();
-                    let mut temp = String::new();
-                    temp.push_str(chars[0..(first_index)].iter().collect::<String>().as_str());
-                    temp.push_str(mem_acss.as_str());
-                    temp.push_str(chars[index..].iter().collect::<String>().as_str());
-                    final_instr = temp;
-                    index += mem_acss.len()
-                } else {
-                    return Err(CompilationError::InvalidInlineAsm(instr.to_string()));
-                }
-            } else {
+            }
+            let ident = chars[first_index + 1..index].iter().collect::<String>();
+            if ident.is_empty() {
+                return Err(CompilationError::InvalidInlineAsm(instr.to_string()));
+            }
+            let v_map = cc.variables_map.get(&ident, block)?;
+            final_instr.push_str(&v_map.mem().to_string());
+        } else if is_ident(chars[index]) {
+            while index < chars.len() && is_ident(chars[index]) {
                 index += 1;
             }
+            let ident = chars[first_index..index].iter().collect::<String>();
+            // the mnemonic and numbers are kept as they are
+            if first_index == 0 || chars[first_index].is_ascii_digit() {
+                final_instr.push_str(&ident);
+            } else {
+                final_instr.push_str(&asm_symbol(cc, block, prefix, labels, &ident)?);
+            }
+        } else {
+            final_instr.push(chars[index]);
+            index += 1;
         }
-        cc.codegen.new_instr(parse_asm(final_instr));
-    } else {
-        cc.codegen.new_instr(parse_asm(instr.into()));
     }
+    parse_asm(&final_instr).map_err(invalid)?;
+    cc.ir.inst(IROperation::Asm(final_instr));
     Ok(())
 }
 
+/// Name of an identifier used by inline asm in the generated code
+fn asm_symbol(
+    cc: &CompilerContext,
+    block: &Block,
+    prefix: &str,
+    labels: &HashSet<&str>,
+    ident: &str,
+) -> Result<String, CompilationError> {
+    let keyword = ["byte", "word", "dword", "qword", "rel"].contains(&ident);
+    if keyword || Reg::from_str(ident).is_ok() {
+        return Ok(ident.to_string());
+    }
+    if labels.contains(ident) {
+        return Ok(format!("{prefix}.{ident}"));
+    }
+    if let Ok(v_map) = cc.variables_map.get(ident, block) {
+        return match v_map.base {
+            VariableMapBase::Global(tag) => Ok(tag),
+            VariableMapBase::Stack(_) => Err(CompilationError::Err(format!(
+                "Local variable ({ident}) must be used as %{ident} in inline asm"
+            ))),
+        };
+    }
+    match cc.namespace_map.get(ident) {
+        Some(NSType::Function(_)) => Ok(ident.to_string()),
+        _ => Err(CompilationError::Err(format!(
+            "Unknown symbol ({ident}) in inline asm"
+        ))),
+    }
+}
+
 fn compile_for_loop(cc: &mut CompilerContext, for_stmt: &ForLoop) -> Result<(), CompilationError> {
     insert_variable(
         cc,
@@ -240,18 +335,17 @@ fn compile_for_loop(cc: &mut CompilerContext, for_stmt: &ForLoop) -> Result<(),
             for_stmt.end_expr.etype
         )));
     }
-    cc.codegen
-        .instr1(Jmp, Opr::Loc(for_stmt.block.name_with_prefix("CND")));
-    cc.codegen.set_lable(for_stmt.block.start_name());
+    cc.ir.jmp(for_stmt.block.name_with_prefix("CND"));
+    cc.ir.label(for_stmt.block.start_name());
     compile_block(cc, &for_stmt.block);
 
     let v_map = cc
         .variables_map
         .get(&for_stmt.iterator.ident, &for_stmt.block)?;
-    let mem_acss = v_map.mem();
-    cc.codegen.instr1(Inc, mem_acss);
+    let one = ExprOpr::new(IRExpr::Imm(1), VariableType::Int);
+    assgin_op(cc, &AssignOp::PlusEq, one, v_map.ir_var(), &v_map.vtype)?;
 
-    cc.codegen.set_lable(for_stmt.block.name_with_prefix("CND"));
+    cc.ir.label(for_stmt.block.name_with_prefix("CND"));
     let cmp = CompareExpr {
         left: Box::new(Expr {
             loc: for_stmt.iterator.loc.clone(),
@@ -262,74 +356,65 @@ fn compile_for_loop(cc: &mut CompilerContext, for_stmt: &ForLoop) -> Result<(),
     };
     let condition_eo = compile_compare_expr(cc, &for_stmt.block, &cmp)?;
     VariableType::Bool.cast(&condition_eo.vtype)?;
-    mov_unknown_to_register(cc, RAX, condition_eo.value);
-    cc.codegen.instr2(Test, RAX, RAX);
-    cc.codegen
-        .instr1(Jne, Opr::Loc(for_stmt.block.start_name()));
-    cc.codegen.set_lable(for_stmt.block.end_name());
+    cc.ir.inst(IROperation::Jnz(
+        condition_eo.value,
+        for_stmt.block.start_name().into(),
+    ));
+    cc.ir.label(for_stmt.block.end_name());
     Ok(())
 }
 
 fn compile_while(cc: &mut CompilerContext, w_stmt: &WhileStmt) -> Result<(), CompilationError> {
-    cc.codegen
-        .instr1(Jmp, Opr::Loc(w_stmt.block.name_with_prefix("CND")));
-    cc.codegen.set_lable(w_stmt.block.start_name());
+    cc.ir.jmp(w_stmt.block.name_with_prefix("CND"));
+    cc.ir.label(w_stmt.block.start_name());
     compile_block(cc, &w_stmt.block);
-    cc.codegen.set_lable(w_stmt.block.name_with_prefix("CND"));
+    cc.ir.label(w_stmt.block.name_with_prefix("CND"));
     // Jump after a compare
     let condition_eo = compile_expr(cc, &w_stmt.block, &w_stmt.condition)?;
     VariableType::Bool.cast(&condition_eo.vtype)?;
-    mov_unknown_to_register(cc, RAX, condition_eo.value);
-    cc.codegen.instr2(Test, RAX, RAX);
-    cc.codegen.instr1(Jne, Opr::Loc(w_stmt.block.start_name()));
-    cc.codegen.set_lable(w_stmt.block.end_name());
+    cc.ir.inst(IROperation::Jnz(
+        condition_eo.value,
+        w_stmt.block.start_name().into(),
+    ));
+    cc.ir.label(w_stmt.block.end_name());
     Ok(())
 }
 
 fn assgin_op(
     cc: &mut CompilerContext,
     op: &AssignOp,
-    opr: Opr,
-    mem_acss: MemAddr,
+    right: ExprOpr,
+    var: IRVar,
+    vtype: &VariableType,
 ) -> Result<(), CompilationError> {
-    mov_unknown_to_register(cc, RAX, opr);
-    let reg_size = mem_acss.size;
-    match op {
-        AssignOp::Eq => {
-            cc.codegen.instr2(Mov, mem_acss, RAX.convert(reg_size));
-            Ok(())
-        }
-        AssignOp::PlusEq => {
-            cc.codegen.instr2(Add, mem_acss, RAX.convert(reg_size));
-            Ok(())
-        }
-        AssignOp::SubEq => {
-            cc.codegen.instr2(Sub, mem_acss, RAX.convert(reg_size));
-            Ok(())
-        }
-        AssignOp::MultiEq => {
-            mov_unknown_to_register(cc, RBX, mem_acss.clone().into());
-            cc.codegen.instr2(Imul, RAX, RBX);
-            cc.codegen.instr2(Mov, mem_acss, RAX.convert(reg_size));
-            Ok(())
-        }
-        AssignOp::DevideEq => {
-            cc.codegen.instr2(Mov, RBX, RAX);
-            mov_unknown_to_register(cc, RAX, mem_acss.clone().into());
-            cc.codegen.instr0(Cqo);
-            cc.codegen.instr1(Idiv, RBX);
-            cc.codegen.instr2(Mov, mem_acss, RAX.convert(reg_size));
-            Ok(())
-        }
-        AssignOp::ModEq => {
-            cc.codegen.instr2(Mov, RBX, RAX);
-            mov_unknown_to_register(cc, RAX, mem_acss.clone().into());
-            cc.codegen.instr0(Cqo);
-            cc.codegen.instr1(Idiv, RBX);
-            cc.codegen.instr2(Mov, mem_acss, RDX.convert(reg_size));
-            Ok(())
-        }
+    let is_float = vtype == &VariableType::Float;
+    if is_float && matches!(op, AssignOp::ModEq) {
+        return Err(CompilationError::InValidBinaryOperation(
+            Op::Mod,
+            vtype.to_string(),
+            right.vtype.to_string(),
+        ));
     }
+    let value = convert_expr(cc, right, vtype);
+    let opr = match (op, is_float) {
+        (AssignOp::Eq, _) => {
+            cc.ir.inst(IROperation::Store(var, value));
+            return Ok(());
+        }
+        (AssignOp::PlusEq, false) => IROperation::Add,
+        (AssignOp::SubEq, false) => IROperation::Sub,
+        (AssignOp::MultiEq, false) => IROperation::Mult,
+        (AssignOp::DevideEq, false) => IROperation::Divide,
+        (AssignOp::ModEq, _) => IROperation::Mod,
+        (AssignOp::PlusEq, true) => IROperation::FAdd,
+        (AssignOp::SubEq, true) => IROperation::FSub,
+        (AssignOp::MultiEq, true) => IROperation::FMult,
+        (AssignOp::DevideEq, true) => IROperation::FDivide,
+    };
+    let current = cc.ir.inst(IROperation::Load(var.clone())).unwrap();
+    let result = cc.ir.inst(opr(current, value)).unwrap();
+    cc.ir.inst(IROperation::Store(var, result));
+    Ok(())
 }
 
 fn compile_assgin(
@@ -341,66 +426,46 @@ fn compile_assgin(
         ExprType::Variable(v) => {
             let v_map = cc.variables_map.get(v, block)?;
             if !v_map.is_mut {
-                return Err(CompilationError::ImmutableVariable(v.to_owned()));
+                return Err(CompilationError::ImmutableVariable(
+                    v.to_owned(),
+                    v_map.decl.clone(),
+                ));
             }
             let right_eo = compile_expr(cc, block, &assign.right)?;
             v_map.vtype.cast(&right_eo.vtype)?;
-            assgin_op(cc, &assign.op, right_eo.value, v_map.mem())?;
-            Ok(())
+            assgin_op(cc, &assign.op, right_eo, v_map.ir_var(), &v_map.vtype)
         }
         ExprType::ArrayIndex(ai) => {
             let v_map = cc.variables_map.get(&ai.ident, block)?;
             if !v_map.is_mut {
-                return Err(CompilationError::ImmutableVariable(ai.ident.clone()));
+                return Err(CompilationError::ImmutableVariable(
+                    ai.ident.clone(),
+                    v_map.decl.clone(),
+                ));
             }
             let right_eo = compile_expr(cc, block, &assign.right)?;
-            if right_eo.is_temp() {
-                save_temp_value(cc, right_eo.value.clone());
-            }
-            let _ = match &v_map.vtype {
-                VariableType::Array(t, _) => t.cast(&right_eo.vtype)?,
-                _ => unreachable!(),
+            let item_type = match &v_map.vtype {
+                VariableType::Array(t, _) => t.as_ref().clone(),
+                t => return Err(CompilationError::UnexpectedType(t.to_string())),
             };
+            item_type.cast(&right_eo.vtype)?;
             let indexer = compile_expr(cc, block, &ai.indexer)?;
-            mov_unknown_to_register(cc, RBX, indexer.value);
-            let mem = MemAddr::new_sib_s(
-                v_map.vtype.item_size(),
-                RBP,
-                v_map.offset,
-                RBX,
-                v_map.vtype.item_size(),
-            );
-            if right_eo.is_temp() {
-                restore_last_temp_value(cc, RAX);
-                assgin_op(cc, &assign.op, RAX.into(), mem)?;
-            } else {
-                assgin_op(cc, &assign.op, right_eo.value, mem)?;
-            }
-            Ok(())
+            let var = v_map
+                .ir_var()
+                .indexed(indexer.value, v_map.vtype.item_size());
+            assgin_op(cc, &assign.op, right_eo, var, &item_type)
         }
         ExprType::Access(ident, expr) => {
             let v_map = cc.variables_map.get(ident, block)?;
             let VariableType::Struct(struc) = v_map.vtype.clone() else {
-                unreachable!();
+                return Err(CompilationError::UnexpectedType(v_map.vtype.to_string()));
             };
-            match &expr.etype {
-                ExprType::Variable(i) => {
-                    let inner_var = struc.items.get(i).unwrap();
-                    let right_eo = compile_expr(cc, block, &assign.right)?;
-                    inner_var.vtype.cast(&right_eo.vtype)?;
-                    cc.codegen.instr2(Mov, RDX, mem!(RBP, v_map.offset));
-                    cc.codegen.instr2(Add, RDX, inner_var.offset);
-                    let mem = MemAddr::new_s(inner_var.vtype.item_size(), RDX);
-                    assgin_op(cc, &assign.op, right_eo.value, mem)?;
-                }
-                ExprType::ArrayIndex(_) => todo!(),
-                ExprType::Access(_, _) => todo!(),
-                _ => {
-                    return Err(CompilationError::UnexpectedType(struc.ident));
-                }
-            }
-
-            Ok(())
+            let inner_var = struct_member(&struc, expr)?;
+            let right_eo = compile_expr(cc, block, &assign.right)?;
+            inner_var.vtype.cast(&right_eo.vtype)?;
+            let ptr = cc.ir.inst(IROperation::Load(v_map.ir_var())).unwrap();
+            let var = IRVar::ptr(ptr, inner_var.offset, inner_var.vtype.item_size());
+            assgin_op(cc, &assign.op, right_eo, var, &inner_var.vtype)
         }
         _ => Err(CompilationError::UnexpectedType("Literal".to_owned())),
     }
@@ -25,19 +25,16 @@ use std::collections::HashMap;
 *
 **********************************************************************************************/
 use crate::{
-    assembler::{
-        instructions::Opr,
-        memory::MemAddr,
-        mnemonic::Mnemonic::*,
-        register::Reg::{self, *},
-        utils::mov_unknown_to_register,
-    },
-    error_handeling::{error, CompilationError},
-    memq,
+    assembler::{memory::MemAddr, register::Reg},
+    error_handeling::{CompilationError, Loc},
+    ir::{IRExpr, IROperation, IRVar},
     parser::{block::Block, types::VariableType, variable_decl::VariableDeclare},
 };
 
-use super::{expr::compile_expr, CompilerContext, NSType};
+use super::{
+    expr::{compile_expr, convert_expr},
+    CompilerContext, NSType,
+};
 
 #[derive(Debug, Clone)]
 pub enum VariableMapBase {
@@ -51,6 +48,8 @@ pub struct VariableMap {
     pub offset: i32,
     pub vtype: VariableType,
     pub is_mut: bool,
+    /// Location of the declaration
+    pub decl: Option<Loc>,
 }
 
 impl VariableMap {
@@ -59,6 +58,7 @@ impl VariableMap {
         Self {
             base,
             is_mut,
+            decl: None,
             offset: new_offset,
             vtype,
         }
@@ -68,14 +68,13 @@ impl VariableMap {
         matches!(self.base, VariableMapBase::Global(_))
     }
 
-    pub fn mem_with_offset_reg(&self, offset_reg: Reg) -> MemAddr {
-        MemAddr::new_sib_s(
-            self.vtype.item_size(),
-            Reg::RBP,
-            self.offset,
-            offset_reg,
-            self.vtype.item_size(),
-        )
+    /// Memory location of the variable in IR
+    pub fn ir_var(&self) -> IRVar {
+        let size = self.mem().size;
+        match &self.base {
+            VariableMapBase::Global(g) => IRVar::global(g, size),
+            VariableMapBase::Stack(_) => IRVar::frame(self.offset, size),
+        }
     }
 
     pub fn mem(&self) -> MemAddr {
@@ -88,6 +87,7 @@ impl VariableMap {
             | VariableType::ULong
             | VariableType::Custom(_)
             | VariableType::Pointer
+            | VariableType::Float
             | VariableType::String => MemAddr::new_disp_s(8, Reg::RBP, self.offset),
             VariableType::Bool | VariableType::Char => {
                 MemAddr::new_disp_s(1, Reg::RBP, self.offset)
@@ -97,7 +97,6 @@ impl VariableMap {
                 MemAddr::new_disp_s(item_vtype.item_size(), Reg::RBP, self.offset)
             }
             VariableType::Struct(_) => MemAddr::new_disp_s(8, Reg::RBP, self.offset),
-            VariableType::Float => todo!(),
         }
     }
 
@@ -183,42 +182,41 @@ pub fn insert_variable(
         let Some(NSType::Struct(struct_map)) = cc.namespace_map.get(s) else {
             return Err(CompilationError::UnknownType(s.to_owned()));
         };
-        let struct_tag = cc.codegen.add_bss_seg(struct_map.size());
-        let mem_acss = memq!(RBP, -(cc.mem_offset as i32 + 8));
-        cc.codegen.instr2(Mov, mem_acss, Opr::Rela(struct_tag));
+        let struct_tag = cc.ir.add_bss(struct_map.size());
+        let var = IRVar::frame(-(cc.mem_offset as i32 + 8), 8);
+        cc.ir
+            .inst(IROperation::Store(var, IRExpr::Data(struct_tag)));
         vtype = VariableType::Struct(struct_map.clone());
     }
     // compile initial value
     if var.init_value.is_some() {
         let init_value = var.init_value.clone().unwrap();
         let expro = compile_expr(cc, block, &init_value)?;
-        match vtype.cast(&expro.vtype) {
-            Ok(vt) => {
-                let mem_acss = match &var_base {
-                    VariableMapBase::Stack(_) => MemAddr::new_disp_s(
-                        vt.item_size(),
-                        RBP,
-                        -((cc.mem_offset + vt.size()) as i32),
-                    ),
-                    VariableMapBase::Global(_) => {
-                        let tag = cc.codegen.add_bss_seg(vt.size());
-                        var_base = VariableMapBase::Global(tag.to_string());
-                        MemAddr::new_rela_s(vt.item_size(), tag.to_string())
-                    }
-                };
-                if expro.value.is_register() {
-                    cc.codegen.instr2(Mov, mem_acss, expro.value.sized(&vt));
-                } else {
-                    mov_unknown_to_register(cc, RAX, expro.value);
-                    cc.codegen
-                        .instr2(Mov, mem_acss, RAX.convert(vt.item_size()));
-                }
-                vtype = vt;
+        let vt = vtype.cast(&expro.vtype)?;
+        if vt.is_any() {
+            return Err(CompilationError::Err(format!(
+                "Type of variable ({}) can not be inferred",
+                var.ident
+            )));
+        }
+        // declared type is kept when mixing integers and floats
+        let vt = match vtype.is_numeric() && vt == VariableType::Float {
+            true => vtype.clone(),
+            false => vt,
+        };
+        let var = match &var_base {
+            VariableMapBase::Stack(_) => {
+                IRVar::frame(-((cc.mem_offset + vt.size()) as i32), vt.item_size())
             }
-            Err(msg) => {
-                error(msg, init_value.loc.clone());
+            VariableMapBase::Global(_) => {
+                let tag = cc.ir.add_bss(vt.size());
+                var_base = VariableMapBase::Global(tag.to_string());
+                IRVar::global(tag, vt.item_size())
             }
-        }
+        };
+        let value = convert_expr(cc, expro, &vt);
+        cc.ir.inst(IROperation::Store(var, value));
+        vtype = vt;
     }
     // Type checking
     if vtype == VariableType::Any {
@@ -226,13 +224,17 @@ pub fn insert_variable(
     }
     match &var_base {
         VariableMapBase::Stack(_) => {
-            let var_map = VariableMap::new(var_base, cc.mem_offset, vtype.clone(), var.mutable);
-            cc.codegen.instr2(Sub, RSP, vtype.size());
+            let mut var_map = VariableMap::new(var_base, cc.mem_offset, vtype.clone(), var.mutable);
+            var_map.decl = Some(var.loc.clone());
             cc.mem_offset += vtype.size();
             let _ = cc.variables_map.insert(&var.ident, var_map);
         }
         VariableMapBase::Global(_) => {
-            let var_map = VariableMap::new(var_base, 0, vtype.clone(), var.mutable);
+            if var.init_value.is_none() {
+                var_base = VariableMapBase::Global(cc.ir.add_bss(vtype.size()));
+            }
+            let mut var_map = VariableMap::new(var_base, 0, vtype.clone(), var.mutable);
+            var_map.decl = Some(var.loc.clone());
             let _ = cc.variables_map.insert(&var.ident, var_map);
         }
     }
@@ -0,0 +1,283 @@
+/**********************************************************************************************
+*
+*   error_codes: Stable error codes and their explanations for nmet --explain
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+
+/// Explanation of an error code
+/// E00xx codes are compilation errors and E01xx codes are syntax errors
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// Code that reports the error
+    pub example: &'static str,
+    /// How the example is fixed
+    pub fix_note: &'static str,
+    pub fix: &'static str,
+}
+
+pub static ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "Use of an undefined variable",
+        description: "A variable was used before it was declared or outside of the block it was declared in.",
+        example: "func main() {\n    print x;\n}",
+        fix_note: "Declare the variable with 'var' before using it:",
+        fix: "func main() {\n    var x @int = 1;\n    print x;\n}",
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "Name is not a function",
+        description: "A name that belongs to a struct or another item was called like a function.",
+        example: "struct Point {\n    x @int,\n}\n\nfunc main() {\n    Point();\n}",
+        fix_note: "Declare a variable with the struct type instead of calling it:",
+        fix: "struct Point {\n    x @int,\n}\n\nfunc main() {\n    var p @Point;\n    p.x = 1;\n}",
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "Unknown type",
+        description: "The type of a variable is neither a builtin type nor a defined struct, or it can not be \
+                      inferred because the variable has no type and no initial value.",
+        example: "func main() {\n    var p @Pointt;\n}",
+        fix_note: "Use a builtin type or the name of a defined struct:",
+        fix: "struct Point {\n    x @int,\n}\n\nfunc main() {\n    var p @Point;\n}",
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "Unexpected type",
+        description: "A value was used in a way that its type does not support, like accessing \
+                      a member of a variable that is not a struct.",
+        example: "func main() {\n    var a @int = 1;\n    print a.x;\n}",
+        fix_note: "Only access members of struct variables:",
+        fix: "struct Point {\n    x @int,\n}\n\nfunc main() {\n    var a @Point;\n    a.x = 1;\n    print a.x;\n}",
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "Invalid type casting",
+        description: "Two types were mixed in an assignment or an operation but one can not be \
+                      converted to the other. Only numeric types are casted implicitly.",
+        example: "func main() {\n    var s @str = 1;\n}",
+        fix_note: "Use a value of the declared type:",
+        fix: "func main() {\n    var s @str = \"1\";\n}",
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "Invalid binary operation",
+        description: "The operator is not defined for the types of its operands. Floating point \
+                      values only support +, -, * and /.",
+        example: "func main() {\n    print 1.5 % 2.0;\n}",
+        fix_note: "Use an operation that is supported by the types:",
+        fix: "func main() {\n    print 15 % 2;\n}",
+    },
+    ErrorCode {
+        code: "E0007",
+        title: "Function is not in scope",
+        description: "A function was called but no function, ffi mapping or import with that name exists.",
+        example: "func main() {\n    greet();\n}",
+        fix_note: "Define or import the function before calling it:",
+        fix: "func greet() {\n    print \"Hi\\n\";\n}\n\nfunc main() {\n    greet();\n}",
+    },
+    ErrorCode {
+        code: "E0008",
+        title: "Invalid variable reference in inline asm",
+        description: "A '%' in an asm instruction has to be followed by the name of a variable.",
+        example: "func main() {\n    asm {\n        \"mov rax, %\"\n    }\n}",
+        fix_note: "Write the name of the variable after '%':",
+        fix: "func main() {\n    var x @u64 = 1;\n    asm {\n        \"mov rax, %x\"\n    }\n}",
+    },
+    ErrorCode {
+        code: "E0009",
+        title: "Assignment to a constant variable",
+        description: "Variables declared with ':=' and function arguments are constant and can not \
+                      be assigned after they are declared.",
+        example: "func main() {\n    var x := 1;\n    x = 2;\n}",
+        fix_note: "Declare the variable with '=' to make it mutable:",
+        fix: "func main() {\n    var x @int = 1;\n    x = 2;\n}",
+    },
+    ErrorCode {
+        code: "E0010",
+        title: "Mismatched types",
+        description: "An expression has a different type than the one that is required, like \
+                      dereferencing a value that is not a pointer.",
+        example: "func main() {\n    var a @int = 1;\n    print *a;\n}",
+        fix_note: "Only dereference pointers:",
+        fix: "func main() {\n    var a @int = 1;\n    var p @ptr = &a;\n    print *p;\n}",
+    },
+    ErrorCode {
+        code: "E0011",
+        title: "Break or continue outside of a loop",
+        description: "break and continue can only be used inside of while and for loops.",
+        example: "func main() {\n    break;\n}",
+        fix_note: "Move the statement into a loop:",
+        fix: "func main() {\n    while true {\n        break;\n    }\n}",
+    },
+    ErrorCode {
+        code: "E0012",
+        title: "Invalid program",
+        description: "The program is invalid for a reason without a specific error code, like an \
+                      unknown instruction in inline asm. The message of the error describes the problem.",
+        example: "func main() {\n    asm {\n        \"movx rax, 1\"\n    }\n}",
+        fix_note: "Fix the problem described by the message:",
+        fix: "func main() {\n    asm {\n        \"mov rax, 1\"\n    }\n}",
+    },
+    ErrorCode {
+        code: "E0100",
+        title: "Unexpected character",
+        description: "The source code contains a character that is not part of the Nmet syntax. \
+                      The character is ignored and lexing continues after it.",
+        example: "func main() {\n    print 1 ^ 2;\n}",
+        fix_note: "Remove the character or use a supported operator:",
+        fix: "func main() {\n    print 1 + 2;\n}",
+    },
+    ErrorCode {
+        code: "E0101",
+        title: "Invalid char literal",
+        description: "Char literals contain exactly one character or escape sequence between single quotes.",
+        example: "func main() {\n    print 'ab';\n}",
+        fix_note: "Use a string literal for more than one character:",
+        fix: "func main() {\n    print \"ab\";\n}",
+    },
+    ErrorCode {
+        code: "E0102",
+        title: "Unterminated string literal",
+        description: "String literals have to be closed with a double quote on the same line.",
+        example: "func main() {\n    print \"hello;\n}",
+        fix_note: "Close the string literal:",
+        fix: "func main() {\n    print \"hello\";\n}",
+    },
+    ErrorCode {
+        code: "E0103",
+        title: "Invalid escape sequence",
+        description: "Only \\n, \\t, \\r, \\0 and \\\\ can be used as escape sequences, together \
+                      with \\' in char literals and \\\" in string literals.",
+        example: "func main() {\n    print \"\\q\";\n}",
+        fix_note: "Escape the backslash if it is part of the text:",
+        fix: "func main() {\n    print \"\\\\q\";\n}",
+    },
+    ErrorCode {
+        code: "E0104",
+        title: "Invalid number literal",
+        description: "Number literals are decimal, hexadecimal (0x), binary (0b) or floating point \
+                      numbers that fit in the integer type.",
+        example: "func main() {\n    print 12a;\n}",
+        fix_note: "Write a valid number:",
+        fix: "func main() {\n    print 0x12a;\n}",
+    },
+    ErrorCode {
+        code: "E0105",
+        title: "Unexpected token",
+        description: "The parser found a token that can not appear at this position, like a \
+                      statement without a semicolon.",
+        example: "func main() {\n    print 1\n}",
+        fix_note: "Add the expected token:",
+        fix: "func main() {\n    print 1;\n}",
+    },
+    ErrorCode {
+        code: "E0106",
+        title: "Duplicate definition",
+        description: "Functions, structs and static variables share one namespace and every name \
+                      can only be defined once, including names that are imported.",
+        example: "func main() {\n}\n\nfunc main() {\n}",
+        fix_note: "Rename or remove one of the definitions:",
+        fix: "func helper() {\n}\n\nfunc main() {\n}",
+    },
+    ErrorCode {
+        code: "E0107",
+        title: "Imported file not found",
+        description: "The path of an import is relative to the directory where the compiler is \
+                      executed and is written without the .nmt extension.",
+        example: "import \"./missing\" :: helper\n\nfunc main() {\n}",
+        fix_note: "Import a file that exists:",
+        fix: "import \"./std/syscalls/proc\" :: syscall_exit\n\nfunc main() {\n}",
+    },
+    ErrorCode {
+        code: "E0108",
+        title: "Unknown function attribute",
+        description: "Only #inline, #noinline and #allow(warning) can be written before a function definition.",
+        example: "#fast\nfunc main() {\n}",
+        fix_note: "Use a supported attribute:",
+        fix: "#inline\nfunc helper() {\n}\n\nfunc main() {\n}",
+    },
+    ErrorCode {
+        code: "E0109",
+        title: "Invalid pre-processing function",
+        description: "Pre-processing conditions start with #if, compare platform names and have to \
+                      end with #end.",
+        example: "func main() {\n    #if LINUX\n        print 1;\n}",
+        fix_note: "Close the condition with #end:",
+        fix: "func main() {\n    #if LINUX\n        print 1;\n    #end\n}",
+    },
+    ErrorCode {
+        code: "E0110",
+        title: "Invalid inline asm operand",
+        description: "Outputs of an asm block are bound to registers starting with '=' while inputs \
+                      and clobbers are plain register names.",
+        example: "func main() {\n    var x @int = 0;\n    asm {\n        \"mov rax, 1\"\n        : \"rax\" (x)\n    }\n}",
+        fix_note: "Mark the output register with '=':",
+        fix: "func main() {\n    var x @int = 0;\n    asm {\n        \"mov rax, 1\"\n        : \"=rax\" (x)\n    }\n}",
+    },
+    ErrorCode {
+        code: "E0111",
+        title: "Unknown warning",
+        description: "The name in an #allow attribute is not a warning of the compiler. The known \
+                      warnings are unused-return and unused-value.",
+        example: "#allow(unused)\nfunc main() {\n}",
+        fix_note: "Use the name of a warning:",
+        fix: "#allow(unused-value)\nfunc main() {\n}",
+    },
+];
+
+/// Long description of an error code with an example and its fix
+pub fn explain(code: &str) -> Option<String> {
+    let err = ERROR_CODES.iter().find(|e| e.code == code)?;
+    let indent = |code: &str| {
+        code.lines()
+            .map(|l| format!("    {l}\n"))
+            .collect::<String>()
+    };
+    Some(format!(
+        "{}: {}\n\n{}\n\nErroneous code example:\n\n{}\n{}\n\n{}",
+        err.code,
+        err.title,
+        err.description,
+        indent(err.example),
+        err.fix_note,
+        indent(err.fix)
+    ))
+}
+
+#[test]
+fn test_error_code_fixes_parse() {
+    for (i, err) in ERROR_CODES.iter().enumerate() {
+        assert!(ERROR_CODES[..i].iter().all(|e| e.code != err.code));
+        let file = crate::parser::parse_source(
+            &crate::vfs::DiskFs,
+            format!("{}.nmt", err.code),
+            err.fix.into(),
+            0,
+        );
+        assert!(file.is_ok(), "fix of {} does not parse", err.code);
+    }
+    assert!(explain("E0009").unwrap().contains("    var x := 1;\n"));
+    assert!(explain("E9999").is_none());
+}
@@ -22,9 +22,12 @@
 *     3. This notice may not be removed or altered from any source distribution.
 *
 **********************************************************************************************/
-use std::{error::Error, fmt::Display, process::exit};
+use std::{error::Error, fmt::Display, fs};
 
-use crate::parser::{expr::Op, types::VariableType};
+use crate::{
+    log_error,
+    parser::{expr::Op, types::VariableType},
+};
 
 #[derive(Debug)]
 pub enum CompilationError {
@@ -36,32 +39,118 @@ pub enum CompilationError {
     InValidBinaryOperation(Op, String, String),
     FunctionOutOfScope(String),
     InvalidInlineAsm(String),
-    ImmutableVariable(String),
+    /// Name of the variable and location of its declaration
+    ImmutableVariable(String, Option<Loc>),
     UnmatchingTypes(VariableType, VariableType),
     NotLoopBlock,
     Err(String),
+    /// Error located at the expression or asm line that caused it
+    /// instead of the statement it is reported for
+    At(Loc, Box<CompilationError>),
 }
 impl Display for CompilationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::UndefinedVariable(v) => write!(f,"Undifiend Variable ({v})"),
-            Self::UndefinedNameSpace(v) => write!(f,"Undifiend Name Space ({v})"),
-            Self::UnknownType(v) => write!(f,"Unknown vaiable type ({v})"),
-            Self::UnexpectedType(t) => write!(f,"Unexpected type ({t})"),
-            Self::InvalidTypeCasting(a, b) => write!(f, "Types ({a}) and ({b}) can not be casted to eachother for this operation"),
-            Self::InValidBinaryOperation(op, a, b) => write!(f,"Invalid Operation ({op}) on types ({a}) and ({b})"),
-            Self::FunctionOutOfScope(s) => write!(f,"Error: Function {s} is not avaliable in this scope. Make sure you are calling the correct function"),
-            Self::InvalidInlineAsm(i) => write!(f,"Invalid Identifier for Inline asm instruct ({i})"),
-            Self::ImmutableVariable(v) => write!(f,"Variable ({v}) is not mutable. Did you forgot to define it with '=' insted of ':=' ?" ),
+            Self::UndefinedVariable(v) => write!(f, "Undifiend Variable ({v})"),
+            Self::UndefinedNameSpace(v) => write!(f, "Undifiend Name Space ({v})"),
+            Self::UnknownType(v) => write!(f, "Unknown vaiable type ({v})"),
+            Self::UnexpectedType(t) => write!(f, "Unexpected type ({t})"),
+            Self::InvalidTypeCasting(a, b) => write!(
+                f,
+                "Types ({a}) and ({b}) can not be casted to eachother for this operation"
+            ),
+            Self::InValidBinaryOperation(op, a, b) => {
+                write!(f, "Invalid Operation ({op}) on types ({a}) and ({b})")
+            }
+            Self::FunctionOutOfScope(s) => write!(f, "Function {s} is not avaliable in this scope"),
+            Self::InvalidInlineAsm(i) => {
+                write!(f, "Invalid Identifier for Inline asm instruct ({i})")
+            }
+            Self::ImmutableVariable(v, _) => write!(f, "Variable ({v}) is not mutable"),
             Self::UnmatchingTypes(a, b) => write!(f, "Expected type ({a}), found type ({b})"),
             Self::NotLoopBlock => write!(f, "Can not break or continue out of non-loop blocks!"),
             Self::Err(e) => write!(f, "{e}"),
+            Self::At(_, e) => write!(f, "{e}"),
         }
     }
 }
 
 impl Error for CompilationError {}
 
+impl CompilationError {
+    /// Stable error code see error_codes
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UndefinedVariable(_) => "E0001",
+            Self::UndefinedNameSpace(_) => "E0002",
+            Self::UnknownType(_) => "E0003",
+            Self::UnexpectedType(_) => "E0004",
+            Self::InvalidTypeCasting(_, _) => "E0005",
+            Self::InValidBinaryOperation(_, _, _) => "E0006",
+            Self::FunctionOutOfScope(_) => "E0007",
+            Self::InvalidInlineAsm(_) => "E0008",
+            Self::ImmutableVariable(_, _) => "E0009",
+            Self::UnmatchingTypes(_, _) => "E0010",
+            Self::NotLoopBlock => "E0011",
+            Self::Err(_) => "E0012",
+            Self::At(_, e) => e.code(),
+        }
+    }
+
+    /// Locates the error at loc unless it already has a more specific location
+    pub fn at(self, loc: &Loc) -> Self {
+        match self {
+            Self::At(_, _) => self,
+            e => Self::At(loc.clone(), Box::new(e)),
+        }
+    }
+
+    /// Diagnostic of the error found at the given location
+    /// errors with their own location are reported there instead
+    pub fn diagnostic(&self, loc: Loc) -> Diagnostic {
+        if let Self::At(loc, e) = self {
+            return e.diagnostic(loc.clone());
+        }
+        let diag = Diagnostic::error(self, loc).with_code(self.code());
+        match self {
+            Self::UndefinedVariable(_) => diag
+                .with_label("not found in this scope")
+                .with_help("variables have to be declared with 'var' before they are used"),
+            Self::UndefinedNameSpace(_) => diag
+                .with_label("no function or struct with this name")
+                .with_help("check the spelling or import the module that defines it"),
+            Self::UnknownType(_) => diag.with_label("type is unknown").with_help(
+                "give the variable a type like '@int' or define a struct with this name",
+            ),
+            Self::UnexpectedType(_) => diag.with_label("value of this type can not be used here"),
+            Self::InvalidTypeCasting(_, _) => diag
+                .with_label("can not cast between these types")
+                .with_note("only numeric types are casted implicitly"),
+            Self::InValidBinaryOperation(op, _, _) => {
+                diag.with_label(format!("operator ({op}) is not defined for these types"))
+            }
+            Self::FunctionOutOfScope(_) => diag
+                .with_label("not avaliable in this scope")
+                .with_help("make sure you are calling the correct function"),
+            Self::InvalidInlineAsm(_) => diag
+                .with_label("in this asm block")
+                .with_note("variables are referenced in asm with '%name'"),
+            Self::ImmutableVariable(_, Some(decl)) => diag
+                .with_label("can not assign to a constant variable")
+                .with_secondary(decl.clone(), "declared as constant here")
+                .with_help("define it with '=' insted of ':='"),
+            Self::ImmutableVariable(_, None) => diag
+                .with_label("can not assign to a constant variable")
+                .with_note("function arguments are not mutable"),
+            Self::UnmatchingTypes(a, _) => diag.with_label(format!("expected ({a})")),
+            Self::NotLoopBlock => diag
+                .with_label("not inside of a loop")
+                .with_note("break and continue can only be used in while and for loops"),
+            Self::Err(_) | Self::At(_, _) => diag,
+        }
+    }
+}
+
 /// Code Location
 #[derive(Debug, PartialEq, Clone)]
 pub struct Loc {
@@ -89,8 +178,355 @@ impl Display for Loc {
     }
 }
 
-/// eprint error msg with location and exit the program
-pub fn error(msg: impl ToString, loc: Loc) -> ! {
-    eprintln!("\x1b[91m[{}]\x1b[0m {}", loc, msg.to_string());
-    exit(-1);
+/// Syntax error found by the lexer or the parser
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// Stable error code see error_codes
+    pub code: &'static str,
+    pub msg: String,
+    pub loc: Loc,
+}
+
+impl ParseError {
+    pub fn new(code: &'static str, msg: impl ToString, loc: Loc) -> Self {
+        Self {
+            code,
+            msg: msg.to_string(),
+            loc,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(&self.msg, self.loc.clone()).with_code(self.code)
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// How diagnostics are printed to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+    /// Colored text with source snippets
+    #[default]
+    Human,
+    /// One json object per line
+    Json,
+}
+
+/// Severity of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Message attached to a location of the source code
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub loc: Loc,
+    pub msg: String,
+}
+
+/// Error or warning with everything needed to explain it to the user
+/// Rendered with the source lines of the primary and secondary locations
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Stable error code e.g: E0004
+    pub code: Option<&'static str>,
+    pub severity: Severity,
+    pub msg: String,
+    /// Primary location
+    pub loc: Loc,
+    /// Message under the primary location
+    pub label: Option<String>,
+    /// Related locations
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, msg: impl ToString, loc: Loc) -> Self {
+        Self {
+            code: None,
+            severity,
+            msg: msg.to_string(),
+            loc,
+            label: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(msg: impl ToString, loc: Loc) -> Self {
+        Self::new(Severity::Error, msg, loc)
+    }
+
+    pub fn warning(msg: impl ToString, loc: Loc) -> Self {
+        Self::new(Severity::Warning, msg, loc)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, msg: impl ToString) -> Self {
+        self.label = Some(msg.to_string());
+        self
+    }
+
+    pub fn with_secondary(mut self, loc: Loc, msg: impl ToString) -> Self {
+        self.secondary.push(Label {
+            loc,
+            msg: msg.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl ToString) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl ToString) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// eprint the diagnostic using the source files on the disk
+    pub fn emit(&self, format: ErrorFormat) {
+        self.emit_with(format, &|path| fs::read_to_string(path).ok());
+    }
+
+    /// eprint the diagnostic in the given error format
+    /// * source_of - source code of a file path
+    pub fn emit_with(&self, format: ErrorFormat, source_of: &dyn Fn(&str) -> Option<String>) {
+        match format {
+            ErrorFormat::Human => eprint!("{}", self.render(source_of, true)),
+            ErrorFormat::Json => eprintln!("{}", self.to_json(source_of)),
+        }
+    }
+
+    /// Single line json object of the diagnostic
+    /// columns are 1 based and the end column is exclusive
+    pub fn to_json(&self, source_of: &dyn Fn(&str) -> Option<String>) -> String {
+        let span = |loc: &Loc| {
+            let len = source_of(&loc.file_path)
+                .and_then(|s| s.lines().nth(loc.line - 1).map(|l| span_len(l, loc.col)))
+                .unwrap_or(1);
+            format!(
+                "\"file\":{},\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{}",
+                json_str(&loc.file_path),
+                loc.line,
+                loc.col,
+                loc.line,
+                loc.col + len
+            )
+        };
+        let opt = |s: Option<&str>| s.map(json_str).unwrap_or("null".to_string());
+        let secondary = self
+            .secondary
+            .iter()
+            .map(|l| format!("{{{},\"label\":{}}}", span(&l.loc), json_str(&l.msg)))
+            .collect::<Vec<String>>();
+        let notes = self
+            .notes
+            .iter()
+            .map(|n| json_str(n))
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"code\":{},\"severity\":\"{}\",\"message\":{},{},\"label\":{},\"secondary\":[{}],\"notes\":[{}],\"help\":{}}}",
+            opt(self.code),
+            self.severity,
+            json_str(&self.msg),
+            span(&self.loc),
+            opt(self.label.as_deref()),
+            secondary.join(","),
+            notes.join(","),
+            opt(self.help.as_deref()),
+        )
+    }
+
+    /// Renders the diagnostic with the source lines of every location
+    /// and an underline under the token at each of them
+    /// * source_of - source code of a file path
+    /// * color - use terminal colors
+    pub fn render(&self, source_of: &dyn Fn(&str) -> Option<String>, color: bool) -> String {
+        let paint = |code: &str, text: &str| match color {
+            true => format!("\x1b[{code}m{text}\x1b[0m"),
+            false => text.to_string(),
+        };
+        let (sev_color, mark) = match self.severity {
+            Severity::Error => ("1;91", '^'),
+            Severity::Warning => ("1;93", '^'),
+        };
+        let mut spans = vec![(&self.loc, self.label.as_deref(), mark, sev_color)];
+        for label in self.secondary.iter() {
+            spans.push((&label.loc, Some(label.msg.as_str()), '-', "1;94"));
+        }
+        let width = spans
+            .iter()
+            .map(|s| s.0.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(width);
+        let bar = paint("1;94", "|");
+        let severity = match self.code {
+            Some(code) => format!("{}[{code}]", self.severity),
+            None => self.severity.to_string(),
+        };
+        let mut out = format!(
+            "{}: {}\n{pad}{} {}\n",
+            paint(sev_color, &severity),
+            self.msg,
+            paint("1;94", "-->"),
+            self.loc
+        );
+        let mut last_line = None;
+        for (loc, label, mark, mark_color) in spans {
+            let source = source_of(&loc.file_path);
+            let Some(line) = source.as_ref().and_then(|s| s.lines().nth(loc.line - 1)) else {
+                continue;
+            };
+            if loc.file_path != self.loc.file_path {
+                out.push_str(&format!("{pad}{} {loc}\n", paint("1;94", ":::")));
+            }
+            if last_line != Some((&loc.file_path, loc.line)) {
+                out.push_str(&format!("{pad} {bar}\n"));
+                let num = paint("1;94", &format!("{:>width$}", loc.line));
+                out.push_str(&format!("{num} {bar} {line}\n"));
+                last_line = Some((&loc.file_path, loc.line));
+            }
+            // tabs are kept so the underline lines up with the source
+            let indent: String = line
+                .chars()
+                .take(loc.col - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let marks = mark.to_string().repeat(span_len(line, loc.col));
+            let marks = match label {
+                Some(label) => format!("{marks} {label}"),
+                None => marks,
+            };
+            out.push_str(&format!(
+                "{pad} {bar} {indent}{}\n",
+                paint(mark_color, &marks)
+            ));
+        }
+        for note in self.notes.iter() {
+            out.push_str(&format!("{pad} {} note: {note}\n", paint("1;94", "=")));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{pad} {} help: {help}\n", paint("1;94", "=")));
+        }
+        out
+    }
+}
+
+/// Quoted and escaped json string
+fn json_str(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Length of the token starting at the column of a line
+fn span_len(line: &str, col: usize) -> usize {
+    let rest: Vec<char> = line.chars().skip(col - 1).collect();
+    let ident = |c: &char| c.is_alphanumeric() || *c == '_';
+    match rest.first() {
+        Some(c) if ident(c) => rest.iter().take_while(|c| ident(c)).count(),
+        Some(q @ ('"' | '\'')) => match rest.iter().skip(1).position(|c| c == q) {
+            Some(end) => end + 2,
+            None => rest.len(),
+        },
+        Some(c) if c.is_ascii_punctuation() => rest
+            .iter()
+            .take(2)
+            .take_while(|c| "=:<>!&|+-*/%".contains(**c))
+            .count()
+            .max(1),
+        _ => 1,
+    }
+}
+
+/// eprint the number of reported errors
+/// json output only contains the diagnostics
+pub fn report_error_count(action: &str, errors: usize, format: ErrorFormat) {
+    if format == ErrorFormat::Human {
+        log_error!("{action} Failed due to {errors} previous errors!");
+    }
+}
+
+#[test]
+fn test_diagnostic_render() {
+    let source = "func main() {\n    var x := 1;\n\tx = 2;\n}\n";
+    let source_of = |_: &str| Some(source.to_string());
+    let loc = |line, col| Loc::new("test.nmt".into(), line, col);
+    let diag =
+        CompilationError::ImmutableVariable("x".into(), Some(loc(2, 11))).diagnostic(loc(3, 2));
+    assert_eq!(
+        diag.render(&source_of, false),
+        "error[E0009]: Variable (x) is not mutable
+ --> test.nmt:3:2
+  |
+3 | \tx = 2;
+  | \t^ can not assign to a constant variable
+  |
+2 |     var x := 1;
+  |           -- declared as constant here
+  = help: define it with '=' insted of ':='
+"
+    );
+    let diag = Diagnostic::warning("Unused", loc(1, 6)).with_note("a note");
+    assert_eq!(
+        diag.render(&source_of, false),
+        "warning: Unused
+ --> test.nmt:1:6
+  |
+1 | func main() {
+  |      ^^^^
+  = note: a note
+"
+    );
+}
+
+#[test]
+fn test_diagnostic_json() {
+    let source_of = |_: &str| Some("func main() {\n    print \"a\\n\" + 1;\n}\n".to_string());
+    let diag = Diagnostic::error("Types \"a\"", Loc::new("t.nmt".into(), 2, 11))
+        .with_label("here")
+        .with_secondary(Loc::new("t.nmt".into(), 1, 6), "in main")
+        .with_note("a\tnote");
+    assert_eq!(
+        diag.to_json(&source_of),
+        r#"{"code":null,"severity":"error","message":"Types \"a\"","file":"t.nmt","line":2,"col":11,"end_line":2,"end_col":16,"label":"here","secondary":[{"file":"t.nmt","line":1,"col":6,"end_line":1,"end_col":10,"label":"in main"}],"notes":["a\tnote"],"help":null}"#
+    );
 }
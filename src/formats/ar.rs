@@ -0,0 +1,147 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    linker::{
+        archive::{AR_HEADER_SIZE, AR_MAGIC},
+        object::ObjectFile,
+    },
+    utils::IBytes,
+};
+
+/// Object file stored inside an archive
+/// * symbols - global symbols defined by the object (used for the symbol index)
+#[derive(Debug, Clone)]
+pub struct ArMember {
+    pub name: String,
+    pub data: IBytes,
+    pub symbols: Vec<String>,
+}
+
+impl ArMember {
+    /// Creates a member from an elf object, collecting symbols from its SYMTAB
+    pub fn from_object(name: &str, data: IBytes) -> Result<Self, String> {
+        let obj = ObjectFile::from_bytes(name, &data)?;
+        let symbols = obj
+            .symbols
+            .iter()
+            .filter(|s| s.is_global() && !s.is_undefined() && !s.name.is_empty())
+            .map(|s| s.name.clone())
+            .collect();
+        Ok(Self {
+            name: name.to_string(),
+            data,
+            symbols,
+        })
+    }
+}
+
+fn member_header(name: &str, size: usize, mode: &str) -> IBytes {
+    let header = format!("{name:<16}{:<12}{:<6}{:<6}{mode:<8}{size:<10}`\n", 0, 0, 0);
+    debug_assert_eq!(header.len(), AR_HEADER_SIZE);
+    header.into_bytes()
+}
+
+fn push_member(bytes: &mut IBytes, name: &str, data: &[u8], mode: &str) {
+    bytes.extend(member_header(name, data.len(), mode));
+    bytes.extend(data);
+    if !data.len().is_multiple_of(2) {
+        bytes.push(b'\n');
+    }
+}
+
+fn padded_size(size: usize) -> usize {
+    AR_HEADER_SIZE + size + size % 2
+}
+
+/// Generates GNU ar archive bytes
+/// archive layout: global header, symbol index ("/"), long names ("//"), members
+pub fn archive_bytes(members: &[ArMember]) -> IBytes {
+    // Member names longer than 15 bytes live in the long names table
+    let mut long_names = IBytes::new();
+    let mut names = Vec::new();
+    for member in members.iter() {
+        if member.name.len() < 16 {
+            names.push(format!("{}/", member.name));
+        } else {
+            names.push(format!("/{}", long_names.len()));
+            long_names.extend(format!("{}/\n", member.name).as_bytes());
+        }
+    }
+
+    let sym_count: usize = members.iter().map(|m| m.symbols.len()).sum();
+    let sym_names_size: usize = members
+        .iter()
+        .flat_map(|m| m.symbols.iter())
+        .map(|s| s.len() + 1)
+        .sum();
+    let symtab_size = 4 + sym_count * 4 + sym_names_size;
+
+    let mut offset = AR_MAGIC.len() + padded_size(symtab_size);
+    if !long_names.is_empty() {
+        offset += padded_size(long_names.len());
+    }
+    let mut symtab = IBytes::new();
+    let mut sym_names = IBytes::new();
+    symtab.extend((sym_count as u32).to_be_bytes());
+    for member in members.iter() {
+        for sym in member.symbols.iter() {
+            symtab.extend((offset as u32).to_be_bytes());
+            sym_names.extend(sym.as_bytes());
+            sym_names.push(0);
+        }
+        offset += padded_size(member.data.len());
+    }
+    symtab.extend(sym_names);
+
+    let mut bytes = AR_MAGIC.to_vec();
+    push_member(&mut bytes, "/", &symtab, "0");
+    if !long_names.is_empty() {
+        push_member(&mut bytes, "//", &long_names, "");
+    }
+    for (member, name) in members.iter().zip(names.iter()) {
+        push_member(&mut bytes, name, &member.data, "644");
+    }
+    bytes
+}
+
+/// Writes a static library (.a) containing the given members
+pub fn generate_archive(out_path: &Path, members: &[ArMember]) -> io::Result<()> {
+    let stream = File::create(out_path.with_extension("a"))?;
+    let mut file = BufWriter::new(stream);
+    file.write_all(&archive_bytes(members))?;
+    file.flush()
+}
+
+#[test]
+fn archive_round_trip() {
+    use crate::linker::archive::parse_archive;
+
+    let members = vec![
+        ArMember {
+            name: "short.o".to_string(),
+            data: b"odd".to_vec(),
+            symbols: vec!["add".to_string(), "sub".to_string()],
+        },
+        ArMember {
+            name: "a_very_long_member_name.o".to_string(),
+            data: b"even".to_vec(),
+            symbols: vec!["multi".to_string()],
+        },
+    ];
+    let archive = parse_archive(&archive_bytes(&members)).unwrap();
+    assert_eq!(archive.members.len(), 2);
+    for (parsed, member) in archive.members.iter().zip(members.iter()) {
+        assert_eq!(parsed.name, member.name);
+        assert_eq!(parsed.data, member.data);
+    }
+    let symbols: Vec<(&str, usize)> = archive
+        .symbols
+        .iter()
+        .map(|(s, i)| (s.as_str(), *i))
+        .collect();
+    assert_eq!(symbols, vec![("add", 0), ("sub", 0), ("multi", 1)]);
+}
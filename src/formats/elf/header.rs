@@ -88,6 +88,19 @@ impl ElfHeader {
         }
     }
 
+    /// Header of a static executable with program headers right after the elf header
+    pub fn new_exec(e_entry: u64, e_phnum: u16) -> Self {
+        Self {
+            e_type: EType::Exec,
+            e_entry,
+            e_phoff: 64,
+            e_shoff: 0,
+            e_phensize: 56,
+            e_phnum,
+            ..Self::new(0, 0)
+        }
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Self {
         if bytes[0..4] != [0x7F, 0x45, 0x4C, 0x46] {
             panic!("File is not an valid elf file!");
@@ -1,16 +1,13 @@
 #![allow(dead_code)]
 
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-    path::Path,
-};
+use std::path::Path;
 
 use crate::{
     compiler::{CompilerContext, NSType},
     formats::elf::sections::SectionHeader,
     st_info, st_visibility,
     utils::IBytes,
+    vfs::FileSystem,
 };
 
 pub mod flags;
@@ -35,20 +32,32 @@ pub enum SymbolType {
     Other,
 }
 
-pub fn generate_bin(out_path: &Path, cc: &mut CompilerContext) {
-    let file_content = cc.codegen.text_section_bytes();
-    let stream = File::create(out_path.with_extension("bin")).unwrap();
-    let mut file = BufWriter::new(stream);
-    file.write_all(&file_content).unwrap();
-    file.flush().unwrap();
+pub fn generate_bin(
+    fs: &mut dyn FileSystem,
+    out_path: &Path,
+    cc: &mut CompilerContext,
+) -> Result<(), String> {
+    let file_content = cc.codegen.text_section_bytes()?;
+    fs.write(&out_path.with_extension("bin"), &file_content)
+        .map_err(|e| e.to_string())
+}
+
+pub fn generate_elf(
+    fs: &mut dyn FileSystem,
+    out_path: &Path,
+    cc: &mut CompilerContext,
+) -> Result<(), String> {
+    fs.write(&out_path.with_extension("o"), &elf_object_bytes(cc)?)
+        .map_err(|e| e.to_string())
 }
 
-pub fn generate_elf(out_path: &Path, cc: &mut CompilerContext) {
+/// Relocatable elf object file of the generated code
+pub fn elf_object_bytes(cc: &mut CompilerContext) -> Result<Vec<u8>, String> {
     let mut dyn_sections: Vec<Box<dyn Section>> = vec![Box::new(PROGBITSSec::new(
         ".text",
         0x6,
         16,
-        cc.codegen.text_section_bytes(),
+        cc.codegen.text_section_bytes()?,
     ))];
     if !cc.codegen.data_buf.is_empty() {
         dyn_sections.push(Box::new(PROGBITSSec::new(
@@ -111,13 +120,10 @@ pub fn generate_elf(out_path: &Path, cc: &mut CompilerContext) {
         elf_sections.get_section_header_index(".shstrtab") as u16,
     );
 
-    let stream = File::create(out_path.with_extension("o")).unwrap();
-    let mut file = BufWriter::new(stream);
-    file.write_all(&elf_header.to_bytes()).unwrap();
-    file.write_all(&elf_sections.get_section_header_bytes())
-        .unwrap();
-    file.write_all(&elf_sections.bytes()).unwrap();
-    file.flush().unwrap();
+    let mut bytes = elf_header.to_bytes();
+    bytes.extend(elf_sections.get_section_header_bytes());
+    bytes.extend(elf_sections.bytes());
+    Ok(bytes)
 }
 
 pub struct ElfSections {
@@ -260,7 +266,7 @@ pub fn set_symbols(
         // push symbol name to list
         strtab.insert(label);
 
-        if label == "_start" || sym.1 == SymbolType::Ffi {
+        if label == "_start" || sym.1 == SymbolType::Ffi || cc.codegen.globals.contains(label) {
             continue;
         }
         // push symbol info to sym_list
@@ -302,22 +308,66 @@ pub fn set_symbols(
                     st_value: 0,
                 });
             }
-            NSType::Function(f) => {
-                if f.is_extern {
-                    symtab.insert(SymItem {
-                        st_name: strtab.index(&f.ident).unwrap(),
-                        st_info: st_info!(STB_GLOBAL, STT_FUNC),
-                        st_other: st_visibility!(STV_DEFAULT),
-                        st_shndx: 1,
-                        st_size: 0,
-                        st_value: 0,
-                    });
-                }
+            NSType::Function(f) if f.is_extern => {
+                symtab.insert(SymItem {
+                    st_name: strtab.index(&f.ident).unwrap(),
+                    st_info: st_info!(STB_GLOBAL, STT_FUNC),
+                    st_other: st_visibility!(STV_DEFAULT),
+                    st_shndx: 1,
+                    st_size: 0,
+                    st_value: 0,
+                });
             }
             _ => (),
         }
     }
-    if !cc.is_lib() {
+    // Undefined symbols which are not declared in the program (assembled files)
+    for (label, sym) in cc.codegen.symbols_map.iter() {
+        let declared = cc
+            .namespace_map
+            .values()
+            .any(|item| matches!(item, NSType::Ffi(_, ff) if ff == label));
+        if sym.1 == SymbolType::Ffi && !declared {
+            symtab.insert(SymItem {
+                st_name: strtab.index(label).unwrap(),
+                st_info: st_info!(STB_GLOBAL, STT_NOTYPE),
+                st_other: st_visibility!(STV_DEFAULT),
+                st_shndx: 0,
+                st_size: 0,
+                st_value: 0,
+            });
+        }
+    }
+    let exported = cc.codegen.globals.iter().filter(|label| {
+        let implicit_start = *label == "_start" && !cc.is_lib();
+        !implicit_start && cc.codegen.symbols_map.contains_key(*label)
+    });
+    for label in exported {
+        let (offset, sym_type) = cc.codegen.symbols_map[label];
+        let shndx_tag = match sym_type {
+            SymbolType::DataSec => ".data",
+            SymbolType::BssSec => ".bss",
+            _ => ".text",
+        };
+        symtab.insert(SymItem {
+            st_name: strtab.index(label).unwrap(),
+            st_info: st_info!(STB_GLOBAL, STT_NOTYPE),
+            st_other: st_visibility!(STV_DEFAULT),
+            st_shndx: dyn_sections
+                .iter()
+                .position(|s| s.name() == shndx_tag)
+                .unwrap() as u16
+                + 1,
+            st_size: 0,
+            st_value: offset as u64,
+        });
+    }
+    if let Some((offset, _)) = cc
+        .codegen
+        .symbols_map
+        .get("_start")
+        .filter(|_| !cc.is_lib())
+    {
         symtab.insert(SymItem {
             st_name: strtab.index("_start").unwrap(),
             st_info: st_info!(STB_GLOBAL, STT_NOTYPE),
@@ -328,7 +378,7 @@ pub fn set_symbols(
                 .unwrap() as u16
                 + 1,
             st_size: 0,
-            st_value: 0,
+            st_value: *offset as u64,
         });
     }
     symtab
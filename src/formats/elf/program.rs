@@ -1,5 +1,17 @@
 use crate::utils::IBytes;
 
+/// Loadable program segment
+pub static PT_LOAD: u32 = 1;
+/// Segment is executable
+pub static PF_X: u32 = 0x1;
+/// Segment is writable
+pub static PF_W: u32 = 0x2;
+/// Segment is readable
+pub static PF_R: u32 = 0x4;
+
+/// Size of a single program header entry
+pub static PHDR_SIZE: u64 = 56;
+
 #[derive(Debug, Clone)]
 pub struct ProgramHeader {
     p_type: u32,
@@ -26,6 +38,13 @@ impl ProgramHeader {
         }
     }
 
+    /// Loadable segment that takes more space in memory than in file (e.g .bss)
+    pub fn new_load(offset: u64, flags: u32, addr: u64, filesz: u64, memsz: u64) -> Self {
+        let mut res = Self::new_default(PT_LOAD, offset, flags, addr, filesz);
+        res.p_memsz = memsz;
+        res
+    }
+
     pub fn to_bytes(&self) -> IBytes {
         let mut bytes = vec![];
         bytes.extend(self.p_type.to_le_bytes());
@@ -333,11 +333,9 @@ impl PROGBITSSec {
     }
 
     pub fn dmap_to_data(items: &BTreeMap<String, DataItem>) -> IBytes {
-        let mut data = Vec::new();
-        for item in items.values() {
-            data.extend(item.data.clone());
-        }
-        data
+        let mut items: Vec<&DataItem> = items.values().collect();
+        items.sort_by_key(|item| item.index);
+        items.iter().flat_map(|item| item.data.clone()).collect()
     }
 }
 impl Section for PROGBITSSec {
@@ -441,7 +439,8 @@ impl STRTABSec {
     }
 
     pub fn index(&self, name: &str) -> Option<u32> {
-        let substr = name.as_bytes();
+        // names can be shared with the end of longer names but not their start
+        let substr = [name.as_bytes(), &[0]].concat();
         if substr.len() > self.data.len() {
             return None;
         }
@@ -741,10 +740,10 @@ impl Section for RELASec {
 
 #[derive(Debug, Clone)]
 pub struct RelaItem {
-    r_offset: u64,
+    pub r_offset: u64,
     pub r_section: u32,
-    r_platform: u32,
-    r_addend: i64,
+    pub r_platform: u32,
+    pub r_addend: i64,
     pub sym_name: String,
     pub sym_type: SymbolType,
 }
@@ -769,8 +768,8 @@ impl RelaItem {
     pub fn from_bytes(name: &str, bytes: &[u8]) -> Self {
         Self {
             r_offset: slice_to_u64(&bytes[0..8]),
-            r_section: slice_to_u64(&bytes[8..12]) as u32,
-            r_platform: slice_to_u64(&bytes[12..16]) as u32,
+            r_section: slice_to_u64(&bytes[12..16]) as u32,
+            r_platform: slice_to_u64(&bytes[8..12]) as u32,
             r_addend: slice_to_u64(&bytes[16..24]) as i64,
             sym_name: name.to_string(),
             sym_type: SymbolType::Other,
@@ -1 +1,2 @@
+pub mod ar;
 pub mod elf;
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use crate::{
+    assembler::{
+        asm_parser::parse_asm,
+        instructions::{Opr, Oprs},
+        memory::{MemAddr, MemAddrType},
+        mnemonic::Mnemonic::{self, *},
+        register::Reg::{self, *},
+        Codegen,
+    },
+    memq,
+    parser::{expr::CompareOp, types::VariableType},
+};
+
+use super::{
+    regalloc::{allocate_registers, promote_locals, Allocation, Location},
+    IRCall, IRExpr, IRFunc, IRGenerator, IROperation, IRType, IRVar, IRVarBase,
+};
+
+/// Registers used for passing function arguments
+pub static ARG_REGS: [Reg; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+/// Registers used for passing floating point arguments
+pub static FLOAT_ARG_REGS: [Reg; 8] = [XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7];
+/// Registers used for the syscall number and its arguments
+pub static SYSCALL_REGS: [Reg; 4] = [RAX, RDI, RSI, RDX];
+
+fn align16(value: usize) -> usize {
+    value.div_ceil(16) * 16
+}
+
+/// Lowers a single IR function to x86 instructions
+/// temporary values live in registers or in stack slots right after the variables
+/// followed by the slots of saved callee saved registers
+struct FuncLowering<'a> {
+    codegen: &'a mut Codegen,
+    labels: &'a HashMap<String, String>,
+    func: &'a IRFunc,
+    alloc: Allocation,
+}
+
+impl FuncLowering<'_> {
+    fn slot_mem(&self, slot: usize) -> MemAddr {
+        memq!(RBP, -((self.func.frame_size + 8 * (slot + 1)) as i32))
+    }
+
+    fn temp_opr(&self, temp: usize) -> Option<Opr> {
+        match self.alloc.locations[temp]? {
+            Location::Reg(reg) => Some(reg.into()),
+            Location::Stack(slot) => Some(self.slot_mem(slot).into()),
+        }
+    }
+
+    fn label(&self, tag: &str) -> String {
+        self.labels.get(tag).cloned().unwrap_or(tag.to_string())
+    }
+
+    fn load(&mut self, reg: Reg, expr: &IRExpr) {
+        match expr {
+            IRExpr::Imm(val) => match i32::try_from(*val) {
+                Ok(v) if v >= 0 => self.codegen.instr2(Mov, reg, v),
+                _ => self.codegen.instr2(Mov, reg, *val),
+            },
+            IRExpr::TmpVar(t) => match self.temp_opr(*t) {
+                Some(Opr::R64(r)) if r == reg => (),
+                Some(opr) => self.codegen.instr2(Mov, reg, opr),
+                None => unreachable!("Temporary (%{t}) is used without a location"),
+            },
+            IRExpr::Data(tag) => {
+                let label = self.label(tag);
+                self.codegen.instr2(Mov, reg, Opr::Rela(label));
+            }
+        }
+    }
+
+    fn load_xmm(&mut self, reg: Reg, expr: &IRExpr) {
+        match expr {
+            IRExpr::TmpVar(t) => match self.temp_opr(*t) {
+                Some(opr @ Opr::R64(_)) => self.codegen.instr2(Movq, reg, opr),
+                Some(opr) => self.codegen.instr2(Movsd, reg, opr),
+                None => unreachable!("Temporary (%{t}) is used without a location"),
+            },
+            _ => {
+                self.load(RAX, expr);
+                self.codegen.instr2(Movq, reg, RAX);
+            }
+        }
+    }
+
+    fn store_result(&mut self, dst: Option<usize>, reg: Reg) {
+        if let Some(opr) = dst.and_then(|t| self.temp_opr(t)) {
+            self.codegen.instr2(Mov, opr, reg);
+        }
+    }
+
+    /// Memory operand of a location using RDX for the base and RCX for the index
+    fn mem(&mut self, var: &IRVar, size: u8) -> MemAddr {
+        let base = match &var.base {
+            IRVarBase::Frame => RBP,
+            IRVarBase::Global(tag) if var.offset == 0 && var.index.is_none() => {
+                return MemAddr::new_rela_s(size, self.label(tag));
+            }
+            IRVarBase::Global(tag) => {
+                self.load(RDX, &IRExpr::Data(tag.clone()));
+                RDX
+            }
+            IRVarBase::Ptr(addr) => {
+                self.load(RDX, addr);
+                RDX
+            }
+        };
+        match &var.index {
+            Some((index, scale)) => {
+                self.load(RCX, index);
+                MemAddr::new_sib_s(size, base, var.offset, RCX, *scale)
+            }
+            None => MemAddr::new_disp_s(size, base, var.offset),
+        }
+    }
+
+    fn binary(&mut self, mnem: Mnemonic, a: &IRExpr, b: &IRExpr) {
+        self.load(RAX, a);
+        self.load(RBX, b);
+        match mnem {
+            Idiv => {
+                self.codegen.instr0(Cqo);
+                self.codegen.instr1(Idiv, RBX);
+            }
+            Sal | Sar => {
+                self.codegen.instr2(Mov, RCX, RBX);
+                self.codegen.instr2(mnem, RAX, CL);
+            }
+            _ => self.codegen.instr2(mnem, RAX, RBX),
+        }
+    }
+
+    fn fbinary(&mut self, mnem: Mnemonic, a: &IRExpr, b: &IRExpr) {
+        self.load_xmm(XMM0, a);
+        self.load_xmm(XMM1, b);
+        self.codegen.instr2(mnem, XMM0, XMM1);
+        self.codegen.instr2(Movq, RAX, XMM0);
+    }
+
+    /// Sets RAX to 1 if condition holds (after a compare) and 0 otherwise
+    fn cond_result(&mut self, mnem: Mnemonic) {
+        self.codegen.instr2(mnem, RCX, RDX);
+        self.codegen.instr2(Mov, RAX, RCX);
+    }
+
+    /// Arguments that do not fit in registers are pushed right to left
+    /// stack is kept 16 byte aligned at the call instruction
+    fn call(&mut self, target: Opr, call: &IRCall) {
+        let (reg_args, stack_args) = split_args(call);
+        let stack_size = align16(8 * stack_args.len());
+        if stack_size > 8 * stack_args.len() {
+            self.codegen.instr2(Sub, RSP, 8);
+        }
+        for arg in stack_args.iter().rev() {
+            self.load(RAX, arg);
+            self.codegen.instr1(Push, RAX);
+        }
+        self.load_reg_args(&reg_args);
+        self.codegen.instr1(Call, target);
+        if stack_size > 0 {
+            self.codegen.instr2(Add, RSP, Opr::Imm32(stack_size as i64));
+        }
+        if call.ret == IRType::Float {
+            self.codegen.instr2(Movq, RAX, XMM0);
+        }
+    }
+
+    fn load_reg_args(&mut self, reg_args: &[(Reg, &IRExpr)]) {
+        for (reg, arg) in reg_args.iter() {
+            match reg.is_xmm() {
+                true => self.load_xmm(*reg, arg),
+                false => self.load(*reg, arg),
+            }
+        }
+        // Number of vector registers used by variadic functions
+        let floats = reg_args.iter().filter(|(reg, _)| reg.is_xmm()).count();
+        self.codegen.instr2(Mov, RAX, floats as i32);
+    }
+
+    /// Jumps to the function after releasing the stack frame
+    /// so the callee returns directly to the caller of this function
+    /// falls back to a call if addresses inside the frame may be passed to the callee
+    fn tail_call(&mut self, call: &IRCall) {
+        let target = Opr::Loc(call.name.clone());
+        let (reg_args, stack_args) = split_args(call);
+        if !stack_args.is_empty() || self.frame_escapes() {
+            self.call(target, call);
+            if self.func.ret == IRType::Float {
+                self.codegen.instr2(Movq, XMM0, RAX);
+            }
+            self.epilogue();
+            self.codegen.instr0(Ret);
+            return;
+        }
+        self.load_reg_args(&reg_args);
+        self.epilogue();
+        self.codegen.instr1(Jmp, target);
+    }
+
+    /// Inline asm is validated by the compiler so only the static data
+    /// referenced by it has to be renamed to its codegen label
+    fn inline_asm(&mut self, instr: &str) -> Result<(), String> {
+        if let Some(label) = instr.strip_suffix(':') {
+            self.codegen.set_lable(label);
+            return Ok(());
+        }
+        let mut instr = match parse_asm(instr) {
+            Ok(instr) => instr,
+            Err(e) => return Err(format!("Invalid inline asm ({instr}): {e}")),
+        };
+        let rename = |opr: &mut Opr| match opr {
+            Opr::Rela(tag) => *tag = self.label(tag),
+            Opr::Mem(MemAddr {
+                addr_type: MemAddrType::AddrRela(tag),
+                ..
+            }) => *tag = self.label(tag),
+            _ => (),
+        };
+        match &mut instr.oprs {
+            Oprs::None => (),
+            Oprs::One(opr) => rename(opr),
+            Oprs::Two(a, b) => {
+                rename(a);
+                rename(b);
+            }
+        }
+        self.codegen.new_instr(instr);
+        Ok(())
+    }
+
+    fn frame_escapes(&self) -> bool {
+        self.func.instrs.iter().any(|s| match &s.opr {
+            IROperation::Lea(var) => var.base == IRVarBase::Frame,
+            IROperation::Asm(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Restores callee saved registers and releases the stack frame
+    fn epilogue(&mut self) {
+        for (i, reg) in self.alloc.saved_regs.clone().into_iter().enumerate() {
+            let mem = self.slot_mem(self.alloc.stack_slots + i);
+            self.codegen.instr2(Mov, reg, mem);
+        }
+        self.codegen.instr0(Leave);
+    }
+
+    fn prologue(&mut self) {
+        self.codegen.set_lable(&self.func.name);
+        // there is no return address on the stack at the program entry
+        // so it is already 16 byte aligned
+        if self.func.name != "_start" {
+            self.codegen.instr1(Push, RBP);
+        }
+        self.codegen.instr2(Mov, RBP, RSP);
+        let slots = self.alloc.stack_slots + self.alloc.saved_regs.len();
+        let frame = align16(self.func.frame_size + 8 * slots);
+        if frame > 0 {
+            self.codegen.instr2(Sub, RSP, Opr::Imm32(frame as i64));
+        }
+        for (i, reg) in self.alloc.saved_regs.clone().into_iter().enumerate() {
+            let mem = self.slot_mem(self.alloc.stack_slots + i);
+            self.codegen.instr2(Mov, mem, reg);
+        }
+    }
+
+    fn lower(&mut self) -> Result<(), String> {
+        self.prologue();
+        for stmt in self.func.instrs.iter() {
+            let dst = stmt.dst;
+            match &stmt.opr {
+                IROperation::Add(a, b) => self.binary(Add, a, b),
+                IROperation::Sub(a, b) => self.binary(Sub, a, b),
+                IROperation::Mult(a, b) => self.binary(Imul, a, b),
+                IROperation::Divide(a, b) => self.binary(Idiv, a, b),
+                IROperation::Mod(a, b) => {
+                    self.binary(Idiv, a, b);
+                    self.codegen.instr2(Mov, RAX, RDX);
+                }
+                IROperation::Or(a, b) => self.binary(Or, a, b),
+                IROperation::And(a, b) => self.binary(And, a, b),
+                IROperation::Sal(a, b) => self.binary(Sal, a, b),
+                IROperation::Sar(a, b) => self.binary(Sar, a, b),
+                IROperation::Cmp(op, a, b) => {
+                    self.load(RAX, a);
+                    self.load(RBX, b);
+                    self.codegen.instr2(Mov, RCX, 0);
+                    self.codegen.instr2(Mov, RDX, 1);
+                    self.codegen.instr2(Cmp, RAX, RBX);
+                    let mnem = match op {
+                        CompareOp::Eq => Cmove,
+                        CompareOp::NotEq => Cmovne,
+                        CompareOp::Bigger => Cmovg,
+                        CompareOp::Smaller => Cmovl,
+                        CompareOp::BiggerEq => Cmovge,
+                        CompareOp::SmallerEq => Cmovle,
+                    };
+                    self.cond_result(mnem);
+                }
+                IROperation::FAdd(a, b) => self.fbinary(Addsd, a, b),
+                IROperation::FSub(a, b) => self.fbinary(Subsd, a, b),
+                IROperation::FMult(a, b) => self.fbinary(Mulsd, a, b),
+                IROperation::FDivide(a, b) => self.fbinary(Divsd, a, b),
+                IROperation::FCmp(op, a, b) => {
+                    self.load_xmm(XMM0, a);
+                    self.load_xmm(XMM1, b);
+                    self.codegen.instr2(Mov, RCX, 0);
+                    self.codegen.instr2(Mov, RDX, 1);
+                    self.codegen.instr2(Ucomisd, XMM0, XMM1);
+                    let mnem = match op {
+                        CompareOp::Eq => Cmove,
+                        CompareOp::NotEq => Cmovne,
+                        CompareOp::Bigger => Cmova,
+                        CompareOp::Smaller => Cmovb,
+                        CompareOp::BiggerEq => Cmovae,
+                        CompareOp::SmallerEq => Cmovbe,
+                    };
+                    self.cond_result(mnem);
+                }
+                IROperation::IntToFloat(a) => {
+                    self.load(RAX, a);
+                    self.codegen.instr2(Cvtsi2sd, XMM0, RAX);
+                    self.codegen.instr2(Movq, RAX, XMM0);
+                }
+                IROperation::FloatToInt(a) => {
+                    self.load_xmm(XMM0, a);
+                    self.codegen.instr2(Cvttsd2si, RAX, XMM0);
+                }
+                IROperation::Neg(a) => {
+                    self.load(RAX, a);
+                    self.codegen.instr1(Neg, RAX);
+                }
+                IROperation::Not(a) => {
+                    self.load(RAX, a);
+                    self.codegen.instr1(Not, RAX);
+                }
+                IROperation::Mov(a) => self.load(RAX, a),
+                IROperation::Load(var) => {
+                    let mem = self.mem(var, var.size);
+                    match var.size {
+                        1 | 2 => {
+                            self.codegen.instr2(Mov, RAX, 0);
+                            self.codegen.instr2(Mov, RAX.convert(var.size), mem);
+                        }
+                        _ => self.codegen.instr2(Mov, RAX.convert(var.size), mem),
+                    }
+                }
+                IROperation::Lea(var) => match &var.base {
+                    IRVarBase::Global(tag) if var.offset == 0 && var.index.is_none() => {
+                        self.load(RAX, &IRExpr::Data(tag.clone()));
+                    }
+                    _ => {
+                        let mem = self.mem(var, 0);
+                        self.codegen.instr2(Lea, RAX, mem);
+                    }
+                },
+                IROperation::Store(var, val) => {
+                    self.load(RAX, val);
+                    let mem = self.mem(var, var.size);
+                    self.codegen.instr2(Mov, mem, RAX.convert(var.size));
+                }
+                IROperation::Arg(n) => self.codegen.instr2(Mov, RAX, ARG_REGS[*n]),
+                IROperation::FArg(n) => self.codegen.instr2(Movq, RAX, FLOAT_ARG_REGS[*n]),
+                IROperation::StackArg(n) => {
+                    // skipping saved rbp and the return address
+                    let mem = memq!(RBP, 16 + 8 * *n as i32);
+                    self.codegen.instr2(Mov, RAX, mem);
+                }
+                IROperation::Call(call) => self.call(Opr::Loc(call.name.clone()), call),
+                IROperation::Ffi(call) => self.call(Opr::Rela(call.name.clone()), call),
+                IROperation::Syscall(args) => {
+                    for (arg, reg) in args.iter().zip(SYSCALL_REGS.iter()) {
+                        self.load(*reg, arg);
+                    }
+                    self.codegen.instr0(Syscall);
+                }
+                IROperation::Label(tag) => self.codegen.set_lable(&tag.tag),
+                IROperation::Jmp(tag) => self.codegen.instr1(Jmp, Opr::Loc(tag.tag.clone())),
+                IROperation::Jz(val, tag) | IROperation::Jnz(val, tag) => {
+                    self.load(RAX, val);
+                    self.codegen.instr2(Test, RAX, RAX);
+                    let mnem = match stmt.opr {
+                        IROperation::Jz(..) => Jz,
+                        _ => Jne,
+                    };
+                    self.codegen.instr1(mnem, Opr::Loc(tag.tag.clone()));
+                }
+                IROperation::Ret(val) => {
+                    if let Some(val) = val {
+                        self.load(RAX, val);
+                        if self.func.ret == IRType::Float {
+                            self.codegen.instr2(Movq, XMM0, RAX);
+                        }
+                    }
+                    self.epilogue();
+                    self.codegen.instr0(Ret);
+                }
+                IROperation::TailCall(call) => self.tail_call(call),
+                IROperation::Asm(instr) => self.inline_asm(instr)?,
+            }
+            self.store_result(dst, RAX);
+        }
+        Ok(())
+    }
+}
+
+/// Registers of the arguments passed in registers and the arguments passed on the stack
+fn split_args(call: &IRCall) -> (Vec<(Reg, &IRExpr)>, Vec<&IRExpr>) {
+    let (mut ints, mut floats) = (0, 0);
+    let mut reg_args = Vec::new();
+    let mut stack_args = Vec::new();
+    for (arg, ty) in call.args.iter() {
+        match ty {
+            IRType::Int if ints < ARG_REGS.len() => {
+                reg_args.push((ARG_REGS[ints], arg));
+                ints += 1;
+            }
+            IRType::Float if floats < FLOAT_ARG_REGS.len() => {
+                reg_args.push((FLOAT_ARG_REGS[floats], arg));
+                floats += 1;
+            }
+            _ => stack_args.push(arg),
+        }
+    }
+    (reg_args, stack_args)
+}
+
+/// Generates x86 instructions and static data from IR
+pub fn generate_codegen(ir: &IRGenerator, codegen: &mut Codegen) -> Result<(), String> {
+    let mut labels = HashMap::new();
+    for item in ir.data.iter() {
+        let label = match &item.data {
+            Some(data) => codegen.add_data(data.clone(), VariableType::String),
+            None => codegen.add_bss_seg(item.size),
+        };
+        labels.insert(item.tag.clone(), label);
+    }
+    for func in ir.code.iter() {
+        let mut func = func.clone();
+        promote_locals(&mut func);
+        let alloc = allocate_registers(&func);
+        FuncLowering {
+            codegen,
+            labels: &labels,
+            func: &func,
+            alloc,
+        }
+        .lower()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_invalid_inline_asm() {
+    let ir =
+        super::text::parse_ir("func main frame=0 temps=0\n    asm \"movx rax, 1\"\n    ret\nend\n")
+            .unwrap();
+    let error = generate_codegen(&ir, &mut Codegen::new()).unwrap_err();
+    assert!(
+        error.starts_with("Invalid inline asm (movx rax, 1)"),
+        "{error}"
+    );
+}
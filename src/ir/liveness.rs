@@ -0,0 +1,197 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{IRFunc, IROperation};
+
+/// Straight line statements [start, end) of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+    pub succs: Vec<usize>,
+}
+
+/// Splits a function into basic blocks
+/// blocks start at labels and after jumps, returns or tail calls
+pub fn basic_blocks(func: &IRFunc) -> Vec<BasicBlock> {
+    let mut leaders = BTreeSet::new();
+    for (i, stmt) in func.instrs.iter().enumerate() {
+        match stmt.opr {
+            IROperation::Label(_) => {
+                leaders.insert(i);
+            }
+            IROperation::Jmp(_) | IROperation::Jz(..) | IROperation::Jnz(..) => {
+                leaders.insert(i + 1);
+            }
+            IROperation::Ret(_) | IROperation::TailCall(_) => {
+                leaders.insert(i + 1);
+            }
+            _ => (),
+        }
+    }
+    leaders.insert(0);
+    leaders.retain(|l| *l < func.instrs.len());
+    let starts: Vec<usize> = leaders.into_iter().collect();
+    let mut labels = HashMap::new();
+    for (b, start) in starts.iter().enumerate() {
+        if let IROperation::Label(tag) = &func.instrs[*start].opr {
+            labels.insert(tag.tag.clone(), b);
+        }
+    }
+    let mut blocks = Vec::new();
+    for (b, start) in starts.iter().enumerate() {
+        let end = starts.get(b + 1).copied().unwrap_or(func.instrs.len());
+        let next = (b + 1 < starts.len()).then_some(b + 1);
+        let target = |tag: &str| labels.get(tag).copied();
+        let succs = match &func.instrs[end - 1].opr {
+            IROperation::Jmp(tag) => target(&tag.tag).into_iter().collect(),
+            IROperation::Jz(_, tag) | IROperation::Jnz(_, tag) => {
+                target(&tag.tag).into_iter().chain(next).collect()
+            }
+            IROperation::Ret(_) | IROperation::TailCall(_) => vec![],
+            _ => next.into_iter().collect(),
+        };
+        blocks.push(BasicBlock {
+            start: *start,
+            end,
+            succs,
+        });
+    }
+    blocks
+}
+
+/// Temporaries live at the entry of each block
+pub fn live_in_sets(func: &IRFunc, blocks: &[BasicBlock]) -> Vec<BTreeSet<usize>> {
+    let mut uses = vec![BTreeSet::new(); blocks.len()];
+    let mut defs = vec![BTreeSet::new(); blocks.len()];
+    for (b, block) in blocks.iter().enumerate() {
+        for stmt in func.instrs[block.start..block.end].iter() {
+            for t in stmt.opr.used_temps() {
+                if !defs[b].contains(&t) {
+                    uses[b].insert(t);
+                }
+            }
+            if let Some(dst) = stmt.dst {
+                defs[b].insert(dst);
+            }
+        }
+    }
+    let mut live_in = uses.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (b, block) in blocks.iter().enumerate().rev() {
+            let mut live: BTreeSet<usize> = BTreeSet::new();
+            for s in block.succs.iter() {
+                live.extend(live_in[*s].iter());
+            }
+            live.retain(|t| !defs[b].contains(t));
+            live.extend(uses[b].iter());
+            if live != live_in[b] {
+                live_in[b] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// Range of statements where a temporary holds a value that may be used later
+/// the range includes the definitions and all uses of the temporary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiveInterval {
+    pub temp: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Computes the live interval of every temporary used in the function
+/// intervals are sorted by their start
+pub fn live_intervals(func: &IRFunc) -> Vec<LiveInterval> {
+    let blocks = basic_blocks(func);
+    let live_in = live_in_sets(func, &blocks);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; func.temp_vars];
+    let mut extend = |t: usize, i: usize| {
+        ranges[t] = match ranges[t] {
+            Some((s, e)) => Some((s.min(i), e.max(i))),
+            None => Some((i, i)),
+        }
+    };
+    for block in blocks.iter() {
+        let mut live: BTreeSet<usize> = BTreeSet::new();
+        for s in block.succs.iter() {
+            live.extend(live_in[*s].iter());
+        }
+        for t in live.iter() {
+            extend(*t, block.end - 1);
+        }
+        for i in (block.start..block.end).rev() {
+            let stmt = &func.instrs[i];
+            if let Some(dst) = stmt.dst {
+                extend(dst, i);
+                live.remove(&dst);
+            }
+            for t in stmt.opr.used_temps() {
+                extend(t, i);
+                live.insert(t);
+            }
+        }
+        for t in live.iter() {
+            extend(*t, block.start);
+        }
+    }
+    let mut intervals: Vec<LiveInterval> = ranges
+        .into_iter()
+        .enumerate()
+        .filter_map(|(temp, range)| range.map(|(start, end)| LiveInterval { temp, start, end }))
+        .collect();
+    intervals.sort_by_key(|i| (i.start, i.end));
+    intervals
+}
+
+#[test]
+fn test_live_intervals() {
+    use super::text::parse_ir;
+    let source = "
+func main frame=0 temps=4
+    %0 = mov 0
+    %1 = mov 10
+main.loop:
+    %2 = cmp.lt %0, %1
+    jz %2, main.end
+    %3 = call print(%0)
+    %0 = add %0, 1
+    jmp main.loop
+main.end:
+    ret
+end
+";
+    let ir = parse_ir(source).unwrap();
+    let func = &ir.code[0];
+    let blocks = basic_blocks(func);
+    assert_eq!(blocks.len(), 4);
+    assert_eq!(blocks[1].succs, vec![3, 2]);
+    assert_eq!(blocks[2].succs, vec![1]);
+    let intervals = live_intervals(func);
+    // the counter and the limit are live through the whole loop
+    assert!(intervals.contains(&LiveInterval {
+        temp: 0,
+        start: 0,
+        end: 7
+    }));
+    assert!(intervals.contains(&LiveInterval {
+        temp: 1,
+        start: 1,
+        end: 7
+    }));
+    assert!(intervals.contains(&LiveInterval {
+        temp: 2,
+        start: 3,
+        end: 4
+    }));
+    // unused results only live at their definition
+    assert!(intervals.contains(&LiveInterval {
+        temp: 3,
+        start: 5,
+        end: 5
+    }));
+}
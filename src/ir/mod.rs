@@ -1,233 +1,542 @@
-use crate::parser::types::VariableType;
+pub mod codegen;
+pub mod liveness;
+pub mod regalloc;
+pub mod text;
 
-#[derive(Debug, Clone)]
+use crate::parser::expr::CompareOp;
+
+/// Operand of IR operations
+#[derive(Debug, Clone, PartialEq)]
 pub enum IRExpr {
-    Var(VariableType),
-    Imm(u64),
+    /// Immediate value
+    Imm(i64),
+    /// Function local temporary value
     TmpVar(usize),
+    /// Address of a static data or bss segment
+    Data(String),
 }
+
 impl IRExpr {
     pub fn is_imm(&self) -> bool {
         matches!(self, Self::Imm(_))
     }
 }
 
-#[derive(Debug, Clone)]
+/// Base address of a memory location
+#[derive(Debug, Clone, PartialEq)]
+pub enum IRVarBase {
+    /// Stack frame of the current function
+    Frame,
+    /// Static data or bss segment
+    Global(String),
+    /// Address held by a value
+    Ptr(IRExpr),
+}
+
+/// Sized memory location (base + offset + index * scale)
+#[derive(Debug, Clone, PartialEq)]
+pub struct IRVar {
+    pub base: IRVarBase,
+    pub offset: i32,
+    pub index: Option<(IRExpr, u8)>,
+    pub size: u8,
+}
+
+impl IRVar {
+    pub fn frame(offset: i32, size: u8) -> Self {
+        Self {
+            base: IRVarBase::Frame,
+            offset,
+            index: None,
+            size,
+        }
+    }
+
+    pub fn global(tag: impl ToString, size: u8) -> Self {
+        Self {
+            base: IRVarBase::Global(tag.to_string()),
+            offset: 0,
+            index: None,
+            size,
+        }
+    }
+
+    pub fn ptr(addr: IRExpr, offset: i32, size: u8) -> Self {
+        Self {
+            base: IRVarBase::Ptr(addr),
+            offset,
+            index: None,
+            size,
+        }
+    }
+
+    /// Adds an index to the location
+    /// immediate indexes are folded into the offset except for arrays on the stack frame
+    /// so their items are never mistaken for scalar variables
+    pub fn indexed(mut self, index: IRExpr, scale: u8) -> Self {
+        match index {
+            IRExpr::Imm(i) if self.base != IRVarBase::Frame => {
+                self.offset += i as i32 * scale as i32
+            }
+            _ => self.index = Some((index, scale)),
+        }
+        self
+    }
+
+    /// Values used to compute the address
+    pub fn operands(&self) -> Vec<&IRExpr> {
+        let mut oprs = Vec::new();
+        if let IRVarBase::Ptr(addr) = &self.base {
+            oprs.push(addr);
+        }
+        if let Some((index, _)) = &self.index {
+            oprs.push(index);
+        }
+        oprs
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut IRExpr> {
+        let mut oprs = Vec::new();
+        if let IRVarBase::Ptr(addr) = &mut self.base {
+            oprs.push(addr);
+        }
+        if let Some((index, _)) = &mut self.index {
+            oprs.push(index);
+        }
+        oprs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct IRTag {
-    tag: String,
+    pub tag: String,
 }
 
-#[derive(Debug)]
-pub struct IRFunc {
-    instrs: Vec<IRStmt>,
+impl From<String> for IRTag {
+    fn from(tag: String) -> Self {
+        Self { tag }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Register class used for passing a value to or from a function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IRType {
+    Int,
+    Float,
+}
+
+/// Function call
+/// floating point arguments and results are passed in xmm registers
+#[derive(Debug, Clone, PartialEq)]
+pub struct IRCall {
+    pub name: String,
+    pub args: Vec<(IRExpr, IRType)>,
+    pub ret: IRType,
+}
+
+impl IRCall {
+    pub fn new(name: impl ToString, args: Vec<(IRExpr, IRType)>, ret: IRType) -> Self {
+        Self {
+            name: name.to_string(),
+            args,
+            ret,
+        }
+    }
+
+    /// Returns true if every argument is passed in a register
+    pub fn args_in_regs(&self) -> bool {
+        let count = |ty: IRType| self.args.iter().filter(|(_, t)| *t == ty).count();
+        count(IRType::Int) <= codegen::ARG_REGS.len()
+            && count(IRType::Float) <= codegen::FLOAT_ARG_REGS.len()
+    }
+}
+
+/// Floating point values are kept as their 64bit IEEE 754 bit pattern
+#[derive(Debug, Clone, PartialEq)]
 pub enum IROperation {
     Add(IRExpr, IRExpr),
     Sub(IRExpr, IRExpr),
     Mult(IRExpr, IRExpr),
     Divide(IRExpr, IRExpr),
     Mod(IRExpr, IRExpr),
-    Store(IRExpr),
-    Mov(IRExpr, IRExpr),
-    Cmove(IRExpr, IRExpr),
-    Cmovne(IRExpr, IRExpr),
-    Cmovg(IRExpr, IRExpr),
-    Cmovl(IRExpr, IRExpr),
-    Cmovge(IRExpr, IRExpr),
-    Cmovle(IRExpr, IRExpr),
     Or(IRExpr, IRExpr),
     And(IRExpr, IRExpr),
     Sal(IRExpr, IRExpr),
     Sar(IRExpr, IRExpr),
-    Shr(IRExpr, IRExpr),
-    Cmp(IRExpr, IRExpr),
+    /// Results 1 if comparison holds and 0 otherwise
+    Cmp(CompareOp, IRExpr, IRExpr),
     Neg(IRExpr),
     Not(IRExpr),
-    Call(IRExpr),
-    Jmp(IRTag),
-    Jz(IRTag),
-    Jnz(IRTag),
-    Jne(IRTag),
+    FAdd(IRExpr, IRExpr),
+    FSub(IRExpr, IRExpr),
+    FMult(IRExpr, IRExpr),
+    FDivide(IRExpr, IRExpr),
+    /// Floating point comparison
+    FCmp(CompareOp, IRExpr, IRExpr),
+    IntToFloat(IRExpr),
+    /// Truncates floating point value to an integer
+    FloatToInt(IRExpr),
+    /// Copy a value
+    Mov(IRExpr),
+    /// Read a memory location (zero extended)
+    Load(IRVar),
+    /// Address of a memory location
+    Lea(IRVar),
+    Store(IRVar, IRExpr),
+    /// Nth integer argument of the current function
+    Arg(usize),
+    /// Nth floating point argument of the current function
+    FArg(usize),
+    /// Nth argument of the current function passed on the stack
+    StackArg(usize),
+    Call(IRCall),
+    /// Call to an external function
+    Ffi(IRCall),
+    /// Call that replaces the return of the current function (tail call)
+    TailCall(IRCall),
+    /// Syscall number followed by its arguments
     Syscall(Vec<IRExpr>),
-    Inc(IRExpr),
+    Label(IRTag),
+    Jmp(IRTag),
+    /// Jump if value is zero
+    Jz(IRExpr, IRTag),
+    /// Jump if value is not zero
+    Jnz(IRExpr, IRTag),
+    Ret(Option<IRExpr>),
+    /// Raw assembly instruction
+    Asm(String),
 }
 
-#[derive(Debug)]
-pub struct IRGenerator {
-    code: Vec<IRFunc>,
-    data: Vec<IRData>,
+impl IROperation {
+    /// Returns true if operation results a value
+    pub fn has_result(&self) -> bool {
+        !matches!(
+            self,
+            Self::Store(..)
+                | Self::Label(_)
+                | Self::Jmp(_)
+                | Self::Jz(..)
+                | Self::Jnz(..)
+                | Self::Ret(_)
+                | Self::TailCall(_)
+                | Self::Asm(_)
+        )
+    }
+
+    /// Returns true if operation may clobber caller saved registers
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Self::Call(_) | Self::Ffi(_) | Self::TailCall(_) | Self::Syscall(_) | Self::Asm(_)
+        )
+    }
+
+    /// Returns true if operation does more than computing its result
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            Self::Store(..)
+                | Self::Call(_)
+                | Self::Ffi(_)
+                | Self::TailCall(_)
+                | Self::Syscall(_)
+                | Self::Label(_)
+                | Self::Jmp(_)
+                | Self::Jz(..)
+                | Self::Jnz(..)
+                | Self::Ret(_)
+                | Self::Asm(_)
+        )
+    }
+
+    /// All values read by the operation
+    pub fn operands(&self) -> Vec<&IRExpr> {
+        use IROperation::*;
+        match self {
+            Add(a, b)
+            | Sub(a, b)
+            | Mult(a, b)
+            | Divide(a, b)
+            | Mod(a, b)
+            | Or(a, b)
+            | And(a, b)
+            | Sal(a, b)
+            | Sar(a, b)
+            | Cmp(_, a, b)
+            | FAdd(a, b)
+            | FSub(a, b)
+            | FMult(a, b)
+            | FDivide(a, b)
+            | FCmp(_, a, b) => vec![a, b],
+            Neg(a)
+            | Not(a)
+            | IntToFloat(a)
+            | FloatToInt(a)
+            | Mov(a)
+            | Jz(a, _)
+            | Jnz(a, _)
+            | Ret(Some(a)) => vec![a],
+            Load(var) | Lea(var) => var.operands(),
+            Store(var, val) => {
+                let mut oprs = var.operands();
+                oprs.push(val);
+                oprs
+            }
+            Call(call) | Ffi(call) | TailCall(call) => call.args.iter().map(|(a, _)| a).collect(),
+            Syscall(args) => args.iter().collect(),
+            Arg(_) | FArg(_) | StackArg(_) | Label(_) | Jmp(_) | Ret(None) | Asm(_) => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut IRExpr> {
+        use IROperation::*;
+        match self {
+            Add(a, b)
+            | Sub(a, b)
+            | Mult(a, b)
+            | Divide(a, b)
+            | Mod(a, b)
+            | Or(a, b)
+            | And(a, b)
+            | Sal(a, b)
+            | Sar(a, b)
+            | Cmp(_, a, b)
+            | FAdd(a, b)
+            | FSub(a, b)
+            | FMult(a, b)
+            | FDivide(a, b)
+            | FCmp(_, a, b) => vec![a, b],
+            Neg(a)
+            | Not(a)
+            | IntToFloat(a)
+            | FloatToInt(a)
+            | Mov(a)
+            | Jz(a, _)
+            | Jnz(a, _)
+            | Ret(Some(a)) => vec![a],
+            Load(var) | Lea(var) => var.operands_mut(),
+            Store(var, val) => {
+                let mut oprs = var.operands_mut();
+                oprs.push(val);
+                oprs
+            }
+            Call(call) | Ffi(call) | TailCall(call) => {
+                call.args.iter_mut().map(|(a, _)| a).collect()
+            }
+            Syscall(args) => args.iter_mut().collect(),
+            Arg(_) | FArg(_) | StackArg(_) | Label(_) | Jmp(_) | Ret(None) | Asm(_) => vec![],
+        }
+    }
+
+    /// Temporaries read by the operation
+    pub fn used_temps(&self) -> Vec<usize> {
+        self.operands()
+            .into_iter()
+            .filter_map(|e| match e {
+                IRExpr::TmpVar(t) => Some(*t),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Evaluates operation at compile time if all operands are immediate
+    pub fn fold(&self) -> Option<i64> {
+        use IROperation::*;
+        match self {
+            Add(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a.wrapping_add(*b)),
+            Sub(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a.wrapping_sub(*b)),
+            Mult(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a.wrapping_mul(*b)),
+            Divide(IRExpr::Imm(a), IRExpr::Imm(b)) => a.checked_div(*b),
+            Mod(IRExpr::Imm(a), IRExpr::Imm(b)) => a.checked_rem(*b),
+            Or(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a | b),
+            And(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a & b),
+            Sal(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a.wrapping_shl(*b as u32)),
+            Sar(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(a.wrapping_shr(*b as u32)),
+            Cmp(op, IRExpr::Imm(a), IRExpr::Imm(b)) => Some(match op {
+                CompareOp::Eq => a == b,
+                CompareOp::NotEq => a != b,
+                CompareOp::Bigger => a > b,
+                CompareOp::Smaller => a < b,
+                CompareOp::BiggerEq => a >= b,
+                CompareOp::SmallerEq => a <= b,
+            } as i64),
+            Neg(IRExpr::Imm(a)) => Some(a.wrapping_neg()),
+            Not(IRExpr::Imm(a)) => Some(!a),
+            Mov(IRExpr::Imm(a)) => Some(*a),
+            FAdd(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(float_op(*a, *b, |a, b| a + b)),
+            FSub(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(float_op(*a, *b, |a, b| a - b)),
+            FMult(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(float_op(*a, *b, |a, b| a * b)),
+            FDivide(IRExpr::Imm(a), IRExpr::Imm(b)) => Some(float_op(*a, *b, |a, b| a / b)),
+            FCmp(op, IRExpr::Imm(a), IRExpr::Imm(b)) => {
+                let (a, b) = (as_float(*a), as_float(*b));
+                Some(match op {
+                    CompareOp::Eq => a == b,
+                    CompareOp::NotEq => a != b,
+                    CompareOp::Bigger => a > b,
+                    CompareOp::Smaller => a < b,
+                    CompareOp::BiggerEq => a >= b,
+                    CompareOp::SmallerEq => a <= b,
+                } as i64)
+            }
+            IntToFloat(IRExpr::Imm(a)) => Some(from_float(*a as f64)),
+            FloatToInt(IRExpr::Imm(a)) => Some(as_float(*a) as i64),
+            _ => None,
+        }
+    }
+}
+
+/// Floating point value of an immediate
+pub fn as_float(value: i64) -> f64 {
+    f64::from_bits(value as u64)
+}
+
+/// Immediate holding a floating point value
+pub fn from_float(value: f64) -> i64 {
+    value.to_bits() as i64
 }
 
-#[derive(Debug)]
+fn float_op(a: i64, b: i64, op: fn(f64, f64) -> f64) -> i64 {
+    from_float(op(as_float(a), as_float(b)))
+}
+
+/// Three address statement (dst = opr)
+#[derive(Debug, Clone, PartialEq)]
 pub struct IRStmt {
-    operation: Vec<IROperation>,
-    temp_vars: Vec<IRExpr>,
+    pub dst: Option<usize>,
+    pub opr: IROperation,
 }
-impl IRStmt {
-    pub fn new() -> Self {
+
+/// Whether calls to a function may be replaced with its body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IRInline {
+    /// Decided by the size of the function
+    Auto,
+    Always,
+    Never,
+}
+
+/// Function in IR form
+/// * frame_size - size of stack memory used by variables
+/// * temp_vars - number of temporary values
+/// * ret - register class of the returned value
+/// * inline - inlining preference of the function
+#[derive(Debug, Clone)]
+pub struct IRFunc {
+    pub name: String,
+    pub instrs: Vec<IRStmt>,
+    pub temp_vars: usize,
+    pub frame_size: usize,
+    pub ret: IRType,
+    pub inline: IRInline,
+}
+
+impl IRFunc {
+    pub fn new(name: impl ToString) -> Self {
         Self {
-            operation: vec![],
-            temp_vars: vec![]
+            name: name.to_string(),
+            instrs: vec![],
+            temp_vars: 0,
+            frame_size: 0,
+            ret: IRType::Int,
+            inline: IRInline::Auto,
         }
     }
 
+    pub fn new_temp(&mut self) -> usize {
+        self.temp_vars += 1;
+        self.temp_vars - 1
+    }
+
+    /// Appends an operation and returns its result
+    /// operations on immediate values are folded
     pub fn inst(&mut self, opr: IROperation) -> Option<IRExpr> {
-        match &opr {
-            IROperation::Add(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a + b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }                
-            },
-            IROperation::Sub(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a - b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Mult(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a * b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Divide(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a / b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Mod(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a % b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Store(_) => {
-                self.operation.push(opr);
-                Some(IRExpr::TmpVar(self.operation.len() - 1))
-            },
-            IROperation::Mov(ire1, _) 
-                | IROperation::Cmove(ire1, _)
-                | IROperation::Cmovne(ire1, _)
-                | IROperation::Cmovg(ire1, _)
-                | IROperation::Cmovl(ire1, _)
-                | IROperation::Cmovge(ire1, _)
-                | IROperation::Cmovle(ire1, _) => {
-                self.operation.push(opr.clone());
-                Some(ire1.clone())
-            },
-            IROperation::Or(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a | b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::And(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a & b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Sal(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a << b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Sar(ire1, ire2) | IROperation::Shr(ire1, ire2) => {
-                match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => Some(IRExpr::Imm(a >> b)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Cmp(ire1, ire2) => {
-                 match (ire1, ire2) {
-                    (IRExpr::Imm(a) , IRExpr::Imm(b)) => {
-                        if a == b {
-                            Some(IRExpr::Imm(1))
-                        } else {
-                            Some(IRExpr::Imm(0))
-                        }
-                    },
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                 }
-            },
-            IROperation::Neg(ire1) => {
-                match ire1 {
-                    IRExpr::Imm(a) => Some(IRExpr::Imm((-(*a as i64)) as u64)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Not(ire1) => {
-                match ire1 {
-                    IRExpr::Imm(a) => Some(IRExpr::Imm(!a)),
-                    _ => {
-                        self.operation.push(opr);
-                        Some(IRExpr::TmpVar(self.operation.len() - 1))
-                    }
-                }
-            },
-            IROperation::Call(_) => {
-                self.operation.push(opr);
-                Some(IRExpr::TmpVar(self.operation.len() - 1))
-            },
-            IROperation::Jmp(_) 
-                | IROperation::Jz(_)
-                | IROperation::Jnz(_)
-                | IROperation::Jne(_)
-                | IROperation::Syscall(_) => None,
-            IROperation::Inc(ire1) => {
-                match ire1 {
-                    IRExpr::Imm(a) => Some(IRExpr::Imm(a + 1)),
-                    _ => {
-                        self.operation.push(opr.clone());
-                        Some(ire1.clone())
-                    }
-                }
-            },
+        if let Some(val) = opr.fold() {
+            return Some(IRExpr::Imm(val));
+        }
+        if !opr.has_result() {
+            self.instrs.push(IRStmt { dst: None, opr });
+            return None;
         }
+        let dst = self.new_temp();
+        self.instrs.push(IRStmt {
+            dst: Some(dst),
+            opr,
+        });
+        Some(IRExpr::TmpVar(dst))
+    }
+
+    /// Appends an operation that writes to an existing temporary
+    pub fn inst_to(&mut self, dst: usize, opr: IROperation) {
+        self.instrs.push(IRStmt {
+            dst: Some(dst),
+            opr,
+        });
     }
 }
 
-#[derive(Debug)]
+/// Static data (data is None for bss segments)
+#[derive(Debug, Clone)]
 pub struct IRData {
-    tag: String,
-    size: usize,
-    data: Option<Vec<u8>>,
+    pub tag: String,
+    pub size: usize,
+    pub data: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IRGenerator {
+    pub code: Vec<IRFunc>,
+    pub data: Vec<IRData>,
+}
+
+impl IRGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_func(&mut self, name: impl ToString) {
+        self.code.push(IRFunc::new(name));
+    }
+
+    /// Function that is currently being generated
+    pub fn func(&mut self) -> &mut IRFunc {
+        self.code
+            .last_mut()
+            .expect("No function is being generated")
+    }
+
+    pub fn inst(&mut self, opr: IROperation) -> Option<IRExpr> {
+        self.func().inst(opr)
+    }
+
+    pub fn inst_to(&mut self, dst: usize, opr: IROperation) {
+        self.func().inst_to(dst, opr);
+    }
+
+    pub fn label(&mut self, tag: impl ToString) {
+        self.inst(IROperation::Label(tag.to_string().into()));
+    }
+
+    pub fn jmp(&mut self, tag: impl ToString) {
+        self.inst(IROperation::Jmp(tag.to_string().into()));
+    }
+
+    pub fn add_data(&mut self, data: Vec<u8>) -> String {
+        let tag = format!("data{}", self.data.len());
+        self.data.push(IRData {
+            tag: tag.clone(),
+            size: data.len(),
+            data: Some(data),
+        });
+        tag
+    }
+
+    pub fn add_bss(&mut self, size: usize) -> String {
+        let tag = format!("bss{}", self.data.len());
+        self.data.push(IRData {
+            tag: tag.clone(),
+            size,
+            data: None,
+        });
+        tag
+    }
 }
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::assembler::{
+    asm_parser::parse_asm,
+    instructions::{Opr, Oprs},
+    memory::MemAddrType,
+    register::Reg::{self, *},
+};
+
+use super::{
+    liveness::{live_intervals, LiveInterval},
+    IRExpr, IRFunc, IROperation, IRStmt, IRVar, IRVarBase,
+};
+
+/// Registers that are not used as scratch by the code generator
+/// caller saved registers are only given to values that are not live across a call
+pub static CALLER_SAVED_POOL: [Reg; 2] = [R10, R11];
+pub static CALLEE_SAVED_POOL: [Reg; 4] = [R12, R13, R14, R15];
+/// Registers the caller expects to be preserved by the system v abi
+pub static CALLEE_SAVED: [Reg; 5] = [RBX, R12, R13, R14, R15];
+
+/// Where the value of a temporary is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Reg(Reg),
+    /// Index of a stack slot after the variables of the frame
+    Stack(usize),
+}
+
+/// Result of register allocation for a single function
+/// * locations - location of each temporary (None if value is never used)
+/// * stack_slots - number of stack slots used by spilled values
+/// * saved_regs - callee saved registers that have to be restored before returning
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub locations: Vec<Option<Location>>,
+    pub stack_slots: usize,
+    pub saved_regs: Vec<Reg>,
+}
+
+impl Allocation {
+    /// Keeps every temporary in its own stack slot
+    /// callee saved registers named by inline assembly are saved
+    pub fn spill_all(func: &IRFunc) -> Self {
+        Self {
+            locations: (0..func.temp_vars)
+                .map(|t| Some(Location::Stack(t)))
+                .collect(),
+            stack_slots: func.temp_vars,
+            saved_regs: asm_callee_saved(func),
+        }
+    }
+}
+
+/// Linear scan register allocation over the live intervals of temporaries
+/// when registers run out the value that is live the longest is spilled
+pub fn allocate_registers(func: &IRFunc) -> Allocation {
+    // inline assembly may use any register
+    if func
+        .instrs
+        .iter()
+        .any(|s| matches!(s.opr, IROperation::Asm(_)))
+    {
+        return Allocation::spill_all(func);
+    }
+    let calls: Vec<usize> = func
+        .instrs
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.opr.is_call())
+        .map(|(i, _)| i)
+        .collect();
+    let crosses_call = |i: &LiveInterval| calls.iter().any(|c| i.start < *c && *c < i.end);
+
+    let mut locations = vec![None; func.temp_vars];
+    let mut stack_slots = 0;
+    let mut saved_regs: Vec<Reg> = Vec::new();
+    let mut free: Vec<Reg> = CALLER_SAVED_POOL
+        .iter()
+        .chain(CALLEE_SAVED_POOL.iter())
+        .copied()
+        .collect();
+    let mut active: Vec<(LiveInterval, Reg)> = Vec::new();
+    for interval in live_intervals(func) {
+        // values that are only defined are never read
+        if interval.start == interval.end && !is_used_at(func, &interval) {
+            continue;
+        }
+        active.retain(|(a, reg)| {
+            if a.end < interval.start {
+                free.push(*reg);
+                return false;
+            }
+            true
+        });
+        let needs_callee_saved = crosses_call(&interval);
+        let usable = |reg: &Reg| !needs_callee_saved || CALLEE_SAVED_POOL.contains(reg);
+        let reg = match free.iter().position(usable) {
+            Some(index) => Some(free.remove(index)),
+            None => {
+                // steal the register of the active value that lives the longest
+                let victim = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (a, reg))| usable(reg) && a.end > interval.end)
+                    .max_by_key(|(_, (a, _))| a.end)
+                    .map(|(index, _)| index);
+                victim.map(|index| {
+                    let (spilled, reg) = active.remove(index);
+                    locations[spilled.temp] = Some(Location::Stack(stack_slots));
+                    stack_slots += 1;
+                    reg
+                })
+            }
+        };
+        match reg {
+            Some(reg) => {
+                if CALLEE_SAVED_POOL.contains(&reg) && !saved_regs.contains(&reg) {
+                    saved_regs.push(reg);
+                }
+                locations[interval.temp] = Some(Location::Reg(reg));
+                active.push((interval, reg));
+            }
+            None => {
+                locations[interval.temp] = Some(Location::Stack(stack_slots));
+                stack_slots += 1;
+            }
+        }
+    }
+    Allocation {
+        locations,
+        stack_slots,
+        saved_regs,
+    }
+}
+
+/// Callee saved registers used by the inline assembly of a function
+/// including its operands and clobbers
+fn asm_callee_saved(func: &IRFunc) -> Vec<Reg> {
+    let mut regs = Vec::new();
+    let mut add = |reg: Reg| {
+        let reg = reg.convert(8);
+        if CALLEE_SAVED.contains(&reg) && !regs.contains(&reg) {
+            regs.push(reg);
+        }
+    };
+    let mut add_opr = |opr: &Opr| match opr {
+        Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r) => add(*r),
+        Opr::Mem(m) => match m.addr_type {
+            MemAddrType::Addr(r) | MemAddrType::Disp(r, _) => add(r),
+            MemAddrType::Sib(r, _, index, _) => {
+                add(r);
+                add(index);
+            }
+            MemAddrType::AddrRela(_) => (),
+        },
+        _ => (),
+    };
+    for stmt in func.instrs.iter() {
+        let IROperation::Asm(source) = &stmt.opr else {
+            continue;
+        };
+        // labels are not instructions
+        let Ok(instr) = parse_asm(source) else {
+            continue;
+        };
+        match &instr.oprs {
+            Oprs::None => (),
+            Oprs::One(a) => add_opr(a),
+            Oprs::Two(a, b) => {
+                add_opr(a);
+                add_opr(b);
+            }
+        }
+    }
+    regs
+}
+
+fn is_used_at(func: &IRFunc, interval: &LiveInterval) -> bool {
+    func.instrs[interval.start]
+        .opr
+        .used_temps()
+        .contains(&interval.temp)
+}
+
+/// Moves scalar variables of the stack frame into temporaries
+/// so they can be kept in registers
+/// variables are only promoted if they are never accessed by address or by inline assembly
+/// stores to smaller variables are masked to keep the zero extended value of loads
+pub fn promote_locals(func: &mut IRFunc) {
+    let mut sizes: HashMap<i32, u8> = HashMap::new();
+    let mut excluded: HashSet<i32> = HashSet::new();
+    for stmt in func.instrs.iter() {
+        let var = match &stmt.opr {
+            IROperation::Asm(_) => return,
+            IROperation::Lea(var) if var.base == IRVarBase::Frame => return,
+            IROperation::Load(var) | IROperation::Store(var, _) => var,
+            _ => continue,
+        };
+        if var.base != IRVarBase::Frame {
+            continue;
+        }
+        if var.index.is_some() || *sizes.entry(var.offset).or_insert(var.size) != var.size {
+            excluded.insert(var.offset);
+        }
+    }
+    // partially overlapping variables are kept in memory
+    let offsets: Vec<i32> = sizes.keys().copied().collect();
+    for (offset, size) in sizes.iter() {
+        let overlaps = offsets
+            .iter()
+            .any(|o| o != offset && *o > *offset && *o < offset + *size as i32);
+        if overlaps {
+            excluded.insert(*offset);
+            excluded.extend(
+                offsets
+                    .iter()
+                    .filter(|o| **o > *offset && **o < offset + *size as i32),
+            );
+        }
+    }
+    let mut temps: HashMap<i32, usize> = HashMap::new();
+    let mut promoted = |func: &mut IRFunc, var: &IRVar| -> Option<usize> {
+        if var.base != IRVarBase::Frame || var.index.is_some() || excluded.contains(&var.offset) {
+            return None;
+        }
+        Some(*temps.entry(var.offset).or_insert_with(|| func.new_temp()))
+    };
+    let instrs = std::mem::take(&mut func.instrs);
+    for stmt in instrs.into_iter() {
+        match &stmt.opr {
+            IROperation::Load(var) => {
+                if let Some(t) = promoted(func, var) {
+                    func.instrs.push(IRStmt {
+                        dst: stmt.dst,
+                        opr: IROperation::Mov(IRExpr::TmpVar(t)),
+                    });
+                    continue;
+                }
+            }
+            IROperation::Store(var, val) => {
+                if let Some(t) = promoted(func, var) {
+                    let opr = match var.size {
+                        8 => IROperation::Mov(val.clone()),
+                        size => IROperation::And(val.clone(), IRExpr::Imm((1 << (size * 8)) - 1)),
+                    };
+                    func.instrs.push(IRStmt { dst: Some(t), opr });
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        func.instrs.push(stmt);
+    }
+}
+
+#[test]
+fn test_allocate_registers() {
+    use super::text::parse_ir;
+    let source = "
+func main frame=8 temps=4
+    %0 = mov 0
+main.loop:
+    %1 = cmp.lt %0, 10
+    jz %1, main.end
+    %2 = call print(%0)
+    %0 = add %0, 1
+    jmp main.loop
+main.end:
+    %3 = load [frame-8]:8
+    ret %3
+end
+";
+    let ir = parse_ir(source).unwrap();
+    let alloc = allocate_registers(&ir.code[0]);
+    // the counter lives across a call
+    let Some(Location::Reg(counter)) = alloc.locations[0] else {
+        panic!("counter is not in a register");
+    };
+    assert!(CALLEE_SAVED_POOL.contains(&counter));
+    assert_eq!(alloc.saved_regs, vec![counter]);
+    assert!(matches!(alloc.locations[1], Some(Location::Reg(R10))));
+    assert_eq!(alloc.locations[2], None);
+    assert_eq!(alloc.stack_slots, 0);
+}
+
+#[test]
+fn test_spill_registers() {
+    use super::text::parse_ir;
+    // 8 values alive at the same time and only 6 registers
+    let mut source = String::from("func main frame=0 temps=9\n");
+    for t in 0..8 {
+        source.push_str(&format!("    %{t} = mov {t}\n"));
+    }
+    source.push_str("    %8 = syscall(%0, %1, %2, %3, %4, %5, %6, %7)\n    ret\nend\n");
+    let ir = parse_ir(&source).unwrap();
+    let alloc = allocate_registers(&ir.code[0]);
+    let in_regs = alloc
+        .locations
+        .iter()
+        .filter(|l| matches!(l, Some(Location::Reg(_))))
+        .count();
+    assert_eq!(in_regs, 6);
+    assert_eq!(alloc.stack_slots, 2);
+}
+
+#[test]
+fn test_promote_locals() {
+    use super::text::parse_ir;
+    let source = "
+func main frame=24 temps=3
+    store [frame-4]:4, 7
+    %0 = load [frame-4]:4
+    store [frame-24+%0*1]:1, 1
+    %1 = load [frame-24+%0*1]:1
+    %2 = load [frame-12]:8
+    ret %2
+end
+";
+    let mut ir = parse_ir(source).unwrap();
+    let func = &mut ir.code[0];
+    promote_locals(func);
+    assert_eq!(func.temp_vars, 5);
+    assert_eq!(
+        func.instrs[0],
+        IRStmt {
+            dst: Some(3),
+            opr: IROperation::And(IRExpr::Imm(7), IRExpr::Imm(0xffffffff))
+        }
+    );
+    assert_eq!(func.instrs[1].opr, IROperation::Mov(IRExpr::TmpVar(3)));
+    // arrays stay in memory
+    assert!(matches!(func.instrs[2].opr, IROperation::Store(..)));
+    assert_eq!(func.instrs[4].opr, IROperation::Mov(IRExpr::TmpVar(4)));
+}
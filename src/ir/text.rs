@@ -0,0 +1,832 @@
+use std::fmt::Display;
+
+use crate::parser::expr::CompareOp;
+
+use super::{
+    from_float, IRCall, IRData, IRExpr, IRFunc, IRGenerator, IRInline, IROperation, IRStmt, IRType,
+    IRVar, IRVarBase,
+};
+
+// Textual form of the IR
+//
+// data data0 "Hello\n"
+// bss bss1 8
+//
+// func main frame=8 temps=2
+//     %0 = arg 0
+//     store [frame-4]:4, %0
+//     %1 = call fib([frame-4+%0*1]:1)
+//     %2 = call float sqrt(float %1)
+// main.Defer:
+//     ret %1
+// end
+//
+// operands: 12 (immediate), %3 (temporary), @data0 (address of static data)
+// memory:   [frame-4]:4, [@bss0+8]:8, [*%2+8]:4 (address held by a value)
+//           an index can be added before the size: [frame-30+%1*1]:1, [frame-30+2*1]:1
+// floating point immediates of float operations, float arguments and float returns
+// are written as decimals (2.5), other immediates are kept as the bits of an f64
+// functions returning a float have `ret=float` after temps
+// followed by `inline` or `noinline` if the function has an inlining attribute
+// lines starting with ';' are comments
+
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for b in bytes {
+        match b {
+            b'\n' => write!(f, "\\n")?,
+            b'\t' => write!(f, "\\t")?,
+            b'\r' => write!(f, "\\r")?,
+            b'"' => write!(f, "\\\"")?,
+            b'\\' => write!(f, "\\\\")?,
+            0x20..=0x7e => write!(f, "{}", *b as char)?,
+            _ => write!(f, "\\x{b:02x}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Writes an immediate holding a float as a decimal
+struct FloatImm<'a>(&'a IRExpr);
+
+impl Display for FloatImm<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            IRExpr::Imm(v) => write!(f, "{:?}", f64::from_bits(*v as u64)),
+            expr => write!(f, "{expr}"),
+        }
+    }
+}
+
+fn compare_name(op: &CompareOp) -> &'static str {
+    match op {
+        CompareOp::Eq => "eq",
+        CompareOp::NotEq => "ne",
+        CompareOp::Bigger => "gt",
+        CompareOp::Smaller => "lt",
+        CompareOp::BiggerEq => "ge",
+        CompareOp::SmallerEq => "le",
+    }
+}
+
+fn write_list(f: &mut std::fmt::Formatter<'_>, args: &[IRExpr]) -> std::fmt::Result {
+    write!(f, "(")?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{arg}")?;
+    }
+    write!(f, ")")
+}
+
+impl Display for IRCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ret == IRType::Float {
+            write!(f, "float ")?;
+        }
+        write!(f, "{}(", self.name)?;
+        for (i, (arg, ty)) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if *ty == IRType::Float {
+                write!(f, "float {}", FloatImm(arg))?;
+            } else {
+                write!(f, "{arg}")?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl Display for IRExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Imm(v) => write!(f, "{v}"),
+            Self::TmpVar(t) => write!(f, "%{t}"),
+            Self::Data(tag) => write!(f, "@{tag}"),
+        }
+    }
+}
+
+impl Display for IRVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.base {
+            IRVarBase::Frame => write!(f, "[frame")?,
+            IRVarBase::Global(tag) => write!(f, "[@{tag}")?,
+            IRVarBase::Ptr(addr) => write!(f, "[*{addr}")?,
+        }
+        if self.offset != 0 {
+            write!(f, "{:+}", self.offset)?;
+        }
+        match &self.index {
+            Some((IRExpr::Imm(index), scale)) => write!(f, "{index:+}*{scale}")?,
+            Some((index, scale)) => write!(f, "+{index}*{scale}")?,
+            None => (),
+        }
+        write!(f, "]:{}", self.size)
+    }
+}
+
+impl Display for IROperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Add(a, b) => write!(f, "add {a}, {b}"),
+            Self::Sub(a, b) => write!(f, "sub {a}, {b}"),
+            Self::Mult(a, b) => write!(f, "mul {a}, {b}"),
+            Self::Divide(a, b) => write!(f, "div {a}, {b}"),
+            Self::Mod(a, b) => write!(f, "mod {a}, {b}"),
+            Self::Or(a, b) => write!(f, "or {a}, {b}"),
+            Self::And(a, b) => write!(f, "and {a}, {b}"),
+            Self::Sal(a, b) => write!(f, "sal {a}, {b}"),
+            Self::Sar(a, b) => write!(f, "sar {a}, {b}"),
+            Self::Cmp(op, a, b) => write!(f, "cmp.{} {a}, {b}", compare_name(op)),
+            Self::FAdd(a, b) => write!(f, "fadd {}, {}", FloatImm(a), FloatImm(b)),
+            Self::FSub(a, b) => write!(f, "fsub {}, {}", FloatImm(a), FloatImm(b)),
+            Self::FMult(a, b) => write!(f, "fmul {}, {}", FloatImm(a), FloatImm(b)),
+            Self::FDivide(a, b) => write!(f, "fdiv {}, {}", FloatImm(a), FloatImm(b)),
+            Self::FCmp(op, a, b) => write!(
+                f,
+                "fcmp.{} {}, {}",
+                compare_name(op),
+                FloatImm(a),
+                FloatImm(b)
+            ),
+            Self::IntToFloat(a) => write!(f, "itof {a}"),
+            Self::FloatToInt(a) => write!(f, "ftoi {}", FloatImm(a)),
+            Self::Neg(a) => write!(f, "neg {a}"),
+            Self::Not(a) => write!(f, "not {a}"),
+            Self::Mov(a) => write!(f, "mov {a}"),
+            Self::Load(var) => write!(f, "load {var}"),
+            Self::Lea(var) => write!(f, "lea {var}"),
+            Self::Store(var, val) => write!(f, "store {var}, {val}"),
+            Self::Arg(n) => write!(f, "arg {n}"),
+            Self::FArg(n) => write!(f, "farg {n}"),
+            Self::StackArg(n) => write!(f, "stackarg {n}"),
+            Self::Call(call) => write!(f, "call {call}"),
+            Self::Ffi(call) => write!(f, "ffi {call}"),
+            Self::TailCall(call) => write!(f, "tailcall {call}"),
+            Self::Syscall(args) => {
+                write!(f, "syscall")?;
+                write_list(f, args)
+            }
+            Self::Label(tag) => write!(f, "{}:", tag.tag),
+            Self::Jmp(tag) => write!(f, "jmp {}", tag.tag),
+            Self::Jz(val, tag) => write!(f, "jz {val}, {}", tag.tag),
+            Self::Jnz(val, tag) => write!(f, "jnz {val}, {}", tag.tag),
+            Self::Ret(None) => write!(f, "ret"),
+            Self::Ret(Some(val)) => write!(f, "ret {val}"),
+            Self::Asm(instr) => {
+                write!(f, "asm ")?;
+                write_escaped(f, instr.as_bytes())
+            }
+        }
+    }
+}
+
+impl Display for IRStmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let IROperation::Label(_) = self.opr {
+            return write!(f, "{}", self.opr);
+        }
+        match self.dst {
+            Some(dst) => write!(f, "    %{dst} = {}", self.opr),
+            None => write!(f, "    {}", self.opr),
+        }
+    }
+}
+
+impl Display for IRFunc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "func {} frame={} temps={}",
+            self.name, self.frame_size, self.temp_vars
+        )?;
+        if self.ret == IRType::Float {
+            write!(f, " ret=float")?;
+        }
+        match self.inline {
+            IRInline::Auto => (),
+            IRInline::Always => write!(f, " inline")?,
+            IRInline::Never => write!(f, " noinline")?,
+        }
+        writeln!(f)?;
+        for stmt in self.instrs.iter() {
+            match &stmt.opr {
+                IROperation::Ret(Some(val)) if self.ret == IRType::Float => {
+                    writeln!(f, "    ret {}", FloatImm(val))?
+                }
+                _ => writeln!(f, "{stmt}")?,
+            }
+        }
+        writeln!(f, "end")
+    }
+}
+
+impl Display for IRData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.data {
+            Some(data) => {
+                write!(f, "data {} ", self.tag)?;
+                write_escaped(f, data)
+            }
+            None => write!(f, "bss {} {}", self.tag, self.size),
+        }
+    }
+}
+
+impl Display for IRGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for data in self.data.iter() {
+            writeln!(f, "{data}")?;
+        }
+        for func in self.code.iter() {
+            writeln!(f)?;
+            write!(f, "{func}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Cursor over a single line of IR text
+struct LineParser<'a> {
+    chars: Vec<char>,
+    cur: usize,
+    line: &'a str,
+}
+
+impl<'a> LineParser<'a> {
+    fn new(line: &'a str) -> Self {
+        Self {
+            chars: line.chars().collect(),
+            cur: 0,
+            line,
+        }
+    }
+
+    fn err<T>(&self, msg: impl Display) -> Result<T, String> {
+        Err(format!("{msg} in ({})", self.line.trim()))
+    }
+
+    fn skip_ws(&mut self) {
+        while self.cur < self.chars.len() && self.chars[self.cur].is_whitespace() {
+            self.cur += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.chars.get(self.cur).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.cur += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if !self.eat(c) {
+            return self.err(format!("Expected ({c})"));
+        }
+        Ok(())
+    }
+
+    fn is_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        self.skip_ws();
+        let start = self.cur;
+        while self.cur < self.chars.len()
+            && (self.chars[self.cur].is_alphanumeric() || "_.$".contains(self.chars[self.cur]))
+        {
+            self.cur += 1;
+        }
+        if start == self.cur {
+            return self.err("Expected identifier");
+        }
+        Ok(self.chars[start..self.cur].iter().collect())
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        self.skip_ws();
+        let start = self.cur;
+        if matches!(self.chars.get(self.cur), Some('-') | Some('+')) {
+            self.cur += 1;
+        }
+        while self.cur < self.chars.len() && self.chars[self.cur].is_ascii_digit() {
+            self.cur += 1;
+        }
+        let text: String = self.chars[start..self.cur].iter().collect();
+        match text.parse::<i64>() {
+            Ok(v) => Ok(v),
+            Err(_) => self.err(format!("Invalid number ({text})")),
+        }
+    }
+
+    /// Parses a number after a "key=" prefix
+    fn field(&mut self, key: &str) -> Result<usize, String> {
+        let name = self.ident()?;
+        if name != key {
+            return self.err(format!("Expected ({key}) field"));
+        }
+        self.expect('=')?;
+        Ok(self.number()? as usize)
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        self.expect('"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(c) = self.chars.get(self.cur).copied() else {
+                return self.err("Unterminated string");
+            };
+            self.cur += 1;
+            match c {
+                '"' => return Ok(bytes),
+                '\\' => {
+                    let Some(e) = self.chars.get(self.cur).copied() else {
+                        return self.err("Unterminated string");
+                    };
+                    self.cur += 1;
+                    match e {
+                        'n' => bytes.push(b'\n'),
+                        't' => bytes.push(b'\t'),
+                        'r' => bytes.push(b'\r'),
+                        '"' => bytes.push(b'"'),
+                        '\\' => bytes.push(b'\\'),
+                        'x' => {
+                            let hex: String = self.chars.iter().skip(self.cur).take(2).collect();
+                            let Ok(b) = u8::from_str_radix(&hex, 16) else {
+                                return self.err(format!("Invalid escape (\\x{hex})"));
+                            };
+                            self.cur += 2;
+                            bytes.push(b);
+                        }
+                        _ => return self.err(format!("Unsupported escape (\\{e})")),
+                    }
+                }
+                _ => {
+                    let mut buf = [0; 4];
+                    bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self) -> Result<IRExpr, String> {
+        match self.peek() {
+            Some('%') => {
+                self.cur += 1;
+                Ok(IRExpr::TmpVar(self.number()? as usize))
+            }
+            Some('@') => {
+                self.cur += 1;
+                Ok(IRExpr::Data(self.ident()?))
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == '-' => self.imm(),
+            _ => self.err("Expected operand"),
+        }
+    }
+
+    /// Parses an integer or a float (kept as the bits of an f64) immediate
+    fn imm(&mut self) -> Result<IRExpr, String> {
+        let start = self.cur;
+        while let Some(&c) = self.chars.get(self.cur) {
+            let is_sign = matches!(c, '-' | '+')
+                && (self.cur == start || matches!(self.chars[self.cur - 1], 'e' | 'E'));
+            if !(c.is_ascii_alphanumeric() || c == '.' || is_sign) {
+                break;
+            }
+            self.cur += 1;
+        }
+        let text: String = self.chars[start..self.cur].iter().collect();
+        if let Ok(v) = text.parse::<i64>() {
+            return Ok(IRExpr::Imm(v));
+        }
+        match text.parse::<f64>() {
+            Ok(v) => Ok(IRExpr::Imm(from_float(v))),
+            Err(_) => self.err(format!("Invalid number ({text})")),
+        }
+    }
+
+    fn var(&mut self) -> Result<IRVar, String> {
+        self.expect('[')?;
+        let base = match self.peek() {
+            Some('@') => {
+                self.cur += 1;
+                IRVarBase::Global(self.ident()?)
+            }
+            Some('*') => {
+                self.cur += 1;
+                IRVarBase::Ptr(self.expr()?)
+            }
+            _ => {
+                if self.ident()? != "frame" {
+                    return self.err("Expected memory base");
+                }
+                IRVarBase::Frame
+            }
+        };
+        let mut offset = 0;
+        let mut index = None;
+        while let Some(c @ ('+' | '-')) = self.peek() {
+            let next = self.chars[self.cur + 1..]
+                .iter()
+                .find(|c| !c.is_whitespace())
+                .copied();
+            if matches!(next, Some('%') | Some('@')) && c == '+' {
+                self.cur += 1;
+                let value = self.expr()?;
+                self.expect('*')?;
+                let scale = self.number()? as u8;
+                index = Some((value, scale));
+            } else {
+                let value = self.number()?;
+                if self.eat('*') {
+                    index = Some((IRExpr::Imm(value), self.number()? as u8));
+                } else {
+                    offset += value as i32;
+                }
+            }
+        }
+        self.expect(']')?;
+        self.expect(':')?;
+        let size = self.number()? as u8;
+        Ok(IRVar {
+            base,
+            offset,
+            index,
+            size,
+        })
+    }
+
+    fn list(&mut self) -> Result<Vec<IRExpr>, String> {
+        self.expect('(')?;
+        let mut args = Vec::new();
+        if self.eat(')') {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat(')') {
+                return Ok(args);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// Parses `[float] name([float] arg, ...)`
+    fn call(&mut self) -> Result<IRCall, String> {
+        let mut name = self.ident()?;
+        let mut ret = IRType::Int;
+        if name == "float" && self.peek() != Some('(') {
+            ret = IRType::Float;
+            name = self.ident()?;
+        }
+        self.expect('(')?;
+        let mut args = Vec::new();
+        if self.eat(')') {
+            return Ok(IRCall::new(name, args, ret));
+        }
+        loop {
+            let ty = match self.peek() {
+                Some(c) if c.is_alphabetic() => match self.ident()?.as_str() {
+                    "float" => IRType::Float,
+                    ty => return self.err(format!("Unknown argument type ({ty})")),
+                },
+                _ => IRType::Int,
+            };
+            args.push((self.expr()?, ty));
+            if self.eat(')') {
+                return Ok(IRCall::new(name, args, ret));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn binary(&mut self) -> Result<(IRExpr, IRExpr), String> {
+        let a = self.expr()?;
+        self.expect(',')?;
+        Ok((a, self.expr()?))
+    }
+
+    fn operation(&mut self) -> Result<IROperation, String> {
+        let name = self.ident()?;
+        let opr = match name.as_str() {
+            "add" | "sub" | "mul" | "div" | "mod" | "or" | "and" | "sal" | "sar" => {
+                let (a, b) = self.binary()?;
+                match name.as_str() {
+                    "add" => IROperation::Add(a, b),
+                    "sub" => IROperation::Sub(a, b),
+                    "mul" => IROperation::Mult(a, b),
+                    "div" => IROperation::Divide(a, b),
+                    "mod" => IROperation::Mod(a, b),
+                    "or" => IROperation::Or(a, b),
+                    "and" => IROperation::And(a, b),
+                    "sal" => IROperation::Sal(a, b),
+                    _ => IROperation::Sar(a, b),
+                }
+            }
+            "fadd" | "fsub" | "fmul" | "fdiv" => {
+                let (a, b) = self.binary()?;
+                match name.as_str() {
+                    "fadd" => IROperation::FAdd(a, b),
+                    "fsub" => IROperation::FSub(a, b),
+                    "fmul" => IROperation::FMult(a, b),
+                    _ => IROperation::FDivide(a, b),
+                }
+            }
+            "cmp.eq" | "cmp.ne" | "cmp.gt" | "cmp.lt" | "cmp.ge" | "cmp.le" | "fcmp.eq"
+            | "fcmp.ne" | "fcmp.gt" | "fcmp.lt" | "fcmp.ge" | "fcmp.le" => {
+                let (kind, op) = name.split_once('.').unwrap();
+                let op = match op {
+                    "eq" => CompareOp::Eq,
+                    "ne" => CompareOp::NotEq,
+                    "gt" => CompareOp::Bigger,
+                    "lt" => CompareOp::Smaller,
+                    "ge" => CompareOp::BiggerEq,
+                    _ => CompareOp::SmallerEq,
+                };
+                let (a, b) = self.binary()?;
+                if kind == "fcmp" {
+                    IROperation::FCmp(op, a, b)
+                } else {
+                    IROperation::Cmp(op, a, b)
+                }
+            }
+            "itof" => IROperation::IntToFloat(self.expr()?),
+            "ftoi" => IROperation::FloatToInt(self.expr()?),
+            "neg" => IROperation::Neg(self.expr()?),
+            "not" => IROperation::Not(self.expr()?),
+            "mov" => IROperation::Mov(self.expr()?),
+            "load" => IROperation::Load(self.var()?),
+            "lea" => IROperation::Lea(self.var()?),
+            "store" => {
+                let var = self.var()?;
+                self.expect(',')?;
+                IROperation::Store(var, self.expr()?)
+            }
+            "arg" => IROperation::Arg(self.number()? as usize),
+            "farg" => IROperation::FArg(self.number()? as usize),
+            "stackarg" => IROperation::StackArg(self.number()? as usize),
+            "call" => IROperation::Call(self.call()?),
+            "ffi" => IROperation::Ffi(self.call()?),
+            "tailcall" => IROperation::TailCall(self.call()?),
+            "syscall" => IROperation::Syscall(self.list()?),
+            "jmp" => IROperation::Jmp(self.ident()?.into()),
+            "jz" | "jnz" => {
+                let val = self.expr()?;
+                self.expect(',')?;
+                let tag = self.ident()?.into();
+                if name == "jz" {
+                    IROperation::Jz(val, tag)
+                } else {
+                    IROperation::Jnz(val, tag)
+                }
+            }
+            "ret" if self.is_end() => IROperation::Ret(None),
+            "ret" => IROperation::Ret(Some(self.expr()?)),
+            "asm" => match String::from_utf8(self.string()?) {
+                Ok(instr) => IROperation::Asm(instr),
+                Err(_) => return self.err("Invalid utf-8 in asm"),
+            },
+            _ => return self.err(format!("Unknown operation ({name})")),
+        };
+        if !self.is_end() {
+            return self.err("Unexpected trailing input");
+        }
+        Ok(opr)
+    }
+
+    fn stmt(&mut self) -> Result<IRStmt, String> {
+        if self.eat('%') {
+            let dst = self.number()? as usize;
+            self.expect('=')?;
+            return Ok(IRStmt {
+                dst: Some(dst),
+                opr: self.operation()?,
+            });
+        }
+        let start = self.cur;
+        if let Ok(tag) = self.ident() {
+            if self.eat(':') && self.is_end() {
+                return Ok(IRStmt {
+                    dst: None,
+                    opr: IROperation::Label(tag.into()),
+                });
+            }
+        }
+        self.cur = start;
+        Ok(IRStmt {
+            dst: None,
+            opr: self.operation()?,
+        })
+    }
+}
+
+/// Parses the textual form of the IR (see Display implementations)
+/// used to write IR snippets directly in tests
+pub fn parse_ir(source: &str) -> Result<IRGenerator, String> {
+    let mut ir = IRGenerator::new();
+    let mut in_func = false;
+    for (line_num, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        }
+        let mut parser = LineParser::new(line);
+        let result = if in_func {
+            if trimmed == "end" {
+                in_func = false;
+                Ok(())
+            } else {
+                parser.stmt().map(|stmt| ir.func().instrs.push(stmt))
+            }
+        } else {
+            parse_item(&mut parser, &mut ir).map(|is_func| in_func = is_func)
+        };
+        if let Err(e) = result {
+            return Err(format!("line {}: {e}", line_num + 1));
+        }
+    }
+    if in_func {
+        return Err("Unexpected end of input (missing end)".to_string());
+    }
+    Ok(ir)
+}
+
+/// Parses a top level item and returns true if it starts a function
+fn parse_item(parser: &mut LineParser, ir: &mut IRGenerator) -> Result<bool, String> {
+    match parser.ident()?.as_str() {
+        "data" => {
+            let tag = parser.ident()?;
+            let data = parser.string()?;
+            ir.data.push(IRData {
+                tag,
+                size: data.len(),
+                data: Some(data),
+            });
+            Ok(false)
+        }
+        "bss" => {
+            let tag = parser.ident()?;
+            let size = parser.number()? as usize;
+            ir.data.push(IRData {
+                tag,
+                size,
+                data: None,
+            });
+            Ok(false)
+        }
+        "func" => {
+            let mut func = IRFunc::new(parser.ident()?);
+            func.frame_size = parser.field("frame")?;
+            func.temp_vars = parser.field("temps")?;
+            while !parser.is_end() {
+                match parser.ident()?.as_str() {
+                    "ret" if parser.eat('=') && parser.ident()? == "float" => {
+                        func.ret = IRType::Float
+                    }
+                    "inline" => func.inline = IRInline::Always,
+                    "noinline" => func.inline = IRInline::Never,
+                    _ => return parser.err("Expected (ret=float), (inline) or (noinline)"),
+                }
+            }
+            ir.code.push(func);
+            Ok(true)
+        }
+        item => parser.err(format!("Unknown item ({item})")),
+    }
+}
+
+#[test]
+fn parse_ir_text() {
+    let source = r#"
+; comment
+data data0 "Hi\n\x00"
+bss bss1 8
+
+func main frame=32 temps=5
+    %0 = arg 0
+    store [frame-4]:4, %0
+    %1 = load [frame-30+%0*1]:1
+    %2 = cmp.lt %1, -5
+    jz %2, main.$0.BE__
+    %3 = call fib(%1, @data0)
+    store [*%3+8]:8, 1
+    %4 = call float sqrt(float %3, 2)
+main.$0.BE__:
+    syscall(1, 1, @data0, 3)
+    asm "mov rax, \"a\""
+    store [frame-30-2*1]:1, 1
+    ret
+end
+
+func half frame=0 temps=3 ret=float
+    %0 = farg 0
+    %1 = fdiv %0, 2.0
+    %2 = fcmp.lt %1, -1e-7
+    ret 0.1
+end
+"#;
+    let ir = parse_ir(source).unwrap();
+    assert_eq!(ir.data.len(), 2);
+    assert_eq!(ir.data[0].data, Some(b"Hi\n\0".to_vec()));
+    assert_eq!(ir.data[1].size, 8);
+    let func = &ir.code[0];
+    assert_eq!((func.frame_size, func.temp_vars), (32, 5));
+    assert_eq!(
+        func.instrs[2].opr,
+        IROperation::Load(IRVar::frame(-30, 1).indexed(IRExpr::TmpVar(0), 1))
+    );
+    assert_eq!(
+        func.instrs[3].opr,
+        IROperation::Cmp(CompareOp::Smaller, IRExpr::TmpVar(1), IRExpr::Imm(-5))
+    );
+    assert_eq!(
+        func.instrs[6].opr,
+        IROperation::Store(IRVar::ptr(IRExpr::TmpVar(3), 8, 8), IRExpr::Imm(1))
+    );
+    assert_eq!(
+        func.instrs[7].opr,
+        IROperation::Call(IRCall::new(
+            "sqrt",
+            vec![
+                (IRExpr::TmpVar(3), IRType::Float),
+                (IRExpr::Imm(2), IRType::Int)
+            ],
+            IRType::Float
+        ))
+    );
+    assert_eq!(
+        func.instrs[8].opr,
+        IROperation::Label("main.$0.BE__".to_string().into())
+    );
+    assert_eq!(
+        func.instrs[10].opr,
+        IROperation::Asm("mov rax, \"a\"".into())
+    );
+    assert_eq!(
+        func.instrs[11].opr,
+        IROperation::Store(
+            IRVar::frame(-30, 1).indexed(IRExpr::Imm(-2), 1),
+            IRExpr::Imm(1)
+        )
+    );
+    let half = &ir.code[1];
+    assert_eq!(
+        half.instrs[1].opr,
+        IROperation::FDivide(IRExpr::TmpVar(0), IRExpr::Imm(from_float(2.0)))
+    );
+    assert_eq!(
+        half.instrs[2].opr,
+        IROperation::FCmp(
+            CompareOp::Smaller,
+            IRExpr::TmpVar(1),
+            IRExpr::Imm(from_float(-1e-7))
+        )
+    );
+    assert_eq!(
+        half.instrs[3].opr,
+        IROperation::Ret(Some(IRExpr::Imm(from_float(0.1))))
+    );
+    // Printing and parsing again gives the same text
+    let text = ir.to_string();
+    assert!(text.contains("%1 = fdiv %0, 2.0\n    %2 = fcmp.lt %1, -1e-7\n    ret 0.1\n"));
+    assert_eq!(parse_ir(&text).unwrap().to_string(), text);
+}
+
+#[test]
+fn parse_ir_errors() {
+    assert!(parse_ir("func main frame=0 temps=0\n    ret\n").is_err());
+    assert!(parse_ir("func main frame=0 temps=0\n    foo 1\nend\n").is_err());
+    assert!(parse_ir("data data0 \"abc\n").is_err());
+}
+
+#[test]
+fn compiled_ir_round_trip() {
+    use crate::compiler::{compile, CompilerContext};
+    use crate::CompilerOptions;
+
+    for name in [
+        "arrays",
+        "defer",
+        "floats",
+        "static_var",
+        "string_expr",
+        "structs",
+    ] {
+        let path = format!("./tests/{name}.nmt");
+        let mut cc = CompilerContext::new(path.clone(), &CompilerOptions::default());
+        compile(&mut cc, &crate::vfs::DiskFs, path).unwrap();
+        let text = cc.ir.to_string();
+        assert_eq!(parse_ir(&text).unwrap().to_string(), text);
+    }
+}
@@ -27,7 +27,7 @@
 **********************************************************************************************/
 use std::fmt::Display;
 
-use crate::error_handeling::{error, Loc};
+use crate::error_handeling::{Diagnostic, Loc, ParseError, ParseResult};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
@@ -153,6 +153,8 @@ pub enum TokenType {
     Eof,
     /// START OF FILE
     Sof,
+    /// Invalid token that is already reported by the lexer
+    Error,
     // Import module
     Import,
     // Macro Definition
@@ -240,6 +242,7 @@ impl Display for TokenType {
             TokenType::Dot => write!(f, "."),
             TokenType::Eof => write!(f, "Eof"),
             TokenType::Sof => write!(f, "Sof"),
+            TokenType::Error => write!(f, "Error"),
             TokenType::Import => write!(f, "import"),
             TokenType::Macro => write!(f, "macro"),
             TokenType::DoubleOr => write!(f, "||"),
@@ -272,9 +275,12 @@ impl Token {
     /// # Examples
     ///
     /// ```
+    /// use nmet::error_handeling::Loc;
+    /// use nmet::lexer::{Token, TokenType};
+    ///
     /// Token::new(TokenType::Int(0),
     ///     "0".to_string(),
-    ///     ("./path.nmt".to_string(),1,1)
+    ///     Loc::new("./path.nmt".to_string(),1,1)
     ///     );
     /// ```
     pub fn new(t_type: TokenType, literal: String, loc: Loc) -> Self {
@@ -306,6 +312,14 @@ pub struct Lexer {
     pub file_path: String,
     source: Vec<char>,
     pub token: Token,
+    /// Reported syntax errors
+    pub diagnostics: Vec<Diagnostic>,
+    /// Location of the last reported error
+    last_error: Option<Loc>,
+    /// Number of unclosed curly brackets before the current token
+    pub depth: usize,
+    /// Platform that #if conditions are checked against (see target_string_to_number)
+    pub target_platform: u8,
     cur: usize,
     bol: usize,
     row: usize,
@@ -323,12 +337,53 @@ impl Lexer {
             file_path,
             source: source.chars().collect::<Vec<char>>(),
             token: Token::empty(),
+            diagnostics: Vec::new(),
+            last_error: None,
+            depth: 0,
+            target_platform: 0,
             cur: 0,
             bol: 0,
             row: 0,
         }
     }
 
+    /// Collects the syntax error
+    /// Errors of enclosing items at the same location are only reported once
+    /// so parse errors at an error token are skipped silently
+    pub fn report(&mut self, err: ParseError) {
+        if self.last_error.as_ref() == Some(&err.loc) {
+            return;
+        }
+        self.diagnostics.push(err.diagnostic());
+        self.last_error = Some(err.loc);
+    }
+
+    /// Skips tokens after a syntax error until parsing can continue:
+    /// after a semicolon or a closed block, or before a closing curly
+    /// and the given tokens that are not nested in a block
+    /// * depth - curly depth where the item with the error started
+    pub fn synchronize(&mut self, stop: &[TokenType], depth: usize) {
+        loop {
+            match self.get_token_type() {
+                TokenType::Eof => break,
+                TokenType::CCurly if self.depth == depth + 1 => {
+                    self.next_token();
+                    break;
+                }
+                _ if self.depth > depth => (),
+                _ if self.depth < depth => break,
+                TokenType::CCurly => break,
+                TokenType::SemiColon => {
+                    self.next_token();
+                    break;
+                }
+                t if stop.contains(&t) => break,
+                _ => (),
+            }
+            self.next_token();
+        }
+    }
+
     /// Chacks if self.cur is referencing outside of the code file
     fn is_empty(&self) -> bool {
         self.cur >= self.source.len()
@@ -365,31 +420,39 @@ impl Lexer {
         }
     }
 
+    /// Drops the rest of an invalid literal until the closing quote or the end of line
+    fn skip_literal(&mut self, quote: char) {
+        while !self.is_empty() && self.source[self.cur] != '\n' {
+            let char = self.source[self.cur];
+            self.drop();
+            if char == quote {
+                break;
+            }
+        }
+    }
+
     /// Returns type of the current token
-    /// Can exit the program if token is EOF
     pub fn get_token_type(&self) -> TokenType {
-        let tk = self.token.clone();
-        if tk.t_type == TokenType::Eof {
-            error("Expected a Token, found Eof", tk.loc);
-        };
-        tk.t_type
+        self.token.t_type
     }
 
     /// Checks if the current token type matches the giver token type
-    /// Will exit the program if token is not matching
+    /// Returns an error if token is not matching
     ///
     /// # Arguments
     ///
     /// * `t_type` - TokenType for matching
-    pub fn match_token(&mut self, t_type: TokenType) {
+    pub fn match_token(&mut self, t_type: TokenType) -> ParseResult<()> {
         let tk = self.token.clone();
         if tk.t_type == t_type {
             self.next_token();
+            Ok(())
         } else {
-            error(
+            Err(ParseError::new(
+                "E0105",
                 format!("Expected ({}), found ({})", t_type, tk.t_type),
                 tk.loc,
-            );
+            ))
         }
     }
 
@@ -411,14 +474,27 @@ impl Lexer {
     }
 
     /// Scans the next token and sets the current token to the new token
+    /// Invalid tokens are reported and replaced by an error token at the location of the error
     pub fn next_token(&mut self) -> Token {
-        let token = self._next_token();
+        let token = match self._next_token() {
+            Ok(token) => token,
+            Err(err) => {
+                let token = Token::new(TokenType::Error, String::new(), err.loc.clone());
+                self.report(err);
+                token
+            }
+        };
+        match self.token.t_type {
+            TokenType::OCurly => self.depth += 1,
+            TokenType::CCurly => self.depth = self.depth.saturating_sub(1),
+            _ => (),
+        }
         self.token = token.clone();
         token
     }
 
     /// Scans the next token
-    fn _next_token(&mut self) -> Token {
+    fn _next_token(&mut self) -> ParseResult<Token> {
         self.trim_left();
         while !self.is_empty() {
             if self.source[self.cur] == '~' {
@@ -428,11 +504,11 @@ impl Lexer {
                 break;
             }
         }
+        let loc = self.get_current_loc();
         if self.is_empty() {
-            return Token::empty();
+            return Ok(Token::new(TokenType::Eof, String::new(), loc));
         }
         let first = self.source[self.cur];
-        let loc = self.get_current_loc();
 
         if first.is_ascii_alphabetic() || first == '_' {
             let index = self.cur;
@@ -443,8 +519,8 @@ impl Lexer {
             }
             let literal = String::from_iter(self.source[index..self.cur].to_vec());
             match Self::is_keyword(&literal) {
-                Some(keyword_token) => return Token::new(keyword_token, literal, loc),
-                None => return Token::new(TokenType::Identifier, literal, loc),
+                Some(keyword_token) => return Ok(Token::new(keyword_token, literal, loc)),
+                None => return Ok(Token::new(TokenType::Identifier, literal, loc)),
             }
         }
         if first.is_ascii_digit() {
@@ -456,8 +532,8 @@ impl Lexer {
                 self.drop();
             }
             let literal = String::from_iter(self.source[index..self.cur].to_vec());
-            let ttype_and_val = self.parse_numeric_literal(&literal);
-            return Token::new(ttype_and_val, literal, loc);
+            let ttype_and_val = Self::parse_numeric_literal(&literal, &loc)?;
+            return Ok(Token::new(ttype_and_val, literal, loc));
         }
         if first == '\'' {
             return self.tokenize_char_literal();
@@ -474,75 +550,100 @@ impl Lexer {
                 if Self::is_single_char_token(next).is_some() {
                     if let Some(dtt) = Self::is_double_char_token(first, next) {
                         self.drop();
-                        return Token::new(dtt, String::from_iter(vec![first, next]), loc);
+                        return Ok(Token::new(dtt, String::from_iter(vec![first, next]), loc));
                     }
                 }
             }
-            return Token::new(tt, first.to_string(), loc);
+            return Ok(Token::new(tt, first.to_string(), loc));
         }
 
-        error("Unexpected Character", loc);
+        self.drop();
+        Err(ParseError::new(
+            "E0100",
+            format!("Unexpected Character ({first})"),
+            loc,
+        ))
     }
 
     /// Tokenses the char literal
     /// ONLY call when current char is (')
-    fn tokenize_char_literal(&mut self) -> Token {
+    fn tokenize_char_literal(&mut self) -> ParseResult<Token> {
         self.drop();
-        let literal;
-        let char = self.source[self.cur];
         let loc = self.get_current_loc();
-        if char == '\'' {
-            error("char literal can not be empty", loc);
-        }
-        if char == '\\' {
-            self.drop();
-            if self.is_empty() {
-                error("char literal unfinished escape sequence", loc);
+        let literal = match self.source.get(self.cur) {
+            None => {
+                return Err(ParseError::new(
+                    "E0101",
+                    "Error: Char literal is not closed properly",
+                    loc,
+                ))
             }
-            let escape = self.source[self.cur];
-            match escape {
-                'n' => {
-                    literal = '\n';
-                }
-                '\'' => {
-                    literal = '\'';
-                }
-                't' => {
-                    literal = '\t';
-                }
-                'r' => {
-                    literal = '\r';
-                }
-                '\\' => {
-                    literal = '\\';
-                }
-                '0' => {
-                    literal = '\0';
-                }
-                _ => {
-                    error(format!("unsupported escape sequence (\\{})", escape), loc);
-                }
+            Some('\'') => {
+                self.drop();
+                return Err(ParseError::new(
+                    "E0101",
+                    "char literal can not be empty",
+                    loc,
+                ));
             }
-            self.drop();
-        } else {
-            literal = char;
-            self.drop();
-        }
+            Some('\\') => {
+                self.drop();
+                let Some(&escape) = self.source.get(self.cur) else {
+                    return Err(ParseError::new(
+                        "E0103",
+                        "char literal unfinished escape sequence",
+                        loc,
+                    ));
+                };
+                let literal = match escape {
+                    'n' => '\n',
+                    '\'' => '\'',
+                    't' => '\t',
+                    'r' => '\r',
+                    '\\' => '\\',
+                    '0' => '\0',
+                    _ => {
+                        self.skip_literal('\'');
+                        return Err(ParseError::new(
+                            "E0103",
+                            format!("unsupported escape sequence (\\{})", escape),
+                            loc,
+                        ));
+                    }
+                };
+                self.drop();
+                literal
+            }
+            Some(&char) => {
+                self.drop();
+                char
+            }
+        };
 
-        if !self.is_empty() {
-            if self.source[self.cur] != '\'' {
-                error("unsupported char", loc);
+        match self.source.get(self.cur) {
+            Some('\'') => {
+                self.drop();
+                Ok(Token::new(
+                    TokenType::Char(literal),
+                    literal.to_string(),
+                    loc,
+                ))
             }
-            self.drop();
-            Token::new(TokenType::Char(literal), literal.to_string(), loc)
-        } else {
-            error("Error: Char literal is not closed properly", loc);
+            Some(_) => {
+                self.skip_literal('\'');
+                Err(ParseError::new("E0101", "unsupported char", loc))
+            }
+            None => Err(ParseError::new(
+                "E0101",
+                "Error: Char literal is not closed properly",
+                loc,
+            )),
         }
     }
 
     /// Tokenses the string literal
     /// ONLY call when current char is (")
-    fn tokenize_string_literal(&mut self) -> Token {
+    fn tokenize_string_literal(&mut self) -> ParseResult<Token> {
         self.drop();
         let mut literal = String::new();
         let loc = self.get_current_loc();
@@ -552,44 +653,40 @@ impl Lexer {
                 break;
             }
             if char == '\n' {
-                error("string literal not closed before end of line", loc);
+                return Err(ParseError::new(
+                    "E0102",
+                    "string literal not closed before end of line",
+                    loc,
+                ));
             }
             if char == '\\' {
                 self.drop();
                 if self.is_empty() {
-                    error("string literal unfinished escape sequence", loc);
+                    return Err(ParseError::new(
+                        "E0103",
+                        "string literal unfinished escape sequence",
+                        loc,
+                    ));
                 }
 
                 let escape = self.source[self.cur];
                 match escape {
-                    'n' => {
-                        literal.push('\n');
-                        self.drop();
-                    }
-                    '"' => {
-                        literal.push('"');
-                        self.drop();
-                    }
-                    't' => {
-                        literal.push('\t');
-                        self.drop();
-                    }
-                    'r' => {
-                        literal.push('\r');
-                        self.drop();
-                    }
-                    '0' => {
-                        literal.push('\0');
-                        self.drop();
-                    }
-                    '\\' => {
-                        literal.push('\\');
-                        self.drop();
-                    }
+                    'n' => literal.push('\n'),
+                    '"' => literal.push('"'),
+                    't' => literal.push('\t'),
+                    'r' => literal.push('\r'),
+                    '0' => literal.push('\0'),
+                    '\\' => literal.push('\\'),
                     _ => {
-                        error(format!("unsupported escape sequence (\\{})", escape), loc);
+                        self.skip_literal('"');
+                        return Err(ParseError::new(
+                            "E0103",
+                            format!("unsupported escape sequence (\\{})", escape),
+                            loc,
+                        ));
                     }
                 }
+                self.drop();
             } else {
                 literal.push(char);
                 self.drop();
@@ -597,9 +694,13 @@ impl Lexer {
         }
         if !self.is_empty() {
             self.drop();
-            Token::new(TokenType::String, literal, loc)
+            Ok(Token::new(TokenType::String, literal, loc))
         } else {
-            error("Error: String literal is not closed properly", loc);
+            Err(ParseError::new(
+                "E0102",
+                "Error: String literal is not closed properly",
+                loc,
+            ))
         }
     }
 
@@ -704,64 +805,34 @@ impl Lexer {
     }
 
     /// Parse numeric literal to a numeric TokenType
-    /// Can Exit the program if can not parse the lietal
+    /// Returns an error if can not parse the lietal
     ///
     /// # Arguments
     ///
     /// * `literal` - token literal that we whant to check
-    fn parse_numeric_literal(&self, literal: &String) -> TokenType {
+    fn parse_numeric_literal(literal: &str, loc: &Loc) -> ParseResult<TokenType> {
         // 0x001 0xff 0b0010
-        let loc = self.get_current_loc();
-        let mut lit_chars = literal.chars();
-        if literal.contains('x') {
-            self.expect_char(&lit_chars.next(), vec!['0']);
-            self.expect_char(&lit_chars.next(), vec!['x']);
-            let mut value: i64 = 0;
-            for ch in lit_chars {
-                let digit = ch.to_digit(16).unwrap_or_else(|| {
-                    error(
-                        format!("Unknown character in parsing ({})", literal),
-                        loc.clone(),
-                    );
-                });
-                value = (value * 16i64) + digit as i64;
-            }
-            TokenType::Int(value as i32)
-        } else if literal.contains('b') {
-            self.expect_char(&lit_chars.next(), vec!['0']);
-            self.expect_char(&lit_chars.next(), vec!['b']);
-            let mut value: i32 = 0;
-            for ch in lit_chars {
-                let digit = ch.to_digit(2).unwrap_or_else(|| {
-                    error(
-                        format!("Unknown character in parsing ({})", literal),
-                        loc.clone(),
-                    );
-                });
-                value = (value * 2i32) + digit as i32;
-            }
-            TokenType::Int(value)
+        let invalid = || {
+            ParseError::new(
+                "E0104",
+                format!("Unknown character in parsing ({})", literal),
+                loc.clone(),
+            )
+        };
+        if let Some(hex) = literal.strip_prefix("0x") {
+            let value = i64::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            Ok(TokenType::Int(value as i32))
+        } else if let Some(bin) = literal.strip_prefix("0b") {
+            let value = i64::from_str_radix(bin, 2).map_err(|_| invalid())?;
+            Ok(TokenType::Int(value as i32))
         } else if literal.contains('.') {
-            let value: f64 = literal.parse::<f64>().unwrap();
-            TokenType::Float(value)
+            let value: f64 = literal.parse::<f64>().map_err(|_| invalid())?;
+            Ok(TokenType::Float(value))
         } else {
-            let value: i32 = literal.parse::<i32>().unwrap();
-            TokenType::Int(value)
+            let value: i32 = literal.parse::<i32>().map_err(|_| invalid())?;
+            Ok(TokenType::Int(value))
         }
     }
-
-    /// Returns char if exits in a list
-    /// Will Exit the program if no match
-    fn expect_char(&self, copt: &Option<char>, chars: Vec<char>) -> char {
-        let loc = self.get_current_loc();
-        let char = copt.unwrap_or_else(|| {
-            error("Undifined character set for numbers", loc);
-        });
-        if chars.contains(&char) {
-            return char;
-        }
-        char
-    }
 }
 
 #[test]
@@ -781,13 +852,41 @@ fn expr_tokens() {
 #[test]
 fn string_literal() {
     let mut lexer = Lexer::new(String::new(), "\"test\"".to_string());
-    assert_eq!(lexer.tokenize_string_literal().t_type, TokenType::String);
+    assert_eq!(
+        lexer.tokenize_string_literal().unwrap().t_type,
+        TokenType::String
+    );
 }
 
 #[test]
 fn string_literal_escape_seq() {
     let mut lexer = Lexer::new(String::new(), "\"test\\ntest\"".to_string());
-    assert_eq!(lexer.tokenize_string_literal().t_type, TokenType::String);
+    assert_eq!(
+        lexer.tokenize_string_literal().unwrap().t_type,
+        TokenType::String
+    );
     let mut lexer = Lexer::new(String::new(), "\"\\\"test\\\"\"".to_string());
-    assert_eq!(lexer.tokenize_string_literal().t_type, TokenType::String);
+    assert_eq!(
+        lexer.tokenize_string_literal().unwrap().t_type,
+        TokenType::String
+    );
+}
+
+#[test]
+fn invalid_tokens_are_reported() {
+    let mut lexer = Lexer::new(
+        String::new(),
+        "a ^ 1 'ab' \"\\q\" 99999999999 2".to_string(),
+    );
+    assert_eq!(lexer.next_token().t_type, TokenType::Identifier);
+    let error = lexer.next_token();
+    assert_eq!(error.t_type, TokenType::Error);
+    assert_eq!(error.loc, lexer.diagnostics[0].loc);
+    assert_eq!(lexer.next_token().t_type, TokenType::Int(1));
+    for _ in 0..3 {
+        assert_eq!(lexer.next_token().t_type, TokenType::Error);
+    }
+    assert_eq!(lexer.next_token().t_type, TokenType::Int(2));
+    assert_eq!(lexer.next_token().t_type, TokenType::Eof);
+    assert_eq!(lexer.diagnostics.len(), 4);
 }
@@ -0,0 +1,263 @@
+/**********************************************************************************************
+*
+*   Nmet compiler library
+*
+*   This file exposes the compiler as a library so it can be embedded in other tools.
+*   All modules used in the code base must be defined here.
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+use std::path::PathBuf;
+
+pub mod assembler;
+pub mod compiler;
+pub mod error_codes;
+pub mod error_handeling;
+pub mod formats;
+pub mod ir;
+pub mod lexer;
+pub mod linker;
+mod macros;
+pub mod optim;
+pub mod parser;
+pub mod simulator;
+pub mod terms;
+pub mod utils;
+pub mod vfs;
+pub mod warnings;
+
+pub use compiler::CompilerContext;
+pub use error_handeling::Diagnostic;
+pub use lexer::Lexer;
+
+use compiler::{compile_program, impl_bifs};
+use error_handeling::{ErrorFormat, Loc};
+use formats::elf::elf_object_bytes;
+use parser::program::ProgramFile;
+use parser::{parse_source, parse_source_file};
+use vfs::{DiskFs, FileSystem};
+use warnings::Warnings;
+
+// --- Static Compiler Defenition
+pub static VERSION: &str = "v0.8.1";
+
+// nmet [options] (input_file)
+// -nasm -no-link -no-assemble -keep-asm -keep-obj
+// -o outputpath
+// -l<mod_name>
+// -L<mod_path>
+// -T <Target>
+#[derive(Debug, Default, Clone)]
+pub struct CompilerOptions {
+    pub output_path: Option<PathBuf>,
+    pub use_nasm: bool,
+    pub no_linking: bool,
+    pub no_assembling: bool,
+    pub keep_asm: bool,
+    pub keep_obj: bool,
+    pub static_lib: bool,
+    pub dynamic_lib: bool,
+    pub linker_flags: Vec<String>,
+    pub use_libc: bool,
+    pub create_bin: bool,
+    pub target_platform: u8,
+    pub simulate: bool,
+    pub internal_link: bool,
+    pub emit_ir: bool,
+    pub emit_listing: bool,
+    /// Optimization level (-O0, -O1, -O2)
+    pub opt_level: u8,
+    pub print_passes: bool,
+    /// Keep calls in return statements instead of jumping to the function
+    pub no_tail_calls: bool,
+    /// Assemble a nasm file instead of compiling (nmet asm file.asm)
+    pub asm_mode: bool,
+    pub error_format: ErrorFormat,
+    /// Levels of the warnings set by -W flags
+    pub warnings: Warnings,
+}
+
+pub fn target_string_to_number(target: &str) -> u8 {
+    match target {
+        "LINUX" | "linux" => 0,
+        "WINDOWS" | "WIN" | "windows" | "win" => 1,
+        _ => u8::MAX,
+    }
+}
+
+/// Compiles the source code of a program into a relocatable elf object file
+/// Nothing is printed and the process is never exited on errors
+///
+/// # Arguments
+/// * path - path of the program used in diagnostics and the object file symbols
+/// * source - source code of the program, imports are read from the disk
+///
+/// # Returns
+/// Bytes of the object file or the reported errors and warnings
+pub fn compile_to_object(
+    path: &str,
+    source: &str,
+    co: &CompilerOptions,
+) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let program = parse_source(
+        &DiskFs,
+        path.to_string(),
+        source.to_string(),
+        co.target_platform,
+    )?;
+    object_from_program(path, &program, co)
+}
+
+/// Compiles a program file and its imports read from the file system
+/// into a relocatable elf object file, e.g. from a MemoryFs of path to source
+pub fn compile_file_to_object(
+    fs: &dyn FileSystem,
+    path: &str,
+    co: &CompilerOptions,
+) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let program = parse_source_file(fs, path.to_string(), co.target_platform)?;
+    object_from_program(path, &program, co)
+}
+
+fn object_from_program(
+    path: &str,
+    program: &ProgramFile,
+    co: &CompilerOptions,
+) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let mut cc = CompilerContext::new(path.to_string(), co);
+    if compile_program(&mut cc, program).is_err() {
+        return Err(cc.diagnostics);
+    }
+    impl_bifs(&mut cc);
+    if co.opt_level > 0 {
+        cc.codegen.peephole();
+    }
+    elf_object_bytes(&mut cc).map_err(|e| {
+        let loc = Loc::new(path.to_string(), 1, 1);
+        vec![Diagnostic::error(e, loc).with_code("E0012")]
+    })
+}
+
+#[test]
+fn test_compile_to_object() {
+    let co = CompilerOptions::default();
+    let object = compile_to_object("main.nmt", "func main() {\n    print 1;\n}", &co).unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+
+    let errors = compile_to_object("main.nmt", "func main() {\n    print x\n}", &co).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0105"));
+    let errors = compile_to_object("main.nmt", "func main() {\n    print x;\n}", &co).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0001"));
+    let errors = compile_to_object("lib.nmt", "func other() {\n}", &co).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0012"));
+    let asm = |line: &str| format!("func main() {{\n    asm {{\n        \"{line}\"\n    }}\n}}");
+    assert!(compile_to_object("main.nmt", &asm("mov qword [rsp-8], 1"), &co).is_ok());
+    for line in ["add rax, 0x80000000", "cmp rax, 0xFFFFFFFF"] {
+        let errors = compile_to_object("main.nmt", &asm(line), &co).unwrap_err();
+        assert_eq!(errors[0].code, Some("E0012"), "{line}");
+    }
+}
+
+#[test]
+fn test_compile_file_to_object() {
+    let co = CompilerOptions::default();
+    let fs: vfs::MemoryFs = [
+        (
+            "src/main.nmt",
+            "import \"./src/math\" :: add\n\nfunc main() {\n    print add(1, 2);\n}",
+        ),
+        (
+            "src/math.nmt",
+            "func add(a @int, b @int) @int {\n    return a + b;\n}",
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let object = compile_file_to_object(&fs, "./src/main.nmt", &co).unwrap();
+    assert_eq!(&object[..4], b"\x7fELF");
+    assert!(compile_file_to_object(&fs, "src/main.nmt", &co).is_ok());
+
+    let errors = compile_file_to_object(&fs, "src/math.nmt", &co).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0012"));
+    let fs: vfs::MemoryFs = [("main.nmt", "import \"./src/math\"\nfunc main() {}")]
+        .into_iter()
+        .collect();
+    let errors = compile_file_to_object(&fs, "main.nmt", &co).unwrap_err();
+    assert_eq!(errors[0].code, Some("E0107"));
+}
+
+#[test]
+fn test_compile_errors_are_diagnostics() {
+    let co = CompilerOptions::default();
+    let program = |body: &str| {
+        format!("struct P {{\n    x @int,\n    y @int,\n}}\n\nfunc main() {{\n    {body}\n}}")
+    };
+    let source = program("var p @P;\n    p.x = 2;\n    p.y = p.x + 1;\n    print p.y;");
+    assert!(compile_to_object("main.nmt", &source, &co).is_ok());
+    let cases = [
+        ("var p @P;\n    print p.z;", "E0012"),
+        ("var p @P;\n    p.z = 1;", "E0012"),
+        ("var p @P;\n    print p.x.y;", "E0012"),
+        ("var p @P;\n    p.x[0] = 1;", "E0012"),
+        ("var x @int;\n    x.y = 1;", "E0004"),
+        ("var x @int;\n    print x[0];", "E0004"),
+        ("var x @int;\n    x[0] = 1;", "E0004"),
+        ("var a @[int,3];\n    print &a[1];", "E0012"),
+        ("var a @[int,3];\n    print *a;", "E0010"),
+        ("var q @ptr;\n    var y = *q;", "E0012"),
+    ];
+    for (body, code) in cases {
+        let errors = compile_to_object("main.nmt", &program(body), &co).unwrap_err();
+        assert_eq!(errors[0].code, Some(code), "{body}");
+    }
+}
+
+#[test]
+fn test_errors_are_located_at_their_cause() {
+    let co = CompilerOptions::default();
+    let source = "func main() {\n    var a @int = 1;\n    print a + missing;\n    asm {\n        \"mov rax, 1\"\n        \"movx rax, 2\"\n    }\n}";
+    let errors = compile_to_object("main.nmt", source, &co).unwrap_err();
+    let locs: Vec<(usize, usize)> = errors.iter().map(|e| (e.loc.line, e.loc.col)).collect();
+    assert_eq!(locs, [(3, 15), (6, 10)]);
+    // binary expressions are located at their operator, not the token after them
+    let source = "func main() {\n    var s = \"hi\";\n    print 1 + s;\n}";
+    let errors = compile_to_object("main.nmt", source, &co).unwrap_err();
+    assert_eq!((errors[0].loc.line, errors[0].loc.col), (3, 13));
+}
+
+#[test]
+fn test_target_platform_of_parallel_compiles() {
+    let source = "func main() {\n    #if WINDOWS\n        print missing;\n    #end\n}";
+    let linux = CompilerOptions::default();
+    let windows = CompilerOptions {
+        target_platform: target_string_to_number("windows"),
+        ..Default::default()
+    };
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| assert!(compile_to_object("main.nmt", source, &linux).is_ok()));
+            s.spawn(|| {
+                let errors = compile_to_object("main.nmt", source, &windows).unwrap_err();
+                assert_eq!(errors[0].code, Some("E0001"));
+            });
+        }
+    });
+}
@@ -0,0 +1,129 @@
+use crate::formats::elf::sections::slice_to_u64;
+
+/// Magic string at the start of every ar archive
+pub static AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+/// Size of each member header
+pub static AR_HEADER_SIZE: usize = 60;
+
+/// A single file inside an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveMember {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Parsed ar archive
+/// * symbols - symbol index entries (symbol name, index of the defining member)
+/// * members - all regular members in order
+#[derive(Debug, Clone, Default)]
+pub struct Archive {
+    pub symbols: Vec<(String, usize)>,
+    pub members: Vec<ArchiveMember>,
+}
+
+fn header_field(header: &[u8], start: usize, len: usize) -> String {
+    String::from_utf8_lossy(&header[start..start + len])
+        .trim_end()
+        .to_string()
+}
+
+/// Parses symbol index with entry size of 4 (GNU "/") or 8 ("/SYM64/") bytes
+/// Returns (symbol name, header offset of the member) pairs
+fn parse_symbol_index(data: &[u8], entry: usize) -> Result<Vec<(String, usize)>, String> {
+    let read = |index: usize| -> Result<usize, String> {
+        let Some(bytes) = data.get(index..index + entry) else {
+            return Err("Archive symbol index is truncated".to_string());
+        };
+        let mut be = bytes.to_vec();
+        be.reverse();
+        Ok(slice_to_u64(&be) as usize)
+    };
+    let count = read(0)?;
+    let mut offsets = Vec::new();
+    for i in 0..count {
+        offsets.push(read(entry * (i + 1))?);
+    }
+    let mut names = data[entry * (count + 1)..]
+        .split(|x| *x == 0)
+        .map(|x| String::from_utf8_lossy(x).to_string());
+    let mut symbols = Vec::new();
+    for offset in offsets {
+        let Some(name) = names.next() else {
+            return Err("Archive symbol index is truncated".to_string());
+        };
+        symbols.push((name, offset));
+    }
+    Ok(symbols)
+}
+
+/// Parses GNU (and BSD style) ar archives
+pub fn parse_archive(bytes: &[u8]) -> Result<Archive, String> {
+    if !bytes.starts_with(AR_MAGIC) {
+        return Err("File is not an ar archive".to_string());
+    }
+    let mut archive = Archive::default();
+    let mut raw_symbols = Vec::new();
+    let mut long_names: &[u8] = &[];
+    let mut member_offsets = Vec::new();
+    let mut index = AR_MAGIC.len();
+    while index + AR_HEADER_SIZE <= bytes.len() {
+        let header = &bytes[index..index + AR_HEADER_SIZE];
+        if &header[58..60] != b"`\n" {
+            return Err(format!("Invalid archive member header at offset {index}"));
+        }
+        let name = header_field(header, 0, 16);
+        let Ok(size) = header_field(header, 48, 10).parse::<usize>() else {
+            return Err(format!("Invalid archive member size at offset {index}"));
+        };
+        let start = index + AR_HEADER_SIZE;
+        let Some(data) = bytes.get(start..start + size) else {
+            return Err(format!("Archive member ({name}) is truncated"));
+        };
+        match name.as_str() {
+            "/" => raw_symbols = parse_symbol_index(data, 4)?,
+            "/SYM64/" => raw_symbols = parse_symbol_index(data, 8)?,
+            "//" => long_names = data,
+            _ => {
+                let (name, data) = if let Some(offset) = name.strip_prefix('/') {
+                    let Ok(offset) = offset.parse::<usize>() else {
+                        return Err(format!("Invalid archive member name ({name})"));
+                    };
+                    let tail = long_names.get(offset..).unwrap_or_default();
+                    let end = tail.iter().position(|x| *x == b'\n').unwrap_or(tail.len());
+                    let long = String::from_utf8_lossy(&tail[..end]);
+                    (long.trim_end_matches('/').to_string(), data)
+                } else if let Some(len) = name.strip_prefix("#1/") {
+                    let len = len.parse::<usize>().unwrap_or(0).min(data.len());
+                    let long = String::from_utf8_lossy(&data[..len]);
+                    (long.trim_end_matches('\0').to_string(), &data[len..])
+                } else {
+                    (name.trim_end_matches('/').to_string(), data)
+                };
+                member_offsets.push(index);
+                archive.members.push(ArchiveMember {
+                    name,
+                    data: data.to_vec(),
+                });
+            }
+        }
+        index = start + size + (size % 2);
+    }
+    for (sym, offset) in raw_symbols {
+        if let Some(member) = member_offsets.iter().position(|x| *x == offset) {
+            archive.symbols.push((sym, member));
+        }
+    }
+    Ok(archive)
+}
+
+#[test]
+fn parse_gnu_archive() {
+    let bytes = std::fs::read("./tests/libadd.a").unwrap();
+    let archive = parse_archive(&bytes).unwrap();
+    assert_eq!(archive.members.len(), 1);
+    assert_eq!(archive.members[0].name, "addlib.o");
+    let mut symbols: Vec<&str> = archive.symbols.iter().map(|x| x.0.as_str()).collect();
+    symbols.sort();
+    assert_eq!(symbols, vec!["add", "multi", "sub"]);
+    assert!(archive.symbols.iter().all(|x| x.1 == 0));
+}
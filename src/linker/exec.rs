@@ -0,0 +1,116 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::formats::elf::{
+    header::ElfHeader,
+    program::{ProgramHeader, PF_R, PF_W, PF_X, PHDR_SIZE},
+};
+
+/// Virtual address the executable is loaded at
+pub static BASE_ADDR: u64 = 0x400000;
+static PAGE_SIZE: u64 = 0x1000;
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+/// Memory layout of a static executable
+/// * text segment (R X): elf header, program headers and .text
+/// * data segment (R W): .data followed by .bss
+#[derive(Debug, Clone, Copy)]
+pub struct ExecLayout {
+    pub text_offset: u64,
+    pub text_addr: u64,
+    pub text_size: u64,
+    pub data_offset: u64,
+    pub data_addr: u64,
+    pub data_size: u64,
+    pub bss_addr: u64,
+    pub bss_size: u64,
+}
+
+impl ExecLayout {
+    pub fn new(text_size: usize, data_size: usize, bss_size: usize) -> Self {
+        let phnum = if data_size + bss_size > 0 { 2 } else { 1 };
+        let text_offset = align_up(64 + PHDR_SIZE * phnum, 16);
+        let data_offset = align_up(text_offset + text_size as u64, PAGE_SIZE);
+        let data_addr = BASE_ADDR + data_offset;
+        Self {
+            text_offset,
+            text_addr: BASE_ADDR + text_offset,
+            text_size: text_size as u64,
+            data_offset,
+            data_addr,
+            data_size: data_size as u64,
+            bss_addr: data_addr + align_up(data_size as u64, 16),
+            bss_size: bss_size as u64,
+        }
+    }
+
+    pub fn has_data_segment(&self) -> bool {
+        self.data_size + self.bss_size > 0
+    }
+
+    fn program_headers(&self) -> Vec<ProgramHeader> {
+        let text_end = self.text_offset + self.text_size;
+        let mut headers = vec![ProgramHeader::new_load(
+            0,
+            PF_R | PF_X,
+            BASE_ADDR,
+            text_end,
+            text_end,
+        )];
+        if self.has_data_segment() {
+            headers.push(ProgramHeader::new_load(
+                self.data_offset,
+                PF_R | PF_W,
+                self.data_addr,
+                self.data_size,
+                self.bss_addr - self.data_addr + self.bss_size,
+            ));
+        }
+        headers
+    }
+}
+
+/// Writes an ET_EXEC elf file with already relocated sections
+/// * entry - virtual address of the entry point
+pub fn write_exec(
+    path: &Path,
+    layout: &ExecLayout,
+    text: &[u8],
+    data: &[u8],
+    entry: u64,
+) -> io::Result<()> {
+    let headers = layout.program_headers();
+    let mut bytes = ElfHeader::new_exec(entry, headers.len() as u16).to_bytes();
+    for header in headers.iter() {
+        bytes.extend(header.to_bytes());
+    }
+    bytes.resize(layout.text_offset as usize, 0);
+    bytes.extend(text);
+    if layout.has_data_segment() {
+        bytes.resize(layout.data_offset as usize, 0);
+        bytes.extend(data);
+    }
+
+    let stream = File::create(path)?;
+    let mut file = BufWriter::new(stream);
+    file.write_all(&bytes)?;
+    file.flush()?;
+    set_executable(path)
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -1,70 +1,361 @@
-use std::{collections::HashMap, fs};
+pub mod archive;
+pub mod exec;
+pub mod object;
 
-use crate::formats::elf::{
-    header::ElfHeader,
-    sections::{parse_section, STRTABSec, Section, SectionHeader},
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
 };
 
+use self::{
+    archive::{parse_archive, Archive, AR_MAGIC},
+    exec::{write_exec, ExecLayout},
+    object::{ObjSymbol, ObjectFile, SHN_ABS, SHN_COMMON, SHT_NOBITS, SHT_NOTE},
+};
+
+/// Section flag of writable sections
+static SHF_WRITE: u64 = 1;
+/// Section flag of sections that occupy memory at runtime
+static SHF_ALLOC: u64 = 2;
+/// Section flag of executable sections
+static SHF_EXECINSTR: u64 = 4;
+/// Binding of weak symbols
+static STB_WEAK: u8 = 2;
+
+/// Input file of the linker
 #[derive(Debug, Clone)]
-pub struct ElfFile {
-    pub header: ElfHeader,
-    pub sections: HashMap<String, Box<dyn Section>>,
-    pub sec_headers: Vec<SectionHeader>,
+pub enum LinkInput {
+    Object(ObjectFile),
+    Archive(String, Archive),
+}
+
+/// Loads an object file or an ar archive from disk
+pub fn parse_elf_objfile(file_path: &Path) -> Result<LinkInput, String> {
+    let name = file_path.to_string_lossy().to_string();
+    let Ok(bytes) = fs::read(file_path) else {
+        return Err(format!("Cannot read file ({name})"));
+    };
+    if bytes.starts_with(AR_MAGIC) {
+        Ok(LinkInput::Archive(name, parse_archive(&bytes)?))
+    } else {
+        Ok(LinkInput::Object(ObjectFile::from_bytes(&name, &bytes)?))
+    }
 }
 
-impl ElfFile {
-    pub fn new(header: ElfHeader) -> Self {
-        Self {
-            header,
-            sections: HashMap::new(),
-            sec_headers: Vec::new(),
+/// Finds the static libraries requested by -L and -l linker flags
+fn find_libraries(flags: &[String]) -> Result<Vec<PathBuf>, String> {
+    let search: Vec<PathBuf> = flags
+        .iter()
+        .filter_map(|f| f.strip_prefix("-L"))
+        .map(PathBuf::from)
+        .collect();
+    let mut libs = Vec::new();
+    for lib in flags.iter().filter_map(|f| f.strip_prefix("-l")) {
+        let static_name = format!("lib{lib}.a");
+        match search
+            .iter()
+            .map(|d| d.join(&static_name))
+            .find(|p| p.exists())
+        {
+            Some(path) => libs.push(path),
+            None if search
+                .iter()
+                .any(|d| d.join(format!("lib{lib}.so")).exists()) =>
+            {
+                return Err(format!(
+                    "Dynamic library ({lib}) is not supported by the internal linker"
+                ));
+            }
+            None => return Err(format!("Cannot find library ({lib})")),
         }
     }
+    Ok(libs)
+}
+
+/// Output segment of a merged input section
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    Text,
+    Data,
+    Bss,
+}
+
+fn align_to(value: usize, align: u64) -> usize {
+    let align = align.max(1) as usize;
+    value.div_ceil(align) * align
 }
 
-fn find_elf_start(bytes: &[u8]) -> usize {
-    bytes
-        .windows(4)
-        .position(|x| x == b"\x7FELF")
-        .expect("Provided file has no ELF file!")
+/// Static linker for relocatable objects and archives
+/// * objects - every object that ends up in the executable
+/// * globals - defined global symbols (object index, symbol index)
+/// * placement - output segment and offset of each (object, section) pair
+#[derive(Debug, Default)]
+pub struct Linker {
+    objects: Vec<ObjectFile>,
+    globals: HashMap<String, (usize, usize)>,
+    placement: HashMap<(usize, usize), (Segment, usize)>,
+    commons: HashMap<String, usize>,
+    text: Vec<u8>,
+    data: Vec<u8>,
+    bss_size: usize,
 }
 
-#[allow(dead_code)]
-pub fn parse_elf_objfile(file_path: String) -> ElfFile {
-    let source = fs::read(file_path).unwrap();
-    let elf_start = find_elf_start(&source);
-    let elf_bytes = &source[elf_start..];
-    let header = ElfHeader::from_bytes(elf_bytes);
-    let mut elf_file = ElfFile::new(header);
-
-    for i in 0..header.e_shnum {
-        let start = (header.e_shoff + (i * header.e_shentsize) as u64) as usize;
-        let end = start + header.e_shentsize as usize;
-        elf_file
-            .sec_headers
-            .push(SectionHeader::from_bytes(&elf_bytes[start..end]));
+impl Linker {
+    fn add_object(&mut self, obj: ObjectFile) -> Result<(), String> {
+        let index = self.objects.len();
+        for (sym_index, sym) in obj.symbols.iter().enumerate() {
+            if !sym.is_global() || sym.is_undefined() {
+                continue;
+            }
+            match self.globals.get(&sym.name) {
+                Some((other, other_sym)) => {
+                    let other = &self.objects[*other].symbols[*other_sym];
+                    let weak = |s: &ObjSymbol| s.item.st_info >> 4 == STB_WEAK;
+                    if weak(other) && !weak(sym) {
+                        self.globals.insert(sym.name.clone(), (index, sym_index));
+                    } else if !weak(other) && !weak(sym) {
+                        return Err(format!("Multiple definition of ({})", sym.name));
+                    }
+                }
+                None => {
+                    self.globals.insert(sym.name.clone(), (index, sym_index));
+                }
+            }
+        }
+        self.objects.push(obj);
+        Ok(())
+    }
+
+    fn undefined_symbols(&self) -> HashSet<String> {
+        self.objects
+            .iter()
+            .flat_map(|o| o.symbols.iter())
+            .filter(|s| s.is_global() && s.is_undefined() && !s.name.is_empty())
+            .filter(|s| !self.globals.contains_key(&s.name))
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    /// Pulls archive members that define currently undefined symbols
+    fn resolve_archives(&mut self, archives: &[(String, Archive)]) -> Result<(), String> {
+        let mut loaded = HashSet::new();
+        loop {
+            let undefined = self.undefined_symbols();
+            let mut members = Vec::new();
+            for (ar_index, (_, archive)) in archives.iter().enumerate() {
+                for (sym, member) in archive.symbols.iter() {
+                    if undefined.contains(sym) && loaded.insert((ar_index, *member)) {
+                        members.push((ar_index, *member));
+                    }
+                }
+            }
+            if members.is_empty() {
+                return Ok(());
+            }
+            for (ar_index, member) in members {
+                let (name, archive) = &archives[ar_index];
+                let member = &archive.members[member];
+                let name = format!("{name}({})", member.name);
+                self.add_object(ObjectFile::from_bytes(&name, &member.data)?)?;
+            }
+        }
+    }
+
+    /// Merges allocatable sections into text, data and bss segments
+    fn merge_sections(&mut self) {
+        for (obj_index, obj) in self.objects.iter().enumerate() {
+            for (sec_index, sec) in obj.sections.iter().enumerate() {
+                let flags = sec.header.sh_flags;
+                if flags & SHF_ALLOC == 0 || sec.header.sh_type == SHT_NOTE {
+                    continue;
+                }
+                let align = sec.header.sh_addralign;
+                let place = if sec.header.sh_type == SHT_NOBITS {
+                    self.bss_size = align_to(self.bss_size, align);
+                    let offset = self.bss_size;
+                    self.bss_size += sec.header.sh_size as usize;
+                    (Segment::Bss, offset)
+                } else if flags & SHF_WRITE != 0 {
+                    self.data.resize(align_to(self.data.len(), align), 0);
+                    let offset = self.data.len();
+                    self.data.extend(&sec.data);
+                    (Segment::Data, offset)
+                } else if flags & SHF_EXECINSTR != 0 {
+                    self.text.resize(align_to(self.text.len(), align), 0);
+                    let offset = self.text.len();
+                    self.text.extend(&sec.data);
+                    (Segment::Text, offset)
+                } else {
+                    continue;
+                };
+                self.placement.insert((obj_index, sec_index), place);
+            }
+        }
+        // read-only data goes to the end of the text segment
+        for (obj_index, obj) in self.objects.iter().enumerate() {
+            for (sec_index, sec) in obj.sections.iter().enumerate() {
+                let flags = sec.header.sh_flags;
+                if flags & SHF_ALLOC == 0
+                    || flags & (SHF_WRITE | SHF_EXECINSTR) != 0
+                    || sec.header.sh_type == SHT_NOBITS
+                    || sec.header.sh_type == SHT_NOTE
+                {
+                    continue;
+                }
+                self.text
+                    .resize(align_to(self.text.len(), sec.header.sh_addralign), 0);
+                let offset = self.text.len();
+                self.text.extend(&sec.data);
+                self.placement
+                    .insert((obj_index, sec_index), (Segment::Text, offset));
+            }
+        }
+        // common symbols are allocated at the end of bss
+        for obj in self.objects.iter() {
+            for sym in obj.symbols.iter() {
+                if sym.item.st_shndx != SHN_COMMON || self.commons.contains_key(&sym.name) {
+                    continue;
+                }
+                self.bss_size = align_to(self.bss_size, sym.item.st_value);
+                self.commons.insert(sym.name.clone(), self.bss_size);
+                self.bss_size += sym.item.st_size as usize;
+            }
+        }
+    }
+
+    fn segment_addr(layout: &ExecLayout, segment: Segment) -> u64 {
+        match segment {
+            Segment::Text => layout.text_addr,
+            Segment::Data => layout.data_addr,
+            Segment::Bss => layout.bss_addr,
+        }
     }
-    let shst_header = elf_file.sec_headers[elf_file.header.e_shstrndx as usize];
-    let shstrtab = STRTABSec::new_from_bytes(
-        ".shstrtab",
-        &elf_bytes[shst_header.sh_offset as usize
-            ..(shst_header.sh_offset + shst_header.sh_size) as usize],
-    );
-    for sh in elf_file.sec_headers.iter() {
-        if sh.sh_type == 0 {
-            continue;
+
+    /// Virtual address of a symbol inside the final executable
+    fn symbol_addr(&self, layout: &ExecLayout, obj: usize, sym: usize) -> Result<u64, String> {
+        let symbol = &self.objects[obj].symbols[sym];
+        match symbol.item.st_shndx {
+            0 => match self.globals.get(&symbol.name) {
+                Some((o, s)) => self.symbol_addr(layout, *o, *s),
+                None if symbol.item.st_info >> 4 == STB_WEAK => Ok(0),
+                None => Err(format!("Undefined reference to ({})", symbol.name)),
+            },
+            x if x == SHN_ABS => Ok(symbol.item.st_value),
+            x if x == SHN_COMMON => Ok(layout.bss_addr + self.commons[&symbol.name] as u64),
+            shndx => match self.placement.get(&(obj, shndx as usize)) {
+                Some((segment, offset)) => Ok(Self::segment_addr(layout, *segment)
+                    + *offset as u64
+                    + symbol.item.st_value),
+                None => Err(format!(
+                    "Symbol ({}) in ({}) refers to a discarded section",
+                    symbol.name, self.objects[obj].name
+                )),
+            },
         }
-        let name = shstrtab.get(sh.sh_name as usize);
-        let index = sh.sh_offset as usize;
-        let size = sh.sh_size as usize;
-        if let Some(sec) = parse_section(sh, &name, &elf_bytes[index..index + size]) {
-            elf_file.sections.insert(name.clone(), sec);
+    }
+
+    fn relocate(&mut self, layout: &ExecLayout) -> Result<(), String> {
+        for obj_index in 0..self.objects.len() {
+            for (target, items) in self.objects[obj_index].relocations() {
+                let Some((segment, base)) = self.placement.get(&(obj_index, target)).copied()
+                else {
+                    continue;
+                };
+                let sec_addr = Self::segment_addr(layout, segment) + base as u64;
+                for item in items {
+                    let value =
+                        self.symbol_addr(layout, obj_index, item.r_section as usize)? as i64;
+                    let bytes = match segment {
+                        Segment::Text => &mut self.text[base..],
+                        Segment::Data => &mut self.data[base..],
+                        Segment::Bss => continue,
+                    };
+                    apply_relocation(
+                        bytes,
+                        item.r_offset,
+                        item.r_platform,
+                        value + item.r_addend,
+                        sec_addr + item.r_offset,
+                    )?;
+                }
+            }
         }
+        Ok(())
     }
 
-    println!("{:#?}", elf_file.sections);
-    elf_file
+    /// Links the given inputs into a static executable
+    pub fn link(inputs: Vec<LinkInput>, out_path: &Path) -> Result<(), String> {
+        let mut linker = Self::default();
+        let mut archives = Vec::new();
+        for input in inputs {
+            match input {
+                LinkInput::Object(obj) => linker.add_object(obj)?,
+                LinkInput::Archive(name, archive) => archives.push((name, archive)),
+            }
+        }
+        linker.resolve_archives(&archives)?;
+        if let Some(sym) = linker.undefined_symbols().into_iter().min() {
+            return Err(format!("Undefined reference to ({sym})"));
+        }
+        linker.merge_sections();
+        let layout = ExecLayout::new(linker.text.len(), linker.data.len(), linker.bss_size);
+        linker.relocate(&layout)?;
+        let Some((obj, sym)) = linker.globals.get("_start").copied() else {
+            return Err("Executable programs should have an entry point".to_string());
+        };
+        let entry = linker.symbol_addr(&layout, obj, sym)?;
+        write_exec(out_path, &layout, &linker.text, &linker.data, entry).map_err(|e| e.to_string())
+    }
 }
 
-#[allow(dead_code)]
-pub fn generate_elf_exec() {}
+/// Links the object file at path with the libraries in linker flags
+/// and writes the executable next to it
+pub fn link_static(path: &Path, linker_flags: &[String]) -> Result<(), String> {
+    let mut inputs = vec![parse_elf_objfile(&path.with_extension("o"))?];
+    for lib in find_libraries(linker_flags)? {
+        inputs.push(parse_elf_objfile(&lib)?);
+    }
+    Linker::link(inputs, &path.with_extension(""))
+}
+
+/// Patches a relocation inside a section
+/// * value - resolved symbol address plus addend (S + A)
+/// * place - virtual address of the patched location (P)
+pub fn apply_relocation(
+    bytes: &mut [u8],
+    offset: u64,
+    r_type: u32,
+    value: i64,
+    place: u64,
+) -> Result<(), String> {
+    let offset = offset as usize;
+    match r_type {
+        // R_X86_64_64
+        0x1 => bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes()),
+        // R_X86_64_PC32 | R_X86_64_PLT32
+        0x2 | 0x4 => {
+            let rel = value - place as i64;
+            let Ok(rel) = i32::try_from(rel) else {
+                return Err(format!("Relocation target out of range ({rel:#x})"));
+            };
+            bytes[offset..offset + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+        // R_X86_64_32
+        0xa => {
+            let Ok(val) = u32::try_from(value) else {
+                return Err(format!("Relocation value out of range ({value:#x})"));
+            };
+            bytes[offset..offset + 4].copy_from_slice(&val.to_le_bytes());
+        }
+        // R_X86_64_32S
+        0xb => {
+            let Ok(val) = i32::try_from(value) else {
+                return Err(format!("Relocation value out of range ({value:#x})"));
+            };
+            bytes[offset..offset + 4].copy_from_slice(&val.to_le_bytes());
+        }
+        _ => return Err(format!("Unsupported relocation type ({r_type:#x})")),
+    }
+    Ok(())
+}
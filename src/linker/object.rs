@@ -0,0 +1,141 @@
+use crate::formats::elf::{
+    header::{EType, ElfHeader},
+    sections::{RelaItem, SectionHeader, SymItem},
+};
+
+/// Section type of symbol tables
+pub static SHT_SYMTAB: u32 = 2;
+/// Section type of relocations with addends
+pub static SHT_RELA: u32 = 4;
+/// Section type of notes
+pub static SHT_NOTE: u32 = 7;
+/// Section type of uninitialized data
+pub static SHT_NOBITS: u32 = 8;
+/// Section index of undefined symbols
+pub static SHN_UNDEF: u16 = 0;
+/// Section index of absolute symbols
+pub static SHN_ABS: u16 = 0xfff1;
+/// Section index of common symbols
+pub static SHN_COMMON: u16 = 0xfff2;
+
+/// Section of a relocatable object file
+#[derive(Debug, Clone)]
+pub struct ObjSection {
+    pub name: String,
+    pub header: SectionHeader,
+    pub data: Vec<u8>,
+}
+
+/// Symbol of a relocatable object file
+#[derive(Debug, Clone)]
+pub struct ObjSymbol {
+    pub name: String,
+    pub item: SymItem,
+}
+
+impl ObjSymbol {
+    pub fn is_global(&self) -> bool {
+        self.item.st_info >> 4 != 0
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        self.item.st_shndx == SHN_UNDEF
+    }
+}
+
+/// Parsed relocatable (ET_REL) elf file
+/// sections and symbols keep their original indices
+#[derive(Debug, Clone)]
+pub struct ObjectFile {
+    pub name: String,
+    pub sections: Vec<ObjSection>,
+    pub symbols: Vec<ObjSymbol>,
+}
+
+fn read_str(bytes: &[u8], index: usize) -> String {
+    let Some(tail) = bytes.get(index..) else {
+        return String::new();
+    };
+    let end = tail.iter().position(|x| *x == 0).unwrap_or(tail.len());
+    String::from_utf8_lossy(&tail[..end]).to_string()
+}
+
+impl ObjectFile {
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 64 || bytes[0..4] != *b"\x7fELF" {
+            return Err(format!("({name}) is not a valid elf file"));
+        }
+        let header = ElfHeader::from_bytes(bytes);
+        if !matches!(header.e_type, EType::Rel) {
+            return Err(format!("({name}) is not a relocatable object file"));
+        }
+        let mut headers = Vec::new();
+        for i in 0..header.e_shnum as usize {
+            let start = header.e_shoff as usize + i * header.e_shentsize as usize;
+            let Some(sh_bytes) = bytes.get(start..start + header.e_shentsize as usize) else {
+                return Err(format!("({name}) has a truncated section header table"));
+            };
+            headers.push(SectionHeader::from_bytes(sh_bytes));
+        }
+        let section_data = |sh: &SectionHeader| -> Result<Vec<u8>, String> {
+            if sh.sh_type == SHT_NOBITS || sh.sh_type == 0 {
+                return Ok(Vec::new());
+            }
+            let start = sh.sh_offset as usize;
+            match bytes.get(start..start + sh.sh_size as usize) {
+                Some(data) => Ok(data.to_vec()),
+                None => Err(format!("({name}) has a truncated section")),
+            }
+        };
+        let shstrtab = match headers.get(header.e_shstrndx as usize) {
+            Some(sh) => section_data(sh)?,
+            None => Vec::new(),
+        };
+
+        let mut sections = Vec::new();
+        for sh in headers.iter() {
+            sections.push(ObjSection {
+                name: read_str(&shstrtab, sh.sh_name as usize),
+                header: *sh,
+                data: section_data(sh)?,
+            });
+        }
+
+        let mut symbols = Vec::new();
+        if let Some(symtab) = sections.iter().find(|s| s.header.sh_type == SHT_SYMTAB) {
+            let strtab = match sections.get(symtab.header.sh_link as usize) {
+                Some(s) => s.data.clone(),
+                None => Vec::new(),
+            };
+            for entry in symtab.data.chunks_exact(24) {
+                let item = SymItem::from_bytes(entry);
+                symbols.push(ObjSymbol {
+                    name: read_str(&strtab, item.st_name as usize),
+                    item,
+                });
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            sections,
+            symbols,
+        })
+    }
+
+    /// Relocation sections with the index of the section they apply to
+    pub fn relocations(&self) -> Vec<(usize, Vec<RelaItem>)> {
+        let mut relas = Vec::new();
+        for sec in self.sections.iter() {
+            if sec.header.sh_type != SHT_RELA {
+                continue;
+            }
+            let items = sec
+                .data
+                .chunks_exact(24)
+                .map(|x| RelaItem::from_bytes(&sec.name, x))
+                .collect();
+            relas.push((sec.header.sh_info as usize, items));
+        }
+        relas
+    }
+}
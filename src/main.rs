@@ -4,7 +4,7 @@
 *
 *   This file provides and entry point to the compiler all the cli arguments and shell
 *   funtionalites are handeled frem this file.
-*   All modules used in the code base must be defined here.
+*   The compiler itself is exposed by the library defined in lib.rs.
 *
 *   LICENSE: MIT
 *
@@ -32,32 +32,22 @@ use std::fs::remove_file;
 use std::path::PathBuf;
 use std::process::Command;
 use std::str::FromStr;
-use std::sync::Mutex;
 use std::{env::args, process::exit};
 
-mod assembler;
-mod compiler;
-mod error_handeling;
-mod formats;
-mod lexer;
-mod linker;
-mod macros;
-mod optim;
-mod parser;
-mod terms;
-mod ir;
 #[cfg(test)]
 mod tests;
-mod utils;
-use assembler::text::x86_64_nasm_generator;
-use compiler::{compile, CompilerContext};
-use utils::get_output_path_from_input;
-
-use crate::compiler::impl_bifs;
-use crate::utils::padding_right;
-
-// --- Static Compiler Defenition
-pub static VERSION: &str = "v0.8.1";
+use nmet::assembler::asm_file::assemble_file;
+use nmet::assembler::text::x86_64_nasm_generator;
+use nmet::compiler::{compile, impl_bifs, CompilerContext};
+use nmet::error_codes;
+use nmet::error_handeling::{report_error_count, ErrorFormat};
+use nmet::formats::ar::{generate_archive, ArMember};
+use nmet::formats::elf::{generate_bin, generate_elf};
+use nmet::linker::link_static;
+use nmet::simulator;
+use nmet::utils::{get_output_path_from_input, padding_right};
+use nmet::vfs::{DiskFs, FileSystem};
+use nmet::{log_error, log_info, log_success, target_string_to_number, CompilerOptions, VERSION};
 
 /// Terget name for assembling using Nasm
 fn assembler_target(co: &CompilerOptions) -> &'static str {
@@ -71,28 +61,6 @@ fn assembler_target(co: &CompilerOptions) -> &'static str {
     }
 }
 
-// nmet [options] (input_file)
-// -nasm -no-link -no-assemble -keep-asm -keep-obj
-// -o outputpath
-// -l<mod_name>
-// -L<mod_path>
-// -T <Target>
-#[derive(Debug, Default, Clone)]
-pub struct CompilerOptions {
-    pub output_path: Option<PathBuf>,
-    pub use_nasm: bool,
-    pub no_linking: bool,
-    pub no_assembling: bool,
-    pub keep_asm: bool,
-    pub keep_obj: bool,
-    pub static_lib: bool,
-    pub dynamic_lib: bool,
-    pub linker_flags: Vec<String>,
-    pub use_libc: bool,
-    pub create_bin: bool,
-    pub target_platform: u8,
-}
-
 fn copywrite() {
     println!("-------------------------------------------------------------");
     println!("| Nmet {} |", padding_right(VERSION, 54));
@@ -103,6 +71,7 @@ fn copywrite() {
 
 pub fn help_command(program_name: &str) {
     println!("{program_name} [options] (input_file)");
+    println!("{program_name} asm [options] (asm_file)");
     println!("Options:");
     println!(
         "  {} Specify output path (default: \"./build/input_file\")",
@@ -120,10 +89,48 @@ pub fn help_command(program_name: &str) {
         "  {} use Nasm Assembler to assemble generated code",
         padding_right("--nasm", 20)
     );
+    println!(
+        "  {} Only Generates a textual IR file",
+        padding_right("--emit=ir", 20)
+    );
+    println!(
+        "  {} Only Generates a listing of the assembled instructions",
+        padding_right("--emit=listing", 20)
+    );
+    println!(
+        "  {} Print diagnostics as colored text (human) or json lines (json)",
+        padding_right("--error-format=<f>", 20)
+    );
+    println!(
+        "  {} Enable a warning (unused-return, unused-value)",
+        padding_right("-W<name>", 20)
+    );
+    println!("  {} Disable a warning", padding_right("-Wno-<name>", 20));
+    println!(
+        "  {} Treat all warnings or the given warning as errors",
+        padding_right("-Werror[=<name>]", 20)
+    );
+    println!("  {} Enable all warnings", padding_right("-Wall", 20));
+    println!(
+        "  {} Optimization level (default: -O0)",
+        padding_right("-O0 | -O1 | -O2", 20)
+    );
+    println!(
+        "  {} Show the changes made by each optimization pass",
+        padding_right("--print-passes", 20)
+    );
+    println!(
+        "  {} Use calls instead of jumps for calls in return statements",
+        padding_right("--no-tail-calls", 20)
+    );
     println!(
         "  {} Do not link the generated object file",
         padding_right("--no-link", 20)
     );
+    println!(
+        "  {} Generate the executable without using ld",
+        padding_right("--internal-link", 20)
+    );
     println!(
         "  {} Only Generates an asm file",
         padding_right("--no-assemble", 20)
@@ -153,6 +160,10 @@ pub fn help_command(program_name: &str) {
         padding_right("--dynamic-lib", 20)
     );
     println!("  {} Generate a static library", padding_right("--lib", 20));
+    println!(
+        "  {} Explain an error code e.g: E0004",
+        padding_right("--explain <CODE>", 20)
+    );
     println!("  {} Show help", padding_right("-h, --help", 20));
     println!("  {} Show Version", padding_right("-v, --version", 20));
 }
@@ -200,21 +211,35 @@ pub fn link_to_exc(path: PathBuf, co: &CompilerOptions) {
     }
 }
 
+/// Generates the executable using the built-in static linker
+pub fn link_internal(path: PathBuf, co: &CompilerOptions) {
+    log_info!(
+        "Linking object file - generating {}",
+        path.with_extension("").to_string_lossy()
+    );
+    if let Err(e) = link_static(&path, &co.linker_flags) {
+        log_error!("Failed to Link Exectable: {e}");
+        exit(-1);
+    }
+    log_success!("Executable file have been Generated!");
+}
+
 // Link to Static Library
 pub fn link_to_static_lib(path: PathBuf, _: &CompilerOptions) {
     log_info!(
         "Archiving object file - generating {}",
         path.with_extension("a").to_string_lossy()
     );
-    let linker_output = Command::new("ar")
-        .arg("-cvq")
-        .arg(path.with_extension("a"))
-        .arg(path.with_extension("o"))
-        .output()
-        .expect("Can not archive using ar command!");
-    if !linker_output.status.success() {
-        log_error!("Failed to Generate Static Library: Status code non zero");
-        eprintln!("{}", String::from_utf8(linker_output.stderr).unwrap());
+    let obj_path = path.with_extension("o");
+    let member = std::fs::read(&obj_path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            let name = obj_path.file_name().unwrap().to_string_lossy();
+            ArMember::from_object(&name, data)
+        });
+    let result = member.and_then(|m| generate_archive(&path, &[m]).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        log_error!("Failed to Generate Static Library: {e}");
     } else {
         log_success!("Static Library file have been Generated!");
     }
@@ -243,20 +268,104 @@ pub fn link_to_dynamic_lib(path: PathBuf, co: &CompilerOptions) {
     }
 }
 
+/// Links the generated object file based on the requested output
+fn link_output(out_path: PathBuf, co: &CompilerOptions) {
+    if co.no_linking {
+        return;
+    }
+    if co.dynamic_lib {
+        link_to_dynamic_lib(out_path, co);
+    } else if co.static_lib {
+        link_to_static_lib(out_path, co);
+    } else if co.internal_link {
+        link_internal(out_path, co);
+    } else {
+        link_to_exc(out_path, co);
+    }
+}
+
+/// Assembles a nasm file into an elf object file without using nasm
+pub fn setup_assembler(input: String, co: &CompilerOptions) {
+    let out_path = match co.output_path.clone() {
+        None => get_output_path_from_input(input.clone().into()),
+        Some(pt) => pt,
+    };
+    let source = match std::fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(e) => {
+            log_error!("Can not read {input}: {e}");
+            exit(-1);
+        }
+    };
+    let mut compiler_context = CompilerContext::new(input.clone(), co);
+    log_info!("Assembling {input}...");
+    compiler_context.codegen = match assemble_file(&input, &source) {
+        Ok(codegen) => codegen,
+        Err(e) => {
+            log_error!("Failed to Assemble: {e}");
+            exit(-1);
+        }
+    };
+    log_info!("Generating elf object file...");
+    if let Err(e) = generate_elf(&mut DiskFs, out_path.as_path(), &mut compiler_context) {
+        log_error!("Failed to generate the elf object file: {e}");
+        exit(-1);
+    }
+    log_success!("Elf object file Generated!");
+    link_output(out_path.clone(), co);
+    if !co.keep_obj && remove_file(out_path.with_extension("o")).is_ok() {
+        log_info!("Removing object files")
+    }
+}
+
 pub fn setup_compiler(input: String, co: &CompilerOptions) {
     let out_path = match co.output_path.clone() {
         None => get_output_path_from_input(input.clone().into()),
         Some(pt) => pt,
     };
+    let mut fs = DiskFs;
     let mut compiler_context = CompilerContext::new(input.clone(), co);
 
-    compile(&mut compiler_context, input.clone());
+    let result = compile(&mut compiler_context, &fs, input.clone());
+    for line in compiler_context.pass_log.iter() {
+        println!("{line}");
+    }
+    for diag in compiler_context.diagnostics.iter() {
+        diag.emit(co.error_format);
+    }
+    if let Err(errors) = result {
+        report_error_count("Compilation", errors, co.error_format);
+        exit(-1);
+    }
     impl_bifs(&mut compiler_context);
-    let prefix = out_path.parent().unwrap();
-    std::fs::create_dir_all(prefix).unwrap();
+    if co.opt_level > 0 {
+        compiler_context.codegen.peephole();
+    }
+    if co.emit_ir {
+        log_info!("Generating IR text file...");
+        let ir = compiler_context.ir.to_string();
+        fs.write(&out_path.with_extension("ir"), ir.as_bytes())
+            .unwrap();
+        log_success!("IR Text file Generated!");
+        return;
+    }
+    if co.emit_listing {
+        log_info!("Generating listing file...");
+        let listing = match compiler_context.codegen.text_section_listing() {
+            Ok(listing) => listing,
+            Err(e) => {
+                log_error!("Failed to generate the listing: {e}");
+                exit(-1);
+            }
+        };
+        fs.write(&out_path.with_extension("lst"), listing.as_bytes())
+            .unwrap();
+        log_success!("Listing file Generated!");
+        return;
+    }
     if co.use_nasm {
         log_info!("Generating asm text file...");
-        x86_64_nasm_generator(out_path.as_path(), &compiler_context).unwrap();
+        x86_64_nasm_generator(&mut fs, out_path.as_path(), &compiler_context).unwrap();
         log_success!("Nasm Text file Generated!");
         if co.no_assembling {
             return;
@@ -265,22 +374,20 @@ pub fn setup_compiler(input: String, co: &CompilerOptions) {
     } else {
         if co.create_bin {
             log_info!("Generating binary file...");
-            crate::formats::elf::generate_bin(out_path.as_path(), &mut compiler_context);
+            if let Err(e) = generate_bin(&mut fs, out_path.as_path(), &mut compiler_context) {
+                log_error!("Failed to generate the binary file: {e}");
+                exit(-1);
+            }
             log_success!("Instructions Binary file Generated!");
         }
         log_info!("Generating elf object file...");
-        crate::formats::elf::generate_elf(out_path.as_path(), &mut compiler_context);
-        log_success!("Elf object file Generated!");
-    }
-    if !co.no_linking {
-        if co.dynamic_lib {
-            link_to_dynamic_lib(out_path.clone(), co);
-        } else if co.static_lib {
-            link_to_static_lib(out_path.clone(), co);
-        } else {
-            link_to_exc(out_path.clone(), co);
+        if let Err(e) = generate_elf(&mut fs, out_path.as_path(), &mut compiler_context) {
+            log_error!("Failed to generate the elf object file: {e}");
+            exit(-1);
         }
+        log_success!("Elf object file Generated!");
     }
+    link_output(out_path.clone(), co);
     if !co.keep_asm && remove_file(out_path.with_extension("asm")).is_ok() {
         log_info!("Removing asm files")
     }
@@ -296,20 +403,40 @@ fn collect_compiler_options(args: &mut Args) -> (String, CompilerOptions) {
     }
     let compiler_path = args.next().unwrap();
     let mut input_path = String::new();
-    loop {
-        let Some(arg) = args.next() else {
-            break;
-        };
+    while let Some(arg) = args.next() {
         if arg.starts_with("-l") || arg.starts_with("-L") {
             co.linker_flags.push(arg.clone());
             continue;
         }
+        if arg.starts_with("-W") {
+            if let Err(e) = co.warnings.apply_flag(&arg) {
+                log_error!("{e}!");
+                help_command(&compiler_path);
+                exit(-1);
+            }
+            continue;
+        }
         match arg.as_str() {
             "-h" | "--help" => {
                 copywrite();
                 help_command(&compiler_path);
                 exit(0);
             }
+            "--explain" => {
+                let Some(code) = args.next() else {
+                    log_error!("No error code after --explain option!");
+                    help_command(&compiler_path);
+                    exit(-1);
+                };
+                match error_codes::explain(&code) {
+                    Some(explanation) => print!("{explanation}"),
+                    None => {
+                        log_error!("Unknown error code ({code})!");
+                        exit(-1);
+                    }
+                }
+                exit(0);
+            }
             "-v" | "--version" => {
                 copywrite();
                 exit(0);
@@ -336,6 +463,18 @@ fn collect_compiler_options(args: &mut Args) -> (String, CompilerOptions) {
                 co.dynamic_lib = true;
             }
             "-b" | "--bin" => co.create_bin = true,
+            "-s" | "--simulate" => co.simulate = true,
+            "--internal-link" => co.internal_link = true,
+            "--emit=ir" => co.emit_ir = true,
+            "--emit=listing" => co.emit_listing = true,
+            "--error-format=human" => co.error_format = ErrorFormat::Human,
+            "--error-format=json" => co.error_format = ErrorFormat::Json,
+            "-O0" => co.opt_level = 0,
+            "-O1" => co.opt_level = 1,
+            "-O2" => co.opt_level = 2,
+            "--print-passes" => co.print_passes = true,
+            "--no-tail-calls" => co.no_tail_calls = true,
+            "asm" if input_path.is_empty() && !co.asm_mode => co.asm_mode = true,
             "-T" => {
                 let Some(target) = args.next() else {
                     log_error!("No target specified!");
@@ -371,22 +510,18 @@ fn collect_compiler_options(args: &mut Args) -> (String, CompilerOptions) {
     (input_path, co)
 }
 
-static TARGET_PLATFORM: Mutex<u8> = Mutex::new(0);
-
-pub fn target_string_to_number(target: &str) -> u8 {
-    match target {
-        "LINUX" | "linux" => 0,
-        "WINDOWS" | "WIN" | "windows" | "win" => 1,
-        _ => u8::MAX,
-    }
-}
-
 fn main() -> Result<(), Box<dyn Error>> {
     // parse_elf_objfile("./tests/libadd.a".to_string());
 
     let mut args = args();
     let (ipath, co) = collect_compiler_options(&mut args);
-    *TARGET_PLATFORM.lock().unwrap() = co.target_platform;
-    setup_compiler(ipath, &co);
+    if co.simulate {
+        exit(simulator::simulate_program(ipath, &co));
+    }
+    if co.asm_mode {
+        setup_assembler(ipath, &co);
+    } else {
+        setup_compiler(ipath, &co);
+    }
     Ok(())
 }
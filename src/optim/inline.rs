@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{
+    codegen::{ARG_REGS, FLOAT_ARG_REGS},
+    IRCall, IRExpr, IRFunc, IRGenerator, IRInline, IROperation, IRStmt, IRTag, IRType, IRVarBase,
+};
+
+/// Replaces calls to small functions with the body of the function
+/// * threshold - maximum number of statements of a function inlined without the inline attribute
+///
+/// functions are visited bottom up in the call graph so inlined bodies are already flattened
+pub fn inline_calls(ir: &mut IRGenerator, threshold: usize) {
+    let index: HashMap<String, usize> = ir
+        .code
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.name.clone(), i))
+        .collect();
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    for i in 0..ir.code.len() {
+        post_order(ir, &index, i, &mut visited, &mut order);
+    }
+    let mut inlined = 0;
+    for caller in order {
+        let mut func = ir.code[caller].clone();
+        let instrs = std::mem::take(&mut func.instrs);
+        for stmt in instrs.into_iter() {
+            let callee = match &stmt.opr {
+                IROperation::Call(call) | IROperation::TailCall(call) => {
+                    index.get(&call.name).map(|c| (call, *c))
+                }
+                _ => None,
+            };
+            let tail = matches!(stmt.opr, IROperation::TailCall(_));
+            let body = callee
+                .filter(|(_, c)| *c != caller && can_inline(&ir.code[*c], threshold))
+                .and_then(|(call, c)| {
+                    inline_body(&mut func, stmt.dst, tail, call, &ir.code[c], inlined)
+                });
+            match body {
+                Some(body) => {
+                    func.instrs.extend(body);
+                    inlined += 1;
+                }
+                None => func.instrs.push(stmt),
+            }
+        }
+        ir.code[caller] = func;
+    }
+}
+
+fn post_order(
+    ir: &IRGenerator,
+    index: &HashMap<String, usize>,
+    func: usize,
+    visited: &mut HashSet<usize>,
+    order: &mut Vec<usize>,
+) {
+    if !visited.insert(func) {
+        return;
+    }
+    for stmt in ir.code[func].instrs.iter() {
+        if let IROperation::Call(call) | IROperation::TailCall(call) = &stmt.opr {
+            if let Some(callee) = index.get(&call.name) {
+                post_order(ir, index, *callee, visited, order);
+            }
+        }
+    }
+    order.push(func);
+}
+
+/// Number of statements that generate code
+fn size(func: &IRFunc) -> usize {
+    func.instrs
+        .iter()
+        .filter(|s| !matches!(s.opr, IROperation::Label(_)))
+        .count()
+}
+
+fn can_inline(func: &IRFunc, threshold: usize) -> bool {
+    // inline assembly may depend on the stack frame of the function
+    let has_asm = func
+        .instrs
+        .iter()
+        .any(|s| matches!(s.opr, IROperation::Asm(_)));
+    let recursive = func.instrs.iter().any(|s| match &s.opr {
+        IROperation::Call(call) | IROperation::TailCall(call) => call.name == func.name,
+        _ => false,
+    });
+    if has_asm || recursive {
+        return false;
+    }
+    match func.inline {
+        IRInline::Always => true,
+        IRInline::Never => false,
+        IRInline::Auto => size(func) <= threshold,
+    }
+}
+
+/// Arguments in the order they are read by the callee (registers then stack)
+fn split_args(call: &IRCall) -> (Vec<IRExpr>, Vec<IRExpr>, Vec<IRExpr>) {
+    let (mut ints, mut floats, mut stack) = (Vec::new(), Vec::new(), Vec::new());
+    for (arg, ty) in call.args.iter() {
+        match ty {
+            IRType::Int if ints.len() < ARG_REGS.len() => ints.push(arg.clone()),
+            IRType::Float if floats.len() < FLOAT_ARG_REGS.len() => floats.push(arg.clone()),
+            _ => stack.push(arg.clone()),
+        }
+    }
+    (ints, floats, stack)
+}
+
+/// Body of the callee that can replace the call
+/// variables of the callee are moved to the end of the caller stack frame
+/// and returns become jumps to the end of the body
+/// * tail - the call is a tail call so returns and tail calls of the callee can be kept
+fn inline_body(
+    caller: &mut IRFunc,
+    dst: Option<usize>,
+    tail: bool,
+    call: &IRCall,
+    callee: &IRFunc,
+    id: usize,
+) -> Option<Vec<IRStmt>> {
+    let (ints, floats, stack) = split_args(call);
+    let frame_base = caller.frame_size.div_ceil(8) * 8;
+    let temp_base = caller.temp_vars;
+    let prefix = format!("{}.inline{id}", caller.name);
+    let tag = |tag: &IRTag| -> IRTag { format!("{prefix}.{}", tag.tag).into() };
+    let end: IRTag = format!("{prefix}.end").into();
+    let mut body = Vec::new();
+    for stmt in callee.instrs.iter() {
+        let mut opr = stmt.opr.clone();
+        for expr in opr.operands_mut() {
+            if let IRExpr::TmpVar(t) = expr {
+                *t += temp_base;
+            }
+        }
+        if let IROperation::Load(var) | IROperation::Lea(var) | IROperation::Store(var, _) =
+            &mut opr
+        {
+            if var.base == IRVarBase::Frame {
+                var.offset -= frame_base as i32;
+            }
+        }
+        let opr = match opr {
+            IROperation::Arg(n) => IROperation::Mov(ints.get(n)?.clone()),
+            IROperation::FArg(n) => IROperation::Mov(floats.get(n)?.clone()),
+            IROperation::StackArg(n) => IROperation::Mov(stack.get(n)?.clone()),
+            IROperation::Label(t) => IROperation::Label(tag(&t)),
+            IROperation::Jmp(t) => IROperation::Jmp(tag(&t)),
+            IROperation::Jz(val, t) => IROperation::Jz(val, tag(&t)),
+            IROperation::Jnz(val, t) => IROperation::Jnz(val, tag(&t)),
+            IROperation::Ret(val) if tail => IROperation::Ret(val),
+            IROperation::Ret(val) => {
+                if let (Some(dst), Some(val)) = (dst, val) {
+                    body.push(IRStmt {
+                        dst: Some(dst),
+                        opr: IROperation::Mov(val),
+                    });
+                }
+                IROperation::Jmp(end.clone())
+            }
+            // calls in return position of the callee are only tail calls of the caller
+            // if the inlined call was a tail call itself
+            IROperation::TailCall(call) if !tail => {
+                body.push(IRStmt {
+                    dst,
+                    opr: IROperation::Call(call),
+                });
+                IROperation::Jmp(end.clone())
+            }
+            opr => opr,
+        };
+        body.push(IRStmt {
+            dst: stmt.dst.map(|t| t + temp_base),
+            opr,
+        });
+    }
+    if !tail {
+        body.push(IRStmt {
+            dst: None,
+            opr: IROperation::Label(end),
+        });
+    } else if !matches!(
+        body.last().map(|s| &s.opr),
+        Some(IROperation::Ret(_) | IROperation::TailCall(_) | IROperation::Jmp(_))
+    ) {
+        // the callee falls through to the end of its body
+        body.push(IRStmt {
+            dst: None,
+            opr: IROperation::Ret(None),
+        });
+    }
+    caller.temp_vars += callee.temp_vars;
+    caller.frame_size = frame_base + callee.frame_size;
+    Some(body)
+}
+
+#[test]
+fn test_inline_calls() {
+    use crate::ir::text::parse_ir;
+    let mut ir = parse_ir(
+        "
+func add frame=8 temps=3
+    %0 = arg 0
+    store [frame-8]:8, %0
+    %1 = load [frame-8]:8
+    %2 = arg 1
+    jz %2, add.zero
+    ret %1
+add.zero:
+    ret 0
+end
+func big frame=0 temps=0 noinline
+    ret
+end
+func main frame=4 temps=2
+    %0 = call add(1, %1)
+    %1 = call big()
+    ret %0
+end
+",
+    )
+    .unwrap();
+    inline_calls(&mut ir, 8);
+    let main = &ir.code[2];
+    assert_eq!(main.frame_size, 16);
+    assert_eq!(main.temp_vars, 5);
+    assert_eq!(
+        main.to_string(),
+        "func main frame=16 temps=5
+    %2 = mov 1
+    store [frame-16]:8, %2
+    %3 = load [frame-16]:8
+    %4 = mov %1
+    jz %4, main.inline0.add.zero
+    %0 = mov %3
+    jmp main.inline0.end
+main.inline0.add.zero:
+    %0 = mov 0
+    jmp main.inline0.end
+main.inline0.end:
+    %1 = call big()
+    ret %0
+end
+"
+    );
+}
+
+#[test]
+fn test_inline_tail_calls() {
+    use crate::ir::text::parse_ir;
+    let mut ir = parse_ir(
+        "
+func next frame=0 temps=1
+    %0 = arg 0
+    tailcall step(%0)
+end
+func step frame=0 temps=1
+    %0 = arg 0
+    %0 = call next(%0)
+    tailcall next(%0)
+end
+func one frame=0 temps=1
+    %0 = arg 0
+    jz %0, one.zero
+    ret %0
+one.zero:
+    ret 1
+end
+func main frame=0 temps=1
+    %0 = arg 0
+    tailcall one(%0)
+end
+",
+    )
+    .unwrap();
+    inline_calls(&mut ir, 8);
+    assert_eq!(
+        ir.code[1].to_string(),
+        "func step frame=0 temps=3
+    %0 = arg 0
+    %1 = mov %0
+    %0 = call step(%1)
+    jmp step.inline0.end
+step.inline0.end:
+    %2 = mov %0
+    tailcall step(%2)
+end
+"
+    );
+    // returns of a callee inlined in tail position return from the caller
+    assert_eq!(
+        ir.code[3].to_string(),
+        "func main frame=0 temps=2
+    %0 = arg 0
+    %1 = mov %0
+    jz %1, main.inline2.one.zero
+    ret %1
+main.inline2.one.zero:
+    ret 1
+end
+"
+    );
+}
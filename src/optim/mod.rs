@@ -1,99 +1,244 @@
+pub mod inline;
+pub mod passes;
+
 use crate::{
-    assembler::{instructions::Opr, register::Reg},
-    error_handeling::CompilationError,
-    parser::{
-        expr::{CompareOp, Op},
-        types::VariableType,
-    },
+    ir::{regalloc::promote_locals, IRExpr, IRFunc, IRGenerator, IRStmt},
+    parser::types::VariableType,
+};
+
+use self::{
+    inline::inline_calls,
+    passes::{branch_fold, const_prop, copy_prop, cse, dce},
 };
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExprOpr {
-    pub value: Opr,
+    pub value: IRExpr,
     pub vtype: VariableType,
 }
 
 impl ExprOpr {
-    pub fn new(value: impl Into<Opr>, vtype: VariableType) -> Self {
+    pub fn new(value: IRExpr, vtype: VariableType) -> Self {
+        Self { value, vtype }
+    }
+}
+
+/// Optimization passes over IR functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    /// Keeps scalar variables of the stack frame in temporaries
+    PromoteLocals,
+    ConstProp,
+    CopyProp,
+    Cse,
+    Dce,
+    BranchFold,
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PromoteLocals => "promote-locals",
+            Self::ConstProp => "const-prop",
+            Self::CopyProp => "copy-prop",
+            Self::Cse => "cse",
+            Self::Dce => "dce",
+            Self::BranchFold => "branch-fold",
+        }
+    }
+
+    pub fn run(&self, func: &mut IRFunc) {
+        match self {
+            Self::PromoteLocals => promote_locals(func),
+            Self::ConstProp => const_prop(func),
+            Self::CopyProp => copy_prop(func),
+            Self::Cse => cse(func),
+            Self::Dce => dce(func),
+            Self::BranchFold => branch_fold(func),
+        }
+    }
+}
+
+/// Runs a pipeline of passes on every function
+/// * inline_threshold - size of functions inlined before running the passes (None disables inlining)
+/// * iterations - maximum number of times the pipeline is repeated until nothing changes
+/// * log - records the statements changed by each pass
+#[derive(Debug, Clone)]
+pub struct PassManager {
+    pub passes: Vec<Pass>,
+    pub inline_threshold: Option<usize>,
+    pub iterations: usize,
+    pub log: bool,
+}
+
+impl PassManager {
+    pub fn new(passes: Vec<Pass>) -> Self {
         Self {
-            value: value.into(),
-            vtype,
+            passes,
+            inline_threshold: None,
+            iterations: 1,
+            log: false,
+        }
+    }
+
+    /// Pipeline of the optimization level (-O0, -O1, -O2)
+    pub fn for_level(level: u8) -> Self {
+        use Pass::*;
+        match level {
+            0 => Self::new(vec![]),
+            1 => Self {
+                inline_threshold: Some(8),
+                ..Self::new(vec![PromoteLocals, ConstProp, CopyProp, BranchFold, Dce])
+            },
+            _ => Self {
+                inline_threshold: Some(24),
+                iterations: 8,
+                ..Self::new(vec![
+                    PromoteLocals,
+                    ConstProp,
+                    CopyProp,
+                    Cse,
+                    CopyProp,
+                    BranchFold,
+                    Dce,
+                ])
+            },
         }
     }
 
-    pub fn is_temp(&self) -> bool {
-        match &self.value {
-            Opr::Imm8(_) | Opr::Imm32(_) | Opr::Imm64(_) | Opr::Rela(_) | Opr::Loc(_) => false,
-            Opr::Mem(m) => {
-                if m.is_rela() {
-                    return false;
+    /// Returns the lines of the changes made by the passes if log is set
+    pub fn run(&self, ir: &mut IRGenerator) -> Vec<String> {
+        let mut log = Vec::new();
+        if let Some(threshold) = self.inline_threshold {
+            let before = ir.code.clone();
+            inline_calls(ir, threshold);
+            if self.log {
+                for (old, func) in before.iter().zip(ir.code.iter()) {
+                    if old.instrs != func.instrs {
+                        log_changes(&mut log, "inline", func, &old.instrs);
+                    }
                 }
-                m.get_register() != Reg::RBP || m.get_s_register().is_some()
             }
-            Opr::R64(_) | Opr::R32(_) | Opr::R16(_) | Opr::R8(_) => true,
         }
+        for func in ir.code.iter_mut() {
+            self.run_func(func, &mut log);
+        }
+        log
     }
-}
 
-pub fn fold_binary_expr(
-    left: &ExprOpr,
-    right: &ExprOpr,
-    op: &Op,
-) -> Result<ExprOpr, CompilationError> {
-    let res_type = left.vtype.cast(&right.vtype)?;
-    let l_val = left.value.get_literal_value();
-    let r_val = right.value.get_literal_value();
-    let val = match op {
-        Op::Plus => l_val + r_val,
-        Op::Sub => l_val - r_val,
-        Op::Multi => l_val * r_val,
-        Op::Devide => l_val / r_val,
-        Op::Mod => l_val % r_val,
-        Op::And => l_val & r_val,
-        Op::Or => l_val | r_val,
-        Op::Lsh => l_val << r_val,
-        Op::Rsh => l_val >> r_val,
-        Op::LogicalOr => ((l_val != 0) || (r_val != 0)) as i64,
-        Op::LogicalAnd => ((l_val != 0) && (r_val != 0)) as i64,
-        Op::Not => {
-            return Err(CompilationError::InValidBinaryOperation(
-                op.to_owned(),
-                left.vtype.to_string(),
-                right.vtype.to_string(),
-            ));
+    fn run_func(&self, func: &mut IRFunc, log: &mut Vec<String>) {
+        for _ in 0..self.iterations {
+            let mut changed = false;
+            for pass in self.passes.iter() {
+                let before = func.instrs.clone();
+                pass.run(func);
+                if before == func.instrs {
+                    continue;
+                }
+                changed = true;
+                if self.log {
+                    log_changes(log, pass.name(), func, &before);
+                }
+            }
+            if !changed {
+                break;
+            }
         }
-    };
-    Ok(ExprOpr::new(val, res_type))
+    }
 }
 
-pub fn fold_compare_expr(
-    left: &ExprOpr,
-    right: &ExprOpr,
-    op: &CompareOp,
-) -> Result<ExprOpr, CompilationError> {
-    let _ = left.vtype.cast(&right.vtype)?;
-    let l_val = left.value.get_literal_value();
-    let r_val = right.value.get_literal_value();
-    let val = match op {
-        CompareOp::Eq => (l_val == r_val) as i32,
-        CompareOp::NotEq => (l_val != r_val) as i32,
-        CompareOp::Bigger => (l_val > r_val) as i32,
-        CompareOp::Smaller => (l_val < r_val) as i32,
-        CompareOp::BiggerEq => (l_val >= r_val) as i32,
-        CompareOp::SmallerEq => (l_val <= r_val) as i32,
-    };
-    Ok(ExprOpr::new(val, VariableType::Bool))
+fn log_changes(log: &mut Vec<String>, pass: &str, func: &IRFunc, before: &[IRStmt]) {
+    log.push(format!(
+        "--- {} on {} ({} -> {} statements)",
+        pass,
+        func.name,
+        before.len(),
+        func.instrs.len()
+    ));
+    let before: Vec<String> = before.iter().map(|s| s.to_string()).collect();
+    let after: Vec<String> = func.instrs.iter().map(|s| s.to_string()).collect();
+    log.extend(diff_lines(&before, &after));
 }
 
-pub fn fold_unary_expr(left: &ExprOpr, op: &Op) -> Result<ExprOpr, CompilationError> {
-    let l_val = left.value.get_literal_value();
-    let val = match op {
-        Op::Sub => -l_val,
-        Op::Plus => l_val,
-        Op::Not => !l_val,
-        _ => {
-            unreachable!();
+/// Lines removed (-) and added (+) between two versions of a text
+pub fn diff_lines(before: &[String], after: &[String]) -> Vec<String> {
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &before[prefix..before.len() - suffix];
+    let b = &after[prefix..after.len() - suffix];
+    // longest common subsequence of the changed part
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i] == b[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
         }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[test]
+fn test_diff_lines() {
+    let lines = |s: &str| s.split(' ').map(|l| l.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        diff_lines(&lines("a b c d"), &lines("a x c d e")),
+        vec!["-b", "+x", "+e"]
+    );
+}
+
+#[test]
+fn test_pass_manager() {
+    let mut ir = crate::ir::text::parse_ir(
+        "
+func main frame=8 temps=4
+    store [frame-8]:8, 6
+    %0 = load [frame-8]:8
+    %1 = mul %0, 7
+    %2 = cmp.eq %1, 42
+    jz %2, main.else
+    %3 = call print(%1)
+main.else:
+    ret
+end
+",
+    )
+    .unwrap();
+    let mut logged = ir.clone();
+    assert!(PassManager::for_level(2).run(&mut ir).is_empty());
+    assert_eq!(
+        ir.code[0].to_string(),
+        "func main frame=8 temps=5\n    %3 = call print(42)\n    ret\nend\n"
+    );
+    let passes = PassManager {
+        log: true,
+        ..PassManager::for_level(2)
     };
-    Ok(ExprOpr::new(val, left.vtype.to_owned()))
+    let log = passes.run(&mut logged);
+    assert_eq!(log[0], "--- promote-locals on main (8 -> 8 statements)");
+    assert!(log.contains(&"+    %3 = call print(42)".to_string()));
 }
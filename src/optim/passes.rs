@@ -0,0 +1,386 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::ir::{
+    liveness::{basic_blocks, live_in_sets, BasicBlock},
+    IRExpr, IRFunc, IROperation, IRStmt,
+};
+
+/// Known value of a temporary at some point of the function
+type Facts = HashMap<usize, IRExpr>;
+
+/// Replaces the operands of a statement with their known values
+/// returns the constant result of the statement if it can be computed
+fn substitute(stmt: &mut IRStmt, facts: &Facts) -> Option<i64> {
+    for opr in stmt.opr.operands_mut() {
+        if let IRExpr::TmpVar(t) = opr {
+            if let Some(value) = facts.get(t) {
+                *opr = value.clone();
+            }
+        }
+    }
+    stmt.dst.and(stmt.opr.fold())
+}
+
+/// Updates known values after a statement
+/// * keep - decides which kind of values are tracked (immediates or temporaries)
+fn transfer(stmt: &IRStmt, folded: Option<i64>, facts: &mut Facts, keep: fn(&IRExpr) -> bool) {
+    let Some(dst) = stmt.dst else {
+        return;
+    };
+    facts.remove(&dst);
+    facts.retain(|_, v| *v != IRExpr::TmpVar(dst));
+    let value = match (folded, &stmt.opr) {
+        (Some(val), _) => IRExpr::Imm(val),
+        (None, IROperation::Mov(value)) if *value != IRExpr::TmpVar(dst) => value.clone(),
+        _ => return,
+    };
+    if keep(&value) {
+        facts.insert(dst, value);
+    }
+}
+
+fn meet(a: &Facts, b: &Facts) -> Facts {
+    a.iter()
+        .filter(|(t, v)| b.get(t) == Some(v))
+        .map(|(t, v)| (*t, v.clone()))
+        .collect()
+}
+
+fn predecessors(blocks: &[BasicBlock]) -> Vec<Vec<usize>> {
+    let mut preds = vec![vec![]; blocks.len()];
+    for (b, block) in blocks.iter().enumerate() {
+        for s in block.succs.iter() {
+            preds[*s].push(b);
+        }
+    }
+    preds
+}
+
+/// Forward data flow over the values of temporaries
+/// operands are replaced with values that are known on every path reaching them
+fn propagate(func: &mut IRFunc, keep: fn(&IRExpr) -> bool) {
+    let blocks = basic_blocks(func);
+    let preds = predecessors(&blocks);
+    // None means the block has not been reached yet
+    let mut outs: Vec<Option<Facts>> = vec![None; blocks.len()];
+    let block_in = |b: usize, outs: &[Option<Facts>]| -> Facts {
+        if b == 0 {
+            return Facts::new();
+        }
+        preds[b]
+            .iter()
+            .filter_map(|p| outs[*p].as_ref())
+            .fold(None, |acc: Option<Facts>, out| match acc {
+                None => Some(out.clone()),
+                Some(acc) => Some(meet(&acc, out)),
+            })
+            .unwrap_or_default()
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (b, block) in blocks.iter().enumerate() {
+            if b != 0 && preds[b].iter().all(|p| outs[*p].is_none()) {
+                continue;
+            }
+            let mut facts = block_in(b, &outs);
+            for stmt in func.instrs[block.start..block.end].iter() {
+                let mut stmt = stmt.clone();
+                let folded = substitute(&mut stmt, &facts);
+                transfer(&stmt, folded, &mut facts, keep);
+            }
+            if outs[b].as_ref() != Some(&facts) {
+                outs[b] = Some(facts);
+                changed = true;
+            }
+        }
+    }
+    for (b, block) in blocks.iter().enumerate() {
+        let mut facts = block_in(b, &outs);
+        for stmt in func.instrs[block.start..block.end].iter_mut() {
+            let folded = substitute(stmt, &facts);
+            if let Some(val) = folded {
+                stmt.opr = IROperation::Mov(IRExpr::Imm(val));
+            }
+            transfer(stmt, folded, &mut facts, keep);
+        }
+    }
+}
+
+/// Replaces temporaries holding constants with their value and folds the results
+pub fn const_prop(func: &mut IRFunc) {
+    propagate(func, IRExpr::is_imm);
+}
+
+/// Replaces copies of temporaries with the original temporary
+pub fn copy_prop(func: &mut IRFunc) {
+    propagate(func, |v| matches!(v, IRExpr::TmpVar(_)));
+}
+
+/// Removes statements without side effects whose results are never used
+pub fn dce(func: &mut IRFunc) {
+    loop {
+        let blocks = basic_blocks(func);
+        let live_in = live_in_sets(func, &blocks);
+        let mut dead = HashSet::new();
+        for block in blocks.iter() {
+            let mut live: BTreeSet<usize> = BTreeSet::new();
+            for s in block.succs.iter() {
+                live.extend(live_in[*s].iter());
+            }
+            for i in (block.start..block.end).rev() {
+                let stmt = &func.instrs[i];
+                if let Some(dst) = stmt.dst {
+                    let self_copy = stmt.opr == IROperation::Mov(IRExpr::TmpVar(dst));
+                    if self_copy || (!live.contains(&dst) && !stmt.opr.has_side_effects()) {
+                        dead.insert(i);
+                        continue;
+                    }
+                    live.remove(&dst);
+                }
+                live.extend(stmt.opr.used_temps());
+            }
+        }
+        if dead.is_empty() {
+            return;
+        }
+        func.instrs = std::mem::take(&mut func.instrs)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !dead.contains(i))
+            .map(|(_, stmt)| stmt)
+            .collect();
+    }
+}
+
+/// Reuses the result of an identical computation in the same basic block
+pub fn cse(func: &mut IRFunc) {
+    let blocks = basic_blocks(func);
+    for block in blocks.iter() {
+        let mut available: Vec<(IROperation, usize)> = Vec::new();
+        for stmt in func.instrs[block.start..block.end].iter_mut() {
+            let Some(dst) = stmt.dst else {
+                continue;
+            };
+            // memory may change between loads
+            let reusable = !stmt.opr.has_side_effects()
+                && !matches!(stmt.opr, IROperation::Mov(_) | IROperation::Load(_));
+            if reusable {
+                if let Some((_, t)) = available.iter().find(|(opr, _)| *opr == stmt.opr) {
+                    stmt.opr = IROperation::Mov(IRExpr::TmpVar(*t));
+                }
+            }
+            available.retain(|(opr, t)| *t != dst && !opr.used_temps().contains(&dst));
+            if reusable && !stmt.opr.used_temps().contains(&dst) {
+                available.push((stmt.opr.clone(), dst));
+            }
+        }
+    }
+}
+
+/// Resolves jumps on constant conditions and removes unreachable code
+/// jumps to the next statement and labels that are never jumped to are removed
+pub fn branch_fold(func: &mut IRFunc) {
+    func.instrs.retain_mut(|stmt| {
+        let (taken, tag) = match &stmt.opr {
+            IROperation::Jz(IRExpr::Imm(v), tag) => (*v == 0, tag.clone()),
+            IROperation::Jnz(IRExpr::Imm(v), tag) => (*v != 0, tag.clone()),
+            _ => return true,
+        };
+        stmt.opr = IROperation::Jmp(tag);
+        taken
+    });
+    remove_fallthrough_jumps(func);
+    // inline assembly may jump to any label
+    if func
+        .instrs
+        .iter()
+        .any(|s| matches!(s.opr, IROperation::Asm(_)))
+    {
+        return;
+    }
+    let blocks = basic_blocks(func);
+    let mut reachable = vec![false; blocks.len()];
+    let mut stack = vec![0];
+    while let Some(b) = stack.pop() {
+        if b >= blocks.len() || reachable[b] {
+            continue;
+        }
+        reachable[b] = true;
+        stack.extend(blocks[b].succs.iter());
+    }
+    let mut instrs = Vec::new();
+    for (b, block) in blocks.iter().enumerate() {
+        if reachable[b] {
+            instrs.extend(func.instrs[block.start..block.end].iter().cloned());
+        }
+    }
+    func.instrs = instrs;
+    remove_fallthrough_jumps(func);
+    let targets: HashSet<String> = func
+        .instrs
+        .iter()
+        .filter_map(|s| match &s.opr {
+            IROperation::Jmp(tag) | IROperation::Jz(_, tag) | IROperation::Jnz(_, tag) => {
+                Some(tag.tag.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    func.instrs
+        .retain(|s| !matches!(&s.opr, IROperation::Label(tag) if !targets.contains(&tag.tag)));
+}
+
+fn remove_fallthrough_jumps(func: &mut IRFunc) {
+    let mut i = 0;
+    while i < func.instrs.len() {
+        if let IROperation::Jmp(tag) = &func.instrs[i].opr {
+            let falls_through = func.instrs[i + 1..]
+                .iter()
+                .map_while(|s| match &s.opr {
+                    IROperation::Label(label) => Some(label),
+                    _ => None,
+                })
+                .any(|label| label == tag);
+            if falls_through {
+                func.instrs.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+fn parse_func(source: &str) -> IRFunc {
+    crate::ir::text::parse_ir(source).unwrap().code.remove(0)
+}
+
+#[test]
+fn test_const_prop() {
+    let mut func = parse_func(
+        "
+func main frame=0 temps=4
+    %0 = mov 2
+    %1 = mul %0, 21
+    jz %1, main.end
+    %0 = arg 0
+main.end:
+    %2 = add %0, %1
+    %3 = call print(%1)
+    ret %2
+end
+",
+    );
+    const_prop(&mut func);
+    assert_eq!(func.instrs[1].opr, IROperation::Mov(IRExpr::Imm(42)));
+    assert_eq!(
+        func.instrs[2].opr,
+        IROperation::Jz(IRExpr::Imm(42), "main.end".to_string().into())
+    );
+    // %0 is not known after the label since it may be an argument
+    assert_eq!(
+        func.instrs[5].opr,
+        IROperation::Add(IRExpr::TmpVar(0), IRExpr::Imm(42))
+    );
+    assert_eq!(func.instrs[6].opr.operands(), vec![&IRExpr::Imm(42)]);
+}
+
+#[test]
+fn test_copy_prop() {
+    let mut func = parse_func(
+        "
+func main frame=0 temps=4
+    %0 = arg 0
+    %1 = mov %0
+    %2 = add %1, 1
+    %0 = arg 1
+    %3 = add %1, 2
+    ret %3
+end
+",
+    );
+    copy_prop(&mut func);
+    assert_eq!(
+        func.instrs[2].opr,
+        IROperation::Add(IRExpr::TmpVar(0), IRExpr::Imm(1))
+    );
+    // %0 is overwritten so the copy has to be used
+    assert_eq!(
+        func.instrs[4].opr,
+        IROperation::Add(IRExpr::TmpVar(1), IRExpr::Imm(2))
+    );
+}
+
+#[test]
+fn test_dce() {
+    let mut func = parse_func(
+        "
+func main frame=0 temps=4
+    %0 = arg 0
+    %1 = add %0, 1
+    %2 = mul %1, 2
+    %3 = call print(%0)
+    store [frame-8]:8, %0
+    ret
+end
+",
+    );
+    dce(&mut func);
+    assert_eq!(func.instrs.len(), 4);
+    assert!(matches!(func.instrs[1].opr, IROperation::Call(_)));
+}
+
+#[test]
+fn test_cse() {
+    let mut func = parse_func(
+        "
+func main frame=0 temps=5
+    %0 = arg 0
+    %1 = mul %0, 8
+    %2 = mul %0, 8
+    %0 = arg 1
+    %3 = mul %0, 8
+    %4 = add %2, %3
+    ret %4
+end
+",
+    );
+    cse(&mut func);
+    assert_eq!(func.instrs[2].opr, IROperation::Mov(IRExpr::TmpVar(1)));
+    assert_eq!(
+        func.instrs[4].opr,
+        IROperation::Mult(IRExpr::TmpVar(0), IRExpr::Imm(8))
+    );
+}
+
+#[test]
+fn test_branch_fold() {
+    let mut func = parse_func(
+        "
+func main frame=0 temps=1
+    jz 0, main.else
+    %0 = mov 1
+    jmp main.end
+main.else:
+    %0 = mov 2
+main.end:
+    ret %0
+end
+",
+    );
+    branch_fold(&mut func);
+    assert_eq!(
+        func.instrs,
+        vec![
+            IRStmt {
+                dst: Some(0),
+                opr: IROperation::Mov(IRExpr::Imm(2))
+            },
+            IRStmt {
+                dst: None,
+                opr: IROperation::Ret(Some(IRExpr::TmpVar(0)))
+            },
+        ]
+    );
+}
@@ -23,7 +23,7 @@
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::error,
+    error_handeling::{ParseError, ParseResult},
     lexer::{Lexer, TokenType},
 };
 
@@ -80,35 +80,36 @@ impl AssignOp {
 }
 
 /// parse assignemts
-pub fn assign(lexer: &mut Lexer) -> Stmt {
+pub fn assign(lexer: &mut Lexer) -> ParseResult<Stmt> {
     // Location to Start of the stmt
     let loc = lexer.get_token_loc();
-    let left_expr = expr(lexer);
+    let left_expr = expr(lexer)?;
     let token_type = lexer.get_token_type();
     // Stmt is an expr if you encounter a semicolon
     if token_type == TokenType::SemiColon {
-        lexer.match_token(TokenType::SemiColon);
-        Stmt {
+        lexer.match_token(TokenType::SemiColon)?;
+        Ok(Stmt {
             stype: StmtType::Expr(left_expr),
             loc,
-        }
+        })
     } else if token_type.is_assgin_token() {
         let op_type = AssignOp::from_token_type(&token_type);
-        lexer.match_token(token_type);
-        let right_expr = expr(lexer);
-        lexer.match_token(TokenType::SemiColon);
-        return Stmt {
+        lexer.match_token(token_type)?;
+        let right_expr = expr(lexer)?;
+        lexer.match_token(TokenType::SemiColon)?;
+        Ok(Stmt {
             stype: StmtType::Assign(Assign {
                 left: left_expr,
                 right: right_expr,
                 op: op_type,
             }),
             loc,
-        };
+        })
     } else {
-        error(
+        Err(ParseError::new(
+            "E0105",
             format!("Expected Semicolon found ({})", lexer.get_token_type()),
-            loc,
-        );
+            lexer.get_token_loc(),
+        ))
     }
 }
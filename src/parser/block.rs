@@ -23,7 +23,7 @@
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::CompilationError,
+    error_handeling::{CompilationError, ParseError, ParseResult},
     lexer::{Lexer, TokenType},
     parser::stmt::Stmt,
 };
@@ -32,7 +32,7 @@ use super::{
     assign::assign,
     expr::expr,
     preprocessing::parse_pre_functions,
-    stmt::{for_loop, if_stmt, while_stmt, StmtType},
+    stmt::{for_loop, if_stmt, inline_asm, while_stmt, StmtType},
     variable_decl::variable_declare,
 };
 
@@ -45,26 +45,38 @@ pub enum BlockType {
     UnScoped,
 }
 
-pub fn parse_statement_outside_of_block(lexer: &mut Lexer, master: &String) -> Vec<Stmt> {
+/// Tokens that start a stmt where parsing continues after a syntax error
+const STMT_START: &[TokenType] = &[
+    TokenType::Var,
+    TokenType::Print,
+    TokenType::If,
+    TokenType::While,
+    TokenType::For,
+    TokenType::Return,
+    TokenType::Asm,
+    TokenType::Defer,
+    TokenType::Break,
+    TokenType::Continue,
+];
+
+pub fn parse_statement_outside_of_block(
+    lexer: &mut Lexer,
+    master: &String,
+) -> ParseResult<Vec<Stmt>> {
     let mut block = Block::new_unscoped(master.to_string());
     block.parse_stmt(lexer)
 }
 
 pub fn get_last_loop_block_id(child_id: &str) -> Result<String, CompilationError> {
-    let mut last_index = 0;
-    let mut reached_loop = false;
-    for (i, chr) in child_id.chars().enumerate() {
-        if chr == '.' && reached_loop {
-            last_index = i
-        }
-        if chr == '$' {
-            reached_loop = true;
-        }
-    }
-    if last_index == 0 {
+    // the id of the innermost loop ends before the first child after its '$'
+    let Some(start) = child_id.rfind('$') else {
         return Err(CompilationError::NotLoopBlock);
-    }
-    Ok(child_id.split_at(last_index).0.to_string())
+    };
+    let end = match child_id[start..].find('.') {
+        Some(i) => start + i,
+        None => child_id.len(),
+    };
+    Ok(child_id[..end].to_string())
 }
 
 pub fn get_first_block_id(child_id: &str) -> String {
@@ -186,124 +198,139 @@ impl Block {
         format!("{}.{prefix}__", self.id)
     }
 
-    pub fn parse_stmt(&mut self, lexer: &mut Lexer) -> Vec<Stmt> {
-        match lexer.get_token_type() {
+    pub fn parse_stmt(&mut self, lexer: &mut Lexer) -> ParseResult<Vec<Stmt>> {
+        let stmts = match lexer.get_token_type() {
             TokenType::Hash => {
                 let loc = lexer.get_token_loc();
-                parse_pre_functions(lexer, loc, &self.id)
+                parse_pre_functions(lexer, loc, &self.id)?
             }
             TokenType::Var => {
                 let loc = lexer.get_token_loc();
                 let stmt = vec![Stmt {
-                    stype: StmtType::VariableDecl(variable_declare(lexer)),
+                    stype: StmtType::VariableDecl(variable_declare(lexer)?),
                     loc,
                 }];
-                lexer.match_token(TokenType::SemiColon);
+                lexer.match_token(TokenType::SemiColon)?;
                 stmt
             }
             TokenType::Print => {
                 let loc = lexer.get_token_loc();
-                lexer.match_token(TokenType::Print);
-                let expr = expr(lexer);
+                lexer.match_token(TokenType::Print)?;
+                let expr = expr(lexer)?;
                 let stmt = vec![Stmt {
                     stype: StmtType::Print(expr),
                     loc,
                 }];
-                lexer.match_token(TokenType::SemiColon);
+                lexer.match_token(TokenType::SemiColon)?;
                 stmt
             }
             TokenType::Break => {
                 let loc = lexer.get_token_loc();
-                lexer.match_token(TokenType::Break);
+                lexer.match_token(TokenType::Break)?;
                 let stmt = vec![Stmt {
                     stype: StmtType::Break,
                     loc,
                 }];
-                lexer.match_token(TokenType::SemiColon);
+                lexer.match_token(TokenType::SemiColon)?;
                 stmt
             }
             TokenType::Continue => {
                 let loc = lexer.get_token_loc();
-                lexer.match_token(TokenType::Continue);
+                lexer.match_token(TokenType::Continue)?;
                 let stmt = vec![Stmt {
                     stype: StmtType::Continue,
                     loc,
                 }];
-                lexer.match_token(TokenType::SemiColon);
+                lexer.match_token(TokenType::SemiColon)?;
                 stmt
             }
             TokenType::If => {
                 let loc = lexer.get_token_loc();
                 vec![Stmt {
-                    stype: StmtType::If(if_stmt(lexer, self)),
+                    stype: StmtType::If(if_stmt(lexer, self)?),
                     loc,
                 }]
             }
             TokenType::While => {
                 let loc = lexer.get_token_loc();
                 vec![Stmt {
-                    stype: StmtType::While(while_stmt(lexer, self)),
+                    stype: StmtType::While(while_stmt(lexer, self)?),
                     loc,
                 }]
             }
             TokenType::For => {
                 let loc = lexer.get_token_loc();
                 vec![Stmt {
-                    stype: StmtType::ForLoop(for_loop(lexer, self)),
+                    stype: StmtType::ForLoop(for_loop(lexer, self)?),
                     loc,
                 }]
             }
             TokenType::Return => {
                 let loc = lexer.get_token_loc();
-                lexer.match_token(TokenType::Return);
+                lexer.match_token(TokenType::Return)?;
                 let stmt = vec![Stmt {
-                    stype: StmtType::Return(expr(lexer)),
+                    stype: StmtType::Return(expr(lexer)?),
                     loc,
                 }];
-                lexer.match_token(TokenType::SemiColon);
+                lexer.match_token(TokenType::SemiColon)?;
                 stmt
             }
             TokenType::Identifier => {
                 //Assgin Op
-                vec![assign(lexer)]
+                vec![assign(lexer)?]
             }
             TokenType::Asm => {
                 let loc = lexer.get_token_loc();
-                lexer.match_token(TokenType::Asm);
-                lexer.match_token(TokenType::OCurly);
-                let mut instructs = Vec::<String>::new();
-                while lexer.get_token_type() == TokenType::String {
-                    instructs.push(lexer.get_token().literal);
-                    lexer.match_token(TokenType::String);
-                }
-                lexer.match_token(TokenType::CCurly);
                 vec![Stmt {
-                    stype: StmtType::InlineAsm(instructs),
+                    stype: StmtType::InlineAsm(inline_asm(lexer)?),
                     loc,
                 }]
             }
             TokenType::Defer => {
-                lexer.match_token(TokenType::Defer);
+                lexer.match_token(TokenType::Defer)?;
                 if lexer.get_token_type() == TokenType::OCurly {
-                    lexer.match_token(TokenType::OCurly);
+                    lexer.match_token(TokenType::OCurly)?;
                     let mut stmts = Vec::<Stmt>::new();
-                    loop {
-                        if lexer.get_token_type() == TokenType::CCurly {
-                            break;
-                        }
-                        stmts.append(&mut self.parse_stmt(lexer));
+                    while !matches!(lexer.get_token_type(), TokenType::CCurly | TokenType::Eof) {
+                        stmts.append(&mut self.parse_stmt_or_recover(lexer));
                     }
-                    lexer.match_token(TokenType::CCurly);
+                    lexer.match_token(TokenType::CCurly)?;
                     self.defer_stmts.append(&mut stmts);
                     vec![]
                 } else {
-                    let mut stmt = self.parse_stmt(lexer);
+                    let mut stmt = self.parse_stmt(lexer)?;
                     self.defer_stmts.append(&mut stmt);
                     vec![]
                 }
             }
             _ => {
-                todo!();
+                return Err(ParseError::new(
+                    "E0105",
+                    format!(
+                        "Unexpected Token ({}) at the start of a statement",
+                        lexer.get_token_type()
+                    ),
+                    lexer.get_token_loc(),
+                ));
+            }
+        };
+        Ok(stmts)
+    }
+
+    /// Parses a stmt and skips to the next stmt if it has a syntax error
+    fn parse_stmt_or_recover(&mut self, lexer: &mut Lexer) -> Vec<Stmt> {
+        let start = lexer.get_token_loc();
+        let depth = lexer.depth;
+        match self.parse_stmt(lexer) {
+            Ok(stmts) => stmts,
+            Err(err) => {
+                lexer.report(err);
+                // the token has to be dropped if it can not start any stmt
+                if lexer.get_token_loc() == start {
+                    lexer.next_token();
+                }
+                lexer.synchronize(STMT_START, depth);
+                vec![]
             }
         }
     }
@@ -311,17 +338,15 @@ impl Block {
     /// Parse Blocks
     /// # Argumenrs
     /// * lexer - address of mutable lexer
-    ///     Returns a vec of stmts
-    pub fn parse_block(&mut self, lexer: &mut Lexer) {
-        lexer.match_token(TokenType::OCurly);
+    ///   Returns a vec of stmts
+    pub fn parse_block(&mut self, lexer: &mut Lexer) -> ParseResult<()> {
+        lexer.match_token(TokenType::OCurly)?;
         let mut stmts = Vec::<Stmt>::new();
-        loop {
-            if lexer.get_token_type() == TokenType::CCurly {
-                break;
-            }
-            stmts.append(&mut self.parse_stmt(lexer));
+        while !matches!(lexer.get_token_type(), TokenType::CCurly | TokenType::Eof) {
+            stmts.append(&mut self.parse_stmt_or_recover(lexer));
         }
-        lexer.match_token(TokenType::CCurly);
+        lexer.match_token(TokenType::CCurly)?;
         self.stmts.append(&mut stmts);
+        Ok(())
     }
 }
@@ -23,7 +23,7 @@
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::{error, Loc},
+    error_handeling::{Loc, ParseError, ParseResult},
     lexer::{Lexer, TokenType},
 };
 use core::fmt::Display;
@@ -279,82 +279,85 @@ impl CompareOp {
 /// Parsing Expr
 /// returns Least Prioraty operations
 /// e.g: Plus, Minus, bitwise Or
-pub fn expr(lexer: &mut Lexer) -> Expr {
-    let mut term_expr = term(lexer);
+pub fn expr(lexer: &mut Lexer) -> ParseResult<Expr> {
+    let mut term_expr = term(lexer)?;
     loop {
         let t_type = lexer.get_token_type();
         if ExprType::is_binary_op(t_type) || ExprType::is_logical_op(t_type) {
             let op = Op::from_token_type(t_type);
+            let loc = lexer.get_token_loc();
             lexer.next_token();
-            let right = term(lexer);
+            let right = term(lexer)?;
             term_expr = Expr {
                 etype: ExprType::Binary(BinaryExpr {
                     left: Box::new(term_expr),
                     op,
                     right: Box::new(right),
                 }),
-                loc: lexer.get_token_loc(),
+                loc,
             };
         } else {
             break;
         }
     }
-    term_expr
+    Ok(term_expr)
 }
 
 /// Parsing Expr
 /// returns second Prioraty operations
 /// e.g: Multi, Devide, Logical And
-pub fn term(lexer: &mut Lexer) -> Expr {
-    let mut left = factor(lexer);
+pub fn term(lexer: &mut Lexer) -> ParseResult<Expr> {
+    let mut left = factor(lexer)?;
     let mut cur_token = lexer.get_token_type();
     while ExprType::is_term_op(cur_token) || ExprType::is_compare_op(cur_token) {
         if ExprType::is_compare_op(cur_token) {
             let op = CompareOp::from_token_type(lexer.get_token_type());
+            let loc = lexer.get_token_loc();
             lexer.next_token();
-            let right = term(lexer);
+            let right = term(lexer)?;
             left = Expr {
                 etype: ExprType::Compare(CompareExpr {
                     left: Box::new(left),
                     op,
                     right: Box::new(right),
                 }),
-                loc: lexer.get_token_loc(),
+                loc,
             };
         } else if ExprType::is_term_op(cur_token) {
             let op = Op::from_token_type(lexer.get_token_type());
+            let loc = lexer.get_token_loc();
             lexer.next_token();
-            let right = factor(lexer);
+            let right = factor(lexer)?;
             left = Expr {
                 etype: ExprType::Binary(BinaryExpr {
                     left: Box::new(left),
                     op,
                     right: Box::new(right),
                 }),
-                loc: lexer.get_token_loc(),
+                loc,
             };
         }
         cur_token = lexer.get_token_type();
     }
-    left
+    Ok(left)
 }
 
 /// Parsing Expr
 /// returns first Prioraty operations
 /// e.g: Unary, Paran, Power
-pub fn factor(lexer: &mut Lexer) -> Expr {
-    let loc = lexer.get_current_loc();
-    match lexer.get_token_type() {
+pub fn factor(lexer: &mut Lexer) -> ParseResult<Expr> {
+    let loc = lexer.get_token_loc();
+    let expr = match lexer.get_token_type() {
         TokenType::OParen => {
-            lexer.match_token(TokenType::OParen);
-            let value = expr(lexer);
-            lexer.match_token(TokenType::CParen);
+            lexer.match_token(TokenType::OParen)?;
+            let value = expr(lexer)?;
+            lexer.match_token(TokenType::CParen)?;
             value
         }
         TokenType::Plus | TokenType::Minus | TokenType::Not | TokenType::Multi | TokenType::And => {
             let op = Op::from_token_type(lexer.get_token_type());
             lexer.next_token();
-            let value = factor(lexer);
+            let value = factor(lexer)?;
             match op {
                 Op::Multi => Expr {
                     etype: ExprType::DeRef(Box::new(value)),
@@ -382,14 +385,14 @@ pub fn factor(lexer: &mut Lexer) -> Expr {
             }
         }
         TokenType::True => {
-            lexer.match_token(TokenType::True);
+            lexer.match_token(TokenType::True)?;
             Expr {
                 etype: ExprType::Bool(1),
                 loc,
             }
         }
         TokenType::False => {
-            lexer.match_token(TokenType::False);
+            lexer.match_token(TokenType::False)?;
             Expr {
                 etype: ExprType::Bool(0),
                 loc,
@@ -419,14 +422,14 @@ pub fn factor(lexer: &mut Lexer) -> Expr {
         TokenType::Identifier => {
             let ident_name = lexer.get_token().literal;
             if lexer.next_token().is_empty() {
-                return Expr {
+                return Ok(Expr {
                     etype: ExprType::Variable(ident_name),
                     loc,
-                };
+                });
             }
             match lexer.get_token_type() {
                 TokenType::OParen => {
-                    let args = function_call_args(lexer);
+                    let args = function_call_args(lexer)?;
                     Expr {
                         etype: ExprType::FunctionCall(FunctionCall {
                             ident: ident_name,
@@ -436,7 +439,7 @@ pub fn factor(lexer: &mut Lexer) -> Expr {
                     }
                 }
                 TokenType::OBracket => {
-                    let indexer = array_indexer(lexer);
+                    let indexer = array_indexer(lexer)?;
                     Expr {
                         etype: ExprType::ArrayIndex(ArrayIndex {
                             ident: ident_name,
@@ -446,7 +449,7 @@ pub fn factor(lexer: &mut Lexer) -> Expr {
                     }
                 }
                 TokenType::Dot => {
-                    let access_expr = memeber_access(lexer);
+                    let access_expr = memeber_access(lexer)?;
                     Expr {
                         etype: ExprType::Access(ident_name, Box::new(access_expr)),
                         loc,
@@ -459,57 +462,61 @@ pub fn factor(lexer: &mut Lexer) -> Expr {
             }
         }
         _ => {
-            error(
+            return Err(ParseError::new(
+                "E0105",
                 format!(
                     "Unexpected Token ({}) while parsing expr",
                     lexer.get_token_type(),
                 ),
                 loc,
-            );
+            ));
         }
-    }
+    };
+    Ok(expr)
 }
 
 /// Parsing direct Member Access
-pub fn memeber_access(lexer: &mut Lexer) -> Expr {
-    lexer.match_token(TokenType::Dot);
+pub fn memeber_access(lexer: &mut Lexer) -> ParseResult<Expr> {
+    lexer.match_token(TokenType::Dot)?;
     if lexer.get_token_type() != TokenType::Identifier {
-        error(
+        Err(ParseError::new(
+            "E0105",
             "TODO: Invalid Access Operation for struct",
-            lexer.get_current_loc(),
-        );
+            lexer.get_token_loc(),
+        ))
     } else {
-        expr(lexer)
+        // the member binds tighter than any operator after it (a.b + 1)
+        factor(lexer)
     }
 }
 
 /// Parsing Array Index
-pub fn array_indexer(lexer: &mut Lexer) -> Expr {
-    lexer.match_token(TokenType::OBracket);
-    let index = expr(lexer);
-    lexer.match_token(TokenType::CBracket);
-    index
+pub fn array_indexer(lexer: &mut Lexer) -> ParseResult<Expr> {
+    lexer.match_token(TokenType::OBracket)?;
+    let index = expr(lexer)?;
+    lexer.match_token(TokenType::CBracket)?;
+    Ok(index)
 }
 
 /// Parsing Function call
 /// Returns Function call argumets
-pub fn function_call_args(lexer: &mut Lexer) -> Vec<Expr> {
+pub fn function_call_args(lexer: &mut Lexer) -> ParseResult<Vec<Expr>> {
     let mut args = Vec::<Expr>::new();
-    lexer.match_token(TokenType::OParen);
+    lexer.match_token(TokenType::OParen)?;
     loop {
         //|| | expr | expr , expr
         match lexer.get_token_type() {
             TokenType::CParen => {
-                lexer.match_token(TokenType::CParen);
+                lexer.match_token(TokenType::CParen)?;
                 break;
             }
             _ => {
-                args.push(expr(lexer));
+                args.push(expr(lexer)?);
                 if lexer.get_token_type() == TokenType::Comma {
-                    lexer.match_token(TokenType::Comma);
+                    lexer.match_token(TokenType::Comma)?;
                 }
             }
         }
     }
-    args
+    Ok(args)
 }
@@ -23,9 +23,10 @@
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::error,
+    error_handeling::{ParseError, ParseResult},
     lexer::{Lexer, TokenType},
     parser::{block::Block, types::type_def},
+    warnings::Warning,
 };
 
 use super::{block::BlockType, types::VariableType};
@@ -39,16 +40,31 @@ pub struct FunctionArg {
     pub typedef: VariableType,
 }
 
+/// Attributes written before a function definition
+/// e.g: #inline func add(a @int, b @int) @int { ... }
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionAttr {
+    /// Always inline calls to the function
+    Inline,
+    /// Never inline calls to the function
+    NoInline,
+    /// Do not report the warning inside of the function
+    /// e.g: #allow(unused-return)
+    Allow(Warning),
+}
+
 /// Function Definition
 /// * ident - name of the function
 /// * args - list of all function arguments
 /// * block - function block
 /// * typedef - type of acceptable
+/// * attrs - attributes of the function
 #[derive(Debug, Clone)]
 pub struct FunctionDef {
     pub decl: FunctionDecl,
     pub block: Block,
     pub defer_block: Block,
+    pub attrs: Vec<FunctionAttr>,
 }
 
 #[derive(Debug, Clone)]
@@ -59,68 +75,130 @@ pub struct FunctionDecl {
     pub ret_type: VariableType,
 }
 
-pub fn parse_function_declaration(lexer: &mut Lexer) -> FunctionDecl {
-    let loc = lexer.get_current_loc();
+pub fn parse_function_declaration(lexer: &mut Lexer) -> ParseResult<FunctionDecl> {
+    let loc = lexer.get_token_loc();
     let is_extern = match lexer.get_token_type() {
         TokenType::Extern => {
-            lexer.match_token(TokenType::Extern);
+            lexer.match_token(TokenType::Extern)?;
             true
         }
         _ => false,
     };
-    lexer.match_token(TokenType::Func);
+    lexer.match_token(TokenType::Func)?;
     let function_ident_token = lexer.get_token();
     let mut ret_type = VariableType::Void;
-    let fn_ident = match function_ident_token.is_empty() {
-        true => error("Function defenition without identifier", loc),
-        false => function_ident_token.literal,
-    };
-    lexer.match_token(TokenType::Identifier);
-    let args = function_def_args(lexer);
+    if function_ident_token.t_type != TokenType::Identifier {
+        return Err(ParseError::new(
+            "E0105",
+            "Function defenition without identifier",
+            loc,
+        ));
+    }
+    let fn_ident = function_ident_token.literal;
+    lexer.match_token(TokenType::Identifier)?;
+    let args = function_def_args(lexer)?;
     if lexer.get_token_type() == TokenType::ATSign {
-        ret_type = type_def(lexer);
+        ret_type = type_def(lexer)?;
     }
-    FunctionDecl {
+    Ok(FunctionDecl {
         is_extern,
         ident: fn_ident,
         args,
         ret_type,
-    }
+    })
 }
 
 /// Parsing Function definition
-pub fn parse_function_definition(lexer: &mut Lexer) -> FunctionDef {
-    let decl = parse_function_declaration(lexer);
+pub fn parse_function_definition(lexer: &mut Lexer) -> ParseResult<FunctionDef> {
+    let decl = parse_function_declaration(lexer)?;
     let mut block = Block::new_global(decl.ident.clone(), BlockType::Function);
-    block.parse_block(lexer);
+    block.parse_block(lexer)?;
     let mut defer_block = Block::new_global(decl.ident.clone(), BlockType::Function);
     defer_block.stmts = block.defer_stmts.clone();
     block.defer_stmts.clear();
-    FunctionDef {
+    Ok(FunctionDef {
         decl,
         block,
         defer_block,
+        attrs: vec![],
+    })
+}
+
+/// Parsing function attributes
+/// returns list of attributes before the function definition
+pub fn parse_function_attrs(lexer: &mut Lexer) -> ParseResult<Vec<FunctionAttr>> {
+    let mut attrs = Vec::new();
+    while lexer.get_token_type() == TokenType::Hash {
+        let loc = lexer.get_token_loc();
+        lexer.match_token(TokenType::Hash)?;
+        let attr = match lexer.get_token().literal.as_str() {
+            "inline" => FunctionAttr::Inline,
+            "noinline" => FunctionAttr::NoInline,
+            "allow" => {
+                lexer.match_token(TokenType::Identifier)?;
+                lexer.match_token(TokenType::OParen)?;
+                attrs.push(FunctionAttr::Allow(parse_warning_name(lexer)?));
+                lexer.match_token(TokenType::CParen)?;
+                continue;
+            }
+            name => {
+                return Err(ParseError::new(
+                    "E0108",
+                    format!("Unknown function attribute ({name})"),
+                    loc,
+                ))
+            }
+        };
+        lexer.match_token(TokenType::Identifier)?;
+        attrs.push(attr);
+    }
+    Ok(attrs)
+}
+
+/// Parsing the name of a warning with words separated by '-'
+/// words can also be keywords e.g: unused-return
+fn parse_warning_name(lexer: &mut Lexer) -> ParseResult<Warning> {
+    let loc = lexer.get_token_loc();
+    let mut name = String::new();
+    loop {
+        let word = lexer.get_token().literal;
+        if word.is_empty() || !word.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(ParseError::new(
+                "E0105",
+                format!("Expected warning name found ({})", lexer.get_token_type()),
+                lexer.get_token_loc(),
+            ));
+        }
+        name.push_str(&word);
+        lexer.next_token();
+        if lexer.get_token_type() != TokenType::Minus {
+            break;
+        }
+        lexer.match_token(TokenType::Minus)?;
+        name.push('-');
     }
+    Warning::from_name(&name)
+        .ok_or_else(|| ParseError::new("E0111", format!("Unknown warning ({name})"), loc))
 }
 
 /// Parsing Function definition
 /// returns list of function definition arguments
-pub fn function_def_args(lexer: &mut Lexer) -> Vec<FunctionArg> {
-    let loc = lexer.get_current_loc();
+pub fn function_def_args(lexer: &mut Lexer) -> ParseResult<Vec<FunctionArg>> {
+    let loc = lexer.get_token_loc();
     let mut args = Vec::<FunctionArg>::new();
-    lexer.match_token(TokenType::OParen);
+    lexer.match_token(TokenType::OParen)?;
     loop {
         match lexer.get_token_type() {
             TokenType::CParen => {
-                lexer.match_token(TokenType::CParen);
+                lexer.match_token(TokenType::CParen)?;
                 break;
             }
             TokenType::Identifier => {
                 let ident = lexer.get_token().literal;
-                lexer.match_token(TokenType::Identifier);
-                let typedef = type_def(lexer);
+                lexer.match_token(TokenType::Identifier)?;
+                let typedef = type_def(lexer)?;
                 if lexer.get_token_type() == TokenType::Comma {
-                    lexer.match_token(TokenType::Comma);
+                    lexer.match_token(TokenType::Comma)?;
                 }
                 args.push(FunctionArg {
                     ident: ident.to_string(),
@@ -128,12 +206,13 @@ pub fn function_def_args(lexer: &mut Lexer) -> Vec<FunctionArg> {
                 });
             }
             _ => {
-                error(
+                return Err(ParseError::new(
+                    "E0105",
                     format!("Expected Identifier found ({})", lexer.get_token_type()),
                     loc,
-                );
+                ));
             }
         }
     }
-    args
+    Ok(args)
 }
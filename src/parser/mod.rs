@@ -32,25 +32,98 @@ pub mod stmt;
 pub mod structs;
 pub mod types;
 pub mod variable_decl;
-use std::fs;
+use std::path::Path;
 
+use crate::error_handeling::{Diagnostic, Loc};
 use crate::lexer::Lexer;
 use crate::parser::program::*;
+use crate::vfs::FileSystem;
 
 /// Parsing a Single File
 ///
 /// # Arguments
+/// * fs - file system that the file and its imports are read from
 /// * path - path to the code file
+/// * target_platform - platform that #if conditions are checked against
 ///
 /// # Returns
 /// Programfile containing the ast of the parsed file
-///
-/// Can panic if file dose not exists
-pub fn parse_source_file(path: String) -> ProgramFile {
-    let source = fs::read_to_string(path.clone()).unwrap_or_else(|_| {
-        eprintln!("Error reading file \"{}\"", path.clone());
-        panic!("Can not open file!");
-    });
+/// or the reported syntax errors
+pub fn parse_source_file(
+    fs: &dyn FileSystem,
+    path: String,
+    target_platform: u8,
+) -> Result<ProgramFile, Vec<Diagnostic>> {
+    match fs.read_to_string(Path::new(&path)) {
+        Ok(source) => parse_source(fs, path, source, target_platform),
+        Err(e) => Err(vec![Diagnostic::error(
+            format!("Can not read file ({path}): {e}"),
+            Loc::new(path, 1, 1),
+        )]),
+    }
+}
+
+/// Parsing the source code of a file
+/// All syntax errors are collected before returning
+pub fn parse_source(
+    fs: &dyn FileSystem,
+    path: String,
+    source: String,
+    target_platform: u8,
+) -> Result<ProgramFile, Vec<Diagnostic>> {
     let mut lexer = Lexer::new(path, source);
-    generate_ast(&mut lexer)
+    lexer.target_platform = target_platform;
+    let program = generate_ast(&mut lexer, fs);
+    match lexer.diagnostics.is_empty() {
+        true => Ok(program),
+        false => Err(lexer.diagnostics),
+    }
+}
+
+#[test]
+fn syntax_errors_are_recovered() {
+    let fs = crate::vfs::MemoryFs::new();
+    let source = "func main() {
+    var x @int = 1 +;
+    print x
+    var y @int = 2;
+}
+}
+func other( {
+}
+func main() {}
+func last() {
+    while 1 {
+";
+    let locs = |source: &str| -> Vec<(Option<&str>, usize, usize)> {
+        parse_source(&fs, "test.nmt".into(), source.into(), 0)
+            .unwrap_err()
+            .iter()
+            .map(|e| (e.code, e.loc.line, e.loc.col))
+            .collect()
+    };
+    assert_eq!(
+        locs(source),
+        [
+            (Some("E0105"), 2, 21),
+            (Some("E0105"), 4, 5),
+            (Some("E0105"), 6, 1),
+            (Some("E0105"), 7, 11),
+            (Some("E0106"), 9, 1),
+            (Some("E0105"), 12, 1),
+        ]
+    );
+    // errors of invalid tokens are only reported by the lexer
+    let source =
+        "func main() {\n    print \"abc;\n}\nfunc other() {\n    print 12a;\n    print 1 ^ 2;\n}";
+    assert_eq!(
+        locs(source),
+        [
+            (Some("E0102"), 2, 12),
+            (Some("E0104"), 5, 11),
+            (Some("E0100"), 6, 13),
+        ]
+    );
+    let program = parse_source(&fs, "test.nmt".into(), "func main() { print 1; }".into(), 0);
+    assert_eq!(program.unwrap().items.len(), 1);
 }
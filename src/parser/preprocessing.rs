@@ -1,10 +1,8 @@
-use std::process::exit;
-
 use crate::{
-    error_handeling::Loc,
+    error_handeling::{Loc, ParseError, ParseResult},
     lexer::{Lexer, TokenType},
     parser::{block::parse_statement_outside_of_block, expr::ExprType},
-    target_string_to_number, TARGET_PLATFORM,
+    target_string_to_number,
 };
 
 use super::{
@@ -14,116 +12,132 @@ use super::{
 
 pub static PLATFORMS: &[&str] = &["LINUX", "WINDOWS", "UNKNOWN"];
 
-pub fn parse_pre_functions(lexer: &mut Lexer, loc: Loc, master: &String) -> Vec<Stmt> {
-    lexer.match_token(TokenType::Hash);
+pub fn parse_pre_functions(lexer: &mut Lexer, loc: Loc, master: &String) -> ParseResult<Vec<Stmt>> {
+    lexer.match_token(TokenType::Hash)?;
     match lexer.get_token_type() {
         TokenType::If => parse_pre_condition(lexer, loc, master),
-        _ => {
-            eprintln!("{loc}: Unknown pre-processing function!");
-            exit(-1);
-        }
+        _ => Err(ParseError::new(
+            "E0109",
+            "Unknown pre-processing function!",
+            loc,
+        )),
     }
 }
 
-fn skip_to_end(lexer: &mut Lexer) {
+fn missing_end(loc: &Loc) -> ParseError {
+    ParseError::new(
+        "E0109",
+        "Syntax error: pre-processing function should end in #end",
+        loc.clone(),
+    )
+}
+
+fn skip_to_end(lexer: &mut Lexer, loc: &Loc) -> ParseResult<()> {
     loop {
+        if lexer.get_token_type() == TokenType::Eof {
+            return Err(missing_end(loc));
+        }
         if lexer.get_token_type() == TokenType::Hash {
-            lexer.match_token(TokenType::Hash);
+            lexer.match_token(TokenType::Hash)?;
             if lexer.get_token().literal == "end" {
-                lexer.match_token(TokenType::Identifier);
+                lexer.match_token(TokenType::Identifier)?;
                 break;
             }
         }
         lexer.next_token();
     }
+    Ok(())
 }
 
-fn skip_to_next_tag(lexer: &mut Lexer) {
+fn skip_to_next_tag(lexer: &mut Lexer, loc: &Loc) -> ParseResult<()> {
     loop {
+        if lexer.get_token_type() == TokenType::Eof {
+            return Err(missing_end(loc));
+        }
         if lexer.get_token_type() == TokenType::Hash {
-            lexer.match_token(TokenType::Hash);
+            lexer.match_token(TokenType::Hash)?;
             break;
         }
         lexer.next_token();
     }
+    Ok(())
 }
 
-fn parse_pre_condition(lexer: &mut Lexer, loc: Loc, master: &String) -> Vec<Stmt> {
-    lexer.match_token(TokenType::If);
-    let cond_expr = expr(lexer);
-    let result = compile_pre_expr(&cond_expr);
-    if result {
-        let mut stmts = Vec::new();
-        loop {
-            if lexer.get_token_type() == TokenType::Hash {
-                break;
-            }
-            stmts.append(&mut parse_statement_outside_of_block(lexer, master));
+/// Statements until the next pre-processing tag
+fn parse_pre_block(lexer: &mut Lexer, loc: &Loc, master: &String) -> ParseResult<Vec<Stmt>> {
+    let mut stmts = Vec::new();
+    while lexer.get_token_type() != TokenType::Hash {
+        if matches!(lexer.get_token_type(), TokenType::CCurly | TokenType::Eof) {
+            return Err(missing_end(loc));
         }
-        lexer.match_token(TokenType::Hash);
+        stmts.append(&mut parse_statement_outside_of_block(lexer, master)?);
+    }
+    lexer.match_token(TokenType::Hash)?;
+    Ok(stmts)
+}
+
+fn parse_pre_condition(lexer: &mut Lexer, loc: Loc, master: &String) -> ParseResult<Vec<Stmt>> {
+    lexer.match_token(TokenType::If)?;
+    let cond_expr = expr(lexer)?;
+    let result = compile_pre_expr(&cond_expr, lexer.target_platform)?;
+    if result {
+        let stmts = parse_pre_block(lexer, &loc, master)?;
         if lexer.get_token().literal == "end" {
-            lexer.match_token(TokenType::Identifier);
-            stmts
+            lexer.match_token(TokenType::Identifier)?;
         } else {
-            skip_to_end(lexer);
-            stmts
+            skip_to_end(lexer, &loc)?;
         }
+        Ok(stmts)
     } else {
-        skip_to_next_tag(lexer);
+        skip_to_next_tag(lexer, &loc)?;
         if lexer.get_token_type() == TokenType::Identifier && lexer.get_token().literal == "end" {
-            lexer.match_token(TokenType::Identifier);
-            return vec![];
+            lexer.match_token(TokenType::Identifier)?;
+            return Ok(vec![]);
         }
-        let mut stmts = Vec::new();
-        lexer.match_token(TokenType::Else);
+        lexer.match_token(TokenType::Else)?;
         if lexer.get_token_type() == TokenType::If {
             return parse_pre_condition(lexer, loc, master);
         }
-        loop {
-            if lexer.get_token_type() == TokenType::Hash {
-                break;
-            }
-            stmts.append(&mut parse_statement_outside_of_block(lexer, master));
-        }
-        lexer.match_token(TokenType::Hash);
+        let stmts = parse_pre_block(lexer, &loc, master)?;
         if lexer.get_token().literal != "end" {
-            eprintln!("{loc}: Syntax error: pre-processing function should end in #end");
-            exit(-1);
+            return Err(missing_end(&loc));
         }
-        lexer.match_token(TokenType::Identifier);
-        stmts
+        lexer.match_token(TokenType::Identifier)?;
+        Ok(stmts)
     }
 }
 
-fn compile_pre_expr(expr: &Expr) -> bool {
+fn compile_pre_expr(expr: &Expr, target_platform: u8) -> ParseResult<bool> {
+    let unsupported = || {
+        ParseError::new(
+            "E0109",
+            "Unsupported operand for this expression",
+            expr.loc.clone(),
+        )
+    };
     match &expr.etype {
-        ExprType::Bool(b) => b != &0u8,
+        ExprType::Bool(b) => Ok(b != &0u8),
         ExprType::Variable(v) => {
-            PLATFORMS.contains(&v.as_str())
-                && target_string_to_number(v) == *TARGET_PLATFORM.lock().unwrap()
+            Ok(PLATFORMS.contains(&v.as_str()) && target_string_to_number(v) == target_platform)
         }
         ExprType::Unary(ub) => {
             if ub.op == Op::Not {
-                !compile_pre_expr(&ub.right)
+                Ok(!compile_pre_expr(&ub.right, target_platform)?)
             } else {
-                eprintln!("{}: Unsupported operand for this expression", expr.loc);
-                exit(-1);
+                Err(unsupported())
             }
         }
         ExprType::Compare(c) => match c.op {
-            CompareOp::Eq => compile_pre_expr(&c.left) == compile_pre_expr(&c.right),
-            CompareOp::NotEq => compile_pre_expr(&c.left) != compile_pre_expr(&c.right),
-            _ => {
-                eprintln!("{}: Unsupported operand for this expression", expr.loc);
-                exit(-1);
-            }
+            CompareOp::Eq => Ok(compile_pre_expr(&c.left, target_platform)?
+                == compile_pre_expr(&c.right, target_platform)?),
+            CompareOp::NotEq => Ok(compile_pre_expr(&c.left, target_platform)?
+                != compile_pre_expr(&c.right, target_platform)?),
+            _ => Err(unsupported()),
         },
-        _ => {
-            eprintln!(
-                "{}: Unsupported expression for the pre-processing function",
-                expr.loc
-            );
-            exit(-1);
-        }
+        _ => Err(ParseError::new(
+            "E0109",
+            "Unsupported expression for the pre-processing function",
+            expr.loc.clone(),
+        )),
     }
 }
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::Path};
 
 /**********************************************************************************************
 *
@@ -25,13 +25,17 @@ use std::collections::BTreeMap;
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::error,
+    error_handeling::{ParseError, ParseResult},
     lexer::{Lexer, TokenType},
+    vfs::FileSystem,
 };
 
 use super::{
-    function::{parse_function_declaration, parse_function_definition, FunctionDecl, FunctionDef},
-    parse_source_file,
+    function::{
+        parse_function_attrs, parse_function_declaration, parse_function_definition, FunctionDecl,
+        FunctionDef,
+    },
+    parse_source,
     structs::struct_def,
     types::StructType,
     variable_decl::{variable_declare, VariableDeclare},
@@ -74,134 +78,181 @@ impl ProgramItem {
     }
 }
 
+/// Tokens that start a top level item where parsing continues after a syntax error
+const ITEM_START: &[TokenType] = &[
+    TokenType::Func,
+    TokenType::Extern,
+    TokenType::Struct,
+    TokenType::Ffi,
+    TokenType::Static,
+    TokenType::Import,
+    TokenType::Hash,
+];
+
 /// Parse Program
 /// Returns Programfile wich is the ast root
-pub fn generate_ast(lexer: &mut Lexer) -> ProgramFile {
+/// Syntax errors are reported to the lexer and parsing continues with the next item
+/// Imported files are read from the given file system
+pub fn generate_ast(lexer: &mut Lexer, fs: &dyn FileSystem) -> ProgramFile {
     lexer.next_token();
     let mut items = BTreeMap::<String, ProgramItem>::new();
-    loop {
-        if lexer.get_token().is_empty() {
-            break;
-        }
+    while lexer.get_token_type() != TokenType::Eof {
         let loc = lexer.get_token_loc();
-        match lexer.get_token_type() {
-            TokenType::Struct => {
-                let struct_def = struct_def(lexer);
-                let ident = struct_def.ident.clone();
-                let prv_value = items.insert(ident.clone(), ProgramItem::Struct(struct_def));
-                if prv_value.is_some() {
-                    error(
-                        format!("Struct with the name {} already exists", ident.clone()),
-                        loc,
-                    );
-                }
+        if let Err(err) = parse_program_item(lexer, &mut items, fs) {
+            lexer.report(err);
+            // the token has to be dropped if it can not start any item
+            if lexer.get_token_loc() == loc {
+                lexer.next_token();
             }
-            TokenType::Ffi => {
-                let ffi_func = parse_ffi_function_mapping(lexer);
-                let ident = ffi_func.1.ident.clone();
-                let prv_value =
-                    items.insert(ident.clone(), ProgramItem::FFI(ffi_func.0, ffi_func.1));
-                if prv_value.is_some() {
-                    error(
-                        format!("Function with the name {} already exists", ident),
-                        loc,
-                    );
-                }
+            lexer.synchronize(ITEM_START, 0);
+        }
+    }
+    ProgramFile {
+        items: items.values().cloned().collect::<Vec<ProgramItem>>(),
+    }
+}
+
+/// Parse a top level item and insert it into items
+fn parse_program_item(
+    lexer: &mut Lexer,
+    items: &mut BTreeMap<String, ProgramItem>,
+    fs: &dyn FileSystem,
+) -> ParseResult<()> {
+    let loc = lexer.get_token_loc();
+    let (ident, prv_value, kind) = match lexer.get_token_type() {
+        TokenType::Struct => {
+            let struct_def = struct_def(lexer)?;
+            let ident = struct_def.ident.clone();
+            let prv_value = items.insert(ident.clone(), ProgramItem::Struct(struct_def));
+            (ident, prv_value, "Struct")
+        }
+        TokenType::Ffi => {
+            let ffi_func = parse_ffi_function_mapping(lexer)?;
+            let ident = ffi_func.1.ident.clone();
+            let prv_value = items.insert(ident.clone(), ProgramItem::FFI(ffi_func.0, ffi_func.1));
+            (ident, prv_value, "Function")
+        }
+        TokenType::Func | TokenType::Extern => {
+            let function_def = parse_function_definition(lexer)?;
+            let ident = function_def.decl.ident.clone();
+            let prv_value = items.insert(ident.clone(), ProgramItem::Func(function_def));
+            (ident, prv_value, "Function")
+        }
+        TokenType::Hash => {
+            let attrs = parse_function_attrs(lexer)?;
+            if !matches!(lexer.get_token_type(), TokenType::Func | TokenType::Extern) {
+                return Err(ParseError::new(
+                    "E0105",
+                    "Expected a function definition after attributes",
+                    loc,
+                ));
             }
-            TokenType::Func | TokenType::Extern => {
-                let function_def = parse_function_definition(lexer);
-                let ident = function_def.decl.ident.clone();
-                let prv_value = items.insert(ident.clone(), ProgramItem::Func(function_def));
-                if prv_value.is_some() {
-                    error(
-                        format!("Function with the name {} already exists", ident),
-                        loc,
-                    );
+            let mut function_def = parse_function_definition(lexer)?;
+            function_def.attrs = attrs;
+            let ident = function_def.decl.ident.clone();
+            let prv_value = items.insert(ident.clone(), ProgramItem::Func(function_def));
+            (ident, prv_value, "Function")
+        }
+        TokenType::Static => {
+            lexer.match_token(TokenType::Static)?;
+            let var_decl = variable_declare(lexer)?;
+            lexer.match_token(TokenType::SemiColon)?;
+            let ident = var_decl.ident.clone();
+            let prv_value = items.insert(ident.clone(), ProgramItem::StaticVar(var_decl));
+            (ident, prv_value, "Variable")
+        }
+        TokenType::Import => {
+            let import = parse_mod_import(lexer)?;
+            let mut new_path = import.0;
+            new_path.push_str(".nmt");
+            let Ok(source) = fs.read_to_string(Path::new(&new_path)) else {
+                return Err(ParseError::new(
+                    "E0107",
+                    format!("Can not open imported file ({new_path})"),
+                    loc,
+                ));
+            };
+            let new_file = match parse_source(fs, new_path, source, lexer.target_platform) {
+                Ok(new_file) => new_file,
+                Err(diagnostics) => {
+                    lexer.diagnostics.extend(diagnostics);
+                    return Ok(());
                 }
-            }
-            TokenType::Static => {
-                lexer.match_token(TokenType::Static);
-                let var_decl = variable_declare(lexer);
-                lexer.match_token(TokenType::SemiColon);
-                let ident = var_decl.ident.clone();
-                let prv_value = items.insert(ident.clone(), ProgramItem::StaticVar(var_decl));
-                if prv_value.is_some() {
-                    error(
-                        format!("Variable with the name {} already exists", ident),
-                        loc,
-                    );
+            };
+            for item_name in import.1.iter() {
+                if items.contains_key(item_name) {
+                    lexer.report(ParseError::new("E0106", 
+                        format!("Import failed beacuse namespace with the name ({}) already exists in this program",
+                        item_name), loc.clone()));
                 }
             }
-            TokenType::Import => {
-                let import = parse_mod_import(lexer);
-                let mut new_path = import.0;
-                new_path.push_str(".nmt");
-                let new_file = parse_source_file(new_path);
-                for item_name in import.1.iter() {
-                    if items.contains_key(item_name) {
-                        error(
-                            format!("Import failed beacuse namespace with the name ({}) already exists in this program",
-                            item_name), loc);
-                    }
-                }
-                for item in new_file.items {
-                    if import.1.is_empty() {
+            for item in new_file.items {
+                if import.1.is_empty() {
+                    items.insert(item.get_key(), item);
+                } else {
+                    let key = item.get_key();
+                    if import.1.contains(&key) {
                         items.insert(item.get_key(), item);
-                    } else {
-                        let key = item.get_key();
-                        if import.1.contains(&key) {
-                            items.insert(item.get_key(), item);
-                        }
                     }
                 }
             }
-            _ => error(
+            return Ok(());
+        }
+        _ => {
+            return Err(ParseError::new(
+                "E0105",
                 format!(
                     "Unexpected Token ({}) for the top level program",
                     lexer.get_token_type()
                 ),
                 loc,
-            ),
+            ))
         }
+    };
+    if prv_value.is_some() {
+        lexer.report(ParseError::new(
+            "E0106",
+            format!("{kind} with the name {ident} already exists"),
+            loc,
+        ));
     }
-    ProgramFile {
-        items: items.values().cloned().collect::<Vec<ProgramItem>>(),
-    }
+    Ok(())
 }
+
 /// Include FFI
 /// Returns FFI Program Item
 ///
 /// Syntax:
 /// ffi "fopen" func nmt_fopen(pathname @str, mode @str) @FILE
-pub fn parse_ffi_function_mapping(lexer: &mut Lexer) -> (String, FunctionDecl) {
-    lexer.match_token(TokenType::Ffi);
+pub fn parse_ffi_function_mapping(lexer: &mut Lexer) -> ParseResult<(String, FunctionDecl)> {
+    lexer.match_token(TokenType::Ffi)?;
     let module_name = lexer.get_token().literal;
-    lexer.match_token(TokenType::String);
-    let function = parse_function_declaration(lexer);
-    (module_name, function)
+    lexer.match_token(TokenType::String)?;
+    let function = parse_function_declaration(lexer)?;
+    Ok((module_name, function))
 }
 
 /// import Program
 /// Returns Import Program Item
-pub fn parse_mod_import(lexer: &mut Lexer) -> (String, Vec<String>) {
-    lexer.match_token(TokenType::Import);
+pub fn parse_mod_import(lexer: &mut Lexer) -> ParseResult<(String, Vec<String>)> {
+    lexer.match_token(TokenType::Import)?;
     let file_path = lexer.get_token().literal;
-    lexer.match_token(TokenType::String);
+    lexer.match_token(TokenType::String)?;
     if lexer.get_token_type() == TokenType::DoubleColon {
-        lexer.match_token(TokenType::DoubleColon);
+        lexer.match_token(TokenType::DoubleColon)?;
         let mut idents_vec = Vec::<String>::new();
         loop {
             let ident = lexer.get_token().literal;
-            lexer.match_token(TokenType::Identifier);
+            lexer.match_token(TokenType::Identifier)?;
             idents_vec.push(ident);
             if lexer.get_token_type() == TokenType::Comma {
-                lexer.match_token(TokenType::Comma);
+                lexer.match_token(TokenType::Comma)?;
             } else {
                 break;
             }
         }
-        (file_path, idents_vec)
+        Ok((file_path, idents_vec))
     } else {
-        (file_path, vec![])
+        Ok((file_path, vec![]))
     }
 }
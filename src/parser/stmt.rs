@@ -22,7 +22,10 @@
 *     3. This notice may not be removed or altered from any source distribution.
 *
 **********************************************************************************************/
-use crate::error_handeling::Loc;
+use std::str::FromStr;
+
+use crate::assembler::register::Reg;
+use crate::error_handeling::{CompilationError, Loc, ParseError, ParseResult};
 use crate::lexer::{Lexer, TokenType};
 use crate::parser::block::Block;
 use crate::parser::expr::Expr;
@@ -63,7 +66,7 @@ pub enum StmtType {
     /// Return Stmts
     Return(Expr),
     /// Inline Assembly
-    InlineAsm(Vec<String>),
+    InlineAsm(InlineAsm),
     /// Break Stmts
     Break,
     /// CONTINUE Stmts
@@ -112,65 +115,212 @@ pub struct WhileStmt {
     pub block: Block,
 }
 
+/// Inline Assembly Information
+/// * instructs - assembly lines of the block and their locations
+/// * outputs - registers stored in variables after the block
+/// * inputs - variables loaded in registers before the block
+/// * clobbers - registers saved before and restored after the block
+#[derive(Debug, Clone)]
+pub struct InlineAsm {
+    pub instructs: Vec<(String, Loc)>,
+    pub outputs: Vec<AsmOperand>,
+    pub inputs: Vec<AsmOperand>,
+    pub clobbers: Vec<AsmOperand>,
+}
+
+/// Register bound to a variable in an inline asm block
+/// the ident of a clobber is empty
+#[derive(Debug, Clone)]
+pub struct AsmOperand {
+    pub reg: Reg,
+    pub ident: String,
+    pub loc: Loc,
+}
+
+impl InlineAsm {
+    /// Assembly lines of the block including the operands and clobbers
+    /// lines of operands are located at the operand
+    /// the register of an operand is resized to the variable like the memory of %ident
+    /// * size - access size of a variable in bytes
+    pub fn expand(
+        &self,
+        mut size: impl FnMut(&str) -> Result<u8, CompilationError>,
+    ) -> Result<Vec<(String, Loc)>, CompilationError> {
+        let mut size = |opr: &AsmOperand| size(&opr.ident).map_err(|e| e.at(&opr.loc));
+        let mut lines = Vec::new();
+        for clobber in self.clobbers.iter() {
+            lines.push((
+                format!("push {}", clobber.reg.convert(8)),
+                clobber.loc.clone(),
+            ));
+        }
+        for input in self.inputs.iter() {
+            let reg = input.reg.convert(size(input)?);
+            lines.push((format!("mov {reg}, %{}", input.ident), input.loc.clone()));
+        }
+        lines.extend(self.instructs.iter().cloned());
+        for output in self.outputs.iter() {
+            let reg = output.reg.convert(size(output)?);
+            lines.push((format!("mov %{}, {reg}", output.ident), output.loc.clone()));
+        }
+        for clobber in self.clobbers.iter().rev() {
+            lines.push((
+                format!("pop {}", clobber.reg.convert(8)),
+                clobber.loc.clone(),
+            ));
+        }
+        Ok(lines)
+    }
+}
+
+/// Parse Inline Asm Blocks
+/// asm { "instr"... : "=reg" (output), ... : "reg" (input), ... : "reg", ... }
+pub fn inline_asm(lexer: &mut Lexer) -> ParseResult<InlineAsm> {
+    lexer.match_token(TokenType::Asm)?;
+    lexer.match_token(TokenType::OCurly)?;
+    let mut instructs = Vec::<(String, Loc)>::new();
+    while lexer.get_token_type() == TokenType::String {
+        instructs.push((lexer.get_token().literal, lexer.get_token_loc()));
+        lexer.match_token(TokenType::String)?;
+    }
+    let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+    for (i, section) in sections.iter_mut().enumerate() {
+        if lexer.get_token_type() != TokenType::Colon {
+            break;
+        }
+        lexer.match_token(TokenType::Colon)?;
+        while lexer.get_token_type() == TokenType::String {
+            section.push(asm_operand(lexer, i)?);
+            if lexer.get_token_type() != TokenType::Comma {
+                break;
+            }
+            lexer.match_token(TokenType::Comma)?;
+        }
+    }
+    lexer.match_token(TokenType::CCurly)?;
+    let [outputs, inputs, clobbers] = sections;
+    Ok(InlineAsm {
+        instructs,
+        outputs,
+        inputs,
+        clobbers,
+    })
+}
+
+/// Register constraint and the variable of an operand
+/// clobbers have no variable and outputs are marked with =
+fn asm_operand(lexer: &mut Lexer, section: usize) -> ParseResult<AsmOperand> {
+    let loc = lexer.get_token_loc();
+    let constraint = lexer.get_token().literal;
+    lexer.match_token(TokenType::String)?;
+    let name = match (section, constraint.strip_prefix('=')) {
+        (0, Some(name)) => name,
+        (0, None) => {
+            return Err(ParseError::new(
+                "E0110",
+                format!("Output operand ({constraint}) must start with ="),
+                loc,
+            ))
+        }
+        (_, Some(_)) => {
+            return Err(ParseError::new(
+                "E0110",
+                format!("Only output operands can start with = ({constraint})"),
+                loc,
+            ))
+        }
+        (_, None) => constraint.as_str(),
+    };
+    let reg = match Reg::from_str(name) {
+        Ok(reg) if is_operand_reg(reg) => reg,
+        _ => {
+            return Err(ParseError::new(
+                "E0110",
+                format!("Invalid register ({name}) for inline asm operand"),
+                loc,
+            ))
+        }
+    };
+    if section == 2 {
+        return Ok(AsmOperand {
+            reg,
+            ident: String::new(),
+            loc,
+        });
+    }
+    lexer.match_token(TokenType::OParen)?;
+    let ident = lexer.get_token().literal;
+    lexer.match_token(TokenType::Identifier)?;
+    lexer.match_token(TokenType::CParen)?;
+    Ok(AsmOperand { reg, ident, loc })
+}
+
+/// General purpose registers that can be resized and are not used by the stack frame
+fn is_operand_reg(reg: Reg) -> bool {
+    !reg.is_xmm()
+        && !matches!(reg, Reg::AH | Reg::CH | Reg::DH | Reg::BH)
+        && !matches!(reg.convert(8), Reg::RSP | Reg::RBP)
+}
+
 /// Parse If Stmts
-pub fn if_stmt(lexer: &mut Lexer, master: &mut Block) -> IFStmt {
-    lexer.match_token(TokenType::If);
-    let condition = expr(lexer);
+pub fn if_stmt(lexer: &mut Lexer, master: &mut Block) -> ParseResult<IFStmt> {
+    lexer.match_token(TokenType::If)?;
+    let condition = expr(lexer)?;
     let mut then_block = Block::new(master, BlockType::Condition);
-    then_block.parse_block(lexer);
+    then_block.parse_block(lexer)?;
     if lexer.get_token_type() == TokenType::Else {
-        lexer.match_token(TokenType::Else);
+        lexer.match_token(TokenType::Else)?;
         if lexer.get_token_type() == TokenType::If {
-            let else_block = Box::new(ElseBlock::Elif(if_stmt(lexer, master)));
-            IFStmt {
+            let else_block = Box::new(ElseBlock::Elif(if_stmt(lexer, master)?));
+            Ok(IFStmt {
                 condition,
                 then_block,
                 else_block,
-            }
+            })
         } else {
             let mut else_block = Block::new(master, BlockType::Condition);
-            else_block.parse_block(lexer);
-            IFStmt {
+            else_block.parse_block(lexer)?;
+            Ok(IFStmt {
                 condition,
                 then_block,
                 else_block: Box::new(ElseBlock::Else(else_block)),
-            }
+            })
         }
     } else {
-        IFStmt {
+        Ok(IFStmt {
             condition,
             then_block,
             else_block: Box::new(ElseBlock::None),
-        }
+        })
     }
 }
 
 /// parse For Loops
-pub fn for_loop(lexer: &mut Lexer, master: &mut Block) -> ForLoop {
-    lexer.match_token(TokenType::For);
-    let mut iterator = inline_variable_declare(lexer);
+pub fn for_loop(lexer: &mut Lexer, master: &mut Block) -> ParseResult<ForLoop> {
+    lexer.match_token(TokenType::For)?;
+    let mut iterator = inline_variable_declare(lexer)?;
     if iterator.init_value.is_none() {
         iterator.init_value = Some(Expr {
             loc: iterator.loc.clone(),
             etype: super::expr::ExprType::Int(0),
         });
     }
-    lexer.match_token(TokenType::To);
-    let end_expr = expr(lexer);
+    lexer.match_token(TokenType::To)?;
+    let end_expr = expr(lexer)?;
     let mut block = Block::new(master, BlockType::Loop);
-    block.parse_block(lexer);
-    ForLoop {
+    block.parse_block(lexer)?;
+    Ok(ForLoop {
         iterator,
         end_expr,
         block,
-    }
+    })
 }
 
 /// Parse While Stmts
-pub fn while_stmt(lexer: &mut Lexer, master: &mut Block) -> WhileStmt {
-    lexer.match_token(TokenType::While);
-    let condition = expr(lexer);
+pub fn while_stmt(lexer: &mut Lexer, master: &mut Block) -> ParseResult<WhileStmt> {
+    lexer.match_token(TokenType::While)?;
+    let condition = expr(lexer)?;
     let mut block = Block::new(master, BlockType::Loop);
-    block.parse_block(lexer);
-    WhileStmt { condition, block }
+    block.parse_block(lexer)?;
+    Ok(WhileStmt { condition, block })
 }
@@ -24,26 +24,29 @@ use std::collections::BTreeMap;
 *     3. This notice may not be removed or altered from any source distribution.
 *
 **********************************************************************************************/
-use crate::lexer::{Lexer, TokenType};
+use crate::{
+    error_handeling::ParseResult,
+    lexer::{Lexer, TokenType},
+};
 
 use super::types::{type_def, StructItemType, StructType};
 
-pub fn struct_def(lexer: &mut Lexer) -> StructType {
-    lexer.match_token(TokenType::Struct);
+pub fn struct_def(lexer: &mut Lexer) -> ParseResult<StructType> {
+    lexer.match_token(TokenType::Struct)?;
     let struct_ident_token = lexer.get_token();
-    lexer.match_token(TokenType::Identifier);
-    lexer.match_token(TokenType::OCurly);
+    lexer.match_token(TokenType::Identifier)?;
+    lexer.match_token(TokenType::OCurly)?;
     let mut items = BTreeMap::<String, StructItemType>::new();
     let mut offset = 0;
     loop {
         if lexer.get_token_type() == TokenType::CCurly {
-            lexer.match_token(TokenType::CCurly);
+            lexer.match_token(TokenType::CCurly)?;
             break;
         }
         let ident = lexer.get_token().literal;
-        lexer.match_token(TokenType::Identifier);
+        lexer.match_token(TokenType::Identifier)?;
         if lexer.get_token_type() == TokenType::ATSign {
-            let ttype = type_def(lexer);
+            let ttype = type_def(lexer)?;
             offset += ttype.item_size();
             items.insert(
                 ident.clone(),
@@ -51,11 +54,11 @@ pub fn struct_def(lexer: &mut Lexer) -> StructType {
             );
         }
         if lexer.get_token_type() != TokenType::CCurly {
-            lexer.match_token(TokenType::Comma);
+            lexer.match_token(TokenType::Comma)?;
         }
     }
-    StructType {
+    Ok(StructType {
         ident: struct_ident_token.literal,
         items,
-    }
+    })
 }
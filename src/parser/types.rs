@@ -25,7 +25,7 @@
 use std::{collections::BTreeMap, fmt::Display};
 
 use crate::{
-    error_handeling::{error, CompilationError},
+    error_handeling::{CompilationError, ParseError, ParseResult},
     lexer::{Lexer, TokenType},
 };
 
@@ -232,69 +232,64 @@ impl Display for VariableType {
 }
 
 /// Parse type definition
-pub fn type_def(lexer: &mut Lexer) -> VariableType {
-    let loc = lexer.get_current_loc();
-    lexer.match_token(TokenType::ATSign);
+pub fn type_def(lexer: &mut Lexer) -> ParseResult<VariableType> {
+    let loc = lexer.get_token_loc();
+    lexer.match_token(TokenType::ATSign)?;
     match lexer.get_token_type() {
         TokenType::Identifier => {
             let ident = lexer.get_token().literal;
-            lexer.match_token(TokenType::Identifier);
-            VariableType::from_string(ident)
+            lexer.match_token(TokenType::Identifier)?;
+            Ok(VariableType::from_string(ident))
         }
         TokenType::OBracket => {
             let var_type: VariableType;
             let size: usize;
-            lexer.match_token(TokenType::OBracket);
+            lexer.match_token(TokenType::OBracket)?;
             let token = lexer.get_token();
-            if token.is_empty() {
-                error("Expected an Identifier found EOF", loc);
-            }
             if token.t_type == TokenType::Identifier {
                 var_type = VariableType::from_string(lexer.get_token().literal);
-                lexer.match_token(TokenType::Identifier);
+                lexer.match_token(TokenType::Identifier)?;
             } else if token.t_type == TokenType::ATSign {
-                var_type = self::type_def(lexer);
+                var_type = self::type_def(lexer)?;
             } else {
-                error(
+                return Err(ParseError::new(
+                    "E0105",
                     format!(
                         "Error: Expected Identifier found ({})",
                         lexer.get_token_type()
                     ),
                     loc,
-                );
+                ));
             }
-            lexer.match_token(TokenType::Comma);
+            lexer.match_token(TokenType::Comma)?;
             let token = lexer.get_token();
-            if token.is_empty() {
-                error("Error: Expected a Number found EOF", loc);
-            }
             match token.t_type {
                 TokenType::Int(s) => {
                     size = s as usize;
-                    lexer.match_token(TokenType::Int(s));
+                    lexer.match_token(TokenType::Int(s))?;
                 }
                 TokenType::QMark => {
-                    lexer.match_token(TokenType::QMark);
-                    return VariableType::Pointer;
+                    lexer.match_token(TokenType::QMark)?;
+                    return Ok(VariableType::Pointer);
                 }
                 _ => {
-                    error(
+                    return Err(ParseError::new(
+                        "E0105",
                         format!(
                             "Error: Expected Integer Number found ({})",
                             lexer.get_token_type()
                         ),
                         loc,
-                    );
+                    ));
                 }
             }
-            lexer.match_token(TokenType::CBracket);
-            VariableType::Array(Box::new(var_type), size)
-        }
-        _ => {
-            error(
-                format!("Syntax Error: Unknown Token ({})", lexer.get_token_type()),
-                loc,
-            );
+            lexer.match_token(TokenType::CBracket)?;
+            Ok(VariableType::Array(Box::new(var_type), size))
         }
+        _ => Err(ParseError::new(
+            "E0105",
+            format!("Syntax Error: Unknown Token ({})", lexer.get_token_type()),
+            loc,
+        )),
     }
 }
@@ -23,7 +23,7 @@
 *
 **********************************************************************************************/
 use crate::{
-    error_handeling::{error, Loc},
+    error_handeling::{Loc, ParseError, ParseResult},
     lexer::{Lexer, TokenType},
     parser::types::type_def,
 };
@@ -49,55 +49,56 @@ pub struct VariableDeclare {
 }
 
 /// parse variable declare
-pub fn inline_variable_declare(lexer: &mut Lexer) -> VariableDeclare {
+pub fn inline_variable_declare(lexer: &mut Lexer) -> ParseResult<VariableDeclare> {
     let ident_token = lexer.get_token();
     let _loc = lexer.get_token_loc();
-    lexer.match_token(TokenType::Identifier);
+    lexer.match_token(TokenType::Identifier)?;
     let mut is_mutable: bool = true;
     let mut v_type: VariableType = VariableType::Any;
     let mut init_value: Option<Expr> = None;
     if lexer.get_token_type() == TokenType::ATSign {
-        v_type = type_def(lexer);
+        v_type = type_def(lexer)?;
     }
-    let loc = lexer.get_current_loc();
+    let loc = lexer.get_token_loc();
     match lexer.get_token_type() {
         TokenType::DoubleColon => {
             is_mutable = false;
-            lexer.match_token(TokenType::ColonEq);
-            init_value = Some(expr(lexer));
+            lexer.match_token(TokenType::ColonEq)?;
+            init_value = Some(expr(lexer)?);
         }
         TokenType::ColonEq => {
             is_mutable = false;
-            lexer.match_token(TokenType::ColonEq);
-            init_value = Some(expr(lexer));
+            lexer.match_token(TokenType::ColonEq)?;
+            init_value = Some(expr(lexer)?);
         }
         TokenType::Eq => {
             is_mutable = true;
-            lexer.match_token(TokenType::Eq);
-            init_value = Some(expr(lexer));
+            lexer.match_token(TokenType::Eq)?;
+            init_value = Some(expr(lexer)?);
         }
         TokenType::SemiColon | TokenType::To => (),
         _ => {
-            error(
+            return Err(ParseError::new(
+                "E0105",
                 format!(
                     "Expected \"=\" or \":=\" found ({})",
                     lexer.get_token_type()
                 ),
                 loc,
-            );
+            ));
         }
     }
-    VariableDeclare {
+    Ok(VariableDeclare {
         mutable: is_mutable,
         ident: ident_token.literal,
         v_type,
         init_value,
         loc,
-    }
+    })
 }
 
 /// Parse Variable Declaration
-pub fn variable_declare(lexer: &mut Lexer) -> VariableDeclare {
-    lexer.match_token(TokenType::Var);
+pub fn variable_declare(lexer: &mut Lexer) -> ParseResult<VariableDeclare> {
+    lexer.match_token(TokenType::Var)?;
     inline_variable_declare(lexer)
 }
@@ -0,0 +1,482 @@
+/**********************************************************************************************
+*
+*   simulator/asm: Inline assembly emulation for the simulator
+*
+*   Supports the data movement and arithmetic instructions used by inline asm blocks.
+*   System calls are emulated in-process: read (stdin), write (stdout, stderr) and exit.
+*   Labels, jumps and calls of inline asm blocks are not simulated.
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+use std::io::Write;
+
+use crate::{
+    assembler::{
+        asm_parser::parse_asm,
+        instructions::{Instr, Opr, Oprs},
+        memory::{MemAddr, MemAddrType},
+        mnemonic::Mnemonic,
+        register::Reg,
+    },
+    error_handeling::{CompilationError, Loc},
+    ir::codegen::ARG_REGS,
+    parser::types::VariableType,
+};
+
+use super::{Halt, SimValue, Simulator};
+
+pub const RSP_INDEX: usize = 4;
+pub const RBP_INDEX: usize = 5;
+
+const SYS_READ: i64 = 0;
+const SYS_WRITE: i64 = 1;
+const SYS_EXIT: i64 = 60;
+const SYS_EXIT_GROUP: i64 = 231;
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+/// Replaces %ident in an inline asm instruction with the memory address of the variable
+/// * lookup - returns access size and rbp relative displacement of a variable
+pub fn substitute_variables(
+    instr: &str,
+    mut lookup: impl FnMut(&str) -> Result<(u8, i64), CompilationError>,
+) -> Result<String, CompilationError> {
+    let mut result = String::new();
+    let mut chars = instr.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let mut ident = String::new();
+        while let Some(c) = chars.peek() {
+            if !(c.is_alphanumeric() || *c == '_') {
+                break;
+            }
+            ident.push(*c);
+            chars.next();
+        }
+        if ident.is_empty() {
+            return Err(CompilationError::InvalidInlineAsm(instr.to_string()));
+        }
+        let (size, disp) = lookup(&ident)?;
+        result.push_str(&MemAddr::new_disp_s(size, Reg::RBP, disp as i32).to_string());
+    }
+    Ok(result)
+}
+
+/// Name of the label defined by an inline asm line
+fn asm_label(line: &str) -> Option<&str> {
+    line.trim().strip_suffix(':').map(str::trim)
+}
+
+/// Index of the register in the register file and bit offset of its value
+fn reg_index(reg: Reg) -> (usize, u32) {
+    match reg {
+        Reg::AH | Reg::CH | Reg::DH | Reg::BH => (reg.opcode() as usize - 4, 8),
+        _ => (
+            reg.opcode() as usize + if reg.is_extended() { 8 } else { 0 },
+            0,
+        ),
+    }
+}
+
+fn opr_size(opr: &Opr) -> u8 {
+    match opr {
+        Opr::R64(_) => 8,
+        Opr::R32(_) => 4,
+        Opr::R16(_) => 2,
+        Opr::R8(_) => 1,
+        Opr::Mem(m) => m.size,
+        _ => 0,
+    }
+}
+
+/// Truncates value to size bytes and sign extends the result
+fn sign_extend(value: i64, size: u8) -> i64 {
+    match size {
+        1 => value as i8 as i64,
+        2 => value as i16 as i64,
+        4 => value as i32 as i64,
+        _ => value,
+    }
+}
+
+impl Simulator<'_> {
+    fn unsupported(&self, instr: &str, loc: &Loc) -> Halt {
+        Halt::error(
+            loc.clone(),
+            CompilationError::Err(format!(
+                "Instruction ({instr}) is not supported by the simulator"
+            )),
+        )
+    }
+
+    fn read_reg(&self, reg: Reg) -> i64 {
+        let (index, shift) = reg_index(reg);
+        let full = if index == RSP_INDEX {
+            self.sp as i64
+        } else {
+            self.regs[index]
+        };
+        match reg.size() {
+            64 => full,
+            size => (full >> shift) & ((1i64 << size) - 1),
+        }
+    }
+
+    fn write_reg(&mut self, reg: Reg, value: i64) {
+        let (index, shift) = reg_index(reg);
+        let full = self.read_reg(reg.convert(8));
+        let new = match reg.size() {
+            64 => value,
+            32 => value & 0xffff_ffff,
+            size => {
+                let mask = ((1i64 << size) - 1) << shift;
+                (full & !mask) | ((value << shift) & mask)
+            }
+        };
+        if index == RSP_INDEX {
+            self.sp = new as u64;
+        } else {
+            self.regs[index] = new;
+        }
+    }
+
+    fn mem_address(&self, mem: &MemAddr, loc: &Loc) -> Result<u64, Halt> {
+        let addr = match &mem.addr_type {
+            MemAddrType::Addr(r) => self.read_reg(*r),
+            MemAddrType::Disp(r, disp) => self.read_reg(*r).wrapping_add(*disp as i64),
+            MemAddrType::Sib(r, disp, r2, scale) => self
+                .read_reg(*r)
+                .wrapping_add(*disp as i64)
+                .wrapping_add(self.read_reg(*r2).wrapping_mul(*scale as i64)),
+            MemAddrType::AddrRela(rel) => match self.globals.get(rel) {
+                Some(var) => var.addr as i64,
+                None => return Err(self.unsupported(rel, loc)),
+            },
+        };
+        Ok(addr as u64)
+    }
+
+    fn read_opr(&mut self, opr: &Opr, size: u8, loc: &Loc) -> Result<i64, Halt> {
+        match opr {
+            Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r) => Ok(self.read_reg(*r)),
+            Opr::Mem(m) => {
+                let addr = self.mem_address(m, loc)?;
+                let size = if m.size == 0 { size } else { m.size };
+                self.read(addr, size, loc)
+            }
+            Opr::Imm8(i) | Opr::Imm32(i) | Opr::Imm64(i) => Ok(*i),
+            // address of a static variable
+            Opr::Rela(s) => match self.globals.get(s) {
+                Some(var) => Ok(var.addr as i64),
+                None => Err(self.unsupported(s, loc)),
+            },
+            Opr::Loc(s) => Err(self.unsupported(s, loc)),
+            Opr::Xmm(r) => Err(self.unsupported(&r.to_string(), loc)),
+        }
+    }
+
+    fn write_opr(&mut self, opr: &Opr, size: u8, value: i64, loc: &Loc) -> Result<(), Halt> {
+        match opr {
+            Opr::R64(r) | Opr::R32(r) | Opr::R16(r) | Opr::R8(r) => {
+                self.write_reg(*r, value);
+                Ok(())
+            }
+            Opr::Mem(m) => {
+                let addr = self.mem_address(m, loc)?;
+                let size = if m.size == 0 { size } else { m.size };
+                self.write(addr, size, value, loc)
+            }
+            _ => Err(self.unsupported(&opr.to_string(), loc)),
+        }
+    }
+
+    fn push(&mut self, value: i64, loc: &Loc) -> Result<(), Halt> {
+        self.sp -= 8;
+        self.write(self.sp, 8, value, loc)
+    }
+
+    fn pop(&mut self, loc: &Loc) -> Result<i64, Halt> {
+        let value = self.read(self.sp, 8, loc)?;
+        self.sp += 8;
+        Ok(value)
+    }
+
+    /// Executes the lines of an inline asm block
+    /// labels end with a colon and are local to the block
+    pub(super) fn exec_asm_block(&mut self, lines: &[(String, Loc)]) -> Result<(), Halt> {
+        let mut pc = 0;
+        while pc < lines.len() {
+            let (line, loc) = &lines[pc];
+            if asm_label(line).is_some() {
+                pc += 1;
+                continue;
+            }
+            let Some(target) = self.exec_inline_asm(line, loc)? else {
+                pc += 1;
+                continue;
+            };
+            match lines
+                .iter()
+                .position(|(l, _)| asm_label(l) == Some(target.as_str()))
+            {
+                Some(index) => pc = index + 1,
+                None => {
+                    return Err(Halt::error(
+                        loc.clone(),
+                        CompilationError::Err(format!("Undefined label ({target})")),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a single assembly instruction
+    /// Returns the label of a taken jump
+    pub(super) fn exec_asm(&mut self, source: &str, loc: &Loc) -> Result<Option<String>, Halt> {
+        let instr = match parse_asm(source) {
+            Ok(instr) => instr,
+            Err(e) => {
+                return Err(Halt::error(
+                    loc.clone(),
+                    CompilationError::Err(format!("Invalid inline asm ({source}): {e}")),
+                ))
+            }
+        };
+        match (&instr.mnem, &instr.oprs) {
+            (Mnemonic::Jmp, Oprs::One(Opr::Loc(label))) => Ok(Some(label.clone())),
+            (jcc, Oprs::One(Opr::Loc(label))) if jcc.is_jump() => {
+                let code = jcc.condition_code().unwrap();
+                match self.condition(code) {
+                    Some(true) => Ok(Some(label.clone())),
+                    Some(false) => Ok(None),
+                    None => Err(self.unsupported(source, loc)),
+                }
+            }
+            (Mnemonic::Call, Oprs::One(Opr::Loc(name))) => {
+                self.asm_call(name, source, loc)?;
+                Ok(None)
+            }
+            _ => self.exec_instr(&instr, source, loc).map(|_| None),
+        }
+    }
+
+    /// Whether a condition code holds for the operands of the last cmp or test
+    fn condition(&self, code: u8) -> Option<bool> {
+        let (a, b) = self.flags;
+        let (ua, ub) = (a as u64, b as u64);
+        let res = match code {
+            0x0 => a.checked_sub(b).is_none(),
+            0x1 => a.checked_sub(b).is_some(),
+            0x2 => ua < ub,
+            0x3 => ua >= ub,
+            0x4 => a == b,
+            0x5 => a != b,
+            0x6 => ua <= ub,
+            0x7 => ua > ub,
+            0x8 => a.wrapping_sub(b) < 0,
+            0x9 => a.wrapping_sub(b) >= 0,
+            0xc => a < b,
+            0xd => a >= b,
+            0xe => a <= b,
+            0xf => a > b,
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    /// Calls a function of the program with its arguments in the argument registers
+    /// the result is returned in rax
+    fn asm_call(&mut self, name: &str, source: &str, loc: &Loc) -> Result<(), Halt> {
+        let Some(f) = self.functions.get(name).cloned() else {
+            return Err(self.unsupported(source, loc));
+        };
+        let args = &f.decl.args;
+        if args.len() > ARG_REGS.len() || args.iter().any(|a| a.typedef == VariableType::Float) {
+            return Err(self.unsupported(source, loc));
+        }
+        let args = args
+            .iter()
+            .zip(ARG_REGS.iter())
+            .map(|(arg, reg)| SimValue::new(self.read_reg(*reg), arg.typedef.clone()))
+            .collect();
+        let ret = self.call_function(f, args, loc)?;
+        self.write_reg(Reg::RAX, ret.value);
+        Ok(())
+    }
+
+    fn exec_instr(&mut self, instr: &Instr, source: &str, loc: &Loc) -> Result<(), Halt> {
+        match (&instr.mnem, &instr.oprs) {
+            (Mnemonic::Nop, Oprs::None) => Ok(()),
+            (Mnemonic::Syscall, Oprs::None) => self.syscall(),
+            (Mnemonic::Cqo, Oprs::None) => {
+                let rax = self.read_reg(Reg::RAX);
+                self.write_reg(Reg::RDX, if rax < 0 { -1 } else { 0 });
+                Ok(())
+            }
+            (Mnemonic::Push, Oprs::One(src)) => {
+                let value = self.read_opr(src, 8, loc)?;
+                self.push(value, loc)
+            }
+            (Mnemonic::Pop, Oprs::One(dst)) => {
+                let value = self.pop(loc)?;
+                self.write_opr(dst, 8, value, loc)
+            }
+            (Mnemonic::Inc | Mnemonic::Neg | Mnemonic::Not, Oprs::One(dst)) => {
+                let size = opr_size(dst).max(1);
+                let value = self.read_opr(dst, size, loc)?;
+                let value = match instr.mnem {
+                    Mnemonic::Inc => value.wrapping_add(1),
+                    Mnemonic::Neg => value.wrapping_neg(),
+                    _ => !value,
+                };
+                self.write_opr(dst, size, value, loc)
+            }
+            (Mnemonic::Idiv | Mnemonic::Mul, Oprs::One(src)) => {
+                let size = opr_size(src).max(1);
+                let divisor = sign_extend(self.read_opr(src, size, loc)?, size);
+                let rax = self.read_reg(Reg::RAX);
+                if instr.mnem == Mnemonic::Mul {
+                    let res = (rax as u64 as u128) * (divisor as u64 as u128);
+                    self.write_reg(Reg::RAX, res as i64);
+                    self.write_reg(Reg::RDX, (res >> 64) as i64);
+                    return Ok(());
+                }
+                if divisor == 0 {
+                    return Err(Halt::error(
+                        loc.clone(),
+                        CompilationError::Err("Division by zero".into()),
+                    ));
+                }
+                self.write_reg(Reg::RAX, rax.wrapping_div(divisor));
+                self.write_reg(Reg::RDX, rax.wrapping_rem(divisor));
+                Ok(())
+            }
+            (Mnemonic::Lea, Oprs::Two(dst, Opr::Mem(m))) => {
+                let addr = self.mem_address(m, loc)?;
+                self.write_opr(dst, 8, addr as i64, loc)
+            }
+            (mnem, Oprs::Two(dst, src)) => {
+                let size = match opr_size(dst) {
+                    0 => opr_size(src).max(1),
+                    s => s,
+                };
+                let right = self.read_opr(src, size, loc)?;
+                let result = match mnem {
+                    Mnemonic::Mov => right,
+                    Mnemonic::Cmp | Mnemonic::Test => {
+                        let left = sign_extend(self.read_opr(dst, size, loc)?, size);
+                        let right = sign_extend(right, size);
+                        self.flags = match mnem {
+                            Mnemonic::Cmp => (left, right),
+                            _ => (sign_extend(left & right, size), 0),
+                        };
+                        return Ok(());
+                    }
+                    Mnemonic::Cmove
+                    | Mnemonic::Cmovne
+                    | Mnemonic::Cmovg
+                    | Mnemonic::Cmovl
+                    | Mnemonic::Cmovge
+                    | Mnemonic::Cmovle => {
+                        let (a, b) = self.flags;
+                        let cond = match mnem {
+                            Mnemonic::Cmove => a == b,
+                            Mnemonic::Cmovne => a != b,
+                            Mnemonic::Cmovg => a > b,
+                            Mnemonic::Cmovl => a < b,
+                            Mnemonic::Cmovge => a >= b,
+                            _ => a <= b,
+                        };
+                        if !cond {
+                            return Ok(());
+                        }
+                        right
+                    }
+                    _ => {
+                        let left = self.read_opr(dst, size, loc)?;
+                        match mnem {
+                            Mnemonic::Add => left.wrapping_add(right),
+                            Mnemonic::Sub => left.wrapping_sub(right),
+                            Mnemonic::Imul => left.wrapping_mul(right),
+                            Mnemonic::And => left & right,
+                            Mnemonic::Or => left | right,
+                            Mnemonic::Sal => left.wrapping_shl(right as u32 & 63),
+                            Mnemonic::Sar => {
+                                sign_extend(left, size).wrapping_shr(right as u32 & 63)
+                            }
+                            Mnemonic::Shr => ((left as u64).wrapping_shr(right as u32 & 63)) as i64,
+                            _ => return Err(self.unsupported(source, loc)),
+                        }
+                    }
+                };
+                self.write_opr(dst, size, result, loc)
+            }
+            _ => Err(self.unsupported(source, loc)),
+        }
+    }
+
+    fn syscall(&mut self) -> Result<(), Halt> {
+        let number = self.read_reg(Reg::RAX);
+        let fd = self.read_reg(Reg::RDI);
+        let buf = self.read_reg(Reg::RSI) as u64;
+        let count = self.read_reg(Reg::RDX) as usize;
+        let ret = match number {
+            SYS_READ if fd == 0 => {
+                let mut bytes = vec![0u8; count];
+                match self.stdin.read(&mut bytes) {
+                    Ok(n) => match self.memory.write_bytes(buf, &bytes[..n]) {
+                        Ok(_) => n as i64,
+                        Err(_) => -EFAULT,
+                    },
+                    Err(e) => -(e.raw_os_error().unwrap_or(EBADF as i32) as i64),
+                }
+            }
+            SYS_WRITE if fd == 1 || fd == 2 => match self.memory.read_bytes(buf, count) {
+                Ok(bytes) => {
+                    let res = if fd == 1 {
+                        self.stdout.write_all(&bytes)
+                    } else {
+                        let _ = self.stdout.flush();
+                        std::io::stderr().write_all(&bytes)
+                    };
+                    match res {
+                        Ok(_) => count as i64,
+                        Err(e) => -(e.raw_os_error().unwrap_or(EBADF as i32) as i64),
+                    }
+                }
+                Err(_) => -EFAULT,
+            },
+            SYS_READ | SYS_WRITE => -EBADF,
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                let _ = self.stdout.flush();
+                return Err(Halt::Exit(fd as i32));
+            }
+            _ => -ENOSYS,
+        };
+        self.write_reg(Reg::RAX, ret);
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+/**********************************************************************************************
+*
+*   simulator/memory: flat memory model used by the simulator
+*
+*   The simulated address space has two regions, a static region (string literals, static
+*   variables and struct storage) that grows upward and a fixed size stack that grows
+*   downward. Addresses are small enough to be used as rbp relative displacements.
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+
+/// Start of the static region
+pub const STATIC_BASE: u64 = 0x1000;
+/// Highest address of the stack (exclusive)
+pub const STACK_TOP: u64 = 0x0100_0000;
+/// Size of the simulated stack (8 MiB)
+pub const STACK_SIZE: u64 = 0x0080_0000;
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+    static_mem: Vec<u8>,
+    stack: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            static_mem: Vec::new(),
+            stack: vec![0; STACK_SIZE as usize],
+        }
+    }
+
+    /// Reserves zeroed static memory and returns its address
+    pub fn alloc_static(&mut self, size: usize) -> u64 {
+        let aligned = (self.static_mem.len() + 7) & !7;
+        self.static_mem.resize(aligned + size.max(1), 0);
+        STATIC_BASE + aligned as u64
+    }
+
+    /// Places bytes in static memory and returns their address
+    pub fn alloc_static_bytes(&mut self, bytes: &[u8]) -> u64 {
+        let addr = self.alloc_static(bytes.len());
+        self.write_bytes(addr, bytes)
+            .expect("freshly allocated memory must be writable");
+        addr
+    }
+
+    fn slice_mut(&mut self, addr: u64, len: usize) -> Result<&mut [u8], String> {
+        let end = addr.checked_add(len as u64);
+        let static_end = STATIC_BASE + self.static_mem.len() as u64;
+        match end {
+            Some(end) if addr >= STATIC_BASE && end <= static_end => {
+                let start = (addr - STATIC_BASE) as usize;
+                Ok(&mut self.static_mem[start..start + len])
+            }
+            Some(end) if addr >= STACK_TOP - STACK_SIZE && end <= STACK_TOP => {
+                let start = (addr - (STACK_TOP - STACK_SIZE)) as usize;
+                Ok(&mut self.stack[start..start + len])
+            }
+            _ => Err(format!(
+                "Segmentation fault: invalid memory access at 0x{addr:x} ({len} bytes)"
+            )),
+        }
+    }
+
+    pub fn read_bytes(&mut self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        Ok(self.slice_mut(addr, len)?.to_vec())
+    }
+
+    pub fn write_bytes(&mut self, addr: u64, bytes: &[u8]) -> Result<(), String> {
+        self.slice_mut(addr, bytes.len())?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reads a little endian value of `size` bytes, zero extended to 64 bits
+    pub fn read(&mut self, addr: u64, size: u8) -> Result<i64, String> {
+        let bytes = self.read_bytes(addr, size as usize)?;
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(i64::from_le_bytes(buf))
+    }
+
+    /// Writes the lowest `size` bytes of value
+    pub fn write(&mut self, addr: u64, size: u8, value: i64) -> Result<(), String> {
+        let bytes = value.to_le_bytes();
+        self.write_bytes(addr, &bytes[..size as usize])
+    }
+}
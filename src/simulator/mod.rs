@@ -0,0 +1,1133 @@
+/**********************************************************************************************
+*
+*   simulator/mod: Tree walking interpreter for nmet programs
+*
+*   Executes the ast directly instead of generating machine code. Memory layout and value
+*   sizes follow the code generator so programs behave the same way as the compiled binary.
+*   Inline assembly is emulated on a small register file and only the write, read and exit
+*   system calls are supported.
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+mod asm;
+pub mod memory;
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    rc::Rc,
+};
+
+use crate::{
+    error_handeling::{
+        report_error_count, CompilationError, Diagnostic, ErrorFormat, Loc, Severity,
+    },
+    ir::codegen::{ARG_REGS, FLOAT_ARG_REGS},
+    parser::{
+        assign::{Assign, AssignOp},
+        block::Block,
+        expr::{CompareExpr, CompareOp, Expr, ExprType, FunctionCall, Op},
+        function::{FunctionAttr, FunctionDef},
+        parse_source_file,
+        program::{ProgramFile, ProgramItem},
+        stmt::{ElseBlock, ForLoop, IFStmt, Stmt, StmtType},
+        types::{StructType, VariableType},
+        variable_decl::VariableDeclare,
+    },
+    vfs::DiskFs,
+    warnings::{Warning, Warnings},
+    CompilerOptions,
+};
+
+use memory::{Memory, STACK_TOP};
+
+/// Maximum depth of nested function calls before reporting a stack overflow
+const MAX_CALL_DEPTH: usize = 4096;
+
+/// Stack size of the thread running the simulation
+pub const SIM_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+/// The evaluator is recursive and the host stack used by a simulated call depends on
+/// how deeply it is nested, calls report a stack overflow once less than this is left
+const SIM_STACK_RESERVE: usize = 32 * 1024 * 1024;
+
+/// Current address of the host stack which grows downwards
+#[inline(never)]
+fn host_stack_address() -> usize {
+    let marker = 0u8;
+    std::ptr::addr_of!(marker) as usize
+}
+
+/// Runs the simulation on a thread with a stack of SIM_STACK_SIZE
+/// so deep recursions stop at MAX_CALL_DEPTH instead of overflowing the host stack
+pub fn with_sim_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|s| {
+        let handle = std::thread::Builder::new()
+            .name("simulator".into())
+            .stack_size(SIM_STACK_SIZE)
+            .spawn_scoped(s, f)
+            .expect("Can not create the simulator thread");
+        handle
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+/// Reasons for the simulation to stop early
+#[derive(Debug)]
+pub enum Halt {
+    /// Program called the exit syscall
+    Exit(i32),
+    /// Runtime or type error at the given location
+    Error(Loc, Box<CompilationError>),
+}
+
+impl Halt {
+    fn error(loc: Loc, err: CompilationError) -> Self {
+        Self::Error(loc, Box::new(err))
+    }
+}
+
+/// Control flow result of executing a statement
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(SimValue),
+    /// Return of a call that reuses the frame of the returning function
+    TailCall(TailCall),
+}
+
+struct TailCall {
+    func: Rc<FunctionDef>,
+    args: Vec<SimValue>,
+    loc: Loc,
+}
+
+#[derive(Debug, Clone)]
+struct SimValue {
+    value: i64,
+    vtype: VariableType,
+}
+
+impl SimValue {
+    fn new(value: i64, vtype: VariableType) -> Self {
+        Self { value, vtype }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SimVariable {
+    addr: u64,
+    vtype: VariableType,
+    is_mut: bool,
+    /// Location of the declaration
+    decl: Option<Loc>,
+}
+
+impl SimVariable {
+    /// Size of the memory access for reading and writing the variable
+    /// Mirrors VariableMap::mem in the code generator
+    fn access_size(&self) -> u8 {
+        match &self.vtype {
+            VariableType::Array(t, _) => t.item_size(),
+            VariableType::Struct(_) | VariableType::Custom(_) => 8,
+            t => t.item_size(),
+        }
+    }
+}
+
+/// Variables of a single function call
+struct Frame {
+    rbp: u64,
+    scopes: Vec<HashMap<String, SimVariable>>,
+    /// Return type of the function if its calls in return statements are tail calls
+    tail_call_ret: Option<VariableType>,
+}
+
+pub struct Simulator<'a> {
+    functions: HashMap<String, Rc<FunctionDef>>,
+    structs: HashMap<String, StructType>,
+    ffi: HashMap<String, String>,
+    globals: HashMap<String, SimVariable>,
+    frames: Vec<Frame>,
+    memory: Memory,
+    sp: u64,
+    regs: [i64; 16],
+    /// Operands of the last cmp or test instruction
+    flags: (i64, i64),
+    /// String literals and struct storage are allocated once per source location
+    static_cache: HashMap<String, u64>,
+    stdout: &'a mut dyn Write,
+    stdin: &'a mut dyn Read,
+    /// Keep calls in return statements instead of reusing the frame (--no-tail-calls)
+    pub no_tail_calls: bool,
+    /// Format of the reported runtime errors
+    pub error_format: ErrorFormat,
+    /// Address of the host stack when the simulation started
+    stack_base: usize,
+}
+
+/// Parses and simulates the program
+/// Returns the exit code of the program
+pub fn simulate_program(path: String, co: &CompilerOptions) -> i32 {
+    let program = match parse_source_file(&DiskFs, path, co.target_platform) {
+        Ok(program) => program,
+        Err(diagnostics) => {
+            for diag in diagnostics.iter() {
+                diag.emit(co.error_format);
+            }
+            report_error_count("Simulation", diagnostics.len(), co.error_format);
+            return -1;
+        }
+    };
+    let diagnostics = check_warnings(&program, &co.warnings);
+    for diag in diagnostics.iter() {
+        diag.emit(co.error_format);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        report_error_count("Simulation", errors, co.error_format);
+        return -1;
+    }
+    with_sim_stack(|| {
+        let mut stdout = std::io::stdout().lock();
+        let mut stdin = std::io::stdin().lock();
+        let mut sim = Simulator::new(&mut stdout, &mut stdin);
+        sim.no_tail_calls = co.no_tail_calls;
+        sim.error_format = co.error_format;
+        let code = sim.run(&program);
+        let _ = stdout.flush();
+        code
+    })
+}
+
+/// Checks the warnings of every function once before the program is simulated
+/// Returns the reported warnings, denied warnings have the error severity
+pub fn check_warnings(program: &ProgramFile, warnings: &Warnings) -> Vec<Diagnostic> {
+    let mut ret_types = HashMap::new();
+    for item in program.items.iter() {
+        match item {
+            ProgramItem::Func(f) => {
+                ret_types.insert(f.decl.ident.as_str(), &f.decl.ret_type);
+            }
+            ProgramItem::FFI(_, decl) => {
+                ret_types.insert(decl.ident.as_str(), &decl.ret_type);
+            }
+            _ => (),
+        }
+    }
+    let mut checker = WarningChecker {
+        warnings,
+        ret_types,
+        allowed: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for item in program.items.iter() {
+        if let ProgramItem::Func(f) = item {
+            checker.allowed = f
+                .attrs
+                .iter()
+                .filter_map(|attr| match attr {
+                    FunctionAttr::Allow(warning) => Some(*warning),
+                    _ => None,
+                })
+                .collect();
+            checker.block(&f.block);
+            checker.block(&f.defer_block);
+        }
+    }
+    checker.diagnostics
+}
+
+struct WarningChecker<'a> {
+    warnings: &'a Warnings,
+    ret_types: HashMap<&'a str, &'a VariableType>,
+    /// Warnings allowed by the attributes of the current function
+    allowed: Vec<Warning>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl WarningChecker<'_> {
+    fn warn(&mut self, warning: Warning, diag: Diagnostic) {
+        if let Some(diag) = self.warnings.check(warning, &self.allowed, diag) {
+            self.diagnostics.push(diag);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in block.stmts.iter().chain(block.defer_stmts.iter()) {
+            self.stmt(stmt);
+        }
+    }
+
+    fn if_stmt(&mut self, ifs: &IFStmt) {
+        self.block(&ifs.then_block);
+        match ifs.else_block.as_ref() {
+            ElseBlock::Elif(elif) => self.if_stmt(elif),
+            ElseBlock::Else(block) => self.block(block),
+            ElseBlock::None => (),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.stype {
+            StmtType::If(ifs) => self.if_stmt(ifs),
+            StmtType::While(w) => self.block(&w.block),
+            StmtType::ForLoop(f) => self.block(&f.block),
+            StmtType::Expr(e) => match &e.etype {
+                ExprType::FunctionCall(fc) => {
+                    let Some(ret_type) = self.ret_types.get(fc.ident.as_str()) else {
+                        return;
+                    };
+                    if **ret_type != VariableType::Void {
+                        let diag = Diagnostic::warning(
+                            format!("Unused return value of function {}", fc.ident),
+                            stmt.loc.clone(),
+                        )
+                        .with_label("returns a value of type ".to_string() + &ret_type.to_string());
+                        self.warn(Warning::UnusedReturn, diag);
+                    }
+                }
+                _ => {
+                    let diag =
+                        Diagnostic::warning("Expression with no effect ignored", e.loc.clone())
+                            .with_label("this expression is not used");
+                    self.warn(Warning::UnusedValue, diag);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(stdout: &'a mut dyn Write, stdin: &'a mut dyn Read) -> Self {
+        Self {
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            ffi: HashMap::new(),
+            globals: HashMap::new(),
+            frames: Vec::new(),
+            memory: Memory::new(),
+            sp: STACK_TOP,
+            regs: [0; 16],
+            flags: (0, 0),
+            static_cache: HashMap::new(),
+            stdout,
+            stdin,
+            no_tail_calls: false,
+            error_format: ErrorFormat::Human,
+            stack_base: 0,
+        }
+    }
+
+    /// Runs the main function of the program
+    /// Errors are reported to stderr and result in a non zero exit code
+    /// Has to be called on a thread with a stack of SIM_STACK_SIZE (see with_sim_stack)
+    pub fn run(&mut self, program: &ProgramFile) -> i32 {
+        self.stack_base = host_stack_address();
+        match self.run_program(program) {
+            Ok(_) => 0,
+            Err(Halt::Exit(code)) => code,
+            Err(Halt::Error(loc, e)) => {
+                let _ = self.stdout.flush();
+                e.diagnostic(loc).emit(self.error_format);
+                -1
+            }
+        }
+    }
+
+    fn run_program(&mut self, program: &ProgramFile) -> Result<(), Halt> {
+        for item in program.items.iter() {
+            match item {
+                ProgramItem::Func(f) => {
+                    self.functions
+                        .insert(f.decl.ident.clone(), Rc::new(f.clone()));
+                }
+                ProgramItem::Struct(s) => {
+                    self.structs.insert(s.ident.clone(), s.clone());
+                }
+                ProgramItem::FFI(sym, f) => {
+                    self.ffi.insert(f.ident.clone(), sym.clone());
+                }
+                ProgramItem::StaticVar(_) => (),
+            }
+        }
+        for item in program.items.iter() {
+            if let ProgramItem::StaticVar(sv) = item {
+                self.declare_variable(sv, true)?;
+            }
+        }
+        let entry = Loc::new("_start".to_string(), 0, 0);
+        let Some(main) = self.functions.get("main").cloned() else {
+            return Err(Halt::error(
+                entry,
+                CompilationError::Err("Executable programs should have an entry point".into()),
+            ));
+        };
+        self.call_function(main, Vec::new(), &entry)?;
+        Ok(())
+    }
+
+    fn push_scope(&mut self) -> u64 {
+        self.frame().scopes.push(HashMap::new());
+        self.sp
+    }
+
+    fn pop_scope(&mut self, sp: u64) {
+        self.frame().scopes.pop();
+        self.sp = sp;
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("simulator is not in a function")
+    }
+
+    fn get_variable(&self, ident: &str) -> Result<SimVariable, CompilationError> {
+        if let Some(frame) = self.frames.last() {
+            for scope in frame.scopes.iter().rev() {
+                if let Some(var) = scope.get(ident) {
+                    return Ok(var.clone());
+                }
+            }
+        }
+        match self.globals.get(ident) {
+            Some(var) => Ok(var.clone()),
+            None => Err(CompilationError::UndefinedVariable(ident.to_string())),
+        }
+    }
+
+    fn alloc_stack(&mut self, size: usize, loc: &Loc) -> Result<u64, Halt> {
+        let size = (size.max(1) as u64 + 7) & !7;
+        if self.sp - size < memory::STACK_TOP - memory::STACK_SIZE {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::Err("Stack overflow".into()),
+            ));
+        }
+        self.sp -= size;
+        Ok(self.sp)
+    }
+
+    fn read(&mut self, addr: u64, size: u8, loc: &Loc) -> Result<i64, Halt> {
+        self.memory
+            .read(addr, size)
+            .map_err(|e| Halt::error(loc.clone(), CompilationError::Err(e)))
+    }
+
+    fn write(&mut self, addr: u64, size: u8, value: i64, loc: &Loc) -> Result<(), Halt> {
+        self.memory
+            .write(addr, size, value)
+            .map_err(|e| Halt::error(loc.clone(), CompilationError::Err(e)))
+    }
+
+    /// Returns the static address allocated for a source location
+    fn static_for(&mut self, key: String, bytes: &[u8]) -> u64 {
+        if let Some(addr) = self.static_cache.get(&key) {
+            return *addr;
+        }
+        let addr = self.memory.alloc_static_bytes(bytes);
+        self.static_cache.insert(key, addr);
+        addr
+    }
+
+    fn declare_variable(&mut self, var: &VariableDeclare, is_global: bool) -> Result<(), Halt> {
+        let loc = &var.loc;
+        let mut vtype = var.v_type.clone();
+        let mut struct_ptr = None;
+        if let VariableType::Custom(s) = &vtype {
+            let Some(struct_map) = self.structs.get(s).cloned() else {
+                return Err(Halt::error(
+                    loc.clone(),
+                    CompilationError::UnknownType(s.to_owned()),
+                ));
+            };
+            let key = format!("struct:{loc}:{}", var.ident);
+            struct_ptr = Some(self.static_for(key, &vec![0; struct_map.size()]));
+            vtype = VariableType::Struct(struct_map);
+        }
+        let mut init = None;
+        if let Some(init_value) = &var.init_value {
+            let value = self.eval_expr(init_value)?;
+            let vt = at(vtype.cast(&value.vtype), &init_value.loc)?;
+            // declared type is kept when mixing integers and floats
+            if !(vtype.is_numeric() && vt == VariableType::Float) {
+                vtype = vt;
+            }
+            init = Some(convert(&value, &vtype));
+        }
+        if vtype == VariableType::Any {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::UnknownType(var.ident.to_owned()),
+            ));
+        }
+        let addr = if is_global {
+            self.memory.alloc_static(vtype.size())
+        } else {
+            self.alloc_stack(vtype.size(), loc)?
+        };
+        if let Some(ptr) = struct_ptr {
+            self.write(addr, 8, ptr as i64, loc)?;
+        }
+        if let Some(value) = init {
+            self.write(addr, vtype.item_size(), value, loc)?;
+        }
+        let sim_var = SimVariable {
+            addr,
+            vtype,
+            is_mut: var.mutable,
+            decl: Some(var.loc.clone()),
+        };
+        if is_global {
+            self.globals.insert(var.ident.clone(), sim_var);
+        } else {
+            self.frame()
+                .scopes
+                .last_mut()
+                .unwrap()
+                .insert(var.ident.clone(), sim_var);
+        }
+        Ok(())
+    }
+
+    fn call_function(
+        &mut self,
+        f: Rc<FunctionDef>,
+        args: Vec<SimValue>,
+        loc: &Loc,
+    ) -> Result<SimValue, Halt> {
+        let stack_used = self.stack_base.saturating_sub(host_stack_address());
+        if self.frames.len() >= MAX_CALL_DEPTH || stack_used > SIM_STACK_SIZE - SIM_STACK_RESERVE {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::Err("Stack overflow".into()),
+            ));
+        }
+        let ret_type = f.decl.ret_type.clone();
+        let mut call = TailCall {
+            func: f,
+            args,
+            loc: loc.clone(),
+        };
+        // tail calls run in place of the returning function at the same depth
+        loop {
+            match self.exec_function(&call.func, call.args, &call.loc)? {
+                Flow::TailCall(next) => call = next,
+                Flow::Return(v) => return Ok(SimValue::new(convert(&v, &ret_type), ret_type)),
+                _ => unreachable!("functions return a value or a tail call"),
+            }
+        }
+    }
+
+    /// Runs the function in a new frame
+    /// Returns the value of the function or the call replacing its return
+    fn exec_function(
+        &mut self,
+        f: &FunctionDef,
+        args: Vec<SimValue>,
+        loc: &Loc,
+    ) -> Result<Flow, Halt> {
+        if args.len() != f.decl.args.len() {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::Err(format!(
+                    "Function ({}) expects {} arguments but {} were provided",
+                    f.decl.ident,
+                    f.decl.args.len(),
+                    args.len()
+                )),
+            ));
+        }
+        let saved_sp = self.sp;
+        let saved_rbp = self.regs[asm::RBP_INDEX];
+        // return address and saved rbp
+        self.sp -= 16;
+        // deferred statements run after the returned call like in the compiled code
+        let tail_calls = !self.no_tail_calls && f.defer_block.stmts.is_empty();
+        self.frames.push(Frame {
+            rbp: self.sp,
+            scopes: vec![HashMap::new()],
+            tail_call_ret: tail_calls.then(|| f.decl.ret_type.clone()),
+        });
+        self.regs[asm::RBP_INDEX] = self.sp as i64;
+        let mut result = Ok(Flow::Return(SimValue::new(0, f.decl.ret_type.clone())));
+        for (arg, value) in f.decl.args.iter().zip(args) {
+            let addr = match self.alloc_stack(8, loc) {
+                Ok(addr) => addr,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+            let var = SimVariable {
+                addr,
+                vtype: arg.typedef.clone(),
+                is_mut: false,
+                decl: None,
+            };
+            let value = convert(&value, &var.vtype);
+            if let Err(e) = self.write(addr, var.access_size(), value, loc) {
+                result = Err(e);
+                break;
+            }
+            self.frame().scopes[0].insert(arg.ident.clone(), var);
+        }
+        if result.is_ok() {
+            result = match self.exec_stmts(&f.block.stmts) {
+                Ok(Flow::Return(v)) => Ok(Flow::Return(SimValue::new(
+                    convert(&v, &f.decl.ret_type),
+                    f.decl.ret_type.clone(),
+                ))),
+                Ok(Flow::TailCall(call)) => Ok(Flow::TailCall(call)),
+                Ok(_) => result,
+                Err(e) => Err(e),
+            };
+        }
+        if result.is_ok() {
+            if let Err(e) = self.exec_stmts(&f.defer_block.stmts) {
+                result = Err(e);
+            }
+        }
+        self.frames.pop();
+        self.sp = saved_sp;
+        self.regs[asm::RBP_INDEX] = saved_rbp;
+        result
+    }
+
+    fn exec_block(&mut self, block: &Block) -> Result<Flow, Halt> {
+        let sp = self.push_scope();
+        let flow = self.exec_stmts(&block.stmts);
+        let flow = match flow {
+            Ok(flow) => self.exec_stmts(&block.defer_stmts).map(|_| flow),
+            Err(e) => Err(e),
+        };
+        self.pop_scope(sp);
+        flow
+    }
+
+    fn exec_stmts(&mut self, stmts: &[Stmt]) -> Result<Flow, Halt> {
+        for stmt in stmts {
+            match self.exec_stmt(stmt)? {
+                Flow::Normal => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt) -> Result<Flow, Halt> {
+        match &stmt.stype {
+            StmtType::VariableDecl(v) => self.declare_variable(v, false)?,
+            StmtType::Print(e) => self.exec_print(e)?,
+            StmtType::Assign(a) => self.exec_assign(a, &stmt.loc)?,
+            StmtType::If(ifs) => return self.exec_if(ifs),
+            StmtType::While(w) => loop {
+                let cond = self.eval_condition(&w.condition)?;
+                if cond == 0 {
+                    break;
+                }
+                match self.exec_block(&w.block)? {
+                    Flow::Break => break,
+                    flow @ (Flow::Return(_) | Flow::TailCall(_)) => return Ok(flow),
+                    _ => (),
+                }
+            },
+            StmtType::ForLoop(f) => return self.exec_for_loop(f),
+            StmtType::Expr(e) => {
+                // expressions without effect are reported by check_warnings and ignored
+                if let ExprType::FunctionCall(_) = e.etype {
+                    self.eval_expr(e)?;
+                }
+            }
+            StmtType::Return(e) => {
+                if let ExprType::FunctionCall(fc) = &e.etype {
+                    if let Some(call) = self.tail_call(fc, &e.loc)? {
+                        return Ok(Flow::TailCall(call));
+                    }
+                }
+                let value = self.eval_expr(e)?;
+                return Ok(Flow::Return(value));
+            }
+            StmtType::InlineAsm(asm) => {
+                let instrs = asm
+                    .expand(|ident| Ok(self.get_variable(ident)?.access_size()))
+                    .map_err(|e| Halt::error(stmt.loc.clone(), e))?;
+                self.exec_asm_block(&instrs)?;
+            }
+            StmtType::Break => return Ok(Flow::Break),
+            StmtType::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_print(&mut self, expr: &Expr) -> Result<(), Halt> {
+        let text = match &expr.etype {
+            ExprType::String(s) => s.clone(),
+            _ => match self.eval_expr(expr)? {
+                v if v.vtype == VariableType::Float => format!("{:.6}\n", as_float(v.value)),
+                v => format!("{}\n", v.value as u64),
+            },
+        };
+        self.stdout
+            .write_all(text.as_bytes())
+            .map_err(|e| Halt::error(expr.loc.clone(), CompilationError::Err(e.to_string())))
+    }
+
+    fn eval_condition(&mut self, expr: &Expr) -> Result<i64, Halt> {
+        let cond = self.eval_expr(expr)?;
+        at(VariableType::Bool.cast(&cond.vtype), &expr.loc)?;
+        Ok(cond.value)
+    }
+
+    fn exec_if(&mut self, ifs: &IFStmt) -> Result<Flow, Halt> {
+        if self.eval_condition(&ifs.condition)? != 0 {
+            return self.exec_block(&ifs.then_block);
+        }
+        match ifs.else_block.as_ref() {
+            ElseBlock::None => Ok(Flow::Normal),
+            ElseBlock::Else(b) => self.exec_block(b),
+            ElseBlock::Elif(iff) => self.exec_if(iff),
+        }
+    }
+
+    fn exec_for_loop(&mut self, for_stmt: &ForLoop) -> Result<Flow, Halt> {
+        let ExprType::Int(end) = for_stmt.end_expr.etype else {
+            return Err(Halt::error(
+                for_stmt.end_expr.loc.clone(),
+                CompilationError::Err(format!(
+                    "Unsupported iterator type (must be type integer insted of ({:?}))",
+                    for_stmt.end_expr.etype
+                )),
+            ));
+        };
+        let sp = self.push_scope();
+        let res = self.run_for_loop(for_stmt, end as i64);
+        self.pop_scope(sp);
+        res
+    }
+
+    fn run_for_loop(&mut self, for_stmt: &ForLoop, end: i64) -> Result<Flow, Halt> {
+        self.declare_variable(&for_stmt.iterator, false)?;
+        let loc = &for_stmt.iterator.loc;
+        let iter = at(self.get_variable(&for_stmt.iterator.ident), loc)?;
+        let size = iter.access_size();
+        loop {
+            if self.read(iter.addr, size, loc)? >= end {
+                return Ok(Flow::Normal);
+            }
+            match self.exec_block(&for_stmt.block)? {
+                Flow::Break => return Ok(Flow::Normal),
+                flow @ (Flow::Return(_) | Flow::TailCall(_)) => return Ok(flow),
+                _ => (),
+            }
+            let value = self.read(iter.addr, size, loc)?;
+            self.write(iter.addr, size, value.wrapping_add(1), loc)?;
+        }
+    }
+
+    /// Resolves the memory location of the left side of an assignment
+    fn place_of(&mut self, expr: &Expr) -> Result<(u64, u8, VariableType), Halt> {
+        match &expr.etype {
+            ExprType::Variable(v) => {
+                let var = at(self.get_variable(v), &expr.loc)?;
+                if !var.is_mut {
+                    return Err(Halt::error(
+                        expr.loc.clone(),
+                        CompilationError::ImmutableVariable(v.to_owned(), var.decl),
+                    ));
+                }
+                Ok((var.addr, var.access_size(), var.vtype))
+            }
+            ExprType::ArrayIndex(ai) => {
+                let var = at(self.get_variable(&ai.ident), &expr.loc)?;
+                if !var.is_mut {
+                    return Err(Halt::error(
+                        expr.loc.clone(),
+                        CompilationError::ImmutableVariable(ai.ident.clone(), var.decl),
+                    ));
+                }
+                let VariableType::Array(t, _) = &var.vtype else {
+                    return Err(Halt::error(
+                        expr.loc.clone(),
+                        CompilationError::UnexpectedType(var.vtype.to_string()),
+                    ));
+                };
+                let index = self.eval_expr(&ai.indexer)?.value;
+                let size = var.vtype.item_size();
+                let addr = var.addr.wrapping_add((index * size as i64) as u64);
+                Ok((addr, size, t.as_ref().clone()))
+            }
+            ExprType::Access(ident, member) => {
+                let (addr, item_type) = self.member_of(ident, member, &expr.loc)?;
+                Ok((addr, item_type.item_size(), item_type))
+            }
+            _ => Err(Halt::error(
+                expr.loc.clone(),
+                CompilationError::Err("Invalid left side of an assignment".into()),
+            )),
+        }
+    }
+
+    fn exec_assign(&mut self, assign: &Assign, loc: &Loc) -> Result<(), Halt> {
+        let (addr, size, vtype) = self.place_of(&assign.left)?;
+        let right = self.eval_expr(&assign.right)?;
+        let op_type = at(vtype.cast(&right.vtype), loc)?;
+        let value = match assign.op {
+            AssignOp::Eq => right,
+            _ => {
+                let current = SimValue::new(self.read(addr, size, loc)?, vtype.clone());
+                let op = match assign.op {
+                    AssignOp::PlusEq => Op::Plus,
+                    AssignOp::SubEq => Op::Sub,
+                    AssignOp::MultiEq => Op::Multi,
+                    AssignOp::DevideEq => Op::Devide,
+                    AssignOp::ModEq => Op::Mod,
+                    AssignOp::Eq => unreachable!(),
+                };
+                let value = numeric_op(&current, &right, &op, loc)?;
+                SimValue::new(value, op_type)
+            }
+        };
+        self.write(addr, size, convert(&value, &vtype), loc)
+    }
+
+    fn exec_inline_asm(&mut self, instr: &str, loc: &Loc) -> Result<Option<String>, Halt> {
+        let rbp = self.frames.last().map(|f| f.rbp).unwrap_or(STACK_TOP);
+        let source = asm::substitute_variables(instr, |ident| {
+            let var = self.get_variable(ident)?;
+            Ok((var.access_size(), var.addr as i64 - rbp as i64))
+        })
+        .map_err(|e| Halt::error(loc.clone(), e))?;
+        self.exec_asm(&source, loc)
+    }
+
+    /// Address and type of a struct member
+    fn member_of(
+        &mut self,
+        ident: &str,
+        member: &Expr,
+        loc: &Loc,
+    ) -> Result<(u64, VariableType), Halt> {
+        let var = at(self.get_variable(ident), loc)?;
+        let VariableType::Struct(struc) = &var.vtype else {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::UnexpectedType(var.vtype.to_string()),
+            ));
+        };
+        let ExprType::Variable(item) = &member.etype else {
+            return Err(Halt::error(
+                member.loc.clone(),
+                CompilationError::Err("Only direct member access can be simulated".into()),
+            ));
+        };
+        let Some(item) = struc.items.get(item) else {
+            return Err(Halt::error(
+                member.loc.clone(),
+                CompilationError::UndefinedVariable(format!("{ident}.{item}")),
+            ));
+        };
+        let ptr = self.read(var.addr, 8, loc)? as u64;
+        Ok((ptr.wrapping_add(item.offset as u64), item.vtype.clone()))
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<SimValue, Halt> {
+        let loc = &expr.loc;
+        match &expr.etype {
+            ExprType::Int(x) => Ok(SimValue::new(*x as i64, VariableType::Int)),
+            ExprType::Char(x) => Ok(SimValue::new(*x as i64, VariableType::Char)),
+            ExprType::Bool(x) => Ok(SimValue::new(*x as i64, VariableType::Bool)),
+            ExprType::Float(x) => Ok(SimValue::new(x.to_bits() as i64, VariableType::Float)),
+            ExprType::String(s) => {
+                let addr = self.static_for(format!("str:{loc}"), s.as_bytes());
+                Ok(SimValue::new(addr as i64, VariableType::String))
+            }
+            ExprType::Variable(v) => {
+                let var = at(self.get_variable(v), loc)?;
+                let value = self.read(var.addr, var.access_size(), loc)?;
+                Ok(SimValue::new(value, var.vtype))
+            }
+            ExprType::ArrayIndex(ai) => {
+                let var = at(self.get_variable(&ai.ident), loc)?;
+                let VariableType::Array(t, _) = &var.vtype else {
+                    return Err(Halt::error(
+                        loc.clone(),
+                        CompilationError::UnexpectedType(var.vtype.to_string()),
+                    ));
+                };
+                let index = self.eval_expr(&ai.indexer)?.value;
+                let size = var.vtype.item_size();
+                let addr = var.addr.wrapping_add((index * size as i64) as u64);
+                let value = self.read(addr, size, loc)?;
+                Ok(SimValue::new(value, t.as_ref().clone()))
+            }
+            ExprType::Access(ident, member) => {
+                let (addr, vtype) = self.member_of(ident, member, loc)?;
+                let value = self.read(addr, vtype.item_size(), loc)?;
+                Ok(SimValue::new(value, vtype))
+            }
+            ExprType::Ptr(e) => match &e.etype {
+                ExprType::Variable(v) => {
+                    let var = at(self.get_variable(v), loc)?;
+                    Ok(SimValue::new(var.addr as i64, VariableType::Pointer))
+                }
+                _ => Err(Halt::error(
+                    loc.clone(),
+                    CompilationError::Err("Only variables can be referenced".into()),
+                )),
+            },
+            ExprType::DeRef(e) => {
+                let ptr = self.eval_expr(e)?;
+                if ptr.vtype != VariableType::Pointer {
+                    return Err(Halt::error(
+                        loc.clone(),
+                        CompilationError::UnmatchingTypes(VariableType::Pointer, ptr.vtype),
+                    ));
+                }
+                let value = self.read(ptr.value as u64, 8, loc)?;
+                Ok(SimValue::new(value, VariableType::Any))
+            }
+            ExprType::Unary(u) => {
+                let right = self.eval_expr(&u.right)?;
+                let vtype = match right.vtype {
+                    VariableType::UInt => VariableType::Int,
+                    VariableType::ULong => VariableType::Long,
+                    VariableType::Char => VariableType::Int,
+                    t => t,
+                };
+                let value = match u.op {
+                    Op::Sub if vtype == VariableType::Float => {
+                        (-as_float(right.value)).to_bits() as i64
+                    }
+                    Op::Sub => right.value.wrapping_neg(),
+                    Op::Not => !right.value,
+                    Op::Plus => right.value,
+                    _ => {
+                        return Err(Halt::error(
+                            loc.clone(),
+                            CompilationError::Err(format!("Invalid unary operation ({})", u.op)),
+                        ))
+                    }
+                };
+                Ok(SimValue::new(value, vtype))
+            }
+            ExprType::Binary(b) => {
+                let left = self.eval_expr(&b.left)?;
+                let right = self.eval_expr(&b.right)?;
+                if b.op == Op::Not {
+                    return Err(Halt::error(
+                        loc.clone(),
+                        CompilationError::InValidBinaryOperation(
+                            b.op.clone(),
+                            left.vtype.to_string(),
+                            right.vtype.to_string(),
+                        ),
+                    ));
+                }
+                let vtype = match b.op {
+                    Op::LogicalOr | Op::LogicalAnd => VariableType::Bool,
+                    _ => at(left.vtype.cast(&right.vtype), loc)?,
+                };
+                let value = numeric_op(&left, &right, &b.op, loc)?;
+                Ok(SimValue::new(value, vtype))
+            }
+            ExprType::Compare(c) => self.eval_compare(c),
+            ExprType::FunctionCall(fc) => self.eval_function_call(fc, loc),
+        }
+    }
+
+    fn eval_compare(&mut self, cexpr: &CompareExpr) -> Result<SimValue, Halt> {
+        let left = self.eval_expr(&cexpr.left)?;
+        let right = self.eval_expr(&cexpr.right)?;
+        if left.vtype == VariableType::Float || right.vtype == VariableType::Float {
+            let left = as_float(convert(&left, &VariableType::Float));
+            let right = as_float(convert(&right, &VariableType::Float));
+            let res = match cexpr.op {
+                CompareOp::Eq => left == right,
+                CompareOp::NotEq => left != right,
+                CompareOp::Bigger => left > right,
+                CompareOp::Smaller => left < right,
+                CompareOp::BiggerEq => left >= right,
+                CompareOp::SmallerEq => left <= right,
+            };
+            return Ok(SimValue::new(res as i64, VariableType::Bool));
+        }
+        let (left, right) = (left.value, right.value);
+        let res = match cexpr.op {
+            CompareOp::Eq => left == right,
+            CompareOp::NotEq => left != right,
+            CompareOp::Bigger => left > right,
+            CompareOp::Smaller => left < right,
+            CompareOp::BiggerEq => left >= right,
+            CompareOp::SmallerEq => left <= right,
+        };
+        Ok(SimValue::new(res as i64, VariableType::Bool))
+    }
+
+    /// Arguments are evaluated from right to left like the compiled code
+    fn eval_args(&mut self, fc: &FunctionCall) -> Result<Vec<SimValue>, Halt> {
+        let mut args = Vec::new();
+        for arg in fc.args.iter().rev() {
+            args.push(self.eval_expr(arg)?);
+        }
+        args.reverse();
+        Ok(args)
+    }
+
+    /// Call of a return statement that reuses the frame of the current function
+    /// Mirrors replace_tail_call in the compiler: calls with arguments passed on the stack
+    /// or with a return value converted between integer and float are kept
+    fn tail_call(&mut self, fc: &FunctionCall, loc: &Loc) -> Result<Option<TailCall>, Halt> {
+        let Some(ret_type) = self.frames.last().and_then(|f| f.tail_call_ret.as_ref()) else {
+            return Ok(None);
+        };
+        let Some(func) = self.functions.get(&fc.ident).cloned() else {
+            return Ok(None);
+        };
+        let is_float = |t: &VariableType| *t == VariableType::Float;
+        let floats = func
+            .decl
+            .args
+            .iter()
+            .filter(|a| is_float(&a.typedef))
+            .count();
+        let ints = func.decl.args.len() - floats;
+        if is_float(ret_type) != is_float(&func.decl.ret_type)
+            || ints > ARG_REGS.len()
+            || floats > FLOAT_ARG_REGS.len()
+        {
+            return Ok(None);
+        }
+        let args = self.eval_args(fc)?;
+        Ok(Some(TailCall {
+            func,
+            args,
+            loc: loc.clone(),
+        }))
+    }
+
+    fn eval_function_call(&mut self, fc: &FunctionCall, loc: &Loc) -> Result<SimValue, Halt> {
+        let args = self.eval_args(fc)?;
+        if let Some(f) = self.functions.get(&fc.ident).cloned() {
+            return self.call_function(f, args, loc);
+        }
+        if let Some(sym) = self.ffi.get(&fc.ident) {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::Err(format!(
+                    "Foreign function ({}) mapped to ({sym}) can not be simulated",
+                    fc.ident
+                )),
+            ));
+        }
+        Err(Halt::error(
+            loc.clone(),
+            CompilationError::FunctionOutOfScope(fc.ident.clone()),
+        ))
+    }
+}
+
+/// Attaches a location to compilation errors
+fn at<T>(res: Result<T, CompilationError>, loc: &Loc) -> Result<T, Halt> {
+    res.map_err(|e| Halt::error(loc.clone(), e))
+}
+
+/// Floating point value stored in the bits of a simulated value
+fn as_float(value: i64) -> f64 {
+    f64::from_bits(value as u64)
+}
+
+/// Converts the value between the integer and floating point representations
+/// Mirrors convert_expr in the code generator
+fn convert(value: &SimValue, to: &VariableType) -> i64 {
+    let from_float = value.vtype == VariableType::Float;
+    let to_float = to == &VariableType::Float;
+    match (from_float, to_float) {
+        (false, true) => (value.value as f64).to_bits() as i64,
+        (true, false) if to.is_numeric() => as_float(value.value) as i64,
+        _ => value.value,
+    }
+}
+
+/// Evaluates binary operations on floats if one of the operands is a float
+fn numeric_op(left: &SimValue, right: &SimValue, op: &Op, loc: &Loc) -> Result<i64, Halt> {
+    if left.vtype != VariableType::Float && right.vtype != VariableType::Float {
+        return binary_op(left.value, right.value, op, loc);
+    }
+    let l = as_float(convert(left, &VariableType::Float));
+    let r = as_float(convert(right, &VariableType::Float));
+    let value = match op {
+        Op::Plus => l + r,
+        Op::Sub => l - r,
+        Op::Multi => l * r,
+        Op::Devide => l / r,
+        _ => {
+            return Err(Halt::error(
+                loc.clone(),
+                CompilationError::InValidBinaryOperation(
+                    op.clone(),
+                    left.vtype.to_string(),
+                    right.vtype.to_string(),
+                ),
+            ))
+        }
+    };
+    Ok(value.to_bits() as i64)
+}
+
+/// Evaluates binary operations on 64bit values like the generated code
+fn binary_op(left: i64, right: i64, op: &Op, loc: &Loc) -> Result<i64, Halt> {
+    let div_check = || {
+        if right == 0 {
+            Err(Halt::error(
+                loc.clone(),
+                CompilationError::Err("Division by zero".into()),
+            ))
+        } else {
+            Ok(())
+        }
+    };
+    Ok(match op {
+        Op::Plus => left.wrapping_add(right),
+        Op::Sub => left.wrapping_sub(right),
+        Op::Multi => left.wrapping_mul(right),
+        Op::Devide => {
+            div_check()?;
+            left.wrapping_div(right)
+        }
+        Op::Mod => {
+            div_check()?;
+            left.wrapping_rem(right)
+        }
+        Op::Or | Op::LogicalOr => left | right,
+        Op::And | Op::LogicalAnd => left & right,
+        Op::Lsh => left.wrapping_shl(right as u32 & 63),
+        Op::Rsh => left.wrapping_shr(right as u32 & 63),
+        Op::Not => unreachable!(),
+    })
+}
@@ -26,11 +26,38 @@
 *     3. This notice may not be removed or altered from any source distribution.
 *
 **********************************************************************************************/
-use crate::{setup_compiler, utils::get_program_name, CompilerOptions};
+use crate::{setup_assembler, setup_compiler};
+use nmet::{
+    parser::parse_source_file,
+    simulator::{check_warnings, simulate_program, with_sim_stack, Simulator},
+    utils::get_program_name,
+    vfs::{DiskFs, MemoryFs},
+    warnings::Warnings,
+    CompilerOptions,
+};
 use std::{fs::remove_file, path::Path, process::Command};
 
 fn compile_and_test(input: &str, co: CompilerOptions, res_path: &str) {
     setup_compiler(input.into(), &co);
+    run_and_test(co, res_path);
+}
+
+/// Compiles to a nasm file and assembles it with the internal assembler
+fn reassemble_and_test(input: &str, co: CompilerOptions, res_path: &str) {
+    let asm_co = CompilerOptions {
+        use_nasm: true,
+        no_assembling: true,
+        keep_asm: true,
+        ..co.clone()
+    };
+    setup_compiler(input.into(), &asm_co);
+    let asm_path = co.output_path.clone().unwrap().with_extension("asm");
+    setup_assembler(asm_path.to_string_lossy().to_string(), &co);
+    remove_file(&asm_path).unwrap_or(());
+    run_and_test(co, res_path);
+}
+
+fn run_and_test(co: CompilerOptions, res_path: &str) {
     // Test output
     let opath = co
         .output_path
@@ -51,7 +78,35 @@ fn compile_and_test(input: &str, co: CompilerOptions, res_path: &str) {
         String::from_utf8(output.stdout).unwrap(),
         expectation.to_string()
     );
-    remove_file(&opath).unwrap_or_else(|_| ());
+    remove_file(&opath).unwrap_or(());
+}
+
+/// Simulates the program and returns its exit code and output
+fn simulate(input: &str) -> (i32, String) {
+    let program = parse_source_file(&DiskFs, input.into(), 0).unwrap();
+    with_sim_stack(|| {
+        let mut stdout = Vec::new();
+        let mut stdin = std::io::empty();
+        let mut sim = Simulator::new(&mut stdout, &mut stdin);
+        let code = sim.run(&program);
+        (code, String::from_utf8(stdout).unwrap())
+    })
+}
+
+fn simulate_and_test(input: &str, res_path: &str) {
+    let (code, output) = simulate(input);
+    assert_eq!(code, 0);
+    let expectation = std::fs::read_to_string(res_path).unwrap();
+    assert_eq!(output, expectation);
+}
+
+macro_rules! test_sim {
+    ($tname: ident, $in_path: expr, $res_path: expr) => {
+        #[test]
+        fn $tname() {
+            simulate_and_test($in_path, $res_path);
+        }
+    };
 }
 
 macro_rules! test_elf {
@@ -74,6 +129,44 @@ macro_rules! test_elf {
     };
 }
 
+macro_rules! test_exec {
+    ($tname: ident, $in_path: expr, $res_path: expr $(,$flag: literal)*) => {
+        #[test]
+        fn $tname() {
+            // Setup names
+            let program_name = format!("__exec_{}", get_program_name($in_path));
+            let out_path = Path::new(&format!("./build/{program_name}")).to_owned();
+            // Generate executable
+            let co = CompilerOptions {
+                output_path: Some(out_path.clone()),
+                internal_link: true,
+                linker_flags: vec![$($flag.to_string()),*],
+                ..CompilerOptions::default()
+            };
+            compile_and_test($in_path, co, $res_path);
+        }
+    };
+}
+
+macro_rules! test_opt {
+    ($tname: ident, $in_path: expr, $res_path: expr $(,$flag: literal)*) => {
+        #[test]
+        fn $tname() {
+            // Setup names
+            let program_name = format!("__opt_{}", get_program_name($in_path));
+            let out_path = Path::new(&format!("./build/{program_name}")).to_owned();
+            // Generate executable
+            let co = CompilerOptions {
+                output_path: Some(out_path.clone()),
+                opt_level: 2,
+                linker_flags: vec![$($flag.to_string()),*],
+                ..CompilerOptions::default()
+            };
+            compile_and_test($in_path, co, $res_path);
+        }
+    };
+}
+
 macro_rules! test_asm {
     ($tname: ident, $in_path: expr, $res_path: expr $(,$flag: literal)*) => {
         #[test]
@@ -94,6 +187,25 @@ macro_rules! test_asm {
     };
 }
 
+macro_rules! test_reasm {
+    ($tname: ident, $in_path: expr, $res_path: expr $(,$flag: literal)*) => {
+        #[test]
+        fn $tname() {
+            // Setup names
+            let program_name = format!("__reasm_{}", get_program_name($in_path));
+            let out_path = Path::new(&format!("./build/{program_name}")).to_owned();
+            // Generate executable
+            let co = CompilerOptions {
+                output_path: Some(out_path.clone()),
+                asm_mode: true,
+                linker_flags: vec![$($flag.to_string()),*],
+                ..CompilerOptions::default()
+            };
+            reassemble_and_test($in_path, co, $res_path);
+        }
+    };
+}
+
 mod asm {
     use super::*;
 
@@ -143,6 +255,48 @@ mod asm {
     test_asm!(defer, "./tests/defer.nmt", "./tests/defer.txt");
 }
 
+mod reasm {
+    use super::*;
+
+    test_reasm!(for_loops, "./tests/for_loops.nmt", "./tests/for_loops.txt");
+    test_reasm!(
+        static_var,
+        "./tests/static_var.nmt",
+        "./tests/static_var.txt"
+    );
+    test_reasm!(
+        ffi,
+        "./tests/ffi.nmt",
+        "./tests/ffi.txt",
+        "-L./tests",
+        "-ladd"
+    );
+    test_reasm!(
+        string_expr,
+        "./tests/string_expr.nmt",
+        "./tests/string_expr.txt"
+    );
+    test_reasm!(structs, "./tests/structs.nmt", "./tests/structs.txt");
+    test_reasm!(functions, "./tests/functions.nmt", "./tests/functions.txt");
+    test_reasm!(arrays, "./tests/arrays.nmt", "./tests/arrays.txt");
+    test_reasm!(floats, "./tests/floats.nmt", "./tests/floats.txt");
+    test_reasm!(
+        tail_calls,
+        "./tests/tail_calls.nmt",
+        "./tests/tail_calls.txt"
+    );
+    test_reasm!(
+        inline_asm,
+        "./tests/inline_asm.nmt",
+        "./tests/inline_asm.txt"
+    );
+    test_reasm!(
+        asm_operands,
+        "./tests/asm_operands.nmt",
+        "./tests/asm_operands.txt"
+    );
+}
+
 mod elf {
     use super::*;
 
@@ -190,4 +344,270 @@ mod elf {
     test_elf!(assign, "./tests/assign.nmt", "./tests/assign.txt");
     test_elf!(arrays, "./tests/arrays.nmt", "./tests/arrays.txt");
     test_elf!(defer, "./tests/defer.nmt", "./tests/defer.txt");
+    test_elf!(many_args, "./tests/many_args.nmt", "./tests/many_args.txt");
+    test_elf!(recursion, "./tests/recursion.nmt", "./tests/recursion.txt");
+    test_elf!(floats, "./tests/floats.nmt", "./tests/floats.txt");
+    test_elf!(inline, "./tests/inline.nmt", "./tests/inline.txt");
+    test_elf!(
+        tail_calls,
+        "./tests/tail_calls.nmt",
+        "./tests/tail_calls.txt"
+    );
+    test_elf!(
+        inline_asm,
+        "./tests/inline_asm.nmt",
+        "./tests/inline_asm.txt"
+    );
+    test_elf!(
+        asm_operands,
+        "./tests/asm_operands.nmt",
+        "./tests/asm_operands.txt"
+    );
+    test_elf!(
+        callee_saved,
+        "./tests/callee_saved.nmt",
+        "./tests/callee_saved.txt"
+    );
+}
+
+mod exec {
+    use super::*;
+
+    test_exec!(for_loops, "./tests/for_loops.nmt", "./tests/for_loops.txt");
+    test_exec!(
+        ffi,
+        "./tests/ffi.nmt",
+        "./tests/ffi.txt",
+        "-L./tests",
+        "-ladd"
+    );
+    test_exec!(
+        static_var,
+        "./tests/static_var.nmt",
+        "./tests/static_var.txt"
+    );
+    test_exec!(
+        binary_expr,
+        "./tests/binary_expr.nmt",
+        "./tests/binary_expr.txt"
+    );
+    test_exec!(
+        cont_break,
+        "./tests/cont_break.nmt",
+        "./tests/cont_break.txt"
+    );
+    test_exec!(
+        compare_expr,
+        "./tests/compare_expr.nmt",
+        "./tests/compare_expr.txt"
+    );
+    test_exec!(
+        string_expr,
+        "./tests/string_expr.nmt",
+        "./tests/string_expr.txt"
+    );
+    test_exec!(structs, "./tests/structs.nmt", "./tests/structs.txt");
+    test_exec!(loops, "./tests/loops.nmt", "./tests/loops.txt");
+    test_exec!(
+        conditions,
+        "./tests/conditions.nmt",
+        "./tests/conditions.txt"
+    );
+    test_exec!(functions, "./tests/functions.nmt", "./tests/functions.txt");
+    test_exec!(assign, "./tests/assign.nmt", "./tests/assign.txt");
+    test_exec!(arrays, "./tests/arrays.nmt", "./tests/arrays.txt");
+    test_exec!(defer, "./tests/defer.nmt", "./tests/defer.txt");
+    test_exec!(many_args, "./tests/many_args.nmt", "./tests/many_args.txt");
+    test_exec!(floats, "./tests/floats.nmt", "./tests/floats.txt");
+    test_exec!(inline, "./tests/inline.nmt", "./tests/inline.txt");
+    test_exec!(
+        tail_calls,
+        "./tests/tail_calls.nmt",
+        "./tests/tail_calls.txt"
+    );
+    test_exec!(
+        inline_asm,
+        "./tests/inline_asm.nmt",
+        "./tests/inline_asm.txt"
+    );
+    test_exec!(
+        asm_operands,
+        "./tests/asm_operands.nmt",
+        "./tests/asm_operands.txt"
+    );
+}
+
+mod opt {
+    use super::*;
+
+    test_opt!(for_loops, "./tests/for_loops.nmt", "./tests/for_loops.txt");
+    test_opt!(
+        static_var,
+        "./tests/static_var.nmt",
+        "./tests/static_var.txt"
+    );
+    test_opt!(
+        ffi,
+        "./tests/ffi.nmt",
+        "./tests/ffi.txt",
+        "-L./tests",
+        "-ladd"
+    );
+    test_opt!(
+        binary_expr,
+        "./tests/binary_expr.nmt",
+        "./tests/binary_expr.txt"
+    );
+    test_opt!(
+        cont_break,
+        "./tests/cont_break.nmt",
+        "./tests/cont_break.txt"
+    );
+    test_opt!(
+        compare_expr,
+        "./tests/compare_expr.nmt",
+        "./tests/compare_expr.txt"
+    );
+    test_opt!(
+        string_expr,
+        "./tests/string_expr.nmt",
+        "./tests/string_expr.txt"
+    );
+    test_opt!(structs, "./tests/structs.nmt", "./tests/structs.txt");
+    test_opt!(loops, "./tests/loops.nmt", "./tests/loops.txt");
+    test_opt!(
+        conditions,
+        "./tests/conditions.nmt",
+        "./tests/conditions.txt"
+    );
+    test_opt!(functions, "./tests/functions.nmt", "./tests/functions.txt");
+    test_opt!(assign, "./tests/assign.nmt", "./tests/assign.txt");
+    test_opt!(arrays, "./tests/arrays.nmt", "./tests/arrays.txt");
+    test_opt!(defer, "./tests/defer.nmt", "./tests/defer.txt");
+    test_opt!(many_args, "./tests/many_args.nmt", "./tests/many_args.txt");
+    test_opt!(floats, "./tests/floats.nmt", "./tests/floats.txt");
+    test_opt!(inline, "./tests/inline.nmt", "./tests/inline.txt");
+    test_opt!(
+        tail_calls,
+        "./tests/tail_calls.nmt",
+        "./tests/tail_calls.txt"
+    );
+    test_opt!(
+        inline_asm,
+        "./tests/inline_asm.nmt",
+        "./tests/inline_asm.txt"
+    );
+    test_opt!(
+        asm_operands,
+        "./tests/asm_operands.nmt",
+        "./tests/asm_operands.txt"
+    );
+    test_opt!(
+        callee_saved,
+        "./tests/callee_saved.nmt",
+        "./tests/callee_saved.txt"
+    );
+}
+
+mod sim {
+    use super::*;
+
+    test_sim!(for_loops, "./tests/for_loops.nmt", "./tests/for_loops.txt");
+    test_sim!(
+        static_var,
+        "./tests/static_var.nmt",
+        "./tests/static_var.txt"
+    );
+    test_sim!(
+        binary_expr,
+        "./tests/binary_expr.nmt",
+        "./tests/binary_expr.txt"
+    );
+    test_sim!(
+        cont_break,
+        "./tests/cont_break.nmt",
+        "./tests/cont_break.txt"
+    );
+    test_sim!(
+        compare_expr,
+        "./tests/compare_expr.nmt",
+        "./tests/compare_expr.txt"
+    );
+    test_sim!(
+        string_expr,
+        "./tests/string_expr.nmt",
+        "./tests/string_expr.txt"
+    );
+    test_sim!(structs, "./tests/structs.nmt", "./tests/structs.txt");
+    test_sim!(loops, "./tests/loops.nmt", "./tests/loops.txt");
+    test_sim!(
+        conditions,
+        "./tests/conditions.nmt",
+        "./tests/conditions.txt"
+    );
+    test_sim!(functions, "./tests/functions.nmt", "./tests/functions.txt");
+    test_sim!(assign, "./tests/assign.nmt", "./tests/assign.txt");
+    test_sim!(arrays, "./tests/arrays.nmt", "./tests/arrays.txt");
+    test_sim!(defer, "./tests/defer.nmt", "./tests/defer.txt");
+    test_sim!(many_args, "./tests/many_args.nmt", "./tests/many_args.txt");
+    test_sim!(floats, "./tests/floats.nmt", "./tests/floats.txt");
+    test_sim!(inline, "./tests/inline.nmt", "./tests/inline.txt");
+    test_sim!(
+        inline_asm,
+        "./tests/inline_asm.nmt",
+        "./tests/inline_asm.txt"
+    );
+    test_sim!(recursion, "./tests/recursion.nmt", "./tests/recursion.txt");
+    test_sim!(
+        asm_operands,
+        "./tests/asm_operands.nmt",
+        "./tests/asm_operands.txt"
+    );
+    // tail_calls.nmt takes minutes to simulate, this recursion is still deeper than the call limit
+    test_sim!(
+        tail_recursion,
+        "./tests/tail_recursion.nmt",
+        "./tests/tail_recursion.txt"
+    );
+
+    #[test]
+    fn stack_overflow() {
+        // without tail calls the recursion is deeper than the call limit
+        let program = parse_source_file(&DiskFs, "./tests/tail_recursion.nmt".into(), 0).unwrap();
+        let (code, output) = with_sim_stack(|| {
+            let mut stdout = Vec::new();
+            let mut stdin = std::io::empty();
+            let mut sim = Simulator::new(&mut stdout, &mut stdin);
+            sim.no_tail_calls = true;
+            (sim.run(&program), stdout)
+        });
+        assert_eq!(code, -1);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn warnings() {
+        let source = "func one() @int {\n    return 1;\n}\nfunc main() {\n    for i to 3 {\n        i + 1;\n    }\n    one();\n}\n";
+        let fs: MemoryFs = [("main.nmt", source)].into_iter().collect();
+        let program = parse_source_file(&fs, "main.nmt".into(), 0).unwrap();
+        // reported once even though the loop runs three times
+        let mut warnings = Warnings::default();
+        let diags = check_warnings(&program, &warnings);
+        let codes: Vec<_> = diags.iter().map(|d| (d.code, d.loc.line)).collect();
+        assert_eq!(
+            codes,
+            [(Some("unused-value"), 6), (Some("unused-return"), 8)]
+        );
+        // denied warnings stop the simulation before it starts
+        warnings.apply_flag("-Werror=unused-value").unwrap();
+        let co = CompilerOptions {
+            warnings,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join("__sim_warnings.nmt");
+        std::fs::write(&path, source).unwrap();
+        let code = simulate_program(path.to_string_lossy().into(), &co);
+        remove_file(&path).unwrap();
+        assert_eq!(code, -1);
+    }
 }
@@ -0,0 +1,121 @@
+/**********************************************************************************************
+*
+*   vfs: File systems used for reading imported files and writing the generated outputs
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+pub trait FileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    /// Writes the file and creates its parent directories
+    fn write(&mut self, path: &Path, contents: &[u8]) -> io::Result<()>;
+}
+
+/// Files on the disk relative to the working directory
+pub struct DiskFs;
+
+impl FileSystem for DiskFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+    }
+}
+
+/// Files kept in memory for compiling without touching the disk
+/// "./a/b.nmt" and "a/b.nmt" are the same file
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        self.files
+            .insert(Self::normalize(path.as_ref()), contents.into());
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&[u8]> {
+        self.files
+            .get(&Self::normalize(path.as_ref()))
+            .map(|f| f.as_slice())
+    }
+
+    fn normalize(path: &Path) -> PathBuf {
+        path.components()
+            .filter(|c| *c != Component::CurDir)
+            .collect()
+    }
+}
+
+impl<P: AsRef<Path>, S: Into<Vec<u8>>> FromIterator<(P, S)> for MemoryFs {
+    fn from_iter<T: IntoIterator<Item = (P, S)>>(iter: T) -> Self {
+        let mut fs = Self::new();
+        for (path, contents) in iter {
+            fs.insert(path, contents);
+        }
+        fs
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let Some(contents) = self.get(path) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No such file in memory",
+            ));
+        };
+        String::from_utf8(contents.to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn write(&mut self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.insert(path, contents);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_memory_fs() {
+    let mut fs: MemoryFs = [("./std/io.nmt", "func a() {}")].into_iter().collect();
+    assert_eq!(
+        fs.read_to_string(Path::new("std/io.nmt")).unwrap(),
+        "func a() {}"
+    );
+    assert!(fs.read_to_string(Path::new("io.nmt")).is_err());
+    fs.write(Path::new("build/io.o"), &[1, 2]).unwrap();
+    assert_eq!(fs.get("./build/io.o"), Some([1, 2].as_slice()));
+}
@@ -0,0 +1,173 @@
+/**********************************************************************************************
+*
+*   warnings: Named warnings and their levels set by -W flags and #allow attributes
+*
+*   LICENSE: MIT
+*
+*   Copyright (c) 2023-2024 Mahan Farzaneh (@mahanfr)
+*
+*   This software is provided "as-is", without any express or implied warranty. In no event
+*   will the authors be held liable for any damages arising from the use of this software.
+*
+*   Permission is granted to anyone to use this software for any purpose, including commercial
+*   applications, and to alter it and redistribute it freely, subject to the following restrictions:
+*
+*     1. The origin of this software must not be misrepresented; you must not claim that you
+*     wrote the original software. If you use this software in a product, an acknowledgment
+*     in the product documentation would be appreciated but is not required.
+*
+*     2. Altered source versions must be plainly marked as such, and must not be misrepresented
+*     as being the original software.
+*
+*     3. This notice may not be removed or altered from any source distribution.
+*
+**********************************************************************************************/
+use std::collections::BTreeMap;
+
+use crate::error_handeling::{Diagnostic, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Warning {
+    /// Return value of a function call statement is discarded
+    UnusedReturn,
+    /// Expression statement that is not evaluated
+    UnusedValue,
+}
+
+impl Warning {
+    pub const ALL: [Warning; 2] = [Warning::UnusedReturn, Warning::UnusedValue];
+
+    /// Name used by -W flags and #allow
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnusedReturn => "unused-return",
+            Self::UnusedValue => "unused-value",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|w| w.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarnLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Levels of the warnings set on the command line
+/// every warning is reported by default
+#[derive(Debug, Clone, Default)]
+pub struct Warnings {
+    /// Warnings that are disabled or denied
+    levels: BTreeMap<Warning, WarnLevel>,
+    /// Treat every reported warning as an error (-Werror)
+    deny_all: bool,
+}
+
+impl Warnings {
+    pub fn level(&self, warning: Warning) -> WarnLevel {
+        match self.levels.get(&warning) {
+            Some(level) => *level,
+            None if self.deny_all => WarnLevel::Deny,
+            None => WarnLevel::Warn,
+        }
+    }
+
+    /// Applies a warning flag
+    /// -Wall, -W<name>, -Wno-<name>, -Werror or -Werror=<name>
+    pub fn apply_flag(&mut self, flag: &str) -> Result<(), String> {
+        let Some(flag) = flag.strip_prefix("-W") else {
+            return Err(format!("Invalid warning flag ({flag})"));
+        };
+        let (name, level) = match flag {
+            "all" => {
+                self.levels.retain(|_, l| *l != WarnLevel::Allow);
+                return Ok(());
+            }
+            "error" => {
+                self.deny_all = true;
+                return Ok(());
+            }
+            _ => match (flag.strip_prefix("no-"), flag.strip_prefix("error=")) {
+                (Some(name), _) => (name, WarnLevel::Allow),
+                (_, Some(name)) => (name, WarnLevel::Deny),
+                _ => (flag, WarnLevel::Warn),
+            },
+        };
+        let Some(warning) = Warning::from_name(name) else {
+            return Err(format!("Unknown warning ({name})"));
+        };
+        match level {
+            // enabling a warning does not change a denied warning
+            WarnLevel::Warn => {
+                if self.levels.get(&warning) == Some(&WarnLevel::Allow) {
+                    self.levels.remove(&warning);
+                }
+            }
+            level => {
+                self.levels.insert(warning, level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Diagnostic of the warning to report unless it is allowed
+    /// * allowed - warnings allowed by the attributes of the current function
+    ///
+    /// Denied warnings are turned into errors
+    pub fn check(
+        &self,
+        warning: Warning,
+        allowed: &[Warning],
+        diag: Diagnostic,
+    ) -> Option<Diagnostic> {
+        if allowed.contains(&warning) {
+            return None;
+        }
+        let diag = diag.with_code(warning.name());
+        match self.level(warning) {
+            WarnLevel::Allow => None,
+            WarnLevel::Warn => Some(diag),
+            WarnLevel::Deny => {
+                let mut diag = diag.with_note(format!(
+                    "warning ({}) is treated as an error",
+                    warning.name()
+                ));
+                diag.severity = Severity::Error;
+                Some(diag)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_warning_flags() {
+    let mut warnings = Warnings::default();
+    assert_eq!(warnings.level(Warning::UnusedValue), WarnLevel::Warn);
+    warnings.apply_flag("-Wno-unused-value").unwrap();
+    warnings.apply_flag("-Werror").unwrap();
+    assert_eq!(warnings.level(Warning::UnusedValue), WarnLevel::Allow);
+    assert_eq!(warnings.level(Warning::UnusedReturn), WarnLevel::Deny);
+    warnings.apply_flag("-Wall").unwrap();
+    assert_eq!(warnings.level(Warning::UnusedValue), WarnLevel::Deny);
+    let mut warnings = Warnings::default();
+    warnings.apply_flag("-Werror=unused-return").unwrap();
+    warnings.apply_flag("-Wno-unused-value").unwrap();
+    warnings.apply_flag("-Wunused-return").unwrap();
+    warnings.apply_flag("-Wunused-value").unwrap();
+    assert_eq!(warnings.level(Warning::UnusedReturn), WarnLevel::Deny);
+    assert_eq!(warnings.level(Warning::UnusedValue), WarnLevel::Warn);
+    assert_eq!(
+        warnings.apply_flag("-Wunused"),
+        Err("Unknown warning (unused)".to_string())
+    );
+    let diag = Diagnostic::warning("", crate::error_handeling::Loc::new("".into(), 1, 1));
+    assert!(warnings
+        .check(Warning::UnusedValue, &[Warning::UnusedValue], diag.clone())
+        .is_none());
+    let diag = warnings.check(Warning::UnusedReturn, &[], diag).unwrap();
+    assert_eq!(diag.severity, Severity::Error);
+}